@@ -3,22 +3,208 @@
 //
 // BattleLogから行動を集計し、統計情報を計算
 
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::datetime;
+use crate::error::{Result, SmasherError};
+use crate::group::{GroupMap, UNGROUPED};
+
 // 意味：「このプロジェクト内で、定義したmodelの、ActionCounts, AnalysisResult, BattleLogを使いますという宣言」
-use crate::model::{ActionCounts, AnalysisResult, BattleLog};
+use crate::model::{
+    Action, ActionComparison, ActionCounts, ActionType, AggregateCounts, AttackCategory, CategoryComparison, Continuity, ShieldCover, ActivityStrip, AggregateResult, AnalysisResult, AttackStreaks, BattleLog, CategoryTimeSpread, Classifier, ComboStats,
+    DensityPeak, DensitySpike, DensitySpikes, Direction, DirectionBalance, DupPolicy, DuplicateMatch, GrabFollowUps, HalfCounts, RepeatRates, TensionCurve, FlowStyle, FlowSwitches, GroupSummary, IntervalHistogram, ImprovementReport, IntervalShape, IntervalStats, LogDiff, MatchCounts, MatchOrder, MetricTrend, PhaseCounts, PlayStyleCluster,
+    RandomnessCheck, RateComparison, Reliability, Burst, Bursts, RepertoireGrowth, Rhythm, SingleAttackMode, SortKey, StudentRank, TimeStats, Transitions, Trend, WeightShape, WeightedRatios, SPARKLINE_BUCKETS,
+};
+
+/// 歪度・尖度を計算するのに必要な最小の間隔数
+/// これより少ないと値が安定しないのでN/A扱いにする
+const MIN_SHAPE_SAMPLES: usize = 8;
+
+/// リピート率で、直前の行動と比べる間隔の上限のデフォルト（秒、--combo-gap）
+pub const DEFAULT_COMBO_GAP: f64 = 0.4;
+
+/// コンボとみなす攻撃間隔の上限のデフォルト（秒、--combo-window）
+pub const DEFAULT_COMBO_WINDOW: f64 = 1.0;
+
+/// スパイクとみなす密度の、平均からの標準偏差の倍率のデフォルト
+pub const DEFAULT_SPIKE_SIGMA: f64 = 3.0;
+
+/// 試合時間を等分する区間数のデフォルト（序盤・中盤・終盤）
+pub const DEFAULT_TIME_PHASES: usize = 3;
+
+/// 緊張度カーブの時間窓の長さのデフォルト（秒）
+pub const DEFAULT_TENSION_WINDOW: f64 = 10.0;
+
+/// 攻撃の後「すぐにシールドした」とみなす時間の上限のデフォルト（秒）
+pub const DEFAULT_COVER_WINDOW: f64 = 1.0;
+
+/// 隣り合う行動を遷移とみなす間隔の上限のデフォルト（秒）
+/// これより間が空いた組は、別のやりとりとして数えない
+pub const DEFAULT_TRANSITION_GAP: f64 = 3.0;
+
+/// バースト（短い間隔の連続）で同じ連続とみなす行動間隔の上限のデフォルト（秒）
+pub const DEFAULT_BURST_GAP: f64 = 0.8;
+
+/// バーストとみなす最小の行動数のデフォルト
+pub const DEFAULT_BURST_MIN_LEN: usize = 3;
+
+/// 攻撃から次のシールドまでの時間のヒストグラムの区間の幅（秒）
+pub const COVER_BIN_WIDTH: f64 = 0.25;
+
+/// 解析の設定
+#[derive(Debug, Clone)]
+pub struct AnalysisOptions {
+    /// 時間重み付き比率の重みの形
+    pub weight_shape: WeightShape,
+    /// 攻撃継続時間での単発の攻撃の扱い
+    pub single_attack: SingleAttackMode,
+    /// リピート率で、直前の行動と比べる間隔の上限（秒、--combo-gap）
+    pub combo_gap: f64,
+    /// コンボとみなす攻撃間隔の上限（秒、--combo-window）
+    pub combo_window: f64,
+    /// 行動密度の山として表示する最小の密度（1秒あたりの行動数）
+    pub peak_threshold: f64,
+    /// スパイクとみなす密度の、平均からの標準偏差の倍率
+    pub spike_sigma: f64,
+    /// 行動IDごとの時間帯別の使用回数を計算するか（--sparklines）
+    pub sparklines: bool,
+    /// 試合の前半・後半での使用回数を計算するか（--momentum）
+    pub momentum: bool,
+    /// 緊張度カーブの時間窓の長さ（秒）
+    pub tension_window: f64,
+    /// 試合時間を等分する区間数（--time-phases）
+    pub time_phases: usize,
+    /// 攻撃の後「すぐにシールドした」とみなす時間の上限（秒、--cover-window）
+    pub cover_window: f64,
+    /// 隣り合う行動を遷移とみなす間隔の上限（秒、--transition-gap）
+    pub transition_gap: f64,
+    /// バーストで同じ連続とみなす行動間隔の上限（秒、--burst-gap）
+    pub burst_gap: f64,
+    /// バーストとみなす最小の行動数（--burst-min）
+    pub burst_min_len: usize,
+    /// 行動間隔のヒストグラムのビンの幅（秒、--interval-bin。指定しなければ計算しない）
+    pub interval_bin: Option<f64>,
+}
+
+// Default: ライブラリとして使うときに AnalysisOptions::default() でも作れるようにする（new() と同じ）
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        AnalysisOptions::new()
+    }
+}
+
+impl AnalysisOptions {
+    /// デフォルト設定（線形の重み）
+    pub fn new() -> Self {
+        AnalysisOptions {
+            weight_shape: WeightShape::default(),
+            single_attack: SingleAttackMode::default(),
+            combo_gap: DEFAULT_COMBO_GAP,
+            combo_window: DEFAULT_COMBO_WINDOW,
+            peak_threshold: 0.0,
+            spike_sigma: DEFAULT_SPIKE_SIGMA,
+            sparklines: false,
+            momentum: false,
+            tension_window: DEFAULT_TENSION_WINDOW,
+            time_phases: DEFAULT_TIME_PHASES,
+            cover_window: DEFAULT_COVER_WINDOW,
+            transition_gap: DEFAULT_TRANSITION_GAP,
+            burst_gap: DEFAULT_BURST_GAP,
+            burst_min_len: DEFAULT_BURST_MIN_LEN,
+            interval_bin: None,
+        }
+    }
+}
 
 /// # 処理の流れ
 /// 1. 各行動タイプの出現回数を数える
 /// 2. ActionCountsを作成
 /// 3. AnalysisResultを作成（内部で比率計算も行われる）
+/// 4. 間隔分布や時間重み付き比率などの追加指標を計算
+/// 
+/// # 引数
+/// * `battle_log` - 対戦ログデータ
+/// * `options` - 解析の設定
 /// 
 /// # 戻り値
 /// 解析結果（AnalysisResult）
-pub fn analyze(battle_log: &BattleLog) -> AnalysisResult {
+pub fn analyze(battle_log: &BattleLog, options: &AnalysisOptions) -> AnalysisResult {
     let counts = count_actions(battle_log);
     let action_id_counts = count_actions_by_id(battle_log);
     
     // 解析結果を作成（比率計算も含む）
-    AnalysisResult::new(battle_log, counts, action_id_counts)
+    let unknown_id_counts = count_unknown_ids(battle_log, &action_id_counts);
+    let mut result = AnalysisResult::new(battle_log, counts, action_id_counts);
+    result.unknown_ids = unknown_id_counts.iter().map(|(id, _)| id.clone()).collect();
+    result.unknown_id_counts = unknown_id_counts;
+    result.time_stats = compute_time_stats(battle_log, &result.counts);
+    // APMを計算できないときは0.0（改善レポートなどは0.0を「計算できない」として扱う）
+    result.apm = result.time_stats.apm.unwrap_or(0.0);
+    result.time_phases = analyze_by_phase(battle_log, options.time_phases);
+
+    let intervals = compute_intervals(battle_log);
+    result.interval_stats = compute_interval_stats(&intervals);
+    result.interval_shape = compute_interval_shape(&intervals);
+    result.randomness = compute_randomness(&intervals);
+    result.weighted_ratios = compute_weighted_ratios(battle_log, options.weight_shape);
+    result.repertoire = compute_repertoire(battle_log);
+    result.attack_streaks = compute_attack_streaks(battle_log, options.single_attack);
+    result.flow_switches = compute_flow_switches(battle_log);
+    result.tension = compute_tension_curve(battle_log, options.tension_window);
+    result.combos = compute_combos(battle_log, options.combo_window);
+    result.grab_follow_ups = compute_grab_follow_ups(battle_log);
+    result.repeats = compute_repeat_rates(battle_log, options.combo_gap);
+    result.continuity = compute_continuity(battle_log);
+    result.transitions = compute_transitions(battle_log, options.transition_gap);
+    result.bursts = compute_bursts(battle_log, options.burst_gap, options.burst_min_len);
+    result.reliability = compute_reliability(battle_log, &result.counts, &intervals);
+    result.shield_cover = compute_shield_cover(battle_log, options.cover_window);
+    result.density_peaks = find_density_peaks(battle_log, options.peak_threshold);
+    result.density_spikes = find_density_spikes(battle_log, options.spike_sigma);
+    result.activity = compute_activity_strip(battle_log);
+    result.phases = compute_phases(battle_log);
+    result.direction_balance = compute_direction_balance(battle_log);
+    result.attack_categories = count_attack_categories(battle_log);
+    result.time_spreads = compute_time_spreads(battle_log);
+
+    // 行動ID×時間帯の集計は、表示するときだけ計算する
+    if options.sparklines {
+        result.id_usage_buckets = Some(compute_id_usage_buckets(battle_log));
+    }
+    // 前半・後半の集計も、矢印を表示するときだけ計算する
+    if options.momentum {
+        result.half_counts = Some(compute_half_counts(battle_log));
+    }
+    // 間隔のヒストグラムも、ビンの幅を指定したときだけ計算する
+    if let Some(bin_width) = options.interval_bin {
+        result.interval_histogram = compute_interval_histogram(&intervals, bin_width);
+    }
+
+    result
+}
+
+/// 分類の方法を指定して対戦ログを解析する
+/// 
+/// 読み込み時の分類（ActionType::from_action_id）の代わりに `classifier` で分類し直してから解析する。
+/// 分類は行動IDの種類ごとに1回だけ行う。
+/// 
+/// # 引数
+/// * `battle_log` - 対戦ログデータ
+/// * `options` - 解析の設定
+/// * `classifier` - 行動IDの分類方法
+/// 
+/// # 戻り値
+/// 解析結果（AnalysisResult）
+pub fn analyze_with(battle_log: &BattleLog, options: &AnalysisOptions, classifier: &dyn Classifier) -> AnalysisResult {
+    let mut memo: HashMap<&str, ActionType> = HashMap::new();
+    let mut reclassified = battle_log.clone();
+    for (action, original) in reclassified.actions.iter_mut().zip(&battle_log.actions) {
+        action.action_type = memo
+            .entry(&original.original_id)
+            .or_insert_with(|| classifier.classify(&original.original_id))
+            .clone();
+    }
+    analyze(&reclassified, options)
 }
 
 /// 各行動タイプの出現回数を数える
@@ -40,10 +226,2333 @@ fn count_actions_by_id(battle_log: &BattleLog) -> Vec<(String, u32)> {
   // BTreeMap: Dictionaryのように使用できる
     let mut map: std::collections::BTreeMap<String, u32> = std::collections::BTreeMap::new();
     for action in &battle_log.actions {
-        *map.entry(action.original_id.clone()).or_insert(0) += 1;
+        *map.entry(action.original_id.to_string()).or_insert(0) += 1;
     }
     let mut items: Vec<(String, u32)> = map.into_iter().collect();
     // 回数降順、同回数ならID昇順
     items.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
     items
 }
+
+
+/// 未登録の行動ID（Unknownに分類された行動）だけの回数を取り出す
+///
+/// # 引数
+/// * `action_id_counts` - count_actions_by_id の結果（並び順をそのまま使う）
+fn count_unknown_ids(battle_log: &BattleLog, action_id_counts: &[(String, u32)]) -> Vec<(String, u32)> {
+    let unknown: HashSet<&str> = battle_log
+        .actions
+        .iter()
+        .filter(|action| action.action_type == ActionType::Unknown)
+        .map(|action| &*action.original_id)
+        .collect();
+    action_id_counts.iter().filter(|(id, _)| unknown.contains(id.as_str())).cloned().collect()
+}
+
+/// 連続する行動どうしの時間間隔（秒）を計算する
+///
+/// # 戻り値
+/// 行動数より1つ少ない長さの間隔リスト（行動が1つ以下なら空）
+pub fn compute_intervals(battle_log: &BattleLog) -> Vec<f64> {
+    // windows(2): 隣り合う2要素ずつ取り出す
+    battle_log
+        .actions
+        .windows(2)
+        .map(|pair| pair[1].timestamp - pair[0].timestamp)
+        .collect()
+}
+
+/// 間隔の平均・中央値・最小・最大・標準偏差を計算する
+fn compute_interval_stats(intervals: &[f64]) -> Option<IntervalStats> {
+    if intervals.is_empty() {
+        return None;
+    }
+
+    let n = intervals.len() as f64;
+    let mean = intervals.iter().sum::<f64>() / n;
+    let variance = intervals.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+
+    let mut sorted = intervals.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    let median = if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    };
+
+    Some(IntervalStats {
+        mean,
+        median,
+        min: sorted[0],
+        max: sorted[sorted.len() - 1],
+        std_dev: variance.sqrt(),
+    })
+}
+
+/// 間隔分布の歪度・尖度をモーメントから計算する
+///
+/// 歪度 = m3 / m2^1.5、尖度 = m4 / m2^2 - 3（m_k は平均まわりのk次モーメント）
+fn compute_interval_shape(intervals: &[f64]) -> IntervalShape {
+    let mut shape = IntervalShape::new();
+    if intervals.len() < MIN_SHAPE_SAMPLES {
+        return shape;
+    }
+
+    let n = intervals.len() as f64;
+    let mean = intervals.iter().sum::<f64>() / n;
+    let moment = |k: i32| intervals.iter().map(|x| (x - mean).powi(k)).sum::<f64>() / n;
+    let m2 = moment(2);
+
+    // 全部同じ間隔だと分散0で割れないので計算しない
+    if m2 <= f64::EPSILON {
+        return shape;
+    }
+
+    shape.skewness = Some(moment(3) / m2.powf(1.5));
+    shape.kurtosis = Some(moment(4) / (m2 * m2) - 3.0);
+    shape
+}
+
+/// 行動間隔を `bin_width` 秒ごとのビンに分けて数える（--interval-bin）
+///
+/// ビンは最も長い間隔が入るところまで作る（最大 IntervalHistogram::MAX_BINS 個）。
+/// 入りきらない長い間隔は最後のビンにまとめる。
+/// ちょうどビンの境目の間隔は、小数の誤差で前のビンに入らないように1ナノ秒の余裕をみて後ろのビンに入れる
+///
+/// # 戻り値
+/// ヒストグラム。間隔が1つもなければNone
+fn compute_interval_histogram(intervals: &[f64], bin_width: f64) -> Option<IntervalHistogram> {
+    const EPSILON: f64 = 1e-9;
+    let max = intervals.iter().copied().reduce(f64::max)?;
+    let bin = |value: f64| ((value.max(0.0) + EPSILON) / bin_width).floor() as usize;
+
+    let needed = bin(max).saturating_add(1);
+    let bins = needed.min(IntervalHistogram::MAX_BINS);
+    let mut counts = vec![0; bins];
+    for &value in intervals {
+        counts[bin(value).min(bins - 1)] += 1;
+    }
+    Some(IntervalHistogram {
+        bin_width,
+        counts,
+        overflow: needed > bins,
+    })
+}
+
+/// 間隔が指数分布に近いか（行動がランダムに起きているか）を 分散/平均² で判定する
+///
+/// 指数分布では標準偏差と平均が等しいので、この比は1になる。
+/// 間隔が少ないと比がぶれるので、歪度・尖度と同じサンプル数を必要とする
+fn compute_randomness(intervals: &[f64]) -> Option<RandomnessCheck> {
+    if intervals.len() < MIN_SHAPE_SAMPLES {
+        return None;
+    }
+
+    let n = intervals.len() as f64;
+    let mean = intervals.iter().sum::<f64>() / n;
+    // 全部同時刻だと平均0で割れないので判定しない
+    if mean <= f64::EPSILON {
+        return None;
+    }
+    let variance = intervals.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+
+    let dispersion = variance / (mean * mean);
+    Some(RandomnessCheck {
+        dispersion,
+        rhythm: Rhythm::classify(dispersion),
+    })
+}
+
+/// 前方向・後方向の行動を数え、対称性スコア（少ない方/多い方）を計算する
+///
+/// 向きが推定できない行動（Direction::from_action_id がNone）は数えない。
+/// 片方しか使っていなければ0、同じ回数なら1になる
+fn compute_direction_balance(battle_log: &BattleLog) -> DirectionBalance {
+    let mut balance = DirectionBalance::default();
+    for action in &battle_log.actions {
+        match Direction::from_action_id(&action.original_id) {
+            Some(Direction::Forward) => balance.forward += 1,
+            Some(Direction::Backward) => balance.backward += 1,
+            None => {}
+        }
+    }
+
+    let max = balance.forward.max(balance.backward);
+    if max > 0 {
+        balance.score = Some(balance.forward.min(balance.backward) as f64 / max as f64);
+    }
+    balance
+}
+
+/// 攻撃を種類（弱・強・スマッシュなど）ごとに数える
+///
+/// 攻撃に分類された行動だけを数える。ATTACK_IDS にないIDは「その他の攻撃」にする
+///
+/// # 引数
+/// * `battle_log` - 対戦ログ
+///
+/// # 戻り値
+/// 攻撃の種類ごとの回数（1回もなかった種類は含まない。キーは AttackCategory::ALL と同じ順）
+pub fn count_by_attack_category(battle_log: &BattleLog) -> BTreeMap<AttackCategory, u32> {
+    let mut counts = BTreeMap::new();
+    for action in battle_log.actions.iter().filter(|a| a.action_type == ActionType::Attack) {
+        *counts.entry(AttackCategory::from_action_id(&action.original_id)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// 攻撃の種類ごとの回数を、0回の種類も含めて表示する順に並べる（【攻撃内訳】用）
+fn count_attack_categories(battle_log: &BattleLog) -> Vec<(AttackCategory, u32)> {
+    let counts = count_by_attack_category(battle_log);
+    AttackCategory::ALL
+        .iter()
+        .map(|category| (*category, counts.get(category).copied().unwrap_or(0)))
+        .collect()
+}
+
+/// 時間重み付きのカテゴリ比率を計算する
+///
+/// 各行動に試合内の相対位置（最初の行動=0.0、最後の行動=1.0）に応じた重みを掛けて比率を出す。
+/// 全行動が同時刻の場合は重みがすべて等しくなり、通常の比率と一致する。
+fn compute_weighted_ratios(battle_log: &BattleLog, shape: WeightShape) -> WeightedRatios {
+    let mut ratios = WeightedRatios::new(shape);
+
+    let first = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::INFINITY, f64::min);
+    let last = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::NEG_INFINITY, f64::max);
+    let duration = last - first;
+
+    let (mut attack, mut shield, mut dodge, mut unknown) = (0.0, 0.0, 0.0, 0.0);
+    for action in &battle_log.actions {
+        let t = if duration > 0.0 {
+            (action.timestamp - first) / duration
+        } else {
+            0.0
+        };
+        let weight = shape.weight(t);
+        match action.action_type {
+            ActionType::Attack => attack += weight,
+            ActionType::Shield => shield += weight,
+            ActionType::Dodge => dodge += weight,
+            ActionType::Unknown => unknown += weight,
+        }
+    }
+
+    // 通常の比率と同じく、未登録の行動IDも合計に含める
+    let total = attack + shield + dodge + unknown;
+    if total > 0.0 {
+        ratios.attack_ratio = attack / total * 100.0;
+        ratios.shield_ratio = shield / total * 100.0;
+        ratios.dodge_ratio = dodge / total * 100.0;
+        ratios.unknown_ratio = unknown / total * 100.0;
+    }
+    ratios
+}
+
+/// 各行動IDを初めて使った時刻を求め、技の種類の推移を計算する
+///
+fn compute_repertoire(battle_log: &BattleLog) -> RepertoireGrowth {
+    let mut growth = RepertoireGrowth::new();
+
+    let actions = sorted_actions(battle_log);
+
+    let (Some(first), Some(last)) = (actions.first(), actions.last()) else {
+        return growth;
+    };
+    growth.start = first.timestamp;
+    growth.end = last.timestamp;
+
+    let mut seen = std::collections::HashSet::new();
+    for action in actions {
+        // insertは初めて追加したときだけtrueを返す
+        if seen.insert(&*action.original_id) {
+            growth.first_uses.push((action.timestamp, action.original_id.to_string()));
+        }
+    }
+    growth
+}
+
+/// 行動IDごとに、試合時間を等分した各区間で何回使ったかを数える
+///
+/// 最初の行動から最後の行動までを SPARKLINE_BUCKETS 個の区間に等分する。
+/// 区間は左端を含み右端を含まない（最後の区間だけは試合終了時刻を含む）。
+/// 試合時間が0秒のときはすべて最初の区間に入れる。
+fn compute_id_usage_buckets(battle_log: &BattleLog) -> HashMap<String, [u32; SPARKLINE_BUCKETS]> {
+    let mut buckets: HashMap<String, [u32; SPARKLINE_BUCKETS]> = HashMap::new();
+
+    let start = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::INFINITY, f64::min);
+    let end = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::NEG_INFINITY, f64::max);
+    let duration = end - start;
+
+    for action in &battle_log.actions {
+        let index = if duration > 0.0 {
+            let position = (action.timestamp - start) / duration * SPARKLINE_BUCKETS as f64;
+            (position as usize).min(SPARKLINE_BUCKETS - 1)
+        } else {
+            0
+        };
+        buckets.entry(action.original_id.to_string()).or_insert([0; SPARKLINE_BUCKETS])[index] += 1;
+    }
+    buckets
+}
+
+/// 試合を時間で前半と後半に分け、カテゴリごと・行動IDごとの回数を数える
+///
+/// 境目は最初の行動と最後の行動のちょうど真ん中の時刻。境目ちょうどの行動は後半に入れる。
+/// 試合時間が0秒のときはすべて後半に入る（前半の行動数が0なので、使った行動はすべて Rising になる）。
+fn compute_half_counts(battle_log: &BattleLog) -> HalfCounts {
+    let start = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::INFINITY, f64::min);
+    let end = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::NEG_INFINITY, f64::max);
+    let mut halves = HalfCounts {
+        split: if battle_log.actions.is_empty() { 0.0 } else { (start + end) / 2.0 },
+        ..HalfCounts::default()
+    };
+
+    for action in &battle_log.actions {
+        let entry = halves.by_id.entry(action.original_id.to_string()).or_insert((0, 0));
+        if action.timestamp < halves.split {
+            halves.first.increment(&action.action_type);
+            entry.0 += 1;
+        } else {
+            halves.second.increment(&action.action_type);
+            entry.1 += 1;
+        }
+    }
+    halves
+}
+
+/// 行動をタイムスタンプ順に並べた参照のリストを作る
+///
+/// 元の並び順は保証されていないので、時間の流れを扱う解析はこれを使う
+/// （安定ソートなので同時刻の行動は元の順番を保つ）
+fn sorted_actions(battle_log: &BattleLog) -> Vec<&Action> {
+    let mut actions: Vec<&Action> = battle_log.actions.iter().collect();
+    actions.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+    actions
+}
+
+/// 攻撃が連続した区間の長さを合計する
+///
+/// 攻撃が続く間を1区間とし、シールドや回避が入ったら区間を区切る。
+/// 区間の長さは最初の攻撃から最後の攻撃までの時間。
+/// 攻撃が1回だけの区間は `single_mode` に従って0秒か次の行動までの時間とする。
+fn compute_attack_streaks(battle_log: &BattleLog, single_mode: SingleAttackMode) -> AttackStreaks {
+    let mut streaks = AttackStreaks::new(single_mode);
+    let actions = sorted_actions(battle_log);
+
+    let mut i = 0;
+    while i < actions.len() {
+        if actions[i].action_type != ActionType::Attack {
+            i += 1;
+            continue;
+        }
+
+        // iから始まる攻撃の連続の終わり（最後の攻撃の位置）を探す
+        let start = i;
+        while i + 1 < actions.len() && actions[i + 1].action_type == ActionType::Attack {
+            i += 1;
+        }
+        let end = i;
+
+        let length = if start == end {
+            streaks.single_count += 1;
+            match (single_mode, actions.get(end + 1)) {
+                (SingleAttackMode::UntilNext, Some(next)) => next.timestamp - actions[end].timestamp,
+                _ => 0.0,
+            }
+        } else {
+            actions[end].timestamp - actions[start].timestamp
+        };
+
+        streaks.streak_count += 1;
+        streaks.total_secs += length;
+        streaks.longest_secs = streaks.longest_secs.max(length);
+        i += 1;
+    }
+
+    streaks
+}
+
+/// つかみ(g)の後にどの投げ・派生に行ったかを数える
+///
+/// 時刻順でつかみの次の行動を見る。つかみ攻撃(ga)はつかんだまま出せるので飛ばし、
+/// その後の行動が投げ・派生（GrabFollowUps::THROW_IDS）ならその行動IDで数える。
+/// それ以外の行動が続いたときやログが終わったときは「その他」として数える。
+fn compute_grab_follow_ups(battle_log: &BattleLog) -> GrabFollowUps {
+    let actions = sorted_actions(battle_log);
+    let mut follow_ups = GrabFollowUps::default();
+    let mut throws: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for (i, action) in actions.iter().enumerate() {
+        if &*action.original_id != "g" {
+            continue;
+        }
+        follow_ups.grabs += 1;
+        let next = actions[i + 1..].iter().find(|next| &*next.original_id != "ga");
+        match next {
+            Some(next) if GrabFollowUps::THROW_IDS.contains(&&*next.original_id) => {
+                *throws.entry(&next.original_id).or_insert(0) += 1;
+            }
+            _ => follow_ups.others += 1,
+        }
+    }
+
+    follow_ups.throws = throws.into_iter().map(|(id, count)| (id.to_string(), count)).collect();
+    // 回数降順、同回数ならID昇順（BTreeMapの順で並んでいるので安定ソートで保たれる）
+    follow_ups.throws.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    follow_ups
+}
+
+/// 短い間隔で続けた攻撃をコンボとしてまとめる
+///
+/// 時刻順で隣り合う攻撃の間隔が `gap` 秒以内なら同じコンボとする。
+/// シールドや回避が入るか、間隔が `gap` を超えたらコンボは終わり。
+/// 2回以上続いたものだけをコンボとして数える。
+fn compute_combos(battle_log: &BattleLog, gap: f64) -> ComboStats {
+    let mut stats = ComboStats::new(gap);
+    let actions = sorted_actions(battle_log);
+
+    // 各コンボの行動IDの並び
+    let mut combos: Vec<Vec<&str>> = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut previous: Option<&Action> = None;
+    for action in actions {
+        if action.action_type != ActionType::Attack {
+            combos.push(std::mem::take(&mut current));
+        } else if matches!(previous, Some(prev) if prev.action_type == ActionType::Attack && action.timestamp - prev.timestamp <= gap) {
+            current.push(&action.original_id);
+        } else {
+            combos.push(std::mem::replace(&mut current, vec![&action.original_id]));
+        }
+        previous = Some(action);
+    }
+    combos.push(current);
+    combos.retain(|combo| combo.len() >= 2);
+
+    stats.combo_count = combos.len();
+    // 最大コンボが複数あるときは先に出たほう
+    if let Some(longest) = combos.iter().rev().max_by_key(|combo| combo.len()) {
+        stats.max_length = longest.len();
+        stats.longest = longest.iter().map(|id| id.to_string()).collect();
+        stats.mean_length = Some(combos.iter().map(Vec::len).sum::<usize>() as f64 / combos.len() as f64);
+    }
+    stats
+}
+
+/// 秒ごとの行動数（密度）の山（局所最大）を探す
+///
+/// 1秒ずつ区切って行動数を数え、両隣より多い秒を山とする。
+/// 同じ値が続く平らな部分は、その両隣より多ければ1つの山として扱う（行動のない秒は0とみなす）。
+/// 行動のない秒は0なので山にならない。そのため行動のある秒だけを調べればよく、
+/// 試合の時間がどれだけ長くても、行動の数より多くのメモリは使わない。
+///
+/// # 引数
+/// * `threshold` - これより密度が低い山は小さな揺れとみなして除外する
+fn find_density_peaks(battle_log: &BattleLog, threshold: f64) -> Vec<DensityPeak> {
+    let counts = counts_per_second(battle_log);
+    let density_at = |second: i64| counts.get(&second).copied().unwrap_or(0);
+
+    let mut peaks = Vec::new();
+    let mut seconds = counts.iter().peekable();
+    while let Some((&first, &density)) = seconds.next() {
+        // firstから始まる、隣の秒に同じ値が続く並びの終わりを探す
+        let mut last = first;
+        while let Some((&next, &next_density)) = seconds.peek() {
+            if next != last + 1 || next_density != density {
+                break;
+            }
+            last = next;
+            seconds.next();
+        }
+
+        let left = density_at(first - 1);
+        let right = density_at(last + 1);
+        if density > left && density > right && density as f64 >= threshold {
+            peaks.push(DensityPeak {
+                second: first as f64,
+                width: (last - first + 1) as usize,
+                density,
+            });
+        }
+    }
+    peaks
+}
+
+/// 行動のある秒（切り捨て）ごとに行動数を数える
+///
+/// 行動のない秒は入れない（0とみなす）。秒ごとに配列を作ると、
+/// 0秒と50億秒の行動が1つずつあるだけのログでも50億個の要素が必要になってしまうため
+fn counts_per_second(battle_log: &BattleLog) -> BTreeMap<i64, u32> {
+    let mut counts = BTreeMap::new();
+    for action in &battle_log.actions {
+        *counts.entry(action.timestamp.floor() as i64).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// 秒ごとの行動数（密度）が「平均 + sigma × 標準偏差」を超える秒（スパイク）を探す
+///
+/// find_density_peaks と同じく1秒ずつ区切り、最初の行動の秒から最後の行動の秒までを対象にする。
+/// 行動のない秒も0として平均・標準偏差に含める（秒ごとの配列は作らず、0の秒はまとめて計算する）。
+/// 密度がすべて同じ（標準偏差が0）ならスパイクはない。
+///
+/// # 引数
+/// * `sigma` - 閾値に使う標準偏差の倍率
+fn find_density_spikes(battle_log: &BattleLog, sigma: f64) -> DensitySpikes {
+    let mut result = DensitySpikes {
+        sigma,
+        ..DensitySpikes::default()
+    };
+    let counts = counts_per_second(battle_log);
+    let (Some(&first), Some(&last)) = (counts.keys().next(), counts.keys().next_back()) else {
+        return result;
+    };
+
+    let n = (last - first + 1) as f64;
+    result.mean = counts.values().map(|&c| c as f64).sum::<f64>() / n;
+    // 行動のない秒は、どれも (0 - 平均)² なので秒数を掛けてまとめて足す
+    let empty_seconds = n - counts.len() as f64;
+    let squares = counts.values().map(|&c| (c as f64 - result.mean).powi(2)).sum::<f64>() + empty_seconds * result.mean.powi(2);
+    result.std_dev = (squares / n).sqrt();
+    result.threshold = result.mean + sigma * result.std_dev;
+    if result.std_dev == 0.0 {
+        return result;
+    }
+
+    // 閾値は平均より大きいので、行動のない秒（0）がスパイクになることはない
+    result.spikes = counts
+        .iter()
+        .filter(|(_, &density)| density as f64 > result.threshold)
+        .map(|(&second, &density)| DensitySpike {
+            second: second as f64,
+            density,
+        })
+        .collect();
+    result
+}
+
+/// `start` 秒から `slice_secs` 秒ずつ区切った各区間の行動数を数える
+///
+/// 区間は最後の行動を含むところまで作る（行動が1つでもあれば最低1区間）。
+/// 区間が `max_windows` 個を超えるときは、その数に収まるまで区間を広げる。
+/// `start` より前の行動は数えない。
+///
+/// # 戻り値
+/// (実際に使った1区間の長さ, 区間ごとの行動数)
+fn windowed_counts(battle_log: &BattleLog, start: f64, slice_secs: f64, max_windows: usize) -> (f64, Vec<u32>) {
+    let end = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::NEG_INFINITY, f64::max);
+    if end < start {
+        return (slice_secs, Vec::new());
+    }
+
+    let duration = end - start;
+    let slice_secs = if duration / slice_secs >= max_windows as f64 {
+        duration / max_windows.saturating_sub(1).max(1) as f64
+    } else {
+        slice_secs
+    };
+    let window_count = ((duration / slice_secs).floor() as usize + 1).min(max_windows.max(1));
+
+    let mut counts = vec![0u32; window_count];
+    for action in &battle_log.actions {
+        if action.timestamp >= start {
+            let index = ((action.timestamp - start) / slice_secs).floor() as usize;
+            counts[index.min(window_count - 1)] += 1;
+        }
+    }
+    (slice_secs, counts)
+}
+
+/// 1区間の長さの候補（秒）。短い順に試して、区間数が上限に収まる最初のものを使う
+const SLICE_CANDIDATES: &[f64] = &[1.0, 2.0, 5.0, 10.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+/// 試合の長さから1区間の長さを決める
+///
+/// 区間数が ActivityStrip::MAX_CELLS 以下になる最も短い候補を選ぶ。
+/// 候補で足りないほど長い試合は、区間数がちょうど上限になる長さにする。
+fn choose_slice_secs(duration: f64) -> f64 {
+    let cells = |slice: f64| (duration / slice) as usize + 1;
+    SLICE_CANDIDATES
+        .iter()
+        .copied()
+        .find(|&slice| cells(slice) <= ActivityStrip::MAX_CELLS)
+        .unwrap_or_else(|| (duration / (ActivityStrip::MAX_CELLS - 1) as f64).ceil())
+}
+
+/// 試合全体を一定の長さの区間に分けて、区間ごとの行動数を数える
+fn compute_activity_strip(battle_log: &BattleLog) -> ActivityStrip {
+    let mut strip = ActivityStrip::new();
+    let start = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::INFINITY, f64::min);
+    let end = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::NEG_INFINITY, f64::max);
+    if end < start {
+        return strip;
+    }
+
+    (strip.slice_secs, strip.counts) = windowed_counts(battle_log, start, choose_slice_secs(end - start), ActivityStrip::MAX_CELLS);
+    strip
+}
+
+/// カテゴリごとに、行動した時刻の四分位を計算する
+///
+/// 四分位は昇順に並べた時刻を線形補間して求める（p分位 = 位置 (n-1)*p の値）。
+/// 行動が CategoryTimeSpread::MIN_SAMPLES 件未満のカテゴリは四分位を計算せず、時刻だけを持つ。
+fn compute_time_spreads(battle_log: &BattleLog) -> Vec<CategoryTimeSpread> {
+    [ActionType::Attack, ActionType::Shield, ActionType::Dodge]
+        .into_iter()
+        .map(|action_type| {
+            let mut timestamps: Vec<f64> = battle_log
+                .actions
+                .iter()
+                .filter(|a| a.action_type == action_type)
+                .map(|a| a.timestamp)
+                .collect();
+            timestamps.sort_by(|a, b| a.total_cmp(b));
+
+            let quartiles = if timestamps.len() >= CategoryTimeSpread::MIN_SAMPLES {
+                let quantile = |p: f64| {
+                    let position = (timestamps.len() - 1) as f64 * p;
+                    let lower = position.floor() as usize;
+                    let upper = position.ceil() as usize;
+                    timestamps[lower] + (timestamps[upper] - timestamps[lower]) * (position - lower as f64)
+                };
+                Some([0.0, 0.25, 0.5, 0.75, 1.0].map(quantile))
+            } else {
+                None
+            };
+
+            CategoryTimeSpread {
+                action_type,
+                timestamps,
+                quartiles,
+            }
+        })
+        .collect()
+}
+
+/// フェーズマーカーで区切った区間ごとに行動回数を数える
+///
+/// マーカーの時刻からそのフェーズが始まり、次のマーカーの時刻の直前までがそのフェーズ。
+/// ちょうどマーカーの時刻の行動は新しいフェーズに入れる。
+/// 最初のマーカーより前は「開始〜」というフェーズにする（行動がなければ作らない）。
+/// 同じ時刻のマーカーが並んだ場合、先のフェーズは空（0回）になる。
+fn compute_phases(battle_log: &BattleLog) -> Vec<PhaseCounts> {
+    if battle_log.markers.is_empty() {
+        return Vec::new();
+    }
+
+    let mut markers = battle_log.markers.clone();
+    markers.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+    let mut phases = Vec::new();
+    let first_marker = markers[0].timestamp;
+    let first_action = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::INFINITY, f64::min);
+    if first_action < first_marker {
+        phases.push(PhaseCounts {
+            name: "開始〜".to_string(),
+            start: first_action,
+            counts: ActionCounts::new(),
+        });
+    }
+    for marker in &markers {
+        phases.push(PhaseCounts {
+            name: marker.name.clone(),
+            start: marker.timestamp,
+            counts: ActionCounts::new(),
+        });
+    }
+
+    for action in &battle_log.actions {
+        // 開始時刻が行動の時刻以下のフェーズのうち最後のもの（startは昇順）
+        let index = phases.partition_point(|phase| phase.start <= action.timestamp);
+        if index > 0 {
+            phases[index - 1].counts.increment(&action.action_type);
+        }
+    }
+    phases
+}
+
+/// 試合時間（最初の行動から最後の行動まで）を `phases` 個の同じ長さの区間に分け、区間ごとに行動回数を数える
+///
+/// ちょうど区間の境目の行動は後の区間に入れる。最後の行動は最後の区間に入れる。
+/// 試合時間が0秒のときは、すべての行動が境目にあるとみなして最後の区間に入れる。
+/// 行動が時刻順に並んでいなくてもよい。
+///
+/// # 引数
+/// * `battle_log` - 対戦ログデータ
+/// * `phases` - 区間の数（0なら空のベクタ）
+///
+/// # 戻り値
+/// 区間ごとの行動回数（時刻順、長さは `phases`）
+pub fn analyze_by_phase(battle_log: &BattleLog, phases: usize) -> Vec<ActionCounts> {
+    let mut counts = vec![ActionCounts::new(); phases];
+    if phases == 0 || battle_log.actions.is_empty() {
+        return counts;
+    }
+
+    let first = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::INFINITY, f64::min);
+    let last = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::NEG_INFINITY, f64::max);
+    let duration = last - first;
+    for action in &battle_log.actions {
+        // 区間の幅で割るより、先に区間数を掛けたほうが境目ちょうどの値で誤差が出にくい
+        let index = if duration > 0.0 {
+            ((action.timestamp - first) * phases as f64 / duration).floor() as usize
+        } else {
+            phases - 1
+        };
+        counts[index.min(phases - 1)].increment(&action.action_type);
+    }
+    counts
+}
+
+/// 試合時間と1分あたりの行動数（APM）を計算する
+///
+/// 行動が時刻順に並んでいるとは限らないので、最初・最後の行動は最小・最大の時刻で求める。
+/// APMは回数を、最初の行動から最後の行動までの時間（分）で割る。
+///
+/// # 引数
+/// * `counts` - 行動タイプごとの回数（カテゴリ別のAPMに使う）
+///
+/// # 戻り値
+/// 試合時間とAPM。行動が1件以下、またはすべて同じ時刻で時間が0秒ならAPMはNone（無限大やNaNにしない）
+fn compute_time_stats(battle_log: &BattleLog, counts: &ActionCounts) -> TimeStats {
+    if battle_log.actions.is_empty() {
+        return TimeStats::default();
+    }
+    let first = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::INFINITY, f64::min);
+    let last = battle_log.actions.iter().map(|a| a.timestamp).fold(f64::NEG_INFINITY, f64::max);
+    let duration = last - first;
+    let minutes = duration / 60.0;
+    let apm = |count: u32| (minutes > 0.0).then(|| count as f64 / minutes);
+
+    TimeStats {
+        first,
+        last,
+        duration,
+        apm: apm(counts.total()),
+        attack_apm: apm(counts.attack_count),
+        shield_apm: apm(counts.shield_count),
+        dodge_apm: apm(counts.dodge_count),
+        unknown_apm: apm(counts.unknown_count),
+    }
+}
+
+/// 攻撃と防御（シールド・回避）が入れ替わった回数を数える
+///
+/// シールドと回避はどちらも防御として同じ扱いにし、
+/// 時刻順で隣り合う2つの行動の攻撃/防御が違えば1回と数える。
+fn compute_flow_switches(battle_log: &BattleLog) -> FlowSwitches {
+    let mut flow = FlowSwitches::new();
+    let actions = sorted_actions(battle_log);
+
+    let is_attack = |action: &Action| action.action_type == ActionType::Attack;
+    // windows(2): 隣り合う2要素ずつ見る
+    flow.switches = actions.windows(2).filter(|pair| is_attack(pair[0]) != is_attack(pair[1])).count();
+
+    if let (Some(first), Some(last)) = (actions.first(), actions.last()) {
+        let minutes = (last.timestamp - first.timestamp) / 60.0;
+        if minutes > 0.0 {
+            let per_minute = flow.switches as f64 / minutes;
+            flow.per_minute = Some(per_minute);
+            flow.style = Some(FlowStyle::classify(per_minute));
+        }
+    }
+    flow
+}
+
+/// 攻撃⇔防御の遷移行列から、攻め継続率・守り継続率のもとになる回数を数える
+///
+/// 時刻順に隣り合う2つの行動を見る。どちらかが未登録の行動IDなら、その組は数えない
+fn compute_continuity(battle_log: &BattleLog) -> Continuity {
+    let mut continuity = Continuity::default();
+    let actions = sorted_actions(battle_log);
+
+    // 攻撃ならtrue、防御（シールド・回避）ならfalse、未登録ならNone
+    let side = |action: &Action| match action.action_type {
+        ActionType::Attack => Some(true),
+        ActionType::Shield | ActionType::Dodge => Some(false),
+        ActionType::Unknown => None,
+    };
+    for pair in actions.windows(2) {
+        match (side(pair[0]), side(pair[1])) {
+            (Some(true), Some(next)) => {
+                continuity.from_attack += 1;
+                if next {
+                    continuity.attack_to_attack += 1;
+                }
+            }
+            (Some(false), Some(next)) => {
+                continuity.from_defense += 1;
+                if !next {
+                    continuity.defense_to_defense += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    continuity
+}
+
+/// 隣り合う行動どうしの遷移を、行動タイプの行列と行動IDの組で数える
+///
+/// 時刻順に隣り合う2つの行動を1つの遷移とする。間隔が `gap` 秒を超える組は
+/// 別のやりとりとみなして数えず、split に数える（間隔がちょうど `gap` の組は数える）。
+/// 行動IDの組は回数の多いものから Transitions::TOP_ID_TRANSITIONS 件だけ残す
+fn compute_transitions(battle_log: &BattleLog, gap: f64) -> Transitions {
+    let mut transitions = Transitions::new(gap);
+    let actions = sorted_actions(battle_log);
+    let mut id_pairs: BTreeMap<(&str, &str), u32> = BTreeMap::new();
+
+    for pair in actions.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        if to.timestamp - from.timestamp > gap {
+            transitions.split += 1;
+            continue;
+        }
+        transitions.counts[Transitions::index(&from.action_type)][Transitions::index(&to.action_type)] += 1;
+        *id_pairs.entry((&from.original_id, &to.original_id)).or_insert(0) += 1;
+    }
+
+    let mut id_pairs: Vec<((&str, &str), u32)> = id_pairs.into_iter().collect();
+    // 回数降順、同回数ならIDの昇順（BTreeMapの順で並んでいるので安定ソートで保たれる）
+    id_pairs.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    transitions.top_ids = id_pairs
+        .into_iter()
+        .take(Transitions::TOP_ID_TRANSITIONS)
+        .map(|((from, to), count)| (from.to_string(), to.to_string(), count))
+        .collect();
+    transitions
+}
+
+/// 行動を、バースト（短い間隔の連続）と単発の行動に分ける
+///
+/// 時刻順に隣り合う行動の間隔が `gap` 秒未満なら同じ連続とし、`min_len` 個以上続いた連続をバーストとする。
+/// 間隔がちょうど `gap` の組は区切る（小数の誤差で区切りが変わらないように、1ナノ秒の余裕をみる）
+fn compute_bursts(battle_log: &BattleLog, gap: f64, min_len: usize) -> Bursts {
+    let mut bursts = Bursts::new(gap, min_len);
+    let actions = sorted_actions(battle_log);
+    let threshold = gap - 1e-9;
+
+    // 連続の始まりの添字。間が空いたところ（と最後）で、そこまでの連続を振り分ける
+    let mut start = 0;
+    for end in 1..=actions.len() {
+        if end < actions.len() && actions[end].timestamp - actions[end - 1].timestamp < threshold {
+            continue;
+        }
+        let run = &actions[start..end];
+        if run.len() >= min_len {
+            bursts.bursts.push(Burst {
+                start: run[0].timestamp,
+                end: run[run.len() - 1].timestamp,
+                actions: run.len(),
+            });
+            for action in run {
+                bursts.inside.increment(&action.action_type);
+            }
+        } else {
+            for action in run {
+                bursts.outside.increment(&action.action_type);
+            }
+        }
+        start = end;
+    }
+    bursts
+}
+
+/// 記録の質からデータ信頼度を計算する（減点のルールは Reliability を参照）
+///
+/// 逆行・欠損の行数は読み込み時に数えたものを使い、記録の途切れは
+/// 行動間隔が Reliability::GAP_SECS 秒を超えたところを数える
+///
+/// # 引数
+/// * `counts` - 行動回数
+/// * `intervals` - 時刻順に隣り合う行動の間隔（compute_intervals の結果）
+fn compute_reliability(battle_log: &BattleLog, counts: &ActionCounts, intervals: &[f64]) -> Reliability {
+    let gaps = intervals.iter().filter(|&&interval| interval > Reliability::GAP_SECS).count();
+    Reliability::new(battle_log.reordered, battle_log.dropped_rows, gaps, counts)
+}
+
+/// 攻撃から次のシールドまでの時間の分布を計算する
+///
+/// 時刻順の行動を後ろから1回だけ走査し、「その時点より後で最初のシールドの時刻」を持ち回る。
+/// 後にシールドがない攻撃は、時間の統計とヒストグラムには入れず、割合の分母にだけ数える。
+/// 区間の境目ちょうどの時間は、計算誤差で前の区間に落ちないように少しだけ余裕を持たせて判定する。
+/// ヒストグラムの区間は最大 ShieldCover::MAX_BINS 個で、入りきらない長い時間は最後の区間にまとめる
+///
+/// # 戻り値
+/// 分布。攻撃の後のシールドが1つもなければNone（計算できない）
+fn compute_shield_cover(battle_log: &BattleLog, window: f64) -> Option<ShieldCover> {
+    const EPSILON: f64 = 1e-9;
+    let actions = sorted_actions(battle_log);
+
+    let mut next_shield: Option<f64> = None;
+    let mut attacks = 0;
+    let mut latencies = Vec::new();
+    for action in actions.iter().rev() {
+        match action.action_type {
+            ActionType::Shield => next_shield = Some(action.timestamp),
+            ActionType::Attack => {
+                attacks += 1;
+                if let Some(shield) = next_shield {
+                    latencies.push(shield - action.timestamp);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let latency = compute_interval_stats(&latencies)?;
+    let bin = |value: f64| ((value + EPSILON) / COVER_BIN_WIDTH).floor() as usize;
+    let needed = bin(latency.max).saturating_add(1);
+    let bins = needed.min(ShieldCover::MAX_BINS);
+    let mut histogram = vec![0; bins];
+    for &value in &latencies {
+        histogram[bin(value).min(bins - 1)] += 1;
+    }
+    Some(ShieldCover {
+        window,
+        bin_width: COVER_BIN_WIDTH,
+        attacks,
+        within: latencies.iter().filter(|&&value| value <= window + EPSILON).count(),
+        latency,
+        histogram,
+        overflow: needed > bins,
+    })
+}
+
+/// 直前（と2つ前）の行動と同じ行動を繰り返した割合を1回の走査で数える
+///
+/// 直前の行動との間隔が `gap` を超える行動は比較しない（流れが途切れたとみなす）。
+/// 2つ前の行動は、その間の間隔も `gap` 以内のときだけ比較する
+fn compute_repeat_rates(battle_log: &BattleLog, gap: f64) -> RepeatRates {
+    let mut rates = RepeatRates {
+        gap,
+        ..RepeatRates::default()
+    };
+    let actions = sorted_actions(battle_log);
+
+    for i in 1..actions.len() {
+        let (current, previous) = (actions[i], actions[i - 1]);
+        if current.timestamp - previous.timestamp > gap {
+            continue;
+        }
+        rates.compared += 1;
+
+        let same_as_previous = current.original_id == previous.original_id;
+        let same_as_second = i >= 2
+            && previous.timestamp - actions[i - 2].timestamp <= gap
+            && current.original_id == actions[i - 2].original_id;
+        if same_as_previous {
+            rates.same_as_previous += 1;
+        }
+        if same_as_previous || same_as_second {
+            rates.same_as_recent += 1;
+        }
+    }
+    rates
+}
+
+/// 時間窓ごとに攻撃⇔防御の切り替えを数え、緊張度カーブを作る
+///
+/// 切り替えは、切り替わった後の行動の時刻が入る窓に数える。
+/// 窓が TensionCurve::MAX_WINDOWS 個を超えるときは、その数に収まるまで窓を広げる
+fn compute_tension_curve(battle_log: &BattleLog, window_secs: f64) -> TensionCurve {
+    let actions = sorted_actions(battle_log);
+    let (Some(first), Some(last)) = (actions.first(), actions.last()) else {
+        return TensionCurve {
+            window_secs,
+            ..TensionCurve::default()
+        };
+    };
+
+    let duration = last.timestamp - first.timestamp;
+    let max_windows = TensionCurve::MAX_WINDOWS as f64;
+    let window_secs = if duration / window_secs >= max_windows {
+        duration / (max_windows - 1.0)
+    } else {
+        window_secs
+    };
+    let window_count = (duration / window_secs).floor() as usize + 1;
+
+    let mut switches = vec![0; window_count];
+    let is_attack = |action: &Action| action.action_type == ActionType::Attack;
+    for pair in actions.windows(2) {
+        if is_attack(pair[0]) != is_attack(pair[1]) {
+            let index = ((pair[1].timestamp - first.timestamp) / window_secs).floor() as usize;
+            switches[index.min(window_count - 1)] += 1;
+        }
+    }
+
+    TensionCurve {
+        window_secs,
+        start: first.timestamp,
+        switches,
+    }
+}
+
+/// 複数の解析結果をグループごとにまとめ、指標の平均を計算する
+///
+/// 比率は各ログの比率の単純平均（ログごとの行動数の違いで重み付けしない）。
+///
+/// # 引数
+/// * `results` - ログごとの解析結果
+/// * `groups` - 学籍番号 → グループ名 の対応
+///
+/// # 戻り値
+/// グループ名順のグループ別集計（「未分類」は最後）
+pub fn summarize_groups(results: &[AnalysisResult], groups: &GroupMap) -> Vec<GroupSummary> {
+    // グループ名 → そのグループの解析結果
+    let mut members: BTreeMap<&str, Vec<&AnalysisResult>> = BTreeMap::new();
+    for result in results {
+        members.entry(groups.group_of(&result.player_info.student_id)).or_default().push(result);
+    }
+
+    let mean = |values: &[f64]| -> Option<f64> {
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.iter().sum::<f64>() / values.len() as f64)
+        }
+    };
+
+    let mut summaries: Vec<GroupSummary> = members
+        .into_iter()
+        .map(|(name, group)| {
+            let collect = |f: fn(&AnalysisResult) -> f64| group.iter().map(|r| f(r)).collect::<Vec<f64>>();
+            let intervals: Vec<f64> = group.iter().filter_map(|r| r.interval_stats.as_ref().map(|s| s.mean)).collect();
+            GroupSummary {
+                name: name.to_string(),
+                logs: group.len(),
+                mean_actions: mean(&collect(|r| r.counts.total() as f64)).unwrap_or(0.0),
+                attack_ratio: mean(&collect(|r| r.counts.attack_ratio())).unwrap_or(0.0),
+                shield_ratio: mean(&collect(|r| r.counts.shield_ratio())).unwrap_or(0.0),
+                dodge_ratio: mean(&collect(|r| r.counts.dodge_ratio())).unwrap_or(0.0),
+                mean_interval: mean(&intervals),
+            }
+        })
+        .collect();
+
+    // 「未分類」は比較の邪魔にならないように最後に回す（sort_by_keyは安定ソート）
+    summaries.sort_by_key(|summary| summary.name == UNGROUPED);
+    summaries
+}
+
+/// 2つのログの行動IDの使い方がどれくらい似ているかを、コサイン類似度で計算する（--similarity）
+///
+/// 行動IDごとの回数を、行動IDを軸にしたベクトルとみなして、2つのベクトルの向きの近さを測る。
+/// 回数の多さ（試合の長さ）には左右されず、使った行動IDの割合が同じなら1になる。
+/// 回数は0以上なので、結果は0（共通する行動IDがない）〜1になる
+///
+/// # 引数
+/// * `a` - 1つ目のログ
+/// * `b` - 2つ目のログ
+///
+/// # 戻り値
+/// 類似度（0〜1）。どちらかのログに行動が1つもなければNone
+pub fn cosine_similarity(a: &BattleLog, b: &BattleLog) -> Option<f64> {
+    let counts_a: HashMap<String, u32> = count_actions_by_id(a).into_iter().collect();
+    let counts_b: HashMap<String, u32> = count_actions_by_id(b).into_iter().collect();
+    let norm = |counts: &HashMap<String, u32>| counts.values().map(|&count| (count as f64).powi(2)).sum::<f64>().sqrt();
+    let (norm_a, norm_b) = (norm(&counts_a), norm(&counts_b));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return None;
+    }
+
+    let dot: f64 = counts_a
+        .iter()
+        .filter_map(|(id, &count)| counts_b.get(id).map(|&other| count as f64 * other as f64))
+        .sum();
+    // 小数の誤差で1をわずかに超えないように抑える
+    Some((dot / (norm_a * norm_b)).clamp(0.0, 1.0))
+}
+
+/// 複数の解析結果を合算する（複数のCSVを1回で解析したときのまとめ）
+///
+/// 同じ試合のログもすべて合算する（aggregate_files に DupPolicy::KeepAll を渡したときと同じ）。
+/// ファイルのパスは分からないので、duplicates には「1番目」のように指定した順の番号を入れる。
+///
+/// # 引数
+/// * `results` - ログごとの解析結果（表示したい順）
+///
+/// # 戻り値
+/// 合算結果
+pub fn aggregate(results: &[AnalysisResult]) -> AggregateResult {
+    let mut aggregator = Aggregator::new(DupPolicy::KeepAll);
+    for (index, result) in results.iter().enumerate() {
+        aggregator.add(index, format!("{}番目", index + 1), result);
+    }
+    aggregator.finish().expect("DupPolicy::KeepAll では重複してもエラーにならない")
+}
+
+/// 同じ試合（学籍番号と対戦回次が同じ）のログを `policy` に従って取り除いてから合算する
+///
+/// 重複した試合は、扱いに関係なく AggregateResult.duplicates に記録する（警告やJSONで知らせるため）。
+///
+/// # 引数
+/// * `results` - (ファイルのパス, 解析結果) の組（指定した順）
+/// * `policy` - 重複したときの扱い
+///
+/// # 戻り値
+/// 合算結果。`policy` が DupPolicy::Error で重複があればエラー
+pub fn aggregate_files(results: Vec<(String, AnalysisResult)>, policy: DupPolicy) -> Result<AggregateResult> {
+    let mut aggregator = Aggregator::new(policy);
+    for (index, (path, result)) in results.into_iter().enumerate() {
+        aggregator.add(index, path, &result);
+    }
+    aggregator.finish()
+}
+
+/// 合算に使う1試合分の数（解析結果のうち、合算に必要なところだけ）
+#[derive(Debug, Clone)]
+struct AggregateEntry {
+    /// 指定した順でのインデックス
+    index: usize,
+    /// ファイルのパス
+    path: String,
+    /// 試合ごとの行動回数
+    counts: MatchCounts,
+    /// 行動IDごとの回数
+    action_id_counts: Vec<(String, u32)>,
+}
+
+/// 同じ試合（学籍番号と対戦回次が同じ）のログ
+#[derive(Debug, Clone)]
+struct AggregateGroup {
+    /// この試合のログのファイル（インデックス, パス）
+    files: Vec<(usize, String)>,
+    /// 合算に使うログ（DupPolicy::KeepAll ならすべて、それ以外は1つ）
+    kept: Vec<AggregateEntry>,
+}
+
+/// 解析結果を1つずつ受け取って合算する（--jobs の集約スレッドで使う）
+///
+/// 解析結果そのものは持たず、試合ごとの行動回数と行動IDごとの回数だけを残す。
+/// 同じ試合のログが複数あれば、届くたびに `policy` に従ってどれを使うかを決め直す。
+/// 「指定した順」はファイルのインデックスで比べるので、届く順番が変わっても
+/// aggregate_files と同じ結果になる。
+#[derive(Debug, Clone)]
+pub struct Aggregator {
+    policy: DupPolicy,
+    groups: HashMap<(String, u32), AggregateGroup>,
+}
+
+impl Aggregator {
+    /// 空の集計を作る
+    ///
+    /// # 引数
+    /// * `policy` - 同じ試合のログが複数あったときの扱い
+    pub fn new(policy: DupPolicy) -> Self {
+        Aggregator {
+            policy,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// 1つのログの解析結果を加える
+    ///
+    /// # 引数
+    /// * `index` - 指定した順でのインデックス（First / Last と、並び順に使う）
+    /// * `path` - ファイルのパス
+    /// * `result` - 解析結果
+    pub fn add(&mut self, index: usize, path: String, result: &AnalysisResult) {
+        let entry = AggregateEntry {
+            index,
+            path: path.clone(),
+            counts: MatchCounts {
+                player_info: result.player_info.clone(),
+                modified: result.modified,
+                counts: result.counts,
+            },
+            action_id_counts: result.action_id_counts.clone(),
+        };
+        let key = (result.player_info.student_id.clone(), result.player_info.match_number);
+        let group = self.groups.entry(key).or_insert_with(|| AggregateGroup {
+            files: Vec::new(),
+            kept: Vec::new(),
+        });
+        group.files.push((index, path));
+
+        let Some(current) = group.kept.first() else {
+            group.kept.push(entry);
+            return;
+        };
+        let replace = match self.policy {
+            DupPolicy::KeepAll => {
+                group.kept.push(entry);
+                return;
+            }
+            // エラーにするときは、finish でまとめて知らせる
+            DupPolicy::Error | DupPolicy::First => entry.index < current.index,
+            DupPolicy::Last => entry.index > current.index,
+            // 行動数の多いほう、同数ならパスの辞書順で先のほう
+            DupPolicy::Largest => entry
+                .counts
+                .counts
+                .total()
+                .cmp(&current.counts.counts.total())
+                .then_with(|| current.path.cmp(&entry.path))
+                .is_gt(),
+        };
+        if replace {
+            group.kept[0] = entry;
+        }
+    }
+
+    /// 加えたログを合算する
+    ///
+    /// # 戻り値
+    /// 合算結果（aggregate_files と同じ）。`policy` が DupPolicy::Error で重複があればエラー
+    pub fn finish(self) -> Result<AggregateResult> {
+        // 試合は最初に出てきた順に並べる
+        let mut groups: Vec<((String, u32), AggregateGroup)> = self.groups.into_iter().collect();
+        for (_, group) in &mut groups {
+            group.files.sort();
+        }
+        groups.sort_by_key(|(_, group)| group.files[0].0);
+
+        let mut duplicates = Vec::new();
+        let mut kept = Vec::new();
+        for ((student_id, match_number), mut group) in groups {
+            if group.files.len() >= 2 {
+                let files: Vec<String> = group.files.into_iter().map(|(_, path)| path).collect();
+                if self.policy == DupPolicy::Error {
+                    return Err(SmasherError::DuplicateLogs {
+                        student_id,
+                        match_number,
+                        files,
+                    });
+                }
+                group.kept.sort_by_key(|entry| entry.index);
+                duplicates.push(DuplicateMatch {
+                    student_id,
+                    match_number,
+                    files,
+                    kept: group.kept.iter().map(|entry| entry.path.clone()).collect(),
+                });
+            }
+            kept.extend(group.kept);
+        }
+        kept.sort_by_key(|entry| entry.index);
+
+        // 行動回数と行動IDごとの回数は、残したログの合計にする
+        let mut counts = AggregateCounts::new();
+        let mut id_counts: BTreeMap<String, u64> = BTreeMap::new();
+        let mut student_ids: Vec<String> = Vec::new();
+        for entry in &kept {
+            counts += &entry.counts.counts;
+            for (id, count) in &entry.action_id_counts {
+                let total = id_counts.entry(id.clone()).or_insert(0);
+                *total = total.saturating_add(*count as u64);
+            }
+            if !student_ids.contains(&entry.counts.player_info.student_id) {
+                student_ids.push(entry.counts.player_info.student_id.clone());
+            }
+        }
+        let mut action_id_counts: Vec<(String, u64)> = id_counts.into_iter().collect();
+        action_id_counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        Ok(AggregateResult {
+            files: kept.len(),
+            student_ids,
+            counts,
+            action_id_counts,
+            matches: kept.into_iter().map(|entry| entry.counts).collect(),
+            dup_policy: self.policy,
+            duplicates,
+            interrupted: None,
+        })
+    }
+}
+
+/// 学籍番号ごとの合計を、行動数の多い順に並べる（--jobs のランキング）
+///
+/// # 引数
+/// * `aggregate` - 合算結果
+///
+/// # 戻り値
+/// 学籍番号ごとの合計（行動数の多い順、同数なら学籍番号順）
+pub fn rank_students(aggregate: &AggregateResult) -> Vec<StudentRank> {
+    let mut ranks: Vec<StudentRank> = Vec::new();
+    // 学籍番号 → ranks の中の位置（何千試合あっても線形に探さないように）
+    let mut positions: HashMap<&str, usize> = HashMap::new();
+    for m in &aggregate.matches {
+        let student_id = m.player_info.student_id.as_str();
+        match positions.get(student_id) {
+            Some(&at) => {
+                ranks[at].matches += 1;
+                ranks[at].counts += &m.counts;
+            }
+            None => {
+                positions.insert(student_id, ranks.len());
+                ranks.push(StudentRank {
+                    student_id: student_id.to_string(),
+                    matches: 1,
+                    counts: AggregateCounts::from(&m.counts),
+                });
+            }
+        }
+    }
+    ranks.sort_by(|a, b| b.counts.total().cmp(&a.counts.total()).then_with(|| a.student_id.cmp(&b.student_id)));
+    ranks
+}
+
+/// k-meansで中心を更新する最大の回数（収束しなくてもここで止める）
+const MAX_KMEANS_ITERATIONS: usize = 100;
+
+/// 行動IDの使い方が似た試合同士を k 個のクラスタに分ける（簡易k-means、--cluster）
+///
+/// 各試合の特徴は「行動IDごとの回数 / その試合の行動数」を並べたベクトル（試合の長さの影響を除く）。
+/// 初期の中心は乱数を使わず、最初の試合から始めて、それまでの中心から最も遠い試合を順に選ぶ
+/// （同じ入力なら必ず同じ結果になる）。距離はユークリッド距離で、同じ距離なら先のクラスタに入れる。
+/// 試合が同じ特徴ばかりで空になったクラスタは結果に含めない。
+///
+/// # 引数
+/// * `results` - 試合ごとの解析結果（入力した順）
+/// * `k` - クラスタの数（試合数より多ければ試合数にする）
+///
+/// # 戻り値
+/// クラスタのリスト（最初のメンバーの入力順）
+pub fn cluster_matches(results: &[AnalysisResult], k: usize) -> Vec<PlayStyleCluster> {
+    let k = k.min(results.len());
+    if k == 0 {
+        return Vec::new();
+    }
+
+    // すべての試合で使われた行動ID（ID順）を特徴の並びにする
+    let ids: Vec<&str> = results
+        .iter()
+        .flat_map(|r| r.action_id_counts.iter().map(|(id, _)| id.as_str()))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let features: Vec<Vec<f64>> = results
+        .iter()
+        .map(|r| {
+            let total = r.counts.total();
+            ids.iter()
+                .map(|id| {
+                    let count = r.action_id_counts.iter().find(|(i, _)| i == id).map_or(0, |(_, c)| *c);
+                    if total == 0 { 0.0 } else { count as f64 / total as f64 }
+                })
+                .collect()
+        })
+        .collect();
+    let distance = |a: &[f64], b: &[f64]| a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum::<f64>().sqrt();
+    let nearest = |point: &[f64], centers: &[Vec<f64>]| {
+        (0..centers.len())
+            .min_by(|&a, &b| distance(point, &centers[a]).total_cmp(&distance(point, &centers[b])))
+            .unwrap_or(0)
+    };
+
+    // 初期の中心: 最初の試合、その後はそれまでの中心から最も遠い試合（同じ距離なら先の試合）
+    let mut centers = vec![features[0].clone()];
+    while centers.len() < k {
+        let farthest = (0..features.len())
+            .map(|i| (i, centers.iter().map(|c| distance(&features[i], c)).fold(f64::INFINITY, f64::min)))
+            .fold((0, f64::NEG_INFINITY), |best, (i, d)| if d > best.1 { (i, d) } else { best });
+        centers.push(features[farthest.0].clone());
+    }
+
+    let mut assignment: Vec<usize> = features.iter().map(|f| nearest(f, &centers)).collect();
+    for _ in 0..MAX_KMEANS_ITERATIONS {
+        // 中心をメンバーの平均に動かす（メンバーがいなければそのまま）
+        for (c, center) in centers.iter_mut().enumerate() {
+            let members: Vec<&Vec<f64>> = features.iter().zip(&assignment).filter(|(_, &a)| a == c).map(|(f, _)| f).collect();
+            if members.is_empty() {
+                continue;
+            }
+            for (j, value) in center.iter_mut().enumerate() {
+                *value = members.iter().map(|m| m[j]).sum::<f64>() / members.len() as f64;
+            }
+        }
+        let next: Vec<usize> = features.iter().map(|f| nearest(f, &centers)).collect();
+        if next == assignment {
+            break;
+        }
+        assignment = next;
+    }
+
+    // 空のクラスタを除き、最初のメンバーの入力順に並べる
+    let mut order: Vec<usize> = Vec::new();
+    for &c in &assignment {
+        if !order.contains(&c) {
+            order.push(c);
+        }
+    }
+    let mut clusters: Vec<PlayStyleCluster> = order
+        .iter()
+        .map(|&c| {
+            let mut counts = ActionCounts::new();
+            let mut members = Vec::new();
+            for (result, _) in results.iter().zip(&assignment).filter(|(_, &a)| a == c) {
+                counts += result.counts;
+                members.push(result.player_info.clone());
+            }
+            let mut centroid: Vec<(String, f64)> = ids
+                .iter()
+                .zip(&centers[c])
+                .filter(|(_, &share)| share > 0.0)
+                .map(|(id, &share)| (id.to_string(), share))
+                .collect();
+            centroid.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            PlayStyleCluster {
+                members,
+                centroid,
+                counts,
+                nearest: None,
+            }
+        })
+        .collect();
+
+    // 中心が最も近いほかのクラスタ（似たプレイスタイルのまとまり）
+    for (i, cluster) in clusters.iter_mut().enumerate() {
+        cluster.nearest = order
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .map(|(j, &c)| (j, distance(&centers[order[i]], &centers[c])))
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+    }
+    clusters
+}
+
+/// 解析結果から指標の値を取り出す関数（計算できなければNone）
+type MetricExtractor = fn(&AnalysisResult) -> Option<f64>;
+
+/// 改善レポートで追う指標（表示名, JSON名, 単位, 値の取り出し方）
+const IMPROVEMENT_METRICS: [(&str, &str, &str, MetricExtractor); 4] = [
+    ("シールド比率", "shield_ratio", "%", |r| Some(r.counts.shield_ratio())),
+    ("技の種類", "diversity", "種類", |r| Some(r.action_id_counts.len() as f64)),
+    ("APM", "apm", "回/分", actions_per_minute),
+    ("回避比率", "dodge_ratio", "%", |r| Some(r.counts.dodge_ratio())),
+];
+
+/// 1人の学生の複数の試合から改善レポートを作る
+///
+/// 解析結果を対戦回次の順に並べ、指標ごとに最初と最後の値、回次に対する傾き（最小二乗法）を求める。
+/// 回次に欠番があってもよい（傾きは回次の値そのものに対して計算する）。
+/// `order` が Mtime なら、ファイルの更新日時の順に並べ、最初の試合からの日数に対する傾きを求める。
+/// APMは試合時間が0秒の試合では計算できないので、その試合を除いて推移を求める。
+///
+/// # 引数
+/// * `results` - 同じ学生の各試合の解析結果（順番は問わない）
+/// * `order` - 試合の並べ方（推移の横軸）
+///
+/// # 戻り値
+/// 改善レポート。結果が空のとき、学籍番号が混ざっているとき、
+/// Mtime なのに更新日時が取れない試合があるときはエラー
+pub fn improvement_report(results: &[AnalysisResult], order: MatchOrder) -> Result<ImprovementReport> {
+    let Some(first) = results.first() else {
+        return Err(SmasherError::EmptyData("改善レポートを作る試合がありません".to_string()));
+    };
+    let student_id = &first.player_info.student_id;
+    if let Some(other) = results.iter().find(|r| &r.player_info.student_id != student_id) {
+        return Err(SmasherError::InvalidArgument(format!(
+            "改善レポートは同じ学生の試合だけで作れます: {} と {} が混ざっています",
+            student_id, other.player_info.student_id
+        )));
+    }
+
+    let mut sorted: Vec<&AnalysisResult> = results.iter().collect();
+    sorted.sort_by(|a, b| a.cmp_by(b, SortKey::MatchNumber, false));
+    if order == MatchOrder::Mtime {
+        if let Some(missing) = sorted.iter().find(|r| r.modified.is_none()) {
+            return Err(SmasherError::InvalidArgument(format!(
+                "--order mtime: 対戦回次 {} のファイルの更新日時を取得できません",
+                missing.player_info.match_number
+            )));
+        }
+        // 更新日時が同じ試合は回次の順のままにする（sort_by_keyは安定ソート）
+        sorted.sort_by_key(|r| r.modified);
+    }
+    // 横軸の値（回次、または最初の試合からの日数）
+    let earliest = sorted[0].modified;
+    let axis = |r: &AnalysisResult| match (order, earliest, r.modified) {
+        (MatchOrder::Mtime, Some(earliest), Some(modified)) => datetime::days_between(earliest, modified),
+        _ => r.player_info.match_number as f64,
+    };
+
+    let metrics = IMPROVEMENT_METRICS
+        .iter()
+        .filter_map(|&(label, key, unit, extract)| {
+            let points: Vec<(f64, f64)> = sorted
+                .iter()
+                .filter_map(|r| extract(r).map(|value| (axis(r), value)))
+                .collect();
+            let (&(_, first), &(_, last)) = (points.first()?, points.last()?);
+            let slope = linear_slope(&points);
+            Some(MetricTrend {
+                label,
+                key,
+                unit,
+                first,
+                last,
+                slope,
+                trend: slope.map(|slope| classify_trend(slope, &points)),
+            })
+        })
+        .collect();
+
+    Ok(ImprovementReport {
+        student_id: student_id.clone(),
+        match_numbers: sorted.iter().map(|r| r.player_info.match_number).collect(),
+        order,
+        modified: sorted.iter().map(|r| r.modified).collect(),
+        metrics,
+    })
+}
+
+/// 1分あたりの行動数（試合時間が0秒ならNone）
+fn actions_per_minute(result: &AnalysisResult) -> Option<f64> {
+    result.time_stats.apm
+}
+
+/// 点 (x, y) に最小二乗法で直線を当てはめたときの傾き
+///
+/// xが1種類しかない（または点が1つ以下）と傾きが決まらないのでNone
+fn linear_slope(points: &[(f64, f64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum();
+    if sxx <= f64::EPSILON {
+        None
+    } else {
+        Some(sxy / sxx)
+    }
+}
+
+/// 傾きから推移の向きを決める
+///
+/// 全回次を通した変化量（傾き×回次の幅）が平均値の Trend::FLAT_FRACTION 未満なら横ばいとする
+fn classify_trend(slope: f64, points: &[(f64, f64)]) -> Trend {
+    let span = points[points.len() - 1].0 - points[0].0;
+    let mean = points.iter().map(|(_, y)| y.abs()).sum::<f64>() / points.len() as f64;
+    let change = slope * span;
+    if change.abs() < mean * Trend::FLAT_FRACTION || change == 0.0 {
+        Trend::Flat
+    } else if change > 0.0 {
+        Trend::Rising
+    } else {
+        Trend::Falling
+    }
+}
+
+/// 2つのログで、行動IDごとの使用頻度に差があるかを調べる（2標本の比率の差のz検定）
+///
+/// 各行動IDについて、全行動に占める割合を2つのログで比べる。
+/// 合算した割合 p で標準誤差 sqrt(p(1-p)(1/n1+1/n2)) を求め、z = (p2 - p1) / 標準誤差 とする。
+/// 厳密な検定ではなく、差の大きさの目安として使う。
+///
+/// # 引数
+/// * `a` - 1つ目のログ（上達前など）
+/// * `b` - 2つ目のログ（上達後など）
+///
+/// # 戻り値
+/// 行動IDごとの比較（p値の小さい順、計算できないものは最後）
+pub fn compare_actions(a: &BattleLog, b: &BattleLog) -> Vec<ActionComparison> {
+    let counts_a: BTreeMap<String, u32> = count_actions_by_id(a).into_iter().collect();
+    let counts_b: BTreeMap<String, u32> = count_actions_by_id(b).into_iter().collect();
+    let (total_a, total_b) = (a.actions.len() as f64, b.actions.len() as f64);
+
+    let mut ids: Vec<&String> = counts_a.keys().chain(counts_b.keys()).collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut comparisons: Vec<ActionComparison> = ids
+        .into_iter()
+        .map(|id| {
+            let count_a = counts_a.get(id).copied().unwrap_or(0);
+            let count_b = counts_b.get(id).copied().unwrap_or(0);
+            let ratio = |count: u32, total: f64| if total > 0.0 { count as f64 / total } else { 0.0 };
+            let (ratio_a, ratio_b) = (ratio(count_a, total_a), ratio(count_b, total_b));
+
+            let pooled = ratio(count_a + count_b, total_a + total_b);
+            let test = two_proportion_z_test(count_a, a.actions.len(), count_b, b.actions.len());
+            // 期待度数 n*p と n*(1-p) のどれかが小さいと正規近似がずれる
+            let small_sample = [total_a, total_b]
+                .iter()
+                .any(|&n| n * pooled.min(1.0 - pooled) < ActionComparison::MIN_EXPECTED);
+
+            ActionComparison {
+                action_id: id.clone(),
+                count_a,
+                count_b,
+                ratio_a,
+                ratio_b,
+                z: test.map(|(z, _)| z),
+                p_value: test.map(|(_, p)| p),
+                small_sample,
+            }
+        })
+        .collect();
+
+    comparisons.sort_by(|x, y| match (x.p_value, y.p_value) {
+        (Some(px), Some(py)) => px.total_cmp(&py),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    comparisons
+}
+
+/// 2つのログで、行動IDごとの1分あたりの回数を比べる（--normalize per-minute）
+///
+/// 試合の長さが違うログどうしでも比べられるように、回数をそれぞれの試合時間（TimeStats.duration）を
+/// 分に直した値で割る。
+///
+/// # 引数
+/// * `a` - 1つ目のログ
+/// * `b` - 2つ目のログ
+///
+/// # 戻り値
+/// 1分あたりの回数の差が大きい順（同じならIDの順）の比較。
+/// どちらかの試合時間が0秒（行動が1件以下、またはすべて同じ時刻）なら割れないのでNone
+pub fn compare_per_minute(a: &BattleLog, b: &BattleLog) -> Option<Vec<RateComparison>> {
+    let minutes = |battle_log: &BattleLog| {
+        let duration = compute_time_stats(battle_log, &count_actions(battle_log)).duration;
+        (duration > 0.0).then_some(duration / 60.0)
+    };
+    let (minutes_a, minutes_b) = (minutes(a)?, minutes(b)?);
+    let counts_a: BTreeMap<String, u32> = count_actions_by_id(a).into_iter().collect();
+    let counts_b: BTreeMap<String, u32> = count_actions_by_id(b).into_iter().collect();
+
+    let mut ids: Vec<&String> = counts_a.keys().chain(counts_b.keys()).collect();
+    ids.sort();
+    ids.dedup();
+
+    let mut rates: Vec<RateComparison> = ids
+        .into_iter()
+        .map(|id| {
+            let count_a = counts_a.get(id).copied().unwrap_or(0);
+            let count_b = counts_b.get(id).copied().unwrap_or(0);
+            RateComparison {
+                action_id: id.clone(),
+                count_a,
+                count_b,
+                per_minute_a: count_a as f64 / minutes_a,
+                per_minute_b: count_b as f64 / minutes_b,
+            }
+        })
+        .collect();
+    // IDの順に並んでいるので、安定ソートで同じ差のものはIDの順のまま
+    rates.sort_by(|x, y| y.difference().abs().total_cmp(&x.difference().abs()));
+    Some(rates)
+}
+
+/// 2つのログで、攻撃・シールド・回避の割合に差があるかを調べる（2標本の比率の差のz検定）
+///
+/// どちらかのログの行動数が CategoryComparison::MIN_ACTIONS 未満なら、
+/// 正規近似があてにならないのでz値・p値は出さず、suppressed を true にする。
+///
+/// # 引数
+/// * `a` - 1つ目のログ（上達前など）
+/// * `b` - 2つ目のログ（上達後など）
+///
+/// # 戻り値
+/// 攻撃・シールド・回避の順の比較
+pub fn compare_categories(a: &BattleLog, b: &BattleLog) -> Vec<CategoryComparison> {
+    let (counts_a, counts_b) = (count_actions(a), count_actions(b));
+    let (total_a, total_b) = (a.actions.len(), b.actions.len());
+    let suppressed = total_a < CategoryComparison::MIN_ACTIONS || total_b < CategoryComparison::MIN_ACTIONS;
+    let ratio = |count: u32, total: usize| if total > 0 { count as f64 / total as f64 } else { 0.0 };
+
+    [ActionType::Attack, ActionType::Shield, ActionType::Dodge]
+        .into_iter()
+        .map(|action_type| {
+            let (count_a, count_b) = (counts_a.count(&action_type), counts_b.count(&action_type));
+            let test = if suppressed { None } else { two_proportion_z_test(count_a, total_a, count_b, total_b) };
+            CategoryComparison {
+                action_type,
+                count_a,
+                count_b,
+                ratio_a: ratio(count_a, total_a),
+                ratio_b: ratio(count_b, total_b),
+                z: test.map(|(z, _)| z),
+                p_value: test.map(|(_, p)| p),
+                suppressed,
+            }
+        })
+        .collect()
+}
+
+/// 2標本の比率の差のz検定（両側）
+///
+/// 合算した割合 p で標準誤差 sqrt(p(1-p)(1/n1+1/n2)) を求め、
+/// z = (count_b/total_b - count_a/total_a) / 標準誤差 とする。p値は標準正規分布の両側確率。
+///
+/// # 引数
+/// * `count_a` / `total_a` - 1つ目の標本の該当数と総数
+/// * `count_b` / `total_b` - 2つ目の標本の該当数と総数
+///
+/// # 戻り値
+/// (z値, 両側p値)。総数が0のときや、両方とも割合が0か100%で標準誤差が0のときはNone
+pub fn two_proportion_z_test(count_a: u32, total_a: usize, count_b: u32, total_b: usize) -> Option<(f64, f64)> {
+    if total_a == 0 || total_b == 0 {
+        return None;
+    }
+    let (n_a, n_b) = (total_a as f64, total_b as f64);
+    let pooled = (count_a as f64 + count_b as f64) / (n_a + n_b);
+    let std_error = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+    if !(std_error.is_finite() && std_error > 0.0) {
+        return None;
+    }
+    let z = (count_b as f64 / n_b - count_a as f64 / n_a) / std_error;
+    Some((z, erfc(z.abs() / std::f64::consts::SQRT_2)))
+}
+
+/// 相補誤差関数 erfc(x) の近似（Abramowitz & Stegun 7.1.26、誤差は約1.5e-7以下）
+///
+/// 標準正規分布の両側p値は erfc(|z| / √2) で求められる
+fn erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let value = poly * (-x * x).exp();
+    if x >= 0.0 {
+        value
+    } else {
+        2.0 - value
+    }
+}
+
+/// 2つのログを比べて、片方にしかない行動を探す
+///
+/// タイムスタンプと行動IDの両方が一致する行動を同じ行動とみなす。
+/// 同じ行動が複数あるときは、数の差の分だけ片方にしかないものとして扱う。
+///
+/// # 引数
+/// * `old` - 以前のログ
+/// * `new` - 追記された後のログ
+///
+/// # 戻り値
+/// 新しいログにだけある行動と、古いログにだけある行動
+pub fn diff_logs(old: &BattleLog, new: &BattleLog) -> LogDiff {
+    // f64はそのままではHashMapのキーにできないので、ビット列にして比べる
+    let key = |action: &Action| (action.timestamp.to_bits(), action.original_id.to_string());
+
+    // (タイムスタンプ, ID) → 古いログでの残り回数
+    let mut remaining: HashMap<(u64, String), usize> = HashMap::new();
+    for action in &old.actions {
+        *remaining.entry(key(action)).or_insert(0) += 1;
+    }
+
+    let mut added = Vec::new();
+    for action in sorted_actions(new) {
+        match remaining.get_mut(&key(action)) {
+            Some(count) if *count > 0 => *count -= 1,
+            _ => added.push(action.clone()),
+        }
+    }
+
+    // 新しいログで使われなかった分が、古いログにだけある行動
+    let mut removed = Vec::new();
+    for action in sorted_actions(old) {
+        if let Some(count) = remaining.get_mut(&key(action)) {
+            if *count > 0 {
+                *count -= 1;
+                removed.push(action.clone());
+            }
+        }
+    }
+
+    LogDiff { added, removed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{DefaultClassifier, Momentum, PlayerInfo};
+
+    /// (時刻, 行動ID) の並びからログを作る
+    fn log(actions: &[(f64, &str)]) -> BattleLog {
+        let actions = actions.iter().map(|&(timestamp, id)| Action::new(timestamp, id.to_string())).collect();
+        BattleLog::new(PlayerInfo::new("b1022024".to_string(), 1), actions)
+    }
+
+    /// 学籍番号と対戦回次を指定し、行動回数を u32 の上限にした解析結果
+    fn huge_result(student_id: &str, match_number: u32) -> AnalysisResult {
+        let battle_log = BattleLog::new(PlayerInfo::new(student_id.to_string(), match_number), Vec::new());
+        let counts = ActionCounts { attack_count: u32::MAX, shield_count: u32::MAX, dodge_count: 0, unknown_count: 0 };
+        AnalysisResult::new(&battle_log, counts, vec![("us".to_string(), u32::MAX), ("s".to_string(), u32::MAX)])
+    }
+
+    /// 解析結果をファイルごとの結果として、すべて残して合算する
+    fn aggregate_huge(results: &[AnalysisResult]) -> AggregateResult {
+        let files = results.iter().enumerate().map(|(i, r)| (format!("{}.csv", i), r.clone())).collect();
+        aggregate_files(files, DupPolicy::KeepAll).unwrap()
+    }
+
+    #[test]
+    fn aggregates_past_u32_without_wrapping() {
+        let max = u32::MAX as u64;
+        let results = [huge_result("b1", 1), huge_result("b1", 2), huge_result("b2", 1)];
+        for aggregate in [aggregate_huge(&results), aggregate(&results)] {
+            assert_eq!((aggregate.counts.attack_count, aggregate.counts.shield_count), (3 * max, 3 * max));
+            assert_eq!(aggregate.counts.total(), 6 * max);
+            assert_eq!(aggregate.counts.attack_ratio(), 50.0);
+            assert_eq!(aggregate.action_id_counts, vec![("s".to_string(), 3 * max), ("us".to_string(), 3 * max)]);
+        }
+    }
+
+    #[test]
+    fn aggregate_keeps_every_duplicate_and_labels_it_by_position() {
+        let results = [huge_result("b1", 1), huge_result("b2", 1), huge_result("b1", 1)];
+        let aggregate = aggregate(&results);
+        assert_eq!((aggregate.files, aggregate.dup_policy), (3, DupPolicy::KeepAll));
+        let labels = vec!["1番目".to_string(), "3番目".to_string()];
+        assert_eq!(aggregate.duplicates.len(), 1);
+        assert_eq!((&aggregate.duplicates[0].files, &aggregate.duplicates[0].kept), (&labels, &labels));
+        assert_eq!(aggregate.counts, aggregate_huge(&results).counts);
+    }
+
+    #[test]
+    fn ranks_students_past_u32_without_wrapping() {
+        let aggregate = aggregate_huge(&[huge_result("b2", 1), huge_result("b1", 1), huge_result("b1", 2)]);
+        let ranks = rank_students(&aggregate);
+        let summary: Vec<(&str, usize, u64)> =
+            ranks.iter().map(|rank| (rank.student_id.as_str(), rank.matches, rank.counts.total())).collect();
+        assert_eq!(summary, vec![("b1", 2, 4 * u32::MAX as u64), ("b2", 1, 2 * u32::MAX as u64)]);
+    }
+
+    #[test]
+    fn analyze_lists_unknown_ids_without_counting_them_as_attacks() {
+        let battle_log = log(&[(0.0, "us"), (1.0, "ssss"), (2.0, "jump"), (3.0, "ssss"), (4.0, "s")]);
+        let result = analyze(&battle_log, &AnalysisOptions::default());
+        assert_eq!((result.counts.attack_count, result.counts.unknown_count), (1, 3));
+        assert_eq!(result.unknown_id_counts, vec![("ssss".to_string(), 2), ("jump".to_string(), 1)]);
+        assert_eq!(result.unknown_ids, vec!["ssss".to_string(), "jump".to_string()]);
+    }
+
+    /// バーストを (開始, 終了, 行動数) で並べる
+    fn spans(bursts: &Bursts) -> Vec<(f64, f64, usize)> {
+        bursts.bursts.iter().map(|burst| (burst.start, burst.end, burst.actions)).collect()
+    }
+
+    #[test]
+    fn bursts_split_runs_at_the_gap() {
+        // 3.0 と 8.0 は単発。8.0 → 8.8 はちょうど 0.8秒なので区切る
+        let battle_log = log(&[
+            (0.0, "us"), (0.5, "ss"), (1.0, "ds"),
+            (3.0, "s"),
+            (5.0, "j1"), (5.6, "j2"), (6.3, "ss"), (7.0, "us"),
+            (8.0, "sd"),
+            (8.8, "s"), (9.0, "nd"), (9.2, "ss"),
+        ]);
+        let bursts = compute_bursts(&battle_log, 0.8, 3);
+        assert_eq!(spans(&bursts), vec![(0.0, 1.0, 3), (5.0, 7.0, 4), (8.8, 9.2, 3)]);
+        assert_eq!(bursts.inside, ActionCounts { attack_count: 8, shield_count: 1, dodge_count: 1, unknown_count: 0 });
+        assert_eq!(bursts.outside, ActionCounts { attack_count: 0, shield_count: 1, dodge_count: 1, unknown_count: 0 });
+        assert_eq!(bursts.share(), Some(10.0 / 12.0 * 100.0));
+        assert_eq!(bursts.average_len(), Some(10.0 / 3.0));
+    }
+
+    #[test]
+    fn bursts_split_an_exact_gap_despite_rounding() {
+        // 0.30 - 0.25 は小数では 0.05 より少しだけ小さいが、ちょうど gap として区切る
+        let battle_log = log(&[(0.20, "us"), (0.25, "us"), (0.30, "us"), (0.35, "us")]);
+        let bursts = compute_bursts(&battle_log, 0.05, 2);
+        assert!(bursts.bursts.is_empty(), "{:?}", spans(&bursts));
+        // 少しでも短ければ1つの連続
+        let bursts = compute_bursts(&battle_log, 0.0501, 2);
+        assert_eq!(spans(&bursts), vec![(0.20, 0.35, 4)]);
+    }
+
+    #[test]
+    fn bursts_need_min_len_actions() {
+        let battle_log = log(&[(0.0, "us"), (0.1, "us"), (5.0, "us"), (5.1, "us"), (5.2, "us")]);
+        assert_eq!(spans(&compute_bursts(&battle_log, 0.8, 3)), vec![(5.0, 5.2, 3)]);
+        assert_eq!(spans(&compute_bursts(&battle_log, 0.8, 2)), vec![(0.0, 0.1, 2), (5.0, 5.2, 3)]);
+        assert!(compute_bursts(&battle_log, 0.8, 4).bursts.is_empty());
+        assert_eq!(compute_bursts(&battle_log, 0.8, 4).average_len(), None);
+    }
+
+    #[test]
+    fn density_peaks_merge_flat_tops() {
+        // 秒ごとの密度: 0秒=1, 1秒=2, 2秒=2, 3秒=1 → 1〜2秒の平らな山が1つ
+        let battle_log = log(&[(0.1, "us"), (1.1, "us"), (1.5, "ss"), (2.2, "ds"), (2.8, "us"), (3.0, "ss")]);
+        let peaks = find_density_peaks(&battle_log, 0.0);
+        assert_eq!(peaks, vec![DensityPeak { second: 1.0, width: 2, density: 2 }]);
+    }
+
+    #[test]
+    fn density_peaks_below_threshold_are_dropped() {
+        let battle_log = log(&[(0.1, "us"), (5.0, "us"), (5.5, "ss")]);
+        let peaks = find_density_peaks(&battle_log, 2.0);
+        assert_eq!(peaks, vec![DensityPeak { second: 5.0, width: 1, density: 2 }]);
+    }
+
+    #[test]
+    fn density_peaks_handle_a_huge_time_span() {
+        // 以前は秒ごとに配列を作っていたので、50億秒の幅があるとメモリが足りずに落ちた
+        let battle_log = log(&[(0.0, "us"), (5_000_000_000.0, "ss")]);
+        let peaks = find_density_peaks(&battle_log, 0.0);
+        assert_eq!(
+            peaks,
+            vec![
+                DensityPeak { second: 0.0, width: 1, density: 1 },
+                DensityPeak { second: 5_000_000_000.0, width: 1, density: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn density_spikes_count_empty_seconds_as_zero() {
+        // 0〜9秒のうち、行動があるのは0秒（1回）と9秒（6回）だけ
+        let mut actions = vec![(0.5, "us")];
+        actions.extend([(9.0, "us"); 6]);
+        let spikes = find_density_spikes(&log(&actions), 2.0);
+
+        // 平均 = 7 / 10、分散 = (0.3² + 5.3² + 8 × 0.7²) / 10
+        assert!((spikes.mean - 0.7).abs() < 1e-9);
+        assert!((spikes.std_dev - 1.791647287).abs() < 1e-6);
+        assert_eq!(spikes.spikes, vec![DensitySpike { second: 9.0, density: 6 }]);
+    }
+
+    #[test]
+    fn density_spikes_handle_a_huge_time_span() {
+        // ほとんどの秒が0なので、行動のある2つの秒はどちらもスパイクになる
+        let spikes = find_density_spikes(&log(&[(0.0, "us"), (0.5, "us"), (5_000_000_000.0, "ss")]), 3.0);
+        assert!((spikes.mean - 3.0 / 5_000_000_001.0).abs() < 1e-15);
+        assert_eq!(
+            spikes.spikes,
+            vec![
+                DensitySpike { second: 0.0, density: 2 },
+                DensitySpike { second: 5_000_000_000.0, density: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn windowed_counts_widen_slices_over_the_limit() {
+        let battle_log = log(&[(0.0, "us"), (3.0, "us"), (5_000_000_000.0, "ss")]);
+        let (slice_secs, counts) = windowed_counts(&battle_log, 0.0, 1.0, 40);
+        assert_eq!(counts.len(), 40);
+        assert!((slice_secs - 5_000_000_000.0 / 39.0).abs() < 1e-3);
+        assert_eq!((counts[0], counts[39]), (2, 1));
+
+        // 上限に収まるときは指定どおりの長さで区切る
+        let (slice_secs, counts) = windowed_counts(&log(&[(0.0, "us"), (3.5, "us")]), 0.0, 1.0, 40);
+        assert_eq!((slice_secs, counts), (1.0, vec![1, 0, 0, 1]));
+    }
+
+    #[test]
+    fn shield_cover_histogram_bins_by_quarter_seconds() {
+        // 攻撃→シールドの時間: 0.25秒（境目ちょうどは後ろの区間）と 0.6秒
+        let battle_log = log(&[(1.0, "j1"), (1.25, "s"), (2.0, "j1"), (2.6, "s")]);
+        let cover = compute_shield_cover(&battle_log, 0.5).unwrap();
+        assert_eq!(cover.histogram, vec![0, 1, 1]);
+        assert!(!cover.overflow);
+        assert_eq!((cover.attacks, cover.within), (2, 1));
+    }
+
+    #[test]
+    fn shield_cover_histogram_caps_bins_for_huge_latencies() {
+        // 以前はヒストグラムの長さが 最大の時間 / 0.25 だったので、メモリが足りずに落ちた
+        let battle_log = log(&[(0.0, "j1"), (0.1, "s"), (1.0, "j1"), (5_000_000_000.0, "s")]);
+        let cover = compute_shield_cover(&battle_log, 0.5).unwrap();
+        assert_eq!(cover.histogram.len(), ShieldCover::MAX_BINS);
+        assert!(cover.overflow);
+        assert_eq!((cover.histogram[0], cover.histogram[ShieldCover::MAX_BINS - 1]), (1, 1));
+    }
+
+    #[test]
+    fn activity_strip_stays_within_max_cells() {
+        let strip = compute_activity_strip(&log(&[(0.0, "us"), (5_000_000_000.0, "ss")]));
+        assert!(strip.counts.len() <= ActivityStrip::MAX_CELLS);
+        assert_eq!(strip.counts.iter().sum::<u32>(), 2);
+    }
+    /// シールド shields 回と攻撃 attacks 回を1秒おきに並べたログ
+    fn shields_and_attacks(shields: usize, attacks: usize) -> BattleLog {
+        let ids = std::iter::repeat_n("s", shields).chain(std::iter::repeat_n("j1", attacks));
+        let actions: Vec<(f64, &str)> = ids.enumerate().map(|(i, id)| (i as f64, id)).collect();
+        log(&actions)
+    }
+
+    #[test]
+    fn two_proportion_z_test_matches_known_values() {
+        // 期待値は z = (p2-p1)/sqrt(p(1-p)(1/n1+1/n2))、p値 = erfc(|z|/√2) を倍精度で計算したもの
+        let cases = [
+            ((8, 100, 11, 100), (0.723469, 0.469392)),
+            ((50, 100, 65, 100), (2.145596, 0.031905)),
+            ((30, 200, 10, 200), (-3.333333, 0.000858)),
+        ];
+        for ((count_a, total_a, count_b, total_b), (z, p)) in cases {
+            let (actual_z, actual_p) = two_proportion_z_test(count_a, total_a, count_b, total_b).unwrap();
+            assert!((actual_z - z).abs() < 1e-6, "z: {} != {}", actual_z, z);
+            assert!((actual_p - p).abs() < 1e-6, "p: {} != {}", actual_p, p);
+        }
+        // |z| = 1.96 で両側p値がほぼ0.05になる
+        assert!((erfc(1.959964 / std::f64::consts::SQRT_2) - 0.05).abs() < 1e-6);
+    }
+
+    #[test]
+    fn two_proportion_z_test_is_none_without_variance() {
+        assert_eq!(two_proportion_z_test(0, 0, 3, 10), None);
+        assert_eq!(two_proportion_z_test(0, 10, 0, 20), None);
+        assert_eq!(two_proportion_z_test(10, 10, 20, 20), None);
+    }
+
+    #[test]
+    fn compare_categories_tests_each_category() {
+        let (a, b) = (shields_and_attacks(8, 92), shields_and_attacks(11, 89));
+        let categories = compare_categories(&a, &b);
+        let types: Vec<ActionType> = categories.iter().map(|c| c.action_type.clone()).collect();
+        assert_eq!(types, vec![ActionType::Attack, ActionType::Shield, ActionType::Dodge]);
+
+        let shield = &categories[1];
+        assert_eq!((shield.count_a, shield.count_b, shield.suppressed), (8, 11, false));
+        assert!((shield.ratio_b - 0.11).abs() < 1e-12);
+        assert!((shield.p_value.unwrap() - 0.469392).abs() < 1e-6);
+        assert_eq!(shield.significant(), Some(false));
+        // どちらにも回避がないので計算できない
+        assert_eq!((categories[2].p_value, categories[2].significant()), (None, None));
+    }
+
+    #[test]
+    fn compare_categories_suppresses_hint_below_min_actions() {
+        let small = shields_and_attacks(5, CategoryComparison::MIN_ACTIONS - 6);
+        for (a, b) in [(&small, &shields_and_attacks(50, 50)), (&shields_and_attacks(50, 50), &small)] {
+            for category in compare_categories(a, b) {
+                assert!(category.suppressed);
+                assert_eq!((category.z, category.p_value), (None, None));
+            }
+        }
+        let enough = shields_and_attacks(5, CategoryComparison::MIN_ACTIONS - 5);
+        assert!(compare_categories(&enough, &enough).iter().all(|c| !c.suppressed));
+    }
+    /// "jump" を回避として数え、ほかは標準の分類にする。呼ばれた回数も数える
+    struct JumpAsDodge {
+        calls: std::cell::Cell<usize>,
+    }
+
+    impl Classifier for JumpAsDodge {
+        fn classify(&self, action_id: &str) -> ActionType {
+            self.calls.set(self.calls.get() + 1);
+            match action_id {
+                "jump" => ActionType::Dodge,
+                _ => ActionType::from_action_id(action_id),
+            }
+        }
+    }
+
+    #[test]
+    fn analyze_with_uses_the_custom_classifier() {
+        let battle_log = log(&[(0.0, "us"), (1.0, "jump"), (2.0, "s"), (3.0, "jump"), (4.0, "jump")]);
+        let classifier = JumpAsDodge { calls: std::cell::Cell::new(0) };
+        let result = analyze_with(&battle_log, &AnalysisOptions::default(), &classifier);
+        assert_eq!(result.counts, ActionCounts { attack_count: 1, shield_count: 1, dodge_count: 3, unknown_count: 0 });
+        // 分類は行動IDの種類ごとに1回だけ
+        assert_eq!(classifier.calls.get(), 3);
+        // 元のログの分類は変えない
+        assert_eq!(battle_log.actions[1].action_type, ActionType::Unknown);
+    }
+
+    #[test]
+    fn analyze_with_default_classifier_matches_analyze() {
+        let battle_log = log(&[(0.0, "us"), (1.0, "jump"), (2.0, "s"), (3.0, "nd")]);
+        let options = AnalysisOptions::default();
+        assert_eq!(analyze_with(&battle_log, &options, &DefaultClassifier), analyze(&battle_log, &options));
+    }
+    /// 行動IDの並びを String にする
+    fn ids(items: &[&str]) -> Vec<String> {
+        items.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn combos_group_back_to_back_attacks() {
+        // 0.0〜1.8秒の4連続と、1.0秒を超えて空いたあとの2連続
+        let battle_log = log(&[(0.0, "j1"), (0.6, "j2"), (1.2, "ss"), (1.8, "us"), (3.0, "na"), (3.9, "fa")]);
+        let combos = compute_combos(&battle_log, DEFAULT_COMBO_WINDOW);
+        assert_eq!((combos.combo_count, combos.max_length, combos.mean_length), (2, 4, Some(3.0)));
+        assert_eq!(combos.longest, ids(&["j1", "j2", "ss", "us"]));
+        // ちょうど窓の長さの間隔は同じコンボに入る
+        assert_eq!(compute_combos(&log(&[(0.5, "j1"), (1.5, "j2")]), 1.0).combo_count, 1);
+    }
+
+    #[test]
+    fn dodges_and_shields_break_a_combo_inside_the_window() {
+        let battle_log = log(&[(0.0, "j1"), (0.2, "j2"), (0.4, "sd"), (0.6, "ss"), (0.8, "us"), (1.0, "s"), (1.2, "na")]);
+        let combos = compute_combos(&battle_log, DEFAULT_COMBO_WINDOW);
+        assert_eq!((combos.combo_count, combos.max_length, combos.mean_length), (2, 2, Some(2.0)));
+        // 同じ長さなら先に出たほうを最大コンボにする
+        assert_eq!(combos.longest, ids(&["j1", "j2"]));
+    }
+
+    #[test]
+    fn log_without_combos_has_no_lengths() {
+        for battle_log in [log(&[(0.0, "j1"), (2.0, "j2"), (2.5, "s"), (3.0, "nd"), (5.0, "us")]), log(&[])] {
+            let combos = compute_combos(&battle_log, DEFAULT_COMBO_WINDOW);
+            assert_eq!(combos, ComboStats::new(DEFAULT_COMBO_WINDOW));
+        }
+    }
+
+    #[test]
+    fn combo_window_is_separate_from_the_repeat_gap() {
+        let battle_log = log(&[(0.0, "j1"), (0.7, "j1"), (1.4, "j1")]);
+        let mut options = AnalysisOptions { combo_gap: 0.1, ..AnalysisOptions::default() };
+        assert_eq!(analyze(&battle_log, &options).combos.max_length, 3);
+        options.combo_window = 0.5;
+        assert_eq!(analyze(&battle_log, &options).combos.combo_count, 0);
+    }
+
+    #[test]
+    fn transitions_match_a_hand_counted_matrix() {
+        // 3.1秒空いた us→s は数えず、split に入る
+        let battle_log = log(&[(0.0, "us"), (0.5, "s"), (1.0, "us"), (1.3, "nd"), (1.5, "jump"), (1.9, "us"), (5.0, "s"), (5.5, "s")]);
+        let transitions = compute_transitions(&battle_log, 1.0);
+        // 行・列は 攻撃, シールド, 回避, 未登録 の順
+        let expected = [
+            [0, 1, 1, 0], // us→s, us→nd
+            [1, 1, 0, 0], // s→us, s→s
+            [0, 0, 0, 1], // nd→jump
+            [1, 0, 0, 0], // jump→us
+        ];
+        assert_eq!(transitions.counts, expected);
+        assert_eq!(transitions.split, 1);
+        assert_eq!(transitions.counts.iter().flatten().sum::<u32>() + transitions.split, 7);
+    }
+
+    #[test]
+    fn transitions_skip_pairs_across_the_gap() {
+        // 0.5秒ちょうどの組は数え、0.75秒・2秒空いた組は数えない
+        let battle_log = log(&[(0.0, "us"), (0.5, "us"), (1.25, "s"), (3.25, "nd"), (3.5, "us")]);
+        let transitions = compute_transitions(&battle_log, 0.5);
+        let mut expected = [[0; 4]; 4];
+        expected[0][0] = 1; // us→us
+        expected[2][0] = 1; // nd→us
+        assert_eq!(transitions.counts, expected);
+        assert_eq!(transitions.split, 2);
+        assert_eq!(transitions.top_ids, vec![("nd".to_string(), "us".to_string(), 1), ("us".to_string(), "us".to_string(), 1)]);
+        // 間隔を広げればすべて数える
+        assert_eq!(compute_transitions(&battle_log, 2.0).split, 0);
+    }
+
+    #[test]
+    fn alternating_log_switches_on_every_pair() {
+        let battle_log = log(&[(0.0, "us"), (1.0, "s"), (2.0, "us"), (3.0, "nd"), (4.0, "us"), (5.0, "s")]);
+        let flow = compute_flow_switches(&battle_log);
+        assert_eq!(flow.switches, 5);
+        // 5秒で5回 → 1分あたり60回
+        assert_eq!(flow.per_minute, Some(60.0));
+        assert_eq!(flow.style, Some(FlowStyle::classify(60.0)));
+    }
+
+    #[test]
+    fn block_structured_log_switches_only_between_blocks() {
+        // 攻撃3つ → 防御3つ → 攻撃2つ。シールドと回避の入れ替わりは数えない
+        let battle_log = log(&[(0.0, "us"), (1.0, "j1"), (2.0, "na"), (3.0, "s"), (4.0, "nd"), (5.0, "s"), (6.0, "us"), (12.0, "us")]);
+        let flow = compute_flow_switches(&battle_log);
+        assert_eq!(flow.switches, 2);
+        assert_eq!(flow.per_minute, Some(10.0));
+        // 並び順ではなく時刻順に見る
+        let shuffled = log(&[(12.0, "us"), (3.0, "s"), (0.0, "us"), (5.0, "s"), (1.0, "j1"), (6.0, "us"), (2.0, "na"), (4.0, "nd")]);
+        assert_eq!(compute_flow_switches(&shuffled), flow);
+    }
+
+    #[test]
+    fn flow_switches_without_duration_have_no_rate() {
+        for battle_log in [log(&[]), log(&[(1.0, "us")]), log(&[(1.0, "us"), (1.0, "s")])] {
+            let flow = compute_flow_switches(&battle_log);
+            assert_eq!((flow.per_minute, flow.style), (None, None));
+        }
+        assert_eq!(compute_flow_switches(&log(&[(1.0, "us"), (1.0, "s")])).switches, 1);
+    }
+
+    /// (比べた数, 直前と同じ数, 直前か2つ前と同じ数)
+    fn repeat_counts(battle_log: &BattleLog, gap: f64) -> (usize, usize, usize) {
+        let rates = compute_repeat_rates(battle_log, gap);
+        (rates.compared, rates.same_as_previous, rates.same_as_recent)
+    }
+
+    #[test]
+    fn alternating_log_repeats_only_two_back() {
+        let battle_log = log(&[(0.0, "us"), (0.2, "s"), (0.4, "us"), (0.6, "s"), (0.8, "us")]);
+        assert_eq!(repeat_counts(&battle_log, 0.4), (4, 0, 3));
+        let rates = compute_repeat_rates(&battle_log, 0.4);
+        assert_eq!((rates.previous_rate(), rates.recent_rate()), (Some(0.0), Some(0.75)));
+    }
+
+    #[test]
+    fn constant_log_repeats_every_time() {
+        let battle_log = log(&[(0.0, "us"), (0.2, "us"), (0.4, "us"), (0.6, "us")]);
+        assert_eq!(repeat_counts(&battle_log, 0.4), (3, 3, 3));
+        assert_eq!(compute_repeat_rates(&battle_log, 0.4).previous_rate(), Some(1.0));
+    }
+
+    #[test]
+    fn repeats_are_not_compared_across_a_gap() {
+        // 0.2→1.0 の間が空いているので、1.0 の us は比べない
+        let battle_log = log(&[(0.0, "us"), (0.2, "us"), (1.0, "us"), (1.2, "us"), (1.4, "s")]);
+        assert_eq!(repeat_counts(&battle_log, 0.4), (3, 2, 2));
+        // 直前とは続いていても、2つ前との間が空いていれば2つ前とは比べない
+        let battle_log = log(&[(0.0, "us"), (1.0, "s"), (1.2, "us")]);
+        assert_eq!(repeat_counts(&battle_log, 0.4), (1, 0, 0));
+        // どこも続いていなければ割合は出さない
+        let rates = compute_repeat_rates(&log(&[(0.0, "us"), (1.0, "us"), (2.0, "us")]), 0.4);
+        assert_eq!((rates.compared, rates.previous_rate(), rates.recent_rate()), (0, None, None));
+    }
+
+    /// 対戦回次を指定し、1秒おきの行動ID（0秒から）で解析した結果
+    fn match_result(match_number: u32, ids: &[&str]) -> AnalysisResult {
+        let actions = ids.iter().enumerate().map(|(i, id)| Action::new(i as f64, id.to_string())).collect();
+        let battle_log = BattleLog::new(PlayerInfo::new("b1022024".to_string(), match_number), actions);
+        analyze(&battle_log, &AnalysisOptions::default())
+    }
+
+    #[test]
+    fn improvement_report_follows_a_known_progression() {
+        // 回次は 2, 5, 9（欠番あり）で、渡す順番はばらばら
+        // シールドは 25→50→75% と増え、技の種類は 4→3→2 と減り、回避は 25→25→0% と減る。APMはずっと80回/分
+        let results = [
+            match_result(9, &["s", "s", "s", "us"]),
+            match_result(2, &["s", "us", "j1", "nd"]),
+            match_result(5, &["s", "s", "us", "nd"]),
+        ];
+        let report = improvement_report(&results, MatchOrder::Match).unwrap();
+        assert_eq!(report.student_id, "b1022024");
+        assert_eq!(report.match_numbers, vec![2, 5, 9]);
+
+        let metric = |key: &str| report.metrics.iter().find(|m| m.key == key).unwrap();
+        // 回次 x = 2, 5, 9 に対する最小二乗法の傾き（Sxx = 74/3）
+        let expected = [
+            ("shield_ratio", 25.0, 75.0, 525.0 / 74.0, Trend::Rising),
+            ("diversity", 4.0, 2.0, -21.0 / 74.0, Trend::Falling),
+            ("apm", 80.0, 80.0, 0.0, Trend::Flat),
+            ("dodge_ratio", 25.0, 0.0, -275.0 / 74.0, Trend::Falling),
+        ];
+        for (key, first, last, slope, trend) in expected {
+            let trend_of = metric(key);
+            assert_eq!((trend_of.first, trend_of.last, trend_of.trend), (first, last, Some(trend)), "{}", key);
+            let actual = trend_of.slope.unwrap();
+            assert!((actual - slope).abs() < 1e-9, "{}: {} != {}", key, actual, slope);
+            assert_eq!(actual.partial_cmp(&0.0), slope.partial_cmp(&0.0), "{}", key);
+        }
+    }
+
+    /// `id` を `count` 回（10秒おき）使い、`end` 秒にシールドで終わるログ
+    fn uses_over(id: &str, count: usize, end: f64) -> BattleLog {
+        let mut actions: Vec<(f64, &str)> = (0..count).map(|i| (i as f64 * 10.0, id)).collect();
+        actions.push((end, "s"));
+        log(&actions)
+    }
+
+    #[test]
+    fn per_minute_flips_which_log_uses_more() {
+        // A: 2分で上スマ10回（5回/分）、B: 6分で上スマ20回（約3.3回/分）
+        let (a, b) = (uses_over("us", 10, 120.0), uses_over("us", 20, 360.0));
+        let raw = compare_actions(&a, &b);
+        let us = raw.iter().find(|row| row.action_id == "us").unwrap();
+        assert!(us.count_b > us.count_a);
+
+        let rates = compare_per_minute(&a, &b).unwrap();
+        let us = rates.iter().find(|rate| rate.action_id == "us").unwrap();
+        assert_eq!((us.count_a, us.count_b), (10, 20));
+        assert_eq!(us.per_minute_a, 5.0);
+        assert!((us.per_minute_b - 20.0 / 6.0).abs() < 1e-9);
+        assert!(us.difference() < 0.0);
+        // 差の大きい順: us（-1.67）→ s（0.5 → 約0.17）
+        assert_eq!(rates.iter().map(|rate| rate.action_id.as_str()).collect::<Vec<_>>(), vec!["us", "s"]);
+    }
+
+    #[test]
+    fn per_minute_needs_a_duration_on_both_sides() {
+        let long = uses_over("us", 10, 120.0);
+        for short in [log(&[]), log(&[(5.0, "us")]), log(&[(5.0, "us"), (5.0, "s")])] {
+            assert_eq!(compare_per_minute(&long, &short), None);
+            assert_eq!(compare_per_minute(&short, &long), None);
+        }
+    }
+
+    #[test]
+    fn improvement_report_by_mtime_uses_days_since_the_first_match() {
+        let day = |n: u64| Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(86_400 * n));
+        // 回次の順（1, 2, 3）と更新日時の順（2, 3, 1）が食い違う
+        let mut results = [
+            match_result(1, &["s", "s", "s", "us"]),
+            match_result(2, &["s", "us", "j1", "nd"]),
+            match_result(3, &["s", "s", "us", "nd"]),
+        ];
+        for (result, days) in results.iter_mut().zip([10, 0, 4]) {
+            result.modified = day(days);
+        }
+        let report = improvement_report(&results, MatchOrder::Mtime).unwrap();
+        assert_eq!(report.match_numbers, vec![2, 3, 1]);
+        assert_eq!(report.modified, vec![day(0), day(4), day(10)]);
+        // シールド比率 25 → 50 → 75% を、日数 0, 4, 10 に対して当てはめた傾き（1日あたり）
+        let shield = report.metrics.iter().find(|m| m.key == "shield_ratio").unwrap();
+        assert!((shield.slope.unwrap() - 375.0 / 76.0).abs() < 1e-9, "{:?}", shield.slope);
+
+        results[0].modified = None;
+        let error = improvement_report(&results, MatchOrder::Mtime).unwrap_err().to_string();
+        assert!(error.contains("対戦回次 1 のファイルの更新日時を取得できません"), "{}", error);
+        // 回次の順なら更新日時は要らない
+        assert!(improvement_report(&results, MatchOrder::Match).is_ok());
+    }
+
+    #[test]
+    fn half_counts_split_at_the_midpoint() {
+        // 0秒〜8秒の試合なので境目は4秒。ちょうど4秒の行動は後半に入る
+        let battle_log = log(&[(0.0, "us"), (1.0, "us"), (3.9, "s"), (4.0, "s"), (8.0, "s")]);
+        let halves = compute_half_counts(&battle_log);
+        assert_eq!(halves.split, 4.0);
+        assert_eq!((halves.first.attack_count, halves.first.shield_count), (2, 1));
+        assert_eq!((halves.second.attack_count, halves.second.shield_count), (0, 2));
+        assert_eq!(halves.by_id["us"], (2, 0));
+        assert_eq!(halves.by_id["s"], (1, 2));
+        assert_eq!(halves.category_momentum(&ActionType::Shield), Momentum::Rising);
+        assert_eq!(halves.id_momentum("us"), Some(Momentum::Falling));
+        assert_eq!(halves.id_momentum("fa"), None);
+    }
+
+    #[test]
+    fn half_counts_put_a_zero_length_match_in_the_second_half() {
+        let halves = compute_half_counts(&log(&[(2.0, "us"), (2.0, "s")]));
+        assert_eq!((halves.first.total(), halves.second.total()), (0, 2));
+        assert_eq!(halves.id_momentum("us"), Some(Momentum::Rising));
+        // --momentum を付けなければ計算しない
+        assert_eq!(analyze(&log(&[(2.0, "us")]), &AnalysisOptions::default()).half_counts, None);
+    }
+
+    #[test]
+    fn shield_cover_measures_the_time_to_the_next_shield() {
+        // 攻撃 0.0 / 0.2 / 1.0 / 3.0、シールド 0.7 / 2.5（順番はばらばらに渡す）
+        // 時間は 0.7、0.5、1.5 秒。3.0秒の攻撃の後にはシールドがないので、割合の分母にだけ入る
+        let battle_log = log(&[(3.0, "us"), (0.7, "s"), (0.0, "j1"), (2.5, "s"), (0.2, "fa"), (1.0, "dth")]);
+        let cover = compute_shield_cover(&battle_log, 1.0).unwrap();
+        assert!((cover.latency.mean - 0.9).abs() < 1e-9, "{}", cover.latency.mean);
+        assert!((cover.latency.median - 0.7).abs() < 1e-9, "{}", cover.latency.median);
+        assert_eq!((cover.attacks, cover.within), (4, 2));
+        assert_eq!(cover.within_rate(), 0.5);
+        assert_eq!(cover.histogram, vec![0, 0, 2, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn shield_cover_needs_a_shield_after_an_attack() {
+        // シールドの後に攻撃しても、攻撃の後のシールドはない
+        assert_eq!(compute_shield_cover(&log(&[(0.0, "s"), (1.0, "us"), (2.0, "nd")]), 1.0), None);
+        assert_eq!(compute_shield_cover(&log(&[]), 1.0), None);
+        // 窓の境目ちょうど（0.7 - 0.2 = 0.5 の丸め誤差があっても）は窓の中に数える
+        let cover = compute_shield_cover(&log(&[(0.2, "us"), (0.7, "s")]), 0.5).unwrap();
+        assert_eq!((cover.within, cover.histogram.len()), (1, 3));
+    }
+
+    #[test]
+    fn attack_categories_count_only_attacks() {
+        let battle_log = log(&[(0.0, "fa"), (1.0, "j1"), (2.0, "s"), (3.0, "nd"), (4.0, "ssss"), (5.0, "ba"), (6.0, "fth")]);
+        let counts = count_by_attack_category(&battle_log);
+        // シールド・回避・未登録のIDは数えず、0回の種類はキーにならない
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            vec![(AttackCategory::Jab, 1), (AttackCategory::Aerial, 2), (AttackCategory::Grab, 1)]
+        );
+    }
+
+    #[test]
+    fn report_lists_every_attack_category_in_display_order() {
+        let result = analyze(&log(&[(0.0, "us"), (1.0, "us"), (2.0, "DA")]), &AnalysisOptions::default());
+        let categories: Vec<AttackCategory> = result.attack_categories.iter().map(|(category, _)| *category).collect();
+        assert_eq!(categories, AttackCategory::ALL);
+        let count = |category| result.attack_categories.iter().find(|(c, _)| *c == category).unwrap().1;
+        assert_eq!((count(AttackCategory::Smash), count(AttackCategory::DashAttack), count(AttackCategory::Jab)), (2, 1, 0));
+    }
+}
@@ -0,0 +1,1839 @@
+// cli.rs
+// コマンドライン引数解析モジュール
+//
+// 引数の文字列リストをCommand（サブコマンド＋設定）に変換する
+// オプションはOPTIONSテーブルで定義し、解析とシェル補完の両方で使う
+
+use std::fs;
+use std::io;
+
+use crate::analyzer::AnalysisOptions;
+use crate::completion::Shell;
+use crate::convert::ConvertFormat;
+use crate::error::{Result, SmasherError};
+use crate::generate::GenerateOptions;
+use crate::ipc::IpcMode;
+use crate::migrate::MigrateOptions;
+use crate::model::{BattleLog, DupPolicy, Lang, MatchOrder, Normalize, SingleAttackMode, WeightShape};
+use crate::output::Verbosity;
+use crate::parser::{self, ReadOptions, RowOrder, STDIN_PATH};
+use crate::schema::SCHEMA_VERSION;
+use crate::sniff::Encoding;
+use crate::style::ColorChoice;
+
+/// オプションが取る値の種類
+#[derive(Debug, Clone, Copy)]
+pub enum ValueKind {
+    /// 値を取らないフラグ
+    Flag,
+    /// 決められた候補から選ぶ値
+    Choice(&'static [&'static str]),
+    /// 自由入力の値（表示用のプレースホルダ名）
+    Free(&'static str),
+    /// ファイルパスを取る値
+    Path,
+}
+
+/// 1つのオプションの定義
+#[derive(Debug, Clone, Copy)]
+pub struct OptionSpec {
+    /// オプション名（--付き）
+    pub name: &'static str,
+    /// 1文字の短い形式（-なし。ない場合はNone）
+    pub short: Option<char>,
+    /// 値の種類
+    pub value: ValueKind,
+    /// 1行の説明
+    pub description: &'static str,
+}
+
+/// 使えるオプションの一覧
+/// 新しいオプションはここに追加すると、解析とシェル補完の両方に反映される
+pub const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "--weight",
+        short: None,
+        value: ValueKind::Choice(&["linear", "exp"]),
+        description: "時間重み付き比率の重みの形",
+    },
+    OptionSpec {
+        name: "--single-attack",
+        short: None,
+        value: ValueKind::Choice(&["zero", "next"]),
+        description: "攻撃継続時間で単発の攻撃を0秒にするか次の行動までにするか",
+    },
+    OptionSpec {
+        name: "--shift-time",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "すべてのタイムスタンプに足す秒数（負の値で引く）",
+    },
+    OptionSpec {
+        name: "--snap",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "すべてのタイムスタンプをこの秒数刻みの最も近い時刻に丸める（例: 0.1。同じ時刻になった行動も残す）",
+    },
+    OptionSpec {
+        name: "--sort",
+        short: None,
+        value: ValueKind::Flag,
+        description: "タイムスタンプが前の行より小さい行をエラーにせず、時刻順に並べ替えて読み込む",
+    },
+    OptionSpec {
+        name: "--lenient",
+        short: None,
+        value: ValueKind::Flag,
+        description: "解析できない行動データの行をエラーにせず読み飛ばし、飛ばした行を知らせる",
+    },
+    OptionSpec {
+        name: "--strict",
+        short: None,
+        value: ValueKind::Flag,
+        description: "解析できない行動データの行をエラーにする（既定。環境変数 SMASHER_STRICT=0 より優先する）",
+    },
+    OptionSpec {
+        name: "--encoding",
+        short: None,
+        value: ValueKind::Choice(Encoding::NAMES),
+        description: "入力CSVの文字コード（デフォルトauto: BOMで判定。utf16: BOMのないUTF-16も変換して読む）",
+    },
+    OptionSpec {
+        name: "--debounce",
+        short: None,
+        value: ValueKind::Free("ミリ秒"),
+        description: "直前の行と同じ行動IDがこのミリ秒数より短い間隔で続いた行を、入力のノイズとして取り除く",
+    },
+    OptionSpec {
+        name: "--follow",
+        short: None,
+        value: ValueKind::Flag,
+        description: "入力を読み続けて集計を逐次表示する（Ctrl-Cで終了）",
+    },
+    OptionSpec {
+        name: "--snapshot-interval",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "--follow時、この秒数ごとに途中の解析結果をJSONファイルに保存する",
+    },
+    OptionSpec {
+        name: "--snapshot-dir",
+        short: None,
+        value: ValueKind::Path,
+        description: "--snapshot-interval の保存先ディレクトリ（デフォルトはカレントディレクトリ）",
+    },
+    OptionSpec {
+        name: "--watch",
+        short: None,
+        value: ValueKind::Flag,
+        description: "ファイルが更新されるたびに解析し直して表示する（Ctrl-Cで終了）",
+    },
+    OptionSpec {
+        name: "--refresh",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "--follow時に集計を再表示する間隔（秒）",
+    },
+    OptionSpec {
+        name: "--verbose",
+        short: Some('v'),
+        value: ValueKind::Flag,
+        description: "出力を詳しくする（-vvで全情報）",
+    },
+    OptionSpec {
+        name: "--quiet",
+        short: Some('q'),
+        value: ValueKind::Flag,
+        description: "サマリ1行だけを出力する",
+    },
+    OptionSpec {
+        name: "--combo-gap",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "リピート率で、直前の行動からこの秒数より間が空いた行動は比べない（デフォルト0.4）",
+    },
+    OptionSpec {
+        name: "--combo-window",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "この秒数以内に続いた攻撃をコンボとみなす（デフォルト1.0）",
+    },
+    OptionSpec {
+        name: "--tension-window",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "緊張度カーブで攻防の切り替えを数える時間窓の長さ（デフォルト10）",
+    },
+    OptionSpec {
+        name: "--cover-window",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "攻撃の後この秒数以内にシールドしたら「すぐに守れた」とみなす（デフォルト1）",
+    },
+    OptionSpec {
+        name: "--transition-gap",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "行動遷移で、この秒数より間が空いた組は数えない（デフォルト3）",
+    },
+    OptionSpec {
+        name: "--burst-gap",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "この秒数未満の間隔で続いた行動を1つの連続とみなす（バーストの判定、デフォルト0.8）",
+    },
+    OptionSpec {
+        name: "--burst-min",
+        short: None,
+        value: ValueKind::Free("個"),
+        description: "この個数以上続いた連続をバーストとみなす（2以上、デフォルト3）",
+    },
+    OptionSpec {
+        name: "--peak-threshold",
+        short: None,
+        value: ValueKind::Free("回/秒"),
+        description: "行動密度のピークとして表示する最小の密度（小さな山を除外する）",
+    },
+    OptionSpec {
+        name: "--time-phases",
+        short: None,
+        value: ValueKind::Free("区間数"),
+        description: "試合時間を等分して区間ごとの行動比率を表示するときの区間数（デフォルト3: 序盤・中盤・終盤）",
+    },
+    OptionSpec {
+        name: "--spike-sigma",
+        short: None,
+        value: ValueKind::Free("倍率"),
+        description: "秒ごとの行動数が平均+この倍率×標準偏差を超えた秒をスパイクとして報告する（デフォルト3）",
+    },
+    OptionSpec {
+        name: "--sort-categories",
+        short: None,
+        value: ValueKind::Flag,
+        description: "行動回数を攻撃/シールド/回避の固定順ではなく回数の多い順に表示する",
+    },
+    OptionSpec {
+        name: "--sparklines",
+        short: None,
+        value: ValueKind::Flag,
+        description: "行動IDごとの回数に、試合のどの時間帯で使ったかのスパークラインを付ける",
+    },
+    OptionSpec {
+        name: "--interval-bin",
+        short: None,
+        value: ValueKind::Free("秒"),
+        description: "行動間隔をこの秒数ごとのビンに分けたヒストグラムを表示する（長い外れ値は最後のビンにまとめる）",
+    },
+    OptionSpec {
+        name: "--momentum",
+        short: None,
+        value: ValueKind::Flag,
+        description: "行動回数に、前半から後半で使用率が増えたか（↑）減ったか（↓）変わらないか（→）の矢印を付ける",
+    },
+    OptionSpec {
+        name: "--new-since",
+        short: None,
+        value: ValueKind::Path,
+        description: "指定した以前のログと比べて、追記された行動だけを表示する",
+    },
+    OptionSpec {
+        name: "--compare-actions",
+        short: None,
+        value: ValueKind::Flag,
+        description: "2つのCSVで行動IDごとの使用頻度に差があるかを簡易的なz検定で比べる",
+    },
+    OptionSpec {
+        name: "--normalize",
+        short: None,
+        value: ValueKind::Choice(&["raw", "per-minute"]),
+        description: "--compare-actions で回数をどう比べるか（raw: 回数のまま、per-minute: 試合時間で割った1分あたりの回数も出す）",
+    },
+    OptionSpec {
+        name: "--compare-last",
+        short: None,
+        value: ValueKind::Flag,
+        description: "同じファイルを前回解析したときからの行動比率の差を表示する（今回の結果は次回用に保存する）",
+    },
+    OptionSpec {
+        name: "--improvement",
+        short: None,
+        value: ValueKind::Flag,
+        description: "同じ学生の複数のCSVを回次順に並べ、指標の推移（改善レポート）を表示する",
+    },
+    OptionSpec {
+        name: "--order",
+        short: None,
+        value: ValueKind::Choice(&["match", "mtime"]),
+        description: "--improvement で試合を並べる順（match: 対戦回次、mtime: ファイルの更新日時。傾きは1日あたり）",
+    },
+    OptionSpec {
+        name: "--cluster",
+        short: None,
+        value: ValueKind::Free("K"),
+        description: "複数のCSVを、行動IDの使い方が似た試合同士のK個のグループに分ける（簡易k-means）",
+    },
+    OptionSpec {
+        name: "--dup-policy",
+        short: None,
+        value: ValueKind::Choice(DupPolicy::NAMES),
+        description: "複数のファイルを合算するとき、同じ学籍番号・対戦回次のログが複数あったときの扱い（デフォルトlargest: 行動数の多いほう）",
+    },
+    OptionSpec {
+        name: "--jobs",
+        short: Some('j'),
+        value: ValueKind::Free("N"),
+        description: "複数のファイルをN個のスレッドで並列に読み込んで合算する（ファイルごとの結果は表示せず、合算とランキングだけを出す）",
+    },
+    OptionSpec {
+        name: "--top",
+        short: None,
+        value: ValueKind::Free("N"),
+        description: "ランキング（--jobs）に表示する学籍番号の数（デフォルト10。環境変数 SMASHER_TOP でも指定できる）",
+    },
+    OptionSpec {
+        name: "--convert",
+        short: None,
+        value: ValueKind::Choice(&["json"]),
+        description: "解析せずに入力CSVを指定の形式に変換する（--out-dir に書き出す）",
+    },
+    OptionSpec {
+        name: "--output",
+        short: Some('o'),
+        value: ValueKind::Path,
+        description: "レポートだけをファイルに書く（途中経過は端末に出す。ファイルがあれば上書きする）",
+    },
+    OptionSpec {
+        name: "--append",
+        short: None,
+        value: ValueKind::Flag,
+        description: "--output のファイルを上書きせず、末尾に追記する",
+    },
+    OptionSpec {
+        name: "--out-csv",
+        short: None,
+        value: ValueKind::Path,
+        description: "レポートとは別に、1試合1行の集計CSV（英語の列名）をファイルに書く",
+    },
+    OptionSpec {
+        name: "--out-dir",
+        short: None,
+        value: ValueKind::Path,
+        description: "--convert の出力ディレクトリ",
+    },
+    OptionSpec {
+        name: "--overwrite",
+        short: None,
+        value: ValueKind::Flag,
+        description: "--convert で出力先に同名のファイルがあれば上書きする（指定しなければ飛ばす）",
+    },
+    OptionSpec {
+        name: "--groups",
+        short: None,
+        value: ValueKind::Path,
+        description: "学籍番号→グループ名の対応ファイル。複数のCSVをグループ別に集計する",
+    },
+    OptionSpec {
+        name: "--similarity",
+        short: None,
+        value: ValueKind::Flag,
+        description: "2つのCSVの行動IDの使い方がどれくらい似ているかを、コサイン類似度（0〜1）で表示する",
+    },
+    OptionSpec {
+        name: "--narrate",
+        short: None,
+        value: ValueKind::Flag,
+        description: "記号や罫線を使わず、読み上げやすい文章で結果を出力する",
+    },
+    OptionSpec {
+        name: "--ipc",
+        short: None,
+        value: ValueKind::Path,
+        description: "解析結果のJSONをこのUnixソケットに送る",
+    },
+    OptionSpec {
+        name: "--ipc-mode",
+        short: None,
+        value: ValueKind::Choice(&["skip", "wait"]),
+        description: "--ipc の送り先が待ち受けていないとき、送らずに続けるか接続できるまで待つか",
+    },
+    OptionSpec {
+        name: "--lang",
+        short: None,
+        value: ValueKind::Choice(Lang::NAMES),
+        description: "テキストのレポートで行動名を表示する言語（ja: 日本語、en: 英語、ja+en: 併記。JSON・CSVの名前は日本語のまま。環境変数 SMASHER_LANG でも指定できる）",
+    },
+    OptionSpec {
+        name: "--colors",
+        short: None,
+        value: ValueKind::Path,
+        description: "行動IDごとの色を定義したルールファイル（1行に「ID,色」）",
+    },
+    OptionSpec {
+        name: "--color",
+        short: None,
+        value: ValueKind::Choice(&["auto", "always", "never"]),
+        description: "色を付けるか（auto: 端末のときだけ、always: パイプでも付ける、never: 付けない）",
+    },
+    OptionSpec {
+        name: "--width",
+        short: None,
+        value: ValueKind::Free("桁数"),
+        description: "出力をこの桁数に収める（レシート幅の小さな画面向け。見出しは省略、表は縦並びになる）",
+    },
+    OptionSpec {
+        name: "--no-color",
+        short: None,
+        value: ValueKind::Flag,
+        description: "色を付けずに出力する",
+    },
+    OptionSpec {
+        name: "--json",
+        short: None,
+        value: ValueKind::Flag,
+        description: "結果をJSONで出力する（解析結果・explain・--improvement・--compare-actions）",
+    },
+    OptionSpec {
+        name: "--format",
+        short: None,
+        value: ValueKind::Choice(&["text", "json", "csv"]),
+        description: "結果の出力形式（text: 日本語のレポート、json: --json と同じ、csv: 1試合1行の統計CSV。環境変数 SMASHER_FORMAT でも指定できる）",
+    },
+    OptionSpec {
+        name: "--excel",
+        short: None,
+        value: ValueKind::Flag,
+        description: "統計CSVをExcelで文字化けしない形（BOM付きUTF-8、改行CRLF、文字列はすべてダブルクォートで囲む）で出力する（--format csv を含む）",
+    },
+    OptionSpec {
+        name: "--generate",
+        short: None,
+        value: ValueKind::Flag,
+        description: "ランダムな対戦ログのCSVを標準出力に生成する",
+    },
+    OptionSpec {
+        name: "--actions",
+        short: None,
+        value: ValueKind::Free("回数"),
+        description: "--generate で生成する行動の数",
+    },
+    OptionSpec {
+        name: "--seed",
+        short: None,
+        value: ValueKind::Free("シード"),
+        description: "--generate の乱数のシード（同じシードなら同じCSVになる）",
+    },
+    OptionSpec {
+        name: "--ratio",
+        short: None,
+        value: ValueKind::Free("攻撃:シールド:回避"),
+        description: "--generate で生成するカテゴリの比率（例: 6:2:2）",
+    },
+    OptionSpec {
+        name: "--self-check",
+        short: None,
+        value: ValueKind::Flag,
+        description: "行動分類テーブルとfrom_action_idの整合性を診断する（開発者向け）",
+    },
+    OptionSpec {
+        name: "--print-schema",
+        short: None,
+        value: ValueKind::Flag,
+        description: "--json の出力構造をJSON Schemaで表示する（連携するツール向け）",
+    },
+    OptionSpec {
+        name: "--list-formats",
+        short: None,
+        value: ValueKind::Flag,
+        description: "--format に指定できる形式を説明付きで一覧表示する（--json ならJSONで出す）",
+    },
+    OptionSpec {
+        name: "--list-sections",
+        short: None,
+        value: ValueKind::Flag,
+        description: "--json の出力の一番上のキー（セクション）を説明付きで一覧表示する（--json ならJSONで出す）",
+    },
+];
+
+/// --format に指定できる形式の説明（--list-formats）
+///
+/// 形式の名前は OPTIONS の --format の選択肢が元になる。選択肢を増やしたら、ここにも説明を足す
+pub fn format_description(name: &str) -> &'static str {
+    match name {
+        "text" => "読みやすいレポート（既定。--lang で言語を選べる）",
+        "json" => "解析結果を1行のJSONで出す（--json と同じ。構造は --print-schema で確認できる）",
+        "csv" => "1試合1行の統計CSV（--excel ならExcel向け）",
+        _ => "",
+    }
+}
+
+/// --format に指定できる形式と説明の一覧（OPTIONS の --format の選択肢の順）
+pub fn formats() -> Vec<(&'static str, &'static str)> {
+    match find_option("--format").map(|spec| spec.value) {
+        Ok(ValueKind::Choice(names)) => names.iter().map(|&name| (name, format_description(name))).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// --width に指定できる最小の桁数（これより狭いと見出しすら読めなくなる）
+const MIN_WIDTH: usize = 20;
+
+/// 色を付けない設定を読む環境変数（--no-color と同じ意味）
+const ENV_NO_COLOR: &str = "SMASHER_NO_COLOR";
+
+/// 出力形式のデフォルトを読む環境変数（--format と同じ値）
+const ENV_FORMAT: &str = "SMASHER_FORMAT";
+
+/// 行動名の言語のデフォルトを読む環境変数（--lang と同じ値）
+const ENV_LANG: &str = "SMASHER_LANG";
+
+/// ランキングに表示する数のデフォルトを読む環境変数（--top と同じ値）
+const ENV_TOP: &str = "SMASHER_TOP";
+
+/// 解析できない行をエラーにするかを読む環境変数（0/false/no なら --lenient と同じ）
+const ENV_STRICT: &str = "SMASHER_STRICT";
+
+/// ランキングに表示する学籍番号の数のデフォルト（--top）
+pub const DEFAULT_TOP: usize = 10;
+
+/// 環境変数を書き換えるテストが、同時に動く他のテストの引数解析を乱さないようにするロック
+#[cfg(test)]
+static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// SMASHER_* の環境変数を `vars` だけにした状態で `f` を呼び、終わったら元の値に戻す（テスト用）
+///
+/// 環境変数はプロセスに1つしかないので、parse_args を呼ぶテストはこの中で動かす。
+/// 開発者のシェルで設定されている SMASHER_* も、呼んでいる間だけ取り除く
+#[cfg(test)]
+pub fn with_env<T>(vars: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+    let _lock = ENV_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    let names = [ENV_FORMAT, ENV_LANG, ENV_TOP, ENV_STRICT, ENV_NO_COLOR];
+    let saved: Vec<(&str, Option<std::ffi::OsString>)> = names.iter().map(|&name| (name, std::env::var_os(name))).collect();
+    for name in names {
+        std::env::remove_var(name);
+    }
+    for (name, value) in vars {
+        std::env::set_var(name, value);
+    }
+    let result = f();
+    for (name, value) in saved {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+    result
+}
+
+/// --follow時の再表示間隔のデフォルト（秒）
+const DEFAULT_REFRESH_SECS: f64 = 2.0;
+
+/// サブコマンドの一覧（名前, 説明）
+pub const SUBCOMMANDS: &[(&str, &str)] = &[
+    ("completions", "シェル補完スクリプトを出力する"),
+    ("explain", "CSVの1行を解析して分かることをすべて表示する"),
+    ("context", "指定した時刻の前後の行動を表示する"),
+    ("migrate", "以前の --json の出力を新しいスキーマのバージョンに書き直す"),
+];
+
+/// contextで前後それぞれに表示する件数のデフォルト
+const DEFAULT_CONTEXT_COUNT: usize = 5;
+
+/// 実行するコマンド
+#[derive(Debug, Clone)]
+pub enum Command {
+    /// CSVファイルを解析して結果を表示する（通常の動作）
+    /// Optionsは他の値よりずっと大きいので、Boxに入れてCommand自体を小さく保つ
+    Analyze(Box<Options>),
+    /// シェル補完スクリプトを出力する
+    Completions(Shell),
+    /// CSVの1行だけを解析して説明する
+    Explain {
+        /// 説明する行
+        line: String,
+        /// 行番号（--line。エラーメッセージと表示に使う）
+        line_number: usize,
+        /// JSONで出力するか
+        json: bool,
+    },
+    /// 指定した時刻の前後の行動を表示する
+    Context {
+        /// 入力CSVファイルのパス
+        file_path: String,
+        /// 基準の時刻（秒）
+        at: f64,
+        /// 前後それぞれに表示する件数
+        n: usize,
+        /// 元の行の文字列も表示するか（--keep-raw）
+        keep_raw: bool,
+        /// JSONで出力するか
+        json: bool,
+    },
+    /// 以前の --json の出力を新しいスキーマのバージョンに書き直す
+    Migrate(MigrateOptions),
+    /// 行動分類テーブルの整合性を自己診断する
+    SelfCheck,
+    /// JSON出力のスキーマを表示する
+    PrintSchema,
+    /// --format に指定できる形式を一覧表示する
+    ListFormats {
+        /// JSONで出力するか
+        json: bool,
+    },
+    /// JSON出力の一番上のキー（セクション）を一覧表示する
+    ListSections {
+        /// JSONで出力するか
+        json: bool,
+    },
+    /// ランダムな対戦ログのCSVを生成する
+    Generate(GenerateOptions),
+}
+
+/// 解析時にコマンドラインで指定された設定
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// 入力CSVファイルのパス（--groups・--similarity のときだけ複数指定できる）
+    pub file_paths: Vec<String>,
+    /// 解析の設定
+    pub analysis: AnalysisOptions,
+    /// タイムスタンプに足す秒数（--shift-time）
+    pub shift_secs: f64,
+    /// タイムスタンプを丸める刻み（秒、--snap。指定しなければ丸めない）
+    pub snap_secs: Option<f64>,
+    /// タイムスタンプが前の行より小さい行の扱い（--sort）
+    pub row_order: RowOrder,
+    /// 入力CSVの文字コード（--encoding）
+    pub encoding: Encoding,
+    /// 解析できない行動データの行を読み飛ばすか（--lenient・--strict・SMASHER_STRICT）
+    pub lenient: bool,
+    /// 同じ行動IDが続いた行を取り除く間隔の上限（ミリ秒、--debounce）
+    pub debounce_ms: Option<f64>,
+    /// 入力を読み続けて逐次表示するか（--follow）
+    pub follow: bool,
+    /// --follow時の再表示間隔（秒）
+    pub refresh_secs: f64,
+    /// --follow時に途中の解析結果を保存する間隔（秒、--snapshot-interval）
+    pub snapshot_interval: Option<f64>,
+    /// スナップショットの保存先ディレクトリ（--snapshot-dir）
+    pub snapshot_dir: String,
+    /// ファイルの更新を監視して解析し直すか（--watch）
+    pub watch: bool,
+    /// 出力の詳細度
+    pub verbosity: Verbosity,
+    /// 行動回数のカテゴリを回数順に並べるか（--sort-categories）
+    pub sort_categories: bool,
+    /// 文章で出力するか（--narrate）
+    pub narrate: bool,
+    /// 比較する以前のログのパス（--new-since）
+    pub new_since: Option<String>,
+    /// グループ割り当てファイルのパス（--groups）
+    pub groups: Option<String>,
+    /// 試合を分けるクラスタの数（--cluster）
+    pub cluster: Option<usize>,
+    /// 2つのログの行動IDの使い方の類似度を出すか（--similarity）
+    pub similarity: bool,
+    /// 複数の試合から改善レポートを作るか（--improvement）
+    pub improvement: bool,
+    /// 改善レポートで試合を並べる順（--order）
+    pub order: MatchOrder,
+    /// 合算するとき同じ試合のログが複数あったときの扱い（--dup-policy）
+    pub dup_policy: DupPolicy,
+    /// 並列に読み込むスレッドの数（--jobs）。Noneなら1つずつ読んで、ファイルごとの結果も表示する
+    pub jobs: Option<usize>,
+    /// ランキングに表示する学籍番号の数（--top・SMASHER_TOP）
+    pub top: usize,
+    /// 2つのログの行動の使用頻度を比べるか（--compare-actions）
+    pub compare_actions: bool,
+    /// --compare-actions で1分あたりの回数に直して比べるか（--normalize）
+    pub normalize: Normalize,
+    /// 同じファイルの前回の解析結果と比べるか（--compare-last）
+    pub compare_last: bool,
+    /// 解析結果（または改善レポート、合算結果）をJSONで出力するか（--json、--format json）
+    pub json: bool,
+    /// 1試合1行の統計CSVで出力するか（--format csv、--excel）
+    pub csv: bool,
+    /// 統計CSVをExcel向け（BOM付き、CRLF、文字列をすべてクォート）にするか（--excel）
+    pub excel: bool,
+    /// 解析せずに変換する形式（--convert）
+    pub convert: Option<ConvertFormat>,
+    /// 変換したファイルの出力ディレクトリ（--out-dir）
+    pub out_dir: Option<String>,
+    /// レポートを書くファイルのパス（--output）。Noneなら標準出力
+    pub output: Option<String>,
+    /// --output のファイルに追記するか（--append）
+    pub append: bool,
+    /// 1試合1行の集計CSVを書くファイルのパス（--out-csv）
+    pub out_csv: Option<String>,
+    /// 変換で既存のファイルを上書きするか（--overwrite）
+    pub overwrite: bool,
+    /// 結果のJSONを送るUnixソケットのパス（--ipc）
+    pub ipc: Option<String>,
+    /// 送り先が待ち受けていないときの動作（--ipc-mode）
+    pub ipc_mode: IpcMode,
+    /// 行動名を表示する言語（--lang）
+    pub lang: Lang,
+    /// 色ルールファイルのパス（--colors）
+    pub color_rules: Option<String>,
+    /// 色を付けるかどうかの指定（--color。--no-color と SMASHER_NO_COLOR は Never になる）
+    pub color: ColorChoice,
+    /// 出力の最大桁数（--width）。None なら幅を制限しない
+    pub width: Option<usize>,
+}
+
+impl Options {
+    /// CSVファイルから対戦ログを読み込む（--sort、--encoding、--lenient に従う）
+    /// 
+    /// ファイルパスが「-」なら標準入力から読む
+    pub fn read(&self, file_path: &str) -> Result<BattleLog> {
+        let read_options = ReadOptions {
+            order: self.row_order,
+            encoding: self.encoding,
+            keep_raw: false,
+            lenient: self.lenient,
+        };
+        if file_path == STDIN_PATH {
+            return parser::read_battle_log_from_reader(io::stdin().lock(), &read_options);
+        }
+        parser::read_battle_log_as(file_path, &read_options)
+    }
+
+    /// 読み込んだログを、解析する前にオプションに合わせて整える
+    /// 
+    /// --debounce で同じ行動IDの連続を取り除き、--shift-time で時刻をずらし、--snap で時刻を丸める
+    /// 
+    /// --debounce はミリ秒単位の間隔を見るので丸める前に行い、
+    /// 丸めた時刻がちょうど刻みの上に来るように、ずらしたあとで丸める
+    pub fn prepare(&self, battle_log: &mut BattleLog) {
+        if let Some(threshold_ms) = self.debounce_ms {
+            battle_log.debounce(threshold_ms);
+        }
+        battle_log.shift_time(self.shift_secs);
+        if let Some(grid) = self.snap_secs {
+            battle_log.snap_time(grid);
+        }
+    }
+}
+
+/// --time-phases で指定できる最大の区間数
+const MAX_TIME_PHASES: usize = 100;
+
+/// コマンドライン引数を解析する
+///
+/// # 引数
+/// * `args` - プログラム名を除いた引数リスト
+///
+/// # 戻り値
+/// 実行するCommand、またはエラー
+pub fn parse_args(args: &[String]) -> Result<Command> {
+    match args.first().map(|s| s.as_str()) {
+        Some("completions") => parse_completions(&args[1..]),
+        Some("explain") => parse_explain(&args[1..]),
+        Some("context") => parse_context(&args[1..]),
+        Some("migrate") => parse_migrate(&args[1..]),
+        _ => parse_analyze(args),
+    }
+}
+
+/// `completions <shell>` を解析する
+fn parse_completions(args: &[String]) -> Result<Command> {
+    let names = Shell::NAMES.join("|");
+    match args {
+        [shell] => Shell::from_name(shell).map(Command::Completions).ok_or_else(|| {
+            SmasherError::InvalidArgument(format!(
+                "対応していないシェルです: {}（{} のいずれか）",
+                shell, names
+            ))
+        }),
+        _ => Err(SmasherError::InvalidArgument(format!(
+            "使い方: smasher completions {}",
+            names
+        ))),
+    }
+}
+
+/// `explain <行> [--line <行番号>] [--json]` を解析する
+fn parse_explain(args: &[String]) -> Result<Command> {
+    let usage = || {
+        SmasherError::InvalidArgument("使い方: smasher explain \"104.2,ub_a\" [--line 12] [--json]".to_string())
+    };
+
+    let mut line: Option<String> = None;
+    // context などで見つけた行を貼り付けたときに、ファイルでの行番号をそのまま表示できるようにする
+    let mut line_number = 1;
+    let mut json = false;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--line" => {
+                let value = iter.next().ok_or_else(usage)?;
+                line_number = value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    SmasherError::InvalidArgument(format!("--line には1以上の整数を指定してください。実際: {}", value))
+                })?;
+            }
+            _ if line.is_none() => line = Some(arg.clone()),
+            _ => {
+                return Err(SmasherError::InvalidArgument(format!(
+                    "explain に渡せる行は1つだけです: {}",
+                    arg
+                )));
+            }
+        }
+    }
+
+    let line = line.ok_or_else(usage)?;
+    Ok(Command::Explain { line, line_number, json })
+}
+
+/// `context <ファイル> --at <秒> [--n <件数>] [--keep-raw] [--json]` を解析する
+fn parse_context(args: &[String]) -> Result<Command> {
+    let usage = || {
+        SmasherError::InvalidArgument(
+            "使い方: smasher context file.csv --at 84.2 [--n 5] [--keep-raw] [--json]".to_string(),
+        )
+    };
+
+    let mut file_path: Option<String> = None;
+    let mut at: Option<f64> = None;
+    let mut n = DEFAULT_CONTEXT_COUNT;
+    let mut keep_raw = false;
+    let mut json = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--at" => {
+                let value = iter.next().ok_or_else(usage)?;
+                at = Some(value.parse::<f64>().ok().filter(|t| t.is_finite()).ok_or_else(|| {
+                    SmasherError::InvalidArgument(format!("--at には数値を指定してください。実際: {}", value))
+                })?);
+            }
+            "--n" => {
+                let value = iter.next().ok_or_else(usage)?;
+                n = value.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    SmasherError::InvalidArgument(format!("--n には1以上の整数を指定してください。実際: {}", value))
+                })?;
+            }
+            "--keep-raw" => keep_raw = true,
+            "--json" => json = true,
+            _ if !arg.starts_with('-') && file_path.is_none() => file_path = Some(arg.clone()),
+            _ => {
+                return Err(SmasherError::InvalidArgument(format!(
+                    "context では使えない引数です: {}",
+                    arg
+                )));
+            }
+        }
+    }
+
+    match (file_path, at) {
+        (Some(file_path), Some(at)) => Ok(Command::Context { file_path, at, n, keep_raw, json }),
+        _ => Err(usage()),
+    }
+}
+
+/// `migrate <ディレクトリ> [--to-version <N>] [--output-dir <ディレクトリ>]` を解析する
+fn parse_migrate(args: &[String]) -> Result<Command> {
+    let usage = || {
+        SmasherError::InvalidArgument(
+            "使い方: smasher migrate exports/ [--to-version 11] [--output-dir migrated/]".to_string(),
+        )
+    };
+
+    let mut dir: Option<String> = None;
+    // 指定がなければ今のバージョンまで書き直す
+    let mut to_version = SCHEMA_VERSION;
+    let mut output_dir: Option<String> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--to-version" => {
+                let value = iter.next().ok_or_else(usage)?;
+                to_version = value
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|version| (1..=SCHEMA_VERSION).contains(version))
+                    .ok_or_else(|| {
+                        SmasherError::InvalidArgument(format!(
+                            "--to-version には1から{}までの整数を指定してください。実際: {}",
+                            SCHEMA_VERSION, value
+                        ))
+                    })?;
+            }
+            "--output-dir" => output_dir = Some(iter.next().ok_or_else(usage)?.clone()),
+            _ if !arg.starts_with('-') && dir.is_none() => dir = Some(arg.clone()),
+            _ => {
+                return Err(SmasherError::InvalidArgument(format!(
+                    "migrate では使えない引数です: {}",
+                    arg
+                )));
+            }
+        }
+    }
+
+    let dir = dir.ok_or_else(usage)?;
+    Ok(Command::Migrate(MigrateOptions { dir, to_version, output_dir }))
+}
+
+/// 通常の解析モードの引数を解析する
+fn parse_analyze(args: &[String]) -> Result<Command> {
+    let mut file_paths: Vec<String> = Vec::new();
+    let mut self_check = false;
+    let mut print_schema = false;
+    let mut list_formats = false;
+    let mut list_sections = false;
+    let mut analysis = AnalysisOptions::new();
+    let mut shift_secs = 0.0;
+    let mut snap_secs = None;
+    let mut row_order = RowOrder::Strict;
+    let mut lenient = false;
+    let mut strict = false;
+    let mut encoding = Encoding::Auto;
+    let mut debounce_ms = None;
+    let mut follow = false;
+    let mut refresh_secs = DEFAULT_REFRESH_SECS;
+    let mut watch = false;
+    let mut snapshot_interval: Option<f64> = None;
+    let mut snapshot_dir: Option<String> = None;
+    let mut verbose_level = 0;
+    let mut quiet = false;
+    let mut sort_categories = false;
+    let mut narrate = false;
+    let mut new_since: Option<String> = None;
+    let mut groups: Option<String> = None;
+    let mut cluster: Option<usize> = None;
+    let mut similarity = false;
+    let mut improvement = false;
+    let mut compare_actions = false;
+    let mut compare_last = false;
+    let mut convert: Option<ConvertFormat> = None;
+    let mut out_dir: Option<String> = None;
+    let mut output: Option<String> = None;
+    let mut append = false;
+    let mut out_csv: Option<String> = None;
+    let mut overwrite = false;
+    let mut json = false;
+    let mut csv = false;
+    let mut excel = false;
+    let mut order: Option<MatchOrder> = None;
+    let mut normalize: Option<Normalize> = None;
+    let mut dup_policy: Option<DupPolicy> = None;
+    let mut jobs: Option<usize> = None;
+    let mut top: Option<usize> = None;
+    let mut format: Option<String> = None;
+    let mut ipc: Option<String> = None;
+    let mut ipc_mode = IpcMode::Skip;
+    let mut lang: Option<Lang> = None;
+    let mut color_rules: Option<String> = None;
+    let mut no_color = false;
+    let mut color: Option<ColorChoice> = None;
+    let mut width: Option<usize> = None;
+    let mut generate = false;
+    let mut generate_options = GenerateOptions::new();
+    // --generate用のオプションが指定されたか（--generateなしで使われたらエラーにする）
+    let mut generate_only: Option<&str> = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        // 「-」だけは標準入力から読むファイルパスとして扱う
+        if !arg.starts_with('-') || arg == STDIN_PATH {
+            file_paths.push(arg.clone());
+            continue;
+        }
+
+        // 短い形式は -vv のように1つの引数にまとめて書ける
+        let specs: Vec<&OptionSpec> = if arg.starts_with("--") {
+            vec![find_option(arg)?]
+        } else {
+            arg[1..].chars().map(find_short_option).collect::<Result<_>>()?
+        };
+
+        for spec in specs {
+            let value = match spec.value {
+                ValueKind::Flag => None,
+                _ => Some(next_value(&mut iter, spec)?),
+            };
+
+            // テーブルにあるオプションは、ここで設定に反映する
+            match (spec.name, value) {
+                ("--weight", Some(v)) => {
+                    // 候補はnext_valueで検証済み
+                    analysis.weight_shape = WeightShape::from_name(v).unwrap_or_default();
+                }
+                ("--single-attack", Some(v)) => {
+                    analysis.single_attack = SingleAttackMode::from_name(v).unwrap_or_default();
+                }
+                ("--combo-gap", Some(v)) => analysis.combo_gap = parse_positive(spec, v)?,
+                ("--combo-window", Some(v)) => analysis.combo_window = parse_positive(spec, v)?,
+                ("--tension-window", Some(v)) => analysis.tension_window = parse_positive(spec, v)?,
+                ("--cover-window", Some(v)) => analysis.cover_window = parse_positive(spec, v)?,
+                ("--transition-gap", Some(v)) => analysis.transition_gap = parse_positive(spec, v)?,
+                ("--burst-gap", Some(v)) => analysis.burst_gap = parse_positive(spec, v)?,
+                ("--burst-min", Some(v)) => analysis.burst_min_len = parse_count(spec, v)?,
+                ("--peak-threshold", Some(v)) => analysis.peak_threshold = parse_positive(spec, v)?,
+                ("--spike-sigma", Some(v)) => analysis.spike_sigma = parse_positive(spec, v)?,
+                ("--time-phases", Some(v)) => analysis.time_phases = parse_count(spec, v)?,
+                ("--sort-categories", None) => sort_categories = true,
+                ("--sparklines", None) => analysis.sparklines = true,
+                ("--momentum", None) => analysis.momentum = true,
+                ("--interval-bin", Some(v)) => analysis.interval_bin = Some(parse_positive(spec, v)?),
+                ("--shift-time", Some(v)) => shift_secs = parse_number(spec, v)?,
+                ("--snap", Some(v)) => snap_secs = Some(parse_positive(spec, v)?),
+                ("--sort", None) => row_order = RowOrder::Sort,
+                ("--lenient", None) => lenient = true,
+                ("--strict", None) => strict = true,
+                ("--encoding", Some(v)) => encoding = Encoding::from_name(v).unwrap_or_default(),
+                ("--debounce", Some(v)) => debounce_ms = Some(parse_positive(spec, v)?),
+                ("--follow", None) => follow = true,
+                ("--watch", None) => watch = true,
+                ("--snapshot-interval", Some(v)) => snapshot_interval = Some(parse_positive(spec, v)?),
+                ("--snapshot-dir", Some(v)) => snapshot_dir = Some(v.to_string()),
+                ("--refresh", Some(v)) => refresh_secs = parse_positive(spec, v)?,
+                ("--verbose", None) => verbose_level += 1,
+                ("--quiet", None) => quiet = true,
+                ("--new-since", Some(v)) => new_since = Some(v.to_string()),
+                ("--groups", Some(v)) => groups = Some(v.to_string()),
+                ("--cluster", Some(v)) => cluster = Some(parse_count(spec, v)?),
+                ("--similarity", None) => similarity = true,
+                ("--improvement", None) => improvement = true,
+                ("--compare-actions", None) => compare_actions = true,
+                ("--compare-last", None) => compare_last = true,
+                ("--convert", Some(v)) => convert = ConvertFormat::from_name(v),
+                ("--out-dir", Some(v)) => out_dir = Some(v.to_string()),
+                ("--output", Some(v)) => output = Some(v.to_string()),
+                ("--append", None) => append = true,
+                ("--out-csv", Some(v)) => out_csv = Some(v.to_string()),
+                ("--overwrite", None) => overwrite = true,
+                ("--narrate", None) => narrate = true,
+                ("--ipc", Some(v)) => ipc = Some(v.to_string()),
+                // 候補はnext_valueで検証済み
+                ("--lang", Some(v)) => lang = Lang::from_name(v),
+                ("--ipc-mode", Some(v)) => ipc_mode = IpcMode::from_name(v).unwrap_or(IpcMode::Skip),
+                ("--colors", Some(v)) => color_rules = Some(v.to_string()),
+                ("--no-color", None) => no_color = true,
+                ("--color", Some(v)) => color = ColorChoice::from_name(v),
+                ("--width", Some(v)) => {
+                    let cols = parse_count(spec, v)?;
+                    if cols < MIN_WIDTH {
+                        return Err(SmasherError::InvalidArgument(format!(
+                            "--width には{}以上を指定してください。実際: {}",
+                            MIN_WIDTH, v
+                        )));
+                    }
+                    width = Some(cols);
+                }
+                ("--self-check", None) => self_check = true,
+                ("--print-schema", None) => print_schema = true,
+                ("--list-formats", None) => list_formats = true,
+                ("--list-sections", None) => list_sections = true,
+                ("--generate", None) => generate = true,
+                ("--actions", Some(v)) => {
+                    generate_options.actions = parse_count(spec, v)?;
+                    generate_only = Some(spec.name);
+                }
+                ("--seed", Some(v)) => {
+                    generate_options.seed = v.parse::<u64>().map_err(|_| {
+                        SmasherError::InvalidArgument(format!(
+                            "{} には0以上の整数を指定してください。実際: {}",
+                            spec.name, v
+                        ))
+                    })?;
+                    generate_only = Some(spec.name);
+                }
+                ("--ratio", Some(v)) => {
+                    generate_options.ratio = parse_ratio(spec, v)?;
+                    generate_only = Some(spec.name);
+                }
+                ("--json", None) => json = true,
+                ("--excel", None) => excel = true,
+                ("--format", Some(v)) => format = Some(v.to_string()),
+                ("--order", Some(v)) => order = MatchOrder::from_name(v),
+                ("--normalize", Some(v)) => normalize = Normalize::from_name(v),
+                ("--dup-policy", Some(v)) => dup_policy = DupPolicy::from_name(v),
+                ("--jobs", Some(v)) => jobs = Some(parse_count(spec, v)?),
+                ("--top", Some(v)) => top = Some(parse_count(spec, v)?),
+                _ => unreachable!("OPTIONSにあるのに処理がないオプション: {}", spec.name),
+            }
+        }
+    }
+
+    let verbosity = match (quiet, verbose_level) {
+        (true, 0) => Verbosity::Quiet,
+        (true, _) => {
+            return Err(SmasherError::InvalidArgument(
+                "-q と -v は同時に指定できません".to_string(),
+            ));
+        }
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Verbose,
+        (false, _) => Verbosity::Full,
+    };
+
+    // 自己診断は入力ファイルを使わない
+    if self_check {
+        return Ok(Command::SelfCheck);
+    }
+
+    // スキーマの表示も入力ファイルを使わない
+    if print_schema {
+        return Ok(Command::PrintSchema);
+    }
+
+    // 一覧の表示も入力ファイルを使わない。--json か --format json ならJSONで出す
+    let list_json = json || format.as_deref() == Some("json");
+    match (list_formats, list_sections) {
+        (true, true) => {
+            return Err(SmasherError::InvalidArgument(
+                "--list-formats と --list-sections は同時に指定できません".to_string(),
+            ));
+        }
+        (true, false) => return Ok(Command::ListFormats { json: list_json }),
+        (false, true) => return Ok(Command::ListSections { json: list_json }),
+        (false, false) => {}
+    }
+
+    // 生成も入力ファイルを使わない
+    if generate {
+        return Ok(Command::Generate(generate_options));
+    }
+    if let Some(name) = generate_only {
+        return Err(SmasherError::InvalidArgument(format!(
+            "{} は --generate と一緒に指定してください",
+            name
+        )));
+    }
+
+    if file_paths.is_empty() {
+        return Err(SmasherError::InvalidArgument("ファイルパスが指定されていません".to_string()));
+    }
+    // 変換は解析をしないので、解析用のモードとは組み合わせられない
+    if convert.is_some() {
+        if out_dir.is_none() {
+            return Err(SmasherError::InvalidArgument(
+                "--convert には出力ディレクトリ --out-dir も指定してください".to_string(),
+            ));
+        }
+        if follow || watch || new_since.is_some() || groups.is_some() || improvement {
+            return Err(SmasherError::InvalidArgument(
+                "--convert は --follow、--watch、--new-since、--groups、--improvement と同時に指定できません".to_string(),
+            ));
+        }
+    } else if out_dir.is_some() || overwrite {
+        return Err(SmasherError::InvalidArgument(
+            "--out-dir と --overwrite は --convert と一緒に指定してください".to_string(),
+        ));
+    }
+    // --output に書けるのは、1つまたは複数のファイルの解析結果（テキスト・JSON・統計CSV）だけ
+    if let Some(path) = &output {
+        if follow || watch || new_since.is_some() || groups.is_some() || improvement || cluster.is_some() || convert.is_some() || compare_actions || similarity {
+            return Err(SmasherError::InvalidArgument(
+                "--output は --follow、--watch、--new-since、--groups、--improvement、--cluster、--convert、--compare-actions、--similarity と同時に指定できません"
+                    .to_string(),
+            ));
+        }
+        // 入力のログを上書きして消してしまわないようにする
+        if file_paths.iter().any(|input| same_file(input, path)) {
+            return Err(SmasherError::InvalidArgument(format!(
+                "--output に入力ファイルと同じファイル {} は指定できません",
+                path
+            )));
+        }
+    } else if append {
+        return Err(SmasherError::InvalidArgument(
+            "--append は --output と一緒に指定してください".to_string(),
+        ));
+    }
+    // --out-csv に書けるのは、ファイルごとの解析結果があるモード（1つ・複数のファイルの解析、統計CSV）だけ
+    if let Some(path) = &out_csv {
+        if follow || watch || new_since.is_some() || groups.is_some() || improvement || cluster.is_some() || convert.is_some() || compare_actions || similarity || jobs.is_some() {
+            return Err(SmasherError::InvalidArgument(
+                "--out-csv は --follow、--watch、--new-since、--groups、--improvement、--cluster、--convert、--compare-actions、--similarity、--jobs と同時に指定できません"
+                    .to_string(),
+            ));
+        }
+        if file_paths.iter().any(|input| same_file(input, path)) {
+            return Err(SmasherError::InvalidArgument(format!(
+                "--out-csv に入力ファイルと同じファイル {} は指定できません",
+                path
+            )));
+        }
+        if output.as_deref().is_some_and(|output| output == path || same_file(output, path)) {
+            return Err(SmasherError::InvalidArgument(format!(
+                "--out-csv に --output と同じファイル {} は指定できません",
+                path
+            )));
+        }
+    }
+    if compare_actions {
+        if file_paths.len() != 2 {
+            return Err(SmasherError::InvalidArgument(
+                "--compare-actions には比べる2つのファイルを指定してください".to_string(),
+            ));
+        }
+        if follow || watch || new_since.is_some() || groups.is_some() || improvement || convert.is_some() {
+            return Err(SmasherError::InvalidArgument(
+                "--compare-actions は --follow、--watch、--new-since、--groups、--improvement、--convert と同時に指定できません"
+                    .to_string(),
+            ));
+        }
+    }
+    if normalize.is_some() && !compare_actions {
+        return Err(SmasherError::InvalidArgument(
+            "--normalize は --compare-actions と一緒に指定してください".to_string(),
+        ));
+    }
+    if similarity {
+        if file_paths.len() != 2 {
+            return Err(SmasherError::InvalidArgument(
+                "--similarity には比べる2つのファイルを指定してください".to_string(),
+            ));
+        }
+        if follow || watch || new_since.is_some() || groups.is_some() || improvement || convert.is_some() || compare_actions {
+            return Err(SmasherError::InvalidArgument(
+                "--similarity は --follow、--watch、--new-since、--groups、--improvement、--convert、--compare-actions と同時に指定できません"
+                    .to_string(),
+            ));
+        }
+    }
+    // 読み続けるモードと差分表示は1つのファイルだけを扱う
+    if file_paths.len() > 1 && (follow || watch || new_since.is_some()) {
+        return Err(SmasherError::InvalidArgument(format!(
+            "--follow、--watch、--new-since ではファイルパスを1つだけ指定してください: {}",
+            file_paths[1]
+        )));
+    }
+    // 標準入力は1回しか読めず、読み直しや監視もできない
+    let stdin_count = file_paths.iter().chain(&new_since).filter(|path| *path == STDIN_PATH).count();
+    if stdin_count > 1 {
+        return Err(SmasherError::InvalidArgument(
+            "標準入力（-）は1回だけ指定できます".to_string(),
+        ));
+    }
+    if stdin_count > 0 && (follow || watch || convert.is_some() || compare_last) {
+        return Err(SmasherError::InvalidArgument(
+            "標準入力（-）から読むときは --follow、--watch、--convert、--compare-last を指定できません".to_string(),
+        ));
+    }
+    if order.is_some() && !improvement {
+        return Err(SmasherError::InvalidArgument(
+            "--order は --improvement と一緒に指定してください".to_string(),
+        ));
+    }
+    if improvement && (follow || watch || new_since.is_some() || groups.is_some()) {
+        return Err(SmasherError::InvalidArgument(
+            "--improvement は --follow、--watch、--new-since、--groups と同時に指定できません".to_string(),
+        ));
+    }
+    if lenient && strict {
+        return Err(SmasherError::InvalidArgument(
+            "--lenient と --strict は同時に指定できません".to_string(),
+        ));
+    }
+    if no_color && color.is_some_and(|c| c != ColorChoice::Never) {
+        return Err(SmasherError::InvalidArgument(
+            "--no-color と --color auto/always は同時に指定できません".to_string(),
+        ));
+    }
+    // コマンドラインで指定しなかった設定を環境変数で補う
+    let settings = EnvSettings {
+        format,
+        format_flag: json || excel,
+        lang,
+        top,
+        lenient: (lenient || strict).then_some(lenient),
+        color: color.or(no_color.then_some(ColorChoice::Never)),
+    }
+    .resolve()?;
+    let format = settings.format;
+    let lenient = settings.lenient.unwrap_or(false);
+    // --format json は --json の別名。--format text は既定の表示なので何もしない
+    // --excel は --format csv を含む
+    match format.as_deref() {
+        Some("json") if excel => {
+            return Err(SmasherError::InvalidArgument(
+                "--excel と --format json は同時に指定できません".to_string(),
+            ));
+        }
+        Some("json") => json = true,
+        Some("csv") if json => {
+            return Err(SmasherError::InvalidArgument(
+                "--json と --format csv は同時に指定できません".to_string(),
+            ));
+        }
+        Some("csv") => csv = true,
+        Some(_) if json || excel => {
+            return Err(SmasherError::InvalidArgument(
+                "--json・--excel と --format text は同時に指定できません".to_string(),
+            ));
+        }
+        _ => {}
+    }
+    if excel {
+        if json {
+            return Err(SmasherError::InvalidArgument(
+                "--json と --excel は同時に指定できません".to_string(),
+            ));
+        }
+        csv = true;
+    }
+    // 統計CSVは、1つまたは複数のファイルを解析して1試合1行で出すときだけ
+    if csv
+        && (follow || watch || new_since.is_some() || groups.is_some() || improvement || cluster.is_some() || convert.is_some() || compare_actions || similarity || compare_last || ipc.is_some())
+    {
+        return Err(SmasherError::InvalidArgument(
+            "--format csv・--excel は --follow、--watch、--new-since、--groups、--improvement、--cluster、--convert、--compare-actions、--similarity、--compare-last、--ipc と同時に指定できません"
+                .to_string(),
+        ));
+    }
+    if let Some(k) = cluster {
+        if follow || watch || new_since.is_some() || groups.is_some() || improvement || convert.is_some() || compare_actions || similarity || json || ipc.is_some() {
+            return Err(SmasherError::InvalidArgument(
+                "--cluster は --follow、--watch、--new-since、--groups、--improvement、--convert、--compare-actions、--similarity、--json、--ipc と同時に指定できません"
+                    .to_string(),
+            ));
+        }
+        if k > file_paths.len() {
+            return Err(SmasherError::InvalidArgument(format!(
+                "--cluster {} にはクラスタの数以上のファイルを指定してください（指定されたファイル: {} 個）",
+                k,
+                file_paths.len()
+            )));
+        }
+    }
+    // 通常の解析で複数のファイルを指定したときは、ファイルごとの結果とまとめを文章で表示する
+    let batch = file_paths.len() > 1 && groups.is_none() && !improvement && convert.is_none() && !compare_actions && !similarity && cluster.is_none();
+    if batch && ipc.is_some() {
+        return Err(SmasherError::InvalidArgument(
+            "複数のファイルを解析するときは --ipc を指定できません".to_string(),
+        ));
+    }
+    // 前回との比較は、1つのファイルを解析するときだけ（--json では previous_counts として出す）
+    if compare_last
+        && (file_paths.len() > 1 || follow || watch || new_since.is_some() || groups.is_some() || improvement || convert.is_some())
+    {
+        return Err(SmasherError::InvalidArgument(
+            "--compare-last は1つのファイルを解析するときだけ指定できます".to_string(),
+        ));
+    }
+    // 区間ごとに1行表示するので、読み切れないほど多い区間数は受け付けない
+    if analysis.time_phases > MAX_TIME_PHASES {
+        return Err(SmasherError::InvalidArgument(format!(
+            "--time-phases には {} 以下の区間数を指定してください。実際: {}",
+            MAX_TIME_PHASES, analysis.time_phases
+        )));
+    }
+    // 1個だけの「連続」はすべての行動が当てはまってしまうので、バーストは2個以上にする
+    if analysis.burst_min_len < 2 {
+        return Err(SmasherError::InvalidArgument(format!(
+            "--burst-min には2以上の個数を指定してください。実際: {}",
+            analysis.burst_min_len
+        )));
+    }
+    if dup_policy.is_some() && !batch {
+        return Err(SmasherError::InvalidArgument(
+            "--dup-policy は複数のファイルを合算するときだけ指定できます".to_string(),
+        ));
+    }
+    if jobs.is_some() && (!batch || csv) {
+        return Err(SmasherError::InvalidArgument(
+            "--jobs は複数のファイルを合算するときだけ指定できます（--format csv・--excel とは同時に指定できません）".to_string(),
+        ));
+    }
+    if json && (follow || watch || new_since.is_some() || groups.is_some() || convert.is_some() || similarity) {
+        return Err(SmasherError::InvalidArgument(
+            "--json は --follow、--watch、--new-since、--groups、--convert、--similarity と同時に指定できません".to_string(),
+        ));
+    }
+    // --follow は1行ずつ自分で解析するので、読み飛ばしの設定は効かない
+    if follow && lenient {
+        return Err(SmasherError::InvalidArgument(
+            "--follow と --lenient は同時に指定できません".to_string(),
+        ));
+    }
+    if follow && groups.is_some() {
+        return Err(SmasherError::InvalidArgument(
+            "--follow と --groups は同時に指定できません".to_string(),
+        ));
+    }
+    if snapshot_interval.is_some() && !follow {
+        return Err(SmasherError::InvalidArgument(
+            "--snapshot-interval は --follow と一緒に指定してください".to_string(),
+        ));
+    }
+    if snapshot_dir.is_some() && snapshot_interval.is_none() {
+        return Err(SmasherError::InvalidArgument(
+            "--snapshot-dir は --snapshot-interval と一緒に指定してください".to_string(),
+        ));
+    }
+    if watch && (follow || groups.is_some()) {
+        return Err(SmasherError::InvalidArgument(
+            "--watch は --follow や --groups と同時に指定できません".to_string(),
+        ));
+    }
+    if new_since.is_some() && (follow || watch || groups.is_some()) {
+        return Err(SmasherError::InvalidArgument(
+            "--new-since は --follow、--watch、--groups と同時に指定できません".to_string(),
+        ));
+    }
+
+    // NO_COLOR と端末かどうかは Style を作るときに調べる
+    let color = settings.color.unwrap_or(ColorChoice::Auto);
+    let lang = settings.lang.unwrap_or_default();
+
+    Ok(Command::Analyze(Box::new(Options {
+        file_paths,
+        analysis,
+        shift_secs,
+        snap_secs,
+        row_order,
+        lenient,
+        encoding,
+        debounce_ms,
+        follow,
+        refresh_secs,
+        snapshot_interval,
+        snapshot_dir: snapshot_dir.unwrap_or_else(|| ".".to_string()),
+        watch,
+        verbosity,
+        sort_categories,
+        narrate,
+        new_since,
+        groups,
+        cluster,
+        similarity,
+        improvement,
+        order: order.unwrap_or_default(),
+        dup_policy: dup_policy.unwrap_or_default(),
+        jobs,
+        top: settings.top.unwrap_or(DEFAULT_TOP),
+        compare_actions,
+        normalize: normalize.unwrap_or_default(),
+        compare_last,
+        json,
+        csv,
+        excel,
+        convert,
+        out_dir,
+        overwrite,
+        output,
+        append,
+        out_csv,
+        ipc,
+        ipc_mode,
+        lang,
+        color_rules,
+        color,
+        width,
+    })))
+}
+
+/// OPTIONSテーブルからオプションを探す
+fn find_option(name: &str) -> Result<&'static OptionSpec> {
+    OPTIONS
+        .iter()
+        .find(|spec| spec.name == name)
+        .ok_or_else(|| SmasherError::InvalidArgument(format!("未知のオプションです: {}", name)))
+}
+
+/// 短い形式（-v など）のオプションを探す
+fn find_short_option(short: char) -> Result<&'static OptionSpec> {
+    OPTIONS
+        .iter()
+        .find(|spec| spec.short == Some(short))
+        .ok_or_else(|| SmasherError::InvalidArgument(format!("未知のオプションです: -{}", short)))
+}
+
+/// オプションの値（次の引数）を取り出し、候補があれば検証する
+fn next_value<'a>(iter: &mut std::slice::Iter<'a, String>, spec: &OptionSpec) -> Result<&'a str> {
+    let value = iter
+        .next()
+        .map(|s| s.as_str())
+        .ok_or_else(|| {
+            SmasherError::InvalidArgument(format!("{} の値が指定されていません", spec.name))
+        })?;
+
+    check_choice(spec, value)?;
+    Ok(value)
+}
+
+/// 候補のあるオプションなら、値が候補のどれかかを確かめる
+fn check_choice(spec: &OptionSpec, value: &str) -> Result<()> {
+    if let ValueKind::Choice(choices) = spec.value {
+        if !choices.contains(&value) {
+            return Err(SmasherError::InvalidArgument(format!(
+                "{} には {} のいずれかを指定してください。実際: {}{}",
+                spec.name,
+                choices.join(" / "),
+                value,
+                list_hint(spec.name)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// 選択肢を説明付きで一覧表示できるオプションなら、その一覧のオプションを案内する文
+fn list_hint(name: &str) -> &'static str {
+    match name {
+        "--format" => "（説明付きの一覧は --list-formats で表示できます）",
+        _ => "",
+    }
+}
+
+/// 数値（負の値も可）を取るオプションの値を解析する
+fn parse_number(spec: &OptionSpec, value: &str) -> Result<f64> {
+    match value.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(SmasherError::InvalidArgument(format!(
+            "{} には数値を指定してください。実際: {}",
+            spec.name, value
+        ))),
+    }
+}
+
+/// 正の数値を取るオプションの値を解析する
+fn parse_positive(spec: &OptionSpec, value: &str) -> Result<f64> {
+    match value.parse::<f64>() {
+        Ok(n) if n > 0.0 && n.is_finite() => Ok(n),
+        _ => Err(SmasherError::InvalidArgument(format!(
+            "{} には正の数値を指定してください。実際: {}",
+            spec.name, value
+        ))),
+    }
+}
+
+/// 1以上の整数を取るオプションの値を解析する
+fn parse_count(spec: &OptionSpec, value: &str) -> Result<usize> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(SmasherError::InvalidArgument(format!(
+            "{} には1以上の整数を指定してください。実際: {}",
+            spec.name, value
+        ))),
+    }
+}
+
+/// 2つのパスが同じファイルを指すか（どちらかが存在しなければfalse）
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// 「攻撃:シールド:回避」形式の比率を解析する
+fn parse_ratio(spec: &OptionSpec, value: &str) -> Result<[f64; 3]> {
+    let invalid = || {
+        SmasherError::InvalidArgument(format!(
+            "{} には「攻撃:シールド:回避」の形式で0以上の数値を指定してください（合計は正）。実際: {}",
+            spec.name, value
+        ))
+    };
+
+    let parts: Vec<f64> = value
+        .split(':')
+        .map(|part| part.trim().parse::<f64>())
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| invalid())?;
+    let [attack, shield, dodge] = parts[..] else {
+        return Err(invalid());
+    };
+
+    let ratio = [attack, shield, dodge];
+    if ratio.iter().any(|r| !r.is_finite() || *r < 0.0) || ratio.iter().sum::<f64>() <= 0.0 {
+        return Err(invalid());
+    }
+    Ok(ratio)
+}
+
+/// 環境変数で補える設定（コマンドラインで指定されなければNone）
+///
+/// 優先順位は コマンドライン > 環境変数 > 組み込みのデフォルト で、環境変数は resolve でまとめて読む。
+/// このツールには設定ファイルがないので、設定ファイルの段階は設けていない
+#[derive(Debug, Default)]
+struct EnvSettings {
+    /// 出力形式（--format、SMASHER_FORMAT）
+    format: Option<String>,
+    /// --json か --excel を指定したか（形式を指定したものとして扱い、SMASHER_FORMAT は読まない）
+    format_flag: bool,
+    /// 行動名の言語（--lang、SMASHER_LANG）
+    lang: Option<Lang>,
+    /// ランキングに表示する数（--top、SMASHER_TOP）
+    top: Option<usize>,
+    /// 解析できない行を読み飛ばすか（--lenient・--strict、SMASHER_STRICT）
+    lenient: Option<bool>,
+    /// 色を付けるか（--color・--no-color、SMASHER_NO_COLOR）
+    color: Option<ColorChoice>,
+}
+
+impl EnvSettings {
+    /// コマンドラインで指定されなかった設定を環境変数で補う
+    ///
+    /// 環境変数の値が間違っていれば、同じ値をオプションで指定したときと同じエラーに
+    /// 環境変数の名前を添える。コマンドラインで指定した設定の環境変数は読まない
+    fn resolve(mut self) -> Result<Self> {
+        if self.format.is_none() && !self.format_flag {
+            self.format = env_value(ENV_FORMAT, "--format", |spec, v| check_choice(spec, v).map(|_| v.to_string()))?;
+        }
+        if self.lang.is_none() {
+            self.lang = env_value(ENV_LANG, "--lang", |spec, v| check_choice(spec, v).map(|_| Lang::from_name(v)))?.flatten();
+        }
+        if self.top.is_none() {
+            self.top = env_value(ENV_TOP, "--top", parse_count)?;
+        }
+        if self.lenient.is_none() {
+            self.lenient = env_flag(ENV_STRICT)?.map(|strict| !strict);
+        }
+        if self.color.is_none() && env_flag(ENV_NO_COLOR)?.unwrap_or(false) {
+            self.color = Some(ColorChoice::Never);
+        }
+        Ok(self)
+    }
+}
+
+/// 値を取るオプションの代わりになる環境変数を読む
+///
+/// 値はオプションと同じ `parse` で確かめ、間違っていればオプションと同じエラーに環境変数の名前を添える
+///
+/// # 引数
+/// * `name` - 環境変数の名前
+/// * `option` - 対応するオプションの名前（--format など）
+/// * `parse` - オプションの値を解析する関数
+///
+/// # 戻り値
+/// 設定されていないか空ならNone
+fn env_value<T>(name: &str, option: &str, parse: impl Fn(&OptionSpec, &str) -> Result<T>) -> Result<Option<T>> {
+    let value = match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value.trim().to_string(),
+        _ => return Ok(None),
+    };
+    match parse(find_option(option)?, &value) {
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(SmasherError::InvalidArgument(message)) => Err(SmasherError::InvalidArgument(format!(
+            "{}（環境変数 {} の値）",
+            message, name
+        ))),
+        Err(e) => Err(e),
+    }
+}
+
+/// 真偽値の環境変数を読む
+///
+/// コンテナなどでオプションを渡しにくいときのために、環境変数でも設定できるようにする
+///
+/// # 戻り値
+/// 設定されていなければNone、1/true/yes ならtrue、0/false/no（または空）ならfalse
+fn env_flag(name: &str) -> Result<Option<bool>> {
+    let Ok(value) = std::env::var(name) else {
+        return Ok(None);
+    };
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" => Ok(Some(true)),
+        "" | "0" | "false" | "no" => Ok(Some(false)),
+        _ => Err(SmasherError::InvalidArgument(format!(
+            "環境変数 {} には 1/true/yes か 0/false/no を指定してください。実際: {}",
+            name, value
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 環境変数を設定した状態で引数を解析する
+    fn parse_with_env(vars: &[(&str, &str)], args: &[&str]) -> Result<Command> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        with_env(vars, || parse_args(&args))
+    }
+
+    fn options(vars: &[(&str, &str)], args: &[&str]) -> Options {
+        match parse_with_env(vars, args) {
+            Ok(Command::Analyze(options)) => *options,
+            other => panic!("解析のコマンドになっていません: {:?}", other),
+        }
+    }
+
+    fn error(vars: &[(&str, &str)], args: &[&str]) -> String {
+        match parse_with_env(vars, args) {
+            Err(e) => e.to_string(),
+            Ok(command) => panic!("エラーになっていません: {:?}", command),
+        }
+    }
+
+    #[test]
+    fn format_and_lang_default_without_env() {
+        let options = options(&[], &["a.csv"]);
+        assert_eq!((options.json, options.csv, options.lang), (false, false, Lang::Ja));
+    }
+
+    #[test]
+    fn env_sets_format_and_lang() {
+        let from_env = options(&[(ENV_FORMAT, "json"), (ENV_LANG, "en")], &["a.csv"]);
+        assert_eq!((from_env.json, from_env.lang), (true, Lang::En));
+        assert!(options(&[(ENV_FORMAT, "csv")], &["a.csv"]).csv);
+        assert_eq!(options(&[(ENV_FORMAT, " "), (ENV_LANG, "")], &["a.csv"]).lang, Lang::Ja);
+    }
+
+    #[test]
+    fn command_line_wins_over_env() {
+        let from_flags = options(&[(ENV_FORMAT, "csv"), (ENV_LANG, "en")], &["a.csv", "--format", "text", "--lang", "ja+en"]);
+        assert_eq!((from_flags.json, from_flags.csv, from_flags.lang), (false, false, Lang::JaEn));
+        // --json・--excel も --format の代わりなので、環境変数より優先する
+        assert!(options(&[(ENV_FORMAT, "csv")], &["a.csv", "--json"]).json);
+        assert!(!options(&[(ENV_FORMAT, "json")], &["a.csv", "--excel"]).json);
+    }
+
+    #[test]
+    fn invalid_env_value_reports_the_option_error_and_the_variable() {
+        let flag = error(&[], &["a.csv", "--format", "xml"]);
+        let env = error(&[(ENV_FORMAT, "xml")], &["a.csv"]);
+        assert_eq!(env, format!("{}（環境変数 {} の値）", flag, ENV_FORMAT));
+
+        let flag = error(&[], &["a.csv", "--lang", "fr"]);
+        let env = error(&[(ENV_LANG, "fr")], &["a.csv"]);
+        assert_eq!(env, format!("{}（環境変数 {} の値）", flag, ENV_LANG));
+        // コマンドラインで指定していれば、環境変数の値は見ない
+        assert_eq!(options(&[(ENV_LANG, "fr")], &["a.csv", "--lang", "en"]).lang, Lang::En);
+
+        let flag = error(&[], &["a.csv", "--top", "0"]);
+        let env = error(&[(ENV_TOP, "0")], &["a.csv"]);
+        assert_eq!(env, format!("{}（環境変数 {} の値）", flag, ENV_TOP));
+        assert!(error(&[(ENV_STRICT, "maybe")], &["a.csv"]).contains(ENV_STRICT));
+    }
+
+    #[test]
+    fn env_sets_top_strict_and_color() {
+        let defaults = options(&[], &["a.csv"]);
+        assert_eq!((defaults.top, defaults.lenient, defaults.color), (DEFAULT_TOP, false, ColorChoice::Auto));
+
+        let from_env = options(&[(ENV_TOP, "3"), (ENV_STRICT, "0"), (ENV_NO_COLOR, "1")], &["a.csv"]);
+        assert_eq!((from_env.top, from_env.lenient, from_env.color), (3, true, ColorChoice::Never));
+        assert!(!options(&[(ENV_STRICT, "yes")], &["a.csv"]).lenient);
+    }
+
+    #[test]
+    fn command_line_wins_over_top_strict_and_color_env() {
+        let vars = [(ENV_TOP, "3"), (ENV_STRICT, "0"), (ENV_NO_COLOR, "1")];
+        let from_flags = options(&vars, &["a.csv", "--top", "20", "--strict", "--color", "always"]);
+        assert_eq!((from_flags.top, from_flags.lenient, from_flags.color), (20, false, ColorChoice::Always));
+        assert!(options(&[(ENV_STRICT, "1")], &["a.csv", "--lenient"]).lenient);
+        assert!(error(&[], &["a.csv", "--lenient", "--strict"]).contains("--strict"));
+    }
+
+    #[test]
+    fn with_env_hides_and_restores_the_shell_variables() {
+        // 開発者のシェルで SMASHER_TOP が設定されている状態を作る
+        // （ほかのテストの parse_args はすべて with_env の中なので、この値は見えない）
+        {
+            let _lock = ENV_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            std::env::set_var(ENV_TOP, "99");
+        }
+        let inside = with_env(&[], || std::env::var_os(ENV_TOP));
+        let top = options(&[], &["a.csv"]).top;
+        let replaced = with_env(&[(ENV_TOP, "7")], || std::env::var(ENV_TOP).ok());
+        let after = {
+            let _lock = ENV_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let after = std::env::var(ENV_TOP).ok();
+            std::env::remove_var(ENV_TOP);
+            after
+        };
+        assert_eq!((inside, top), (None, DEFAULT_TOP));
+        assert_eq!(replaced.as_deref(), Some("7"));
+        assert_eq!(after.as_deref(), Some("99"));
+    }
+
+    #[test]
+    fn normalize_needs_compare_actions() {
+        assert_eq!(options(&[], &["a.csv", "b.csv", "--compare-actions"]).normalize, Normalize::Raw);
+        let normalized = options(&[], &["a.csv", "b.csv", "--compare-actions", "--normalize", "per-minute"]);
+        assert_eq!(normalized.normalize, Normalize::PerMinute);
+        assert!(error(&[], &["a.csv", "--normalize", "per-minute"]).contains("--normalize は --compare-actions と一緒に指定してください"));
+        assert!(error(&[], &["a.csv", "b.csv", "--compare-actions", "--normalize", "per-second"]).contains("--normalize"));
+    }
+
+    #[test]
+    fn every_format_choice_has_a_description() {
+        let names: Vec<&str> = formats().iter().map(|&(name, _)| name).collect();
+        assert_eq!(names, vec!["text", "json", "csv"]);
+        for (name, description) in formats() {
+            assert!(!description.is_empty(), "{} の説明がありません", name);
+        }
+    }
+
+    #[test]
+    fn list_flags_choose_text_or_json() {
+        let list = |args: &[&str]| match parse_with_env(&[], args) {
+            Ok(Command::ListFormats { json }) => ("formats", json),
+            Ok(Command::ListSections { json }) => ("sections", json),
+            other => panic!("一覧のコマンドになっていません: {:?}", other),
+        };
+        assert_eq!(list(&["--list-formats"]), ("formats", false));
+        assert_eq!(list(&["--list-formats", "--json"]), ("formats", true));
+        assert_eq!(list(&["--list-sections", "--format", "json"]), ("sections", true));
+        assert!(error(&[], &["--list-formats", "--list-sections"]).contains("同時に指定できません"));
+    }
+
+    #[test]
+    fn unknown_format_points_at_the_list_flag() {
+        assert!(error(&[], &["a.csv", "--format", "xml"]).ends_with("（説明付きの一覧は --list-formats で表示できます）"));
+        assert!(!error(&[], &["a.csv", "--lang", "fr"]).contains("--list-"));
+    }
+
+    #[test]
+    fn migrate_defaults_to_the_current_schema_version() {
+        let migrate = |args: &[&str]| match parse_with_env(&[], args) {
+            Ok(Command::Migrate(options)) => options,
+            other => panic!("migrate のコマンドになっていません: {:?}", other),
+        };
+        assert_eq!(
+            migrate(&["migrate", "exports"]),
+            MigrateOptions { dir: "exports".to_string(), to_version: SCHEMA_VERSION, output_dir: None }
+        );
+        let options = migrate(&["migrate", "exports", "--to-version", "6", "--output-dir", "out"]);
+        assert_eq!((options.to_version, options.output_dir.as_deref()), (6, Some("out")));
+        assert!(error(&[], &["migrate", "exports", "--to-version", "99"]).contains("--to-version には1から"));
+        assert!(error(&[], &["migrate"]).contains("使い方: smasher migrate"));
+    }
+
+    #[test]
+    fn a_lone_dash_reads_stdin_once() {
+        assert_eq!(options(&[], &["-", "--json"]).file_paths, vec![STDIN_PATH.to_string()]);
+        assert!(error(&[], &["-", "a.csv", "-"]).contains("標準入力（-）は1回だけ指定できます"));
+        for flag in ["--follow", "--watch", "--compare-last"] {
+            assert!(error(&[], &["-", flag]).contains("標準入力（-）から読むときは"), "{}", flag);
+        }
+    }
+
+    #[test]
+    fn context_keep_raw_and_explain_line_are_parsed() {
+        match parse_with_env(&[], &["context", "a.csv", "--at", "84.2", "--keep-raw"]) {
+            Ok(Command::Context { at, n, keep_raw, json, .. }) => {
+                assert_eq!((at, n, keep_raw, json), (84.2, DEFAULT_CONTEXT_COUNT, true, false));
+            }
+            other => panic!("context のコマンドになっていません: {:?}", other),
+        }
+        match parse_with_env(&[], &["explain", "104.2,ub_a", "--line", "12"]) {
+            Ok(Command::Explain { line_number, .. }) => assert_eq!(line_number, 12),
+            other => panic!("explain のコマンドになっていません: {:?}", other),
+        }
+        assert!(error(&[], &["explain", "104.2,ub_a", "--line", "0"]).contains("--line には1以上の整数"));
+    }
+
+    #[test]
+    fn out_csv_rejects_modes_without_per_file_results_and_clashing_paths() {
+        assert_eq!(options(&[], &["a.csv", "--out-csv", "summary.csv"]).out_csv.as_deref(), Some("summary.csv"));
+        for flag in ["--follow", "--watch", "--improvement"] {
+            assert!(error(&[], &["a.csv", "--out-csv", "summary.csv", flag]).contains("--out-csv は"), "{}", flag);
+        }
+        // 入力ファイルは、書き方が違っても同じファイルなら断る
+        let name = format!("smasher_out_csv_input_{}.csv", std::process::id());
+        let input = std::env::temp_dir().join(&name);
+        fs::write(&input, "b1,1\n1.0,us\n").unwrap();
+        let same = std::env::temp_dir().join(".").join(&name);
+        let message = error(&[], &[&input.to_string_lossy(), "--out-csv", &same.to_string_lossy()]);
+        fs::remove_file(&input).unwrap();
+        assert!(message.contains("入力ファイルと同じファイル"), "{}", message);
+        assert!(error(&[], &["a.csv", "-o", "r.txt", "--out-csv", "r.txt"]).contains("--output と同じファイル r.txt"));
+    }
+}
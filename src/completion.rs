@@ -0,0 +1,293 @@
+// completion.rs
+// シェル補完スクリプト生成モジュール
+//
+// cli.rsのOPTIONS・SUBCOMMANDSテーブルから各シェル用の補完スクリプトを作る
+// オプションを追加すると補完にも自動で反映される
+
+use crate::cli::{ValueKind, OPTIONS, SUBCOMMANDS};
+
+/// 補完スクリプトを生成できるシェル
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+impl Shell {
+    /// 指定できるシェル名の一覧
+    pub const NAMES: &'static [&'static str] = &["bash", "zsh", "fish", "powershell"];
+
+    /// シェル名からShellを取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            "powershell" => Some(Shell::Powershell),
+            _ => None,
+        }
+    }
+}
+
+/// 指定したシェル用の補完スクリプトを生成する
+///
+/// # 引数
+/// * `shell` - 対象のシェル
+///
+/// # 戻り値
+/// 標準出力にそのまま書き出せる補完スクリプト
+pub fn generate(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => generate_bash(),
+        Shell::Zsh => generate_zsh(),
+        Shell::Fish => generate_fish(),
+        Shell::Powershell => generate_powershell(),
+    }
+}
+
+/// すべてのオプション名（短い形式を含む）を並べる
+fn option_names() -> Vec<String> {
+    let mut names = Vec::new();
+    for spec in OPTIONS {
+        names.push(spec.name.to_string());
+        if let Some(short) = spec.short {
+            names.push(format!("-{}", short));
+        }
+    }
+    names
+}
+
+/// すべてのオプション名をスペース区切りで並べる
+fn all_option_names() -> String {
+    option_names().join(" ")
+}
+
+/// すべてのサブコマンド名をスペース区切りで並べる
+fn all_subcommand_names() -> String {
+    SUBCOMMANDS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(" ")
+}
+
+fn generate_bash() -> String {
+    let mut value_cases = String::new();
+    for spec in OPTIONS {
+        match spec.value {
+            ValueKind::Flag => {}
+            ValueKind::Choice(choices) => {
+                value_cases.push_str(&format!(
+                    "        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;\n",
+                    spec.name,
+                    choices.join(" ")
+                ));
+            }
+            ValueKind::Path => {
+                value_cases.push_str(&format!(
+                    "        {}) COMPREPLY=($(compgen -f -- \"$cur\")); return ;;\n",
+                    spec.name
+                ));
+            }
+            ValueKind::Free(_) => {
+                value_cases.push_str(&format!("        {}) COMPREPLY=(); return ;;\n", spec.name));
+            }
+        }
+    }
+
+    format!(
+        r#"# smasher の bash 補完
+_smasher() {{
+    local cur prev
+    cur="${{COMP_WORDS[COMP_CWORD]}}"
+    prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    case "$prev" in
+{value_cases}    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "{options}" -- "$cur"))
+    elif [[ $COMP_CWORD -eq 1 ]]; then
+        COMPREPLY=($(compgen -W "{subcommands}" -- "$cur") $(compgen -f -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}}
+complete -o filenames -F _smasher smasher
+"#,
+        value_cases = value_cases,
+        options = all_option_names(),
+        subcommands = all_subcommand_names(),
+    )
+}
+
+/// zshの_argumentsの説明文で特別な意味を持つ文字をエスケープする
+fn escape_zsh(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\'', "'\\''")
+        .replace(':', "\\:")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+fn generate_zsh() -> String {
+    let mut specs = String::new();
+    for spec in OPTIONS {
+        let action = match spec.value {
+            ValueKind::Flag => String::new(),
+            ValueKind::Choice(choices) => format!(":value:({})", choices.join(" ")),
+            ValueKind::Path => ":file:_files".to_string(),
+            ValueKind::Free(placeholder) => format!(":{}: ", placeholder),
+        };
+        // 短い形式があれば (-v --verbose){-v,--verbose} のように両方を書く
+        let names = match spec.short {
+            Some(short) => format!("'(-{short} {long})'{{-{short},{long}}}'", short = short, long = spec.name),
+            None => format!("'{}", spec.name),
+        };
+        specs.push_str(&format!(
+            "        {}[{}]{}' \\\n",
+            names,
+            escape_zsh(spec.description),
+            action
+        ));
+    }
+
+    format!(
+        r#"#compdef smasher
+# smasher の zsh 補完
+_smasher() {{
+    _arguments \
+{specs}        '1:command or file:_alternative "commands:command:({subcommands})" "files:file:_files"' \
+        '*:file:_files'
+}}
+_smasher "$@"
+"#,
+        specs = specs,
+        subcommands = all_subcommand_names(),
+    )
+}
+
+fn generate_fish() -> String {
+    let mut lines = String::from("# smasher の fish 補完\n");
+    for (name, description) in SUBCOMMANDS {
+        lines.push_str(&format!(
+            "complete -c smasher -n \"__fish_use_subcommand\" -a \"{}\" -d \"{}\"\n",
+            name, description
+        ));
+    }
+    for spec in OPTIONS {
+        let long = spec.name.trim_start_matches("--");
+        let value = match spec.value {
+            ValueKind::Flag => String::new(),
+            ValueKind::Choice(choices) => format!(" -x -a \"{}\"", choices.join(" ")),
+            ValueKind::Path => " -r -F".to_string(),
+            ValueKind::Free(_) => " -x".to_string(),
+        };
+        let short = match spec.short {
+            Some(short) => format!(" -s {}", short),
+            None => String::new(),
+        };
+        lines.push_str(&format!(
+            "complete -c smasher{} -l {}{} -d \"{}\"\n",
+            short, long, value, spec.description
+        ));
+    }
+    lines
+}
+
+fn generate_powershell() -> String {
+    let mut value_cases = String::new();
+    for spec in OPTIONS {
+        if let ValueKind::Choice(choices) = spec.value {
+            let quoted: Vec<String> = choices.iter().map(|c| format!("'{}'", c)).collect();
+            value_cases.push_str(&format!(
+                "        '{}' {{ @({}) }}\n",
+                spec.name,
+                quoted.join(", ")
+            ));
+        }
+    }
+    let options: Vec<String> = option_names().iter().map(|name| format!("'{}'", name)).collect();
+    let subcommands: Vec<String> = SUBCOMMANDS.iter().map(|(name, _)| format!("'{}'", name)).collect();
+
+    format!(
+        r#"# smasher の PowerShell 補完
+Register-ArgumentCompleter -Native -CommandName smasher -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $elements = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    $prevIndex = if ($wordToComplete) {{ $elements.Count - 2 }} else {{ $elements.Count - 1 }}
+    $prev = if ($prevIndex -ge 0) {{ $elements[$prevIndex] }} else {{ '' }}
+    $values = switch ($prev) {{
+{value_cases}        default {{ @({options}) + @({subcommands}) }}
+    }}
+    $values | Where-Object {{ $_ -like "$wordToComplete*" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}
+"#,
+        value_cases = value_cases,
+        options = options.join(", "),
+        subcommands = subcommands.join(", "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// bash の補完候補（`compgen -W "..."` に並べたオプション名）を取り出す
+    fn bash_option_words(script: &str) -> Vec<&str> {
+        let line = script
+            .lines()
+            .skip_while(|line| !line.contains(r#"[[ "$cur" == -* ]]"#))
+            .nth(1)
+            .expect("オプションを補完する行がありません");
+        let start = line.find("-W \"").expect("compgen -W がありません") + 4;
+        let end = start + line[start..].find('"').unwrap();
+        line[start..end].split_whitespace().collect()
+    }
+
+    #[test]
+    fn bash_completes_every_long_option() {
+        let script = generate(Shell::Bash);
+        let words = bash_option_words(&script);
+        for spec in OPTIONS {
+            assert!(words.contains(&spec.name), "bash の補完に {} がありません", spec.name);
+            if let Some(short) = spec.short {
+                assert!(words.contains(&format!("-{}", short).as_str()), "bash の補完に -{} がありません", short);
+            }
+        }
+    }
+
+    #[test]
+    fn bash_completes_choice_values() {
+        let script = generate(Shell::Bash);
+        for spec in OPTIONS {
+            if let ValueKind::Choice(choices) = spec.value {
+                let case = format!("        {}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return ;;", spec.name, choices.join(" "));
+                assert!(script.contains(&case), "bash の補完に {} の値がありません", spec.name);
+            }
+        }
+    }
+
+    #[test]
+    fn every_shell_mentions_every_option_and_subcommand() {
+        for shell in [Shell::Zsh, Shell::Fish, Shell::Powershell] {
+            let script = generate(shell);
+            for spec in OPTIONS {
+                let long = match shell {
+                    Shell::Fish => format!("-l {}", spec.name.trim_start_matches("--")),
+                    _ => spec.name.to_string(),
+                };
+                assert!(script.contains(&long), "{:?} の補完に {} がありません", shell, spec.name);
+            }
+            for (name, _) in SUBCOMMANDS {
+                assert!(script.contains(name), "{:?} の補完に {} がありません", shell, name);
+            }
+        }
+    }
+
+    #[test]
+    fn shell_names_round_trip() {
+        for name in Shell::NAMES {
+            assert!(Shell::from_name(name).is_some(), "{}", name);
+        }
+        assert_eq!(Shell::from_name("tcsh"), None);
+    }
+}
@@ -0,0 +1,105 @@
+// context.rs
+// 指定時刻の前後の行動を表示するモジュール（smasher context）
+//
+// 動画を見返すときに、ある時刻の前後で何をしていたかを確認するためのもの
+
+use crate::json;
+use crate::model::{Action, ActionType};
+
+/// 指定時刻の前後の行動を人が読む形式で表示する
+///
+/// # 引数
+/// * `at` - 基準の時刻（秒）
+/// * `before` - 基準より前の行動（時刻順）
+/// * `after` - 基準以降の行動（時刻順）
+pub fn display_context(at: f64, before: &[&Action], after: &[&Action]) {
+    println!("基準時刻: {:.2}秒", at);
+    println!("【前】");
+    display_rows(at, before);
+    println!("【後】");
+    display_rows(at, after);
+}
+
+/// 行動を1行ずつ、基準時刻からの差・名前・分類・行番号付きで表示する
+/// 
+/// 元の行の文字列を持っている（--keep-raw）行動は、次の行にそれも表示する
+fn display_rows(at: f64, actions: &[&Action]) {
+    if actions.is_empty() {
+        println!("  （なし）");
+        return;
+    }
+    for action in actions {
+        println!(
+            "  {:>+8.2}秒  {:>8.2}秒  {} ({})  [{}]  {}行目",
+            action.timestamp - at,
+            action.timestamp,
+            ActionType::get_action_name(&action.original_id),
+            action.original_id,
+            action.action_type.label(),
+            action.line
+        );
+        if let Some(raw) = &action.raw {
+            println!("      > {}", raw);
+        }
+    }
+}
+
+/// 指定時刻の前後の行動をJSONにする
+pub fn context_to_json(at: f64, before: &[&Action], after: &[&Action]) -> String {
+    format!(
+        "{{\"at\":{},\"before\":[{}],\"after\":[{}]}}",
+        json::number(at),
+        rows_to_json(at, before),
+        rows_to_json(at, after)
+    )
+}
+
+/// 行動のリストをJSONのオブジェクトの並び（カンマ区切り）にする
+/// 
+/// 元の行の文字列（raw）は、持っている（--keep-raw）ときだけキーを入れる
+fn rows_to_json(at: f64, actions: &[&Action]) -> String {
+    actions
+        .iter()
+        .map(|action| {
+            let raw = action
+                .raw
+                .as_ref()
+                .map(|raw| format!(",\"raw\":{}", json::string(raw)))
+                .unwrap_or_default();
+            format!(
+                "{{\"timestamp\":{},\"offset\":{},\"action_id\":{},\"name\":{},\"category\":{},\"line\":{}{}}}",
+                json::number(action.timestamp),
+                json::number(action.timestamp - at),
+                json::string(&action.original_id),
+                json::string(&ActionType::get_action_name(&action.original_id)),
+                json::string(action.action_type.key()),
+                action.line,
+                raw
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(timestamp: f64, id: &str, line: u32, raw: Option<&str>) -> Action {
+        let mut action = Action::new(timestamp, id.to_string());
+        action.line = line;
+        action.raw = raw.map(Box::from);
+        action
+    }
+
+    #[test]
+    fn json_rows_carry_the_line_and_raw_only_when_kept() {
+        let before = action(83.5, "us", 12, None);
+        let after = action(84.2, "s", 13, Some("84.2, s"));
+        let json = context_to_json(84.0, &[&before], &[&after]);
+        assert!(json.starts_with(r#"{"at":84,"before":[{"timestamp":83.5,"#), "{}", json);
+        assert!(json.contains(r#""category":"attack","line":12}],"after""#), "{}", json);
+        assert!(json.ends_with(r#""category":"shield","line":13,"raw":"84.2, s"}]}"#), "{}", json);
+        assert_eq!(context_to_json(0.0, &[], &[]), r#"{"at":0,"before":[],"after":[]}"#);
+    }
+}
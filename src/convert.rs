@@ -0,0 +1,125 @@
+// convert.rs
+// 形式変換モジュール（--convert）
+//
+// 行動ログのCSVを解析せずに別の形式へ書き出す。
+// 手持ちのログをまとめて他のツールへ移すためのもの
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SmasherError};
+use crate::json;
+use crate::model::BattleLog;
+use crate::parser;
+
+/// 変換先の形式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConvertFormat {
+    /// JSON（1ファイルに1つのオブジェクト）
+    Json,
+}
+
+impl ConvertFormat {
+    /// 名前から形式を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(ConvertFormat::Json),
+            _ => None,
+        }
+    }
+
+    /// 出力ファイルの拡張子
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ConvertFormat::Json => "json",
+        }
+    }
+
+    /// 対戦ログをこの形式の文字列にする
+    fn render(&self, battle_log: &BattleLog) -> String {
+        match self {
+            ConvertFormat::Json => battle_log_to_json(battle_log),
+        }
+    }
+}
+
+/// 複数のCSVファイルを変換して、出力ディレクトリに書き出す
+///
+/// 出力ファイル名は入力ファイル名の拡張子を変えたもの（a.csv → out_dir/a.json）。
+/// 出力ディレクトリがなければ作る。
+/// 出力先にファイルがすでにある場合、`overwrite` がfalseならそのファイルは変換せずに飛ばす。
+///
+/// # 引数
+/// * `file_paths` - 入力CSVファイルのパス
+/// * `format` - 変換先の形式
+/// * `out_dir` - 出力ディレクトリ
+/// * `overwrite` - 既存のファイルを上書きするか（--overwrite）
+///
+/// # 戻り値
+/// 成功、またはエラー（読み込めないCSVがあった時点で中断する）
+pub fn convert_files(file_paths: &[String], format: ConvertFormat, out_dir: &str, overwrite: bool) -> Result<()> {
+    fs::create_dir_all(out_dir)?;
+
+    let mut converted = 0;
+    let mut skipped = 0;
+    for file_path in file_paths {
+        let out_path = output_path(file_path, out_dir, format)?;
+        if out_path.exists() && !overwrite {
+            println!("スキップ: {} （すでにあります。上書きするには --overwrite）", out_path.display());
+            skipped += 1;
+            continue;
+        }
+
+        let battle_log = parser::read_battle_log(file_path)?;
+        fs::write(&out_path, format.render(&battle_log) + "\n")?;
+        println!("変換: {} → {}", file_path, out_path.display());
+        converted += 1;
+    }
+
+    println!("{} 件変換しました（スキップ {} 件）", converted, skipped);
+    Ok(())
+}
+
+/// 入力ファイル名の拡張子を変えた、出力ディレクトリ内のパスを作る
+fn output_path(file_path: &str, out_dir: &str, format: ConvertFormat) -> Result<PathBuf> {
+    let stem = Path::new(file_path).file_stem().ok_or_else(|| {
+        SmasherError::InvalidArgument(format!("ファイル名を取得できません: {}", file_path))
+    })?;
+    let mut out_path = Path::new(out_dir).join(stem);
+    out_path.set_extension(format.extension());
+    Ok(out_path)
+}
+
+/// 対戦ログをJSONにする（解析結果は含めず、CSVの内容だけを写す）
+pub fn battle_log_to_json(battle_log: &BattleLog) -> String {
+    let actions: Vec<String> = battle_log
+        .actions
+        .iter()
+        .map(|action| {
+            format!(
+                "{{\"timestamp\":{},\"action_id\":{}}}",
+                json::number(action.timestamp),
+                json::string(&action.original_id)
+            )
+        })
+        .collect();
+    let markers: Vec<String> = battle_log
+        .markers
+        .iter()
+        .map(|marker| {
+            format!(
+                "{{\"timestamp\":{},\"name\":{}}}",
+                json::number(marker.timestamp),
+                json::string(&marker.name)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"student_id\":{},\"match_number\":{},\"actions\":[{}],\"markers\":[{}]}}",
+        json::string(&battle_log.player_info.student_id),
+        battle_log.player_info.match_number,
+        actions.join(","),
+        markers.join(",")
+    )
+}
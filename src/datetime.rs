@@ -0,0 +1,92 @@
+// datetime.rs
+// 日時の書式モジュール
+//
+// ログには日付が入っていないので、ファイルの更新日時を試合の日時の代わりに使う
+// 標準ライブラリにはタイムゾーンの情報がないため、表示はすべてUTCにする
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 日時をUTCの年・月・日・時・分・秒に分ける
+///
+/// 1970年より前の日時（時計が大きくずれているなど）はNone
+fn to_utc(time: SystemTime) -> Option<(i64, u32, u32, u64, u64, u64)> {
+    let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let days = (secs / 86_400) as i64;
+    let rest = secs % 86_400;
+
+    // 1970-01-01からの日数を年月日にする（うるう年を400年周期で数えるアルゴリズム）
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    Some((year, month, day, rest / 3_600, rest % 3_600 / 60, rest % 60))
+}
+
+/// 日時をRFC 3339形式（例: 2025-12-21T14:39:05Z）にする（JSON出力用）
+///
+/// # 戻り値
+/// RFC 3339形式の文字列。1970年より前の日時ならNone
+pub fn rfc3339(time: SystemTime) -> Option<String> {
+    let (year, month, day, hour, minute, second) = to_utc(time)?;
+    Some(format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, hour, minute, second))
+}
+
+/// 日時を表示用の短い形式（例: 2025-12-21 14:39 UTC）にする
+///
+/// # 戻り値
+/// 表示用の文字列。1970年より前の日時なら「不明」
+pub fn display(time: SystemTime) -> String {
+    match to_utc(time) {
+        Some((year, month, day, hour, minute, _)) => {
+            format!("{:04}-{:02}-{:02} {:02}:{:02} UTC", year, month, day, hour, minute)
+        }
+        None => "不明".to_string(),
+    }
+}
+
+/// 2つの日時の差（日）。later が earlier より前なら負になる
+pub fn days_between(earlier: SystemTime, later: SystemTime) -> f64 {
+    match later.duration_since(earlier) {
+        Ok(duration) => duration.as_secs_f64() / 86_400.0,
+        Err(e) => -e.duration().as_secs_f64() / 86_400.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn rfc3339_formats_known_dates_in_utc() {
+        assert_eq!(rfc3339(at(0)).as_deref(), Some("1970-01-01T00:00:00Z"));
+        assert_eq!(rfc3339(at(1_766_327_945)).as_deref(), Some("2025-12-21T14:39:05Z"));
+        // うるう日と、100で割り切れるがうるう年ではない年
+        assert_eq!(rfc3339(at(951_868_799)).as_deref(), Some("2000-02-29T23:59:59Z"));
+        assert_eq!(rfc3339(at(4_107_542_400)).as_deref(), Some("2100-03-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn display_drops_seconds_and_says_unknown_before_1970() {
+        assert_eq!(display(at(1_766_327_945)), "2025-12-21 14:39 UTC");
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(display(before_epoch), "不明");
+        assert_eq!(rfc3339(before_epoch), None);
+    }
+
+    #[test]
+    fn days_between_is_signed() {
+        assert_eq!(days_between(at(0), at(86_400 * 3 / 2)), 1.5);
+        assert_eq!(days_between(at(86_400), at(0)), -1.0);
+    }
+}
@@ -18,18 +18,218 @@ pub enum SmasherError {
     /// ファイルが見つからない、読み込めないなど
     IoError(io::Error),
     
-    /// CSV形式が不正な場合のエラー
-    /// 
-    /// 例：カンマで区切られていない、必要な列が足りないなど
-    InvalidFormat(String),
-    
-    /// データの解析に失敗した場合のエラー
-    /// 
-    /// 例：数値に変換できない、タイムスタンプが不正、など
-    ParseError(String),
-    
     /// データが空の場合のエラー
     EmptyData(String),
+
+    /// コマンドライン引数が不正な場合のエラー
+    /// 
+    /// 例：未知のオプション、オプションの値が足りない、など
+    InvalidArgument(String),
+
+    /// 1行目（学籍番号,対戦回次）が不正な場合のエラー
+    /// 
+    /// 例：カンマで区切られていない、対戦回次が数値でない、など
+    /// 
+    /// 学籍番号などのメタ情報を直せばよいのか、行動データを直せばよいのかを
+    /// 呼び出し側が区別できるように、行動データの行のエラーとは分けている
+    HeaderError {
+        /// 行番号
+        line: usize,
+        /// 不正の種類
+        reason: ParseReason,
+        /// 人が読むための説明
+        detail: String,
+    },
+
+    /// 行動データの行（2行目以降）が不正な場合のエラー
+    /// 
+    /// 例：必要な列が足りない、タイムスタンプが数値でない、など
+    RowError {
+        /// 行番号
+        line: usize,
+        /// 不正の種類
+        reason: ParseReason,
+        /// 人が読むための説明
+        detail: String,
+    },
+
+    /// 入力が上限（ParseLimits）を超えた場合のエラー
+    TooLarge {
+        /// 超えた上限の種類
+        limit: InputLimit,
+        /// 上限の値
+        max: usize,
+        /// 上限を超えた行の番号
+        line: usize,
+    },
+
+    /// 自己診断（--self-check）で問題が見つかった場合のエラー
+    /// 
+    /// 見つかった問題の件数を持つ
+    SelfCheckFailed(usize),
+
+    /// 複数のファイルを解析したとき、一部のファイルを解析できなかった場合のエラー
+    /// 
+    /// 解析できなかったファイルの数と、指定されたファイルの数を持つ
+    FilesFailed {
+        /// 解析できなかったファイルの数
+        failed: usize,
+        /// 指定されたファイルの数
+        total: usize,
+    },
+
+    /// 合算するとき、同じ試合のログが複数あった場合のエラー（--dup-policy error）
+    /// 
+    /// 重複した試合の学籍番号・対戦回次と、そのファイルを持つ
+    DuplicateLogs {
+        /// 学籍番号
+        student_id: String,
+        /// 対戦回次
+        match_number: u32,
+        /// 同じ試合のログのファイル（指定した順）
+        files: Vec<String>,
+    },
+
+    /// 複数のファイルを処理している途中で、Ctrl-C（SIGINT）で中断した場合のエラー
+    /// 
+    /// 中断するまでに処理したファイルの数と、指定されたファイルの数を持つ
+    Interrupted {
+        /// 中断するまでに処理したファイルの数
+        processed: usize,
+        /// 指定されたファイルの数
+        total: usize,
+    },
+
+    /// CSVではないファイル（Excelのブック、ZIP、PDFなど）を指定した場合のエラー
+    /// 
+    /// ファイルの先頭のバイトで判定する（sniff モジュール）
+    WrongFileType {
+        /// ファイルの種類
+        kind: FileKind,
+    },
+}
+
+/// CSVとしてそのままは読めないファイルの種類
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileKind {
+    /// Excelのブック（.xlsx）
+    Xlsx,
+    /// ZIPファイル。中に入っているCSVのファイル名を持つ
+    Zip {
+        /// 中に入っているCSVのファイル名
+        csv_members: Vec<String>,
+    },
+    /// PDF
+    Pdf,
+    /// BOMのないUTF-16のテキスト（--encoding utf16 なら読める）
+    Utf16,
+}
+
+/// CSVの行が不正だった理由（プログラムで判定できるように種類で表す）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseReason {
+    /// 項目が足りない
+    MissingField,
+    /// 項目が多すぎる
+    ExtraField,
+    /// 数値であるべき項目が数値でない（対戦回次など）
+    BadNumber,
+    /// タイムスタンプが数値でない、または有限の値でない
+    BadTimestamp,
+    /// 項目が空
+    EmptyField,
+    /// カンマとタブが混在している
+    MixedDelimiters,
+    /// フェーズマーカー（phase:名前）の名前が不正
+    BadMarker,
+    /// タイムスタンプが負
+    NegativeTimestamp,
+    /// タイムスタンプが前の行より小さい（行の順番が入れ替わっている）
+    OutOfOrder,
+}
+
+/// 入力の大きさの上限の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputLimit {
+    /// 入力全体のバイト数
+    Bytes,
+    /// 行動データの行数
+    Rows,
+    /// 1行のバイト数
+    LineLength,
+}
+
+impl InputLimit {
+    /// 表示用の名前
+    pub fn label(&self) -> &'static str {
+        match self {
+            InputLimit::Bytes => "入力全体のバイト数",
+            InputLimit::Rows => "行動データの行数",
+            InputLimit::LineLength => "1行のバイト数",
+        }
+    }
+}
+
+/// Ctrl-Cで中断したときの終了コード（シェルの慣例に合わせて 128 + SIGINTの番号2）
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+impl SmasherError {
+    /// エラーで終了するときの終了コード
+    /// 
+    /// # 戻り値
+    /// Ctrl-Cで中断したときは INTERRUPTED_EXIT_CODE、それ以外は1
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SmasherError::Interrupted { .. } => INTERRUPTED_EXIT_CODE,
+            _ => 1,
+        }
+    }
+
+    /// 行動データの行のエラー（RowError）を1行目のエラー（HeaderError）に付け替える
+    /// 
+    /// 区切り文字の正規化は1行目と2行目以降で共通なので、1行目を読んでいるときはこれで付け替える
+    pub fn into_header_error(self) -> Self {
+        match self {
+            SmasherError::RowError { line, reason, detail } => SmasherError::HeaderError { line, reason, detail },
+            other => other,
+        }
+    }
+
+    /// エラーの直し方の提案
+    /// 
+    /// # 戻り値
+    /// 提案できる場合はその文、なければNone
+    pub fn suggestion(&self) -> Option<&'static str> {
+        match self {
+            SmasherError::HeaderError { reason, .. } => match reason {
+                ParseReason::MissingField => Some("1行目は「学籍番号,対戦回次」の2項目をカンマ区切りで書いてください（例: b1022024,1）"),
+                ParseReason::ExtraField => Some("1行目は学籍番号と対戦回次の2項目だけにしてください。余分なカンマがないか確認してください"),
+                ParseReason::BadNumber => Some("対戦回次は数値で指定してください（例: b1022024,1）"),
+                ParseReason::EmptyField => Some("学籍番号と対戦回次のどちらも空にしないでください"),
+                ParseReason::MixedDelimiters => Some("区切り文字を半角カンマかタブのどちらかに揃えてください"),
+                ParseReason::BadTimestamp | ParseReason::BadMarker | ParseReason::NegativeTimestamp | ParseReason::OutOfOrder => None,
+            },
+            SmasherError::RowError { reason, .. } => match reason {
+                ParseReason::MissingField => Some("行動データは「タイムスタンプ,行動ID」の形式で書いてください（例: 1.04,us）"),
+                ParseReason::ExtraField => Some("行動データの行は2項目だけにしてください。余分なカンマがないか確認してください"),
+                ParseReason::BadTimestamp => Some("タイムスタンプは秒単位の数値で書いてください（例: 1.04）"),
+                ParseReason::EmptyField => Some("タイムスタンプと行動IDのどちらも空にしないでください"),
+                ParseReason::MixedDelimiters => Some("区切り文字を半角カンマかタブのどちらかに揃えてください"),
+                ParseReason::BadMarker => Some("フェーズ名は英数字と _ - だけで書いてください（例: 120.0,phase:neutral_reset）"),
+                ParseReason::NegativeTimestamp => Some("タイムスタンプは0以上の秒数で書いてください（試合開始からの経過秒数）"),
+                ParseReason::OutOfOrder => Some("行を時刻順に並べ直してください。--sort を付けると読み込むときに時刻順に並べ替えます"),
+                ParseReason::BadNumber => None,
+            },
+            SmasherError::DuplicateLogs { .. } => Some("コピーしたファイルが混ざっていないか確認してください。--dup-policy largest なら行動数の多いほうだけを使います"),
+            SmasherError::WrongFileType { kind } => Some(match kind {
+                FileKind::Xlsx => "Excelでファイルを開き、「名前を付けて保存」で「CSV UTF-8（コンマ区切り）」を選んで保存したファイルを指定してください",
+                FileKind::Zip { .. } => "ZIPファイルを展開して、中のCSVファイルを指定してください",
+                FileKind::Pdf => "PDFは読み込めません。ゲームから書き出した行動ログのCSVファイルを指定してください",
+                FileKind::Utf16 => "--encoding utf16 を付けると、UTF-16のテキストとして変換して読み込みます",
+            }),
+            _ => None,
+        }
+    }
 }
 
 // Displayトレイト (interfaceのようなもの) を実装することで、エラーメッセージを表示できるようにする
@@ -42,15 +242,49 @@ impl fmt::Display for SmasherError {
             SmasherError::IoError(err) => {
                 write!(f, "ファイル入出力エラー: {}", err)
             }
-            SmasherError::InvalidFormat(msg) => {
-                write!(f, "CSV形式エラー: {}", msg)
-            }
-            SmasherError::ParseError(msg) => {
-                write!(f, "データ解析エラー: {}", msg)
-            }
             SmasherError::EmptyData(msg) => {
                 write!(f, "データが空です: {}", msg)
             }
+            SmasherError::InvalidArgument(msg) => {
+                write!(f, "引数エラー: {}", msg)
+            }
+            SmasherError::HeaderError { line, detail, .. } => {
+                write!(f, "ヘッダ形式エラー: {}行目: {}", line, detail)
+            }
+            SmasherError::RowError { line, detail, .. } => {
+                write!(f, "行動データ形式エラー: {}行目: {}", line, detail)
+            }
+            SmasherError::TooLarge { limit, max, line } => {
+                write!(f, "入力が大きすぎます: {}行目で{}の上限 {} を超えました", line, limit.label(), max)
+            }
+            SmasherError::SelfCheckFailed(count) => {
+                write!(f, "自己診断で {} 件の問題が見つかりました", count)
+            }
+            SmasherError::FilesFailed { failed, total } => {
+                write!(f, "{} 件中 {} 件のファイルを解析できませんでした", total, failed)
+            }
+            SmasherError::Interrupted { processed, total } => {
+                write!(f, "{} 件中 {} 件のファイルを処理したところで中断しました", total, processed)
+            }
+            SmasherError::DuplicateLogs { student_id, match_number, files } => {
+                write!(
+                    f,
+                    "同じ試合（{} #{}）のログが複数あります: {}",
+                    student_id,
+                    match_number,
+                    files.join(", ")
+                )
+            }
+            SmasherError::WrongFileType { kind } => match kind {
+                FileKind::Xlsx => write!(f, "CSVではなくExcelのブック（.xlsx）です"),
+                FileKind::Zip { csv_members } => match csv_members.as_slice() {
+                    [] => write!(f, "CSVではなくZIPファイルです（中にCSVは入っていません）"),
+                    [member] => write!(f, "CSVではなくZIPファイルです（中のCSV: {}）", member),
+                    members => write!(f, "CSVではなくZIPファイルです（中にCSVが {} 個入っています）", members.len()),
+                },
+                FileKind::Pdf => write!(f, "CSVではなくPDFファイルです"),
+                FileKind::Utf16 => write!(f, "UTF-16のテキストのようです（先頭にBOMがないため、文字コードを判定できません）"),
+            },
         }
     }
 }
@@ -81,8 +315,68 @@ impl From<io::Error> for SmasherError {
 /// 
 /// 使用例：
 /// ```
+/// # use smasher::error::Result;
 /// fn some_function() -> Result<String> {
 ///     Ok("成功".to_string())
 /// }
 /// ```
-pub type Result<T> = std::result::Result<T, SmasherError>;
\ No newline at end of file
+pub type Result<T> = std::result::Result<T, SmasherError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(reason: ParseReason) -> SmasherError {
+        SmasherError::HeaderError { line: 1, reason, detail: String::new() }
+    }
+
+    fn row(reason: ParseReason) -> SmasherError {
+        SmasherError::RowError { line: 2, reason, detail: String::new() }
+    }
+
+    #[test]
+    fn header_error_suggestions() {
+        assert!(header(ParseReason::MissingField).suggestion().unwrap().contains("学籍番号,対戦回次"));
+        assert!(header(ParseReason::ExtraField).suggestion().unwrap().contains("2項目だけ"));
+        assert!(header(ParseReason::BadNumber).suggestion().unwrap().contains("対戦回次は数値"));
+        assert!(header(ParseReason::EmptyField).suggestion().unwrap().contains("空にしない"));
+        assert!(header(ParseReason::MixedDelimiters).suggestion().unwrap().contains("区切り文字"));
+        // 1行目にはタイムスタンプもフェーズマーカーもないので、提案しない
+        assert_eq!(header(ParseReason::BadTimestamp).suggestion(), None);
+        assert_eq!(header(ParseReason::BadMarker).suggestion(), None);
+        assert_eq!(header(ParseReason::NegativeTimestamp).suggestion(), None);
+        assert_eq!(header(ParseReason::OutOfOrder).suggestion(), None);
+    }
+
+    #[test]
+    fn row_error_suggestions() {
+        assert!(row(ParseReason::MissingField).suggestion().unwrap().contains("タイムスタンプ,行動ID"));
+        assert!(row(ParseReason::ExtraField).suggestion().unwrap().contains("2項目だけ"));
+        assert!(row(ParseReason::BadTimestamp).suggestion().unwrap().contains("秒単位の数値"));
+        assert!(row(ParseReason::EmptyField).suggestion().unwrap().contains("空にしない"));
+        assert!(row(ParseReason::MixedDelimiters).suggestion().unwrap().contains("区切り文字"));
+        assert!(row(ParseReason::BadMarker).suggestion().unwrap().contains("フェーズ名"));
+        assert!(row(ParseReason::NegativeTimestamp).suggestion().unwrap().contains("0以上"));
+        assert!(row(ParseReason::OutOfOrder).suggestion().unwrap().contains("--sort"));
+        // 行動データの行には数値の項目（対戦回次）がないので、提案しない
+        assert_eq!(row(ParseReason::BadNumber).suggestion(), None);
+    }
+
+    #[test]
+    fn header_and_row_errors_are_displayed_differently() {
+        assert!(header(ParseReason::MissingField).to_string().starts_with("ヘッダ形式エラー: 1行目"));
+        assert!(row(ParseReason::MissingField).to_string().starts_with("行動データ形式エラー: 2行目"));
+    }
+
+    #[test]
+    fn into_header_error_remaps_only_row_errors() {
+        match row(ParseReason::MixedDelimiters).into_header_error() {
+            SmasherError::HeaderError { line: 2, reason: ParseReason::MixedDelimiters, .. } => {}
+            other => panic!("HeaderError に付け替えられていません: {:?}", other),
+        }
+        assert!(matches!(
+            SmasherError::EmptyData(String::new()).into_header_error(),
+            SmasherError::EmptyData(_)
+        ));
+    }
+}
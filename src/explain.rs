@@ -0,0 +1,177 @@
+// explain.rs
+// 1行説明モジュール（smasher explain）
+//
+// CSVの1行だけを解析して、その行についてツールが知っていることをすべて表示する
+// 結果がおかしいときに、どの行がどう解釈されたかを確認するためのもの
+
+use crate::error::Result;
+use crate::json;
+use crate::model::ActionType;
+use crate::parser::{self, ParseContext};
+
+/// 1行の解析結果
+#[derive(Debug, Clone)]
+pub struct LineExplanation {
+    /// 入力された行
+    pub input: String,
+    /// 行番号（--line。指定がなければ1）
+    pub line: u32,
+    /// タイムスタンプ（秒）
+    pub timestamp: f64,
+    /// 行動ID
+    pub action_id: String,
+    /// 行動の分類
+    pub action_type: ActionType,
+    /// 日本語名（未登録のIDなら元のID）
+    pub name: String,
+    /// 行動IDの一覧（ATTACK_IDS等）に登録されているか
+    pub known: bool,
+}
+
+/// 1行を解析して説明を作る
+///
+/// 通常の読み込みと同じ処理（区切り文字の正規化 → 行の解析 → 分類）を通すので、
+/// エラーの内容も通常の読み込み時と同じになる
+///
+/// # 引数
+/// * `line` - 行動データの1行（例: "104.2,ub_a"）
+/// * `line_number` - ファイルでの行番号（エラーメッセージと表示に使う）
+///
+/// # 戻り値
+/// 行の説明、または解析エラー
+pub fn explain_line(line: &str, line_number: usize) -> Result<LineExplanation> {
+    let (normalized, _) = parser::normalize_delimiters(line.trim(), line_number)?;
+    let action = parser::parse_action_line(&normalized, line_number, &mut ParseContext::new())?;
+
+    let action_id = action.original_id.to_string();
+    Ok(LineExplanation {
+        input: line.to_string(),
+        line: action.line,
+        timestamp: action.timestamp,
+        name: ActionType::get_action_name(&action_id),
+        known: ActionType::is_known_id(&action_id),
+        action_type: action.action_type,
+        action_id,
+    })
+}
+
+/// 説明を人が読む形式で表示する
+pub fn display_explanation(explanation: &LineExplanation) {
+    println!("入力          : {}", explanation.input);
+    println!("行番号        : {}行目", explanation.line);
+    println!("タイムスタンプ: {:.2}秒", explanation.timestamp);
+    println!("行動ID        : {}", explanation.action_id);
+    println!(
+        "分類          : {} ({})",
+        explanation.action_type.label(),
+        explanation.action_type.key()
+    );
+    println!("名前          : {}", explanation.name);
+    if explanation.known {
+        println!("登録          : {}に登録済み", table_name(&explanation.action_type));
+    } else {
+        println!("登録          : 未登録のID（{}として扱われます）", explanation.action_type.label());
+    }
+}
+
+/// 説明をJSONにする
+pub fn explanation_to_json(explanation: &LineExplanation) -> String {
+    format!(
+        "{{\"input\":{},\"ok\":true,\"line\":{},\"timestamp\":{},\"action_id\":{},\"category\":{},\"name\":{},\"known\":{}}}",
+        json::string(&explanation.input),
+        explanation.line,
+        json::number(explanation.timestamp),
+        json::string(&explanation.action_id),
+        json::string(explanation.action_type.key()),
+        json::string(&explanation.name),
+        explanation.known
+    )
+}
+
+/// 解析エラーをJSONにする
+pub fn error_to_json(input: &str, message: &str) -> String {
+    format!(
+        "{{\"input\":{},\"ok\":false,\"error\":{}}}",
+        json::string(input),
+        json::string(message)
+    )
+}
+
+/// 分類に対応する行動ID一覧の名前
+fn table_name(action_type: &ActionType) -> &'static str {
+    match action_type {
+        ActionType::Attack => "ATTACK_IDS",
+        ActionType::Shield => "SHIELD_IDS",
+        ActionType::Dodge => "DODGE_IDS",
+        ActionType::Unknown => "どの一覧にもない",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ParseReason, SmasherError};
+
+    #[test]
+    fn valid_line_is_explained() {
+        let explanation = explain_line("104.2,ub_a", 7).unwrap();
+        assert_eq!(explanation.input, "104.2,ub_a");
+        assert_eq!((explanation.line, explanation.timestamp), (7, 104.2));
+        assert_eq!(explanation.action_id, "ub_a");
+        assert_eq!(explanation.action_type, ActionType::Attack);
+        assert_eq!(explanation.name, ActionType::get_action_name("ub_a"));
+        assert!(explanation.known);
+        assert_eq!(
+            explanation_to_json(&explanation),
+            format!(
+                "{{\"input\":\"104.2,ub_a\",\"ok\":true,\"line\":7,\"timestamp\":104.2,\"action_id\":\"ub_a\",\"category\":\"attack\",\"name\":{},\"known\":true}}",
+                json::string(&explanation.name)
+            )
+        );
+    }
+
+    #[test]
+    fn alternative_spellings_resolve_to_the_same_id() {
+        // 行動IDの別名の一覧はないので、読み込み時と同じ表記ゆれ（全角カンマ・引用符・空白）を確かめる
+        let canonical = explain_line("104.2,ub_a", 1).unwrap();
+        for line in ["104.2，ub_a", "104.2,\"ub_a\"", "  104.2 , ub_a  "] {
+            let explanation = explain_line(line, 1).unwrap();
+            assert_eq!(explanation.input, line);
+            assert_eq!(
+                (explanation.timestamp, explanation.action_id.as_str(), explanation.known),
+                (canonical.timestamp, "ub_a", true),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_id_is_explained_as_unknown() {
+        let explanation = explain_line("3.5,jump", 1).unwrap();
+        assert_eq!(explanation.action_type, ActionType::Unknown);
+        assert_eq!(explanation.name, "jump");
+        assert!(!explanation.known);
+        assert!(explanation_to_json(&explanation).ends_with("\"category\":\"unknown\",\"name\":\"jump\",\"known\":false}"));
+    }
+
+    #[test]
+    fn malformed_line_reports_the_parse_error() {
+        let cases = [
+            ("104.2", ParseReason::MissingField),
+            ("104.2,us,extra", ParseReason::ExtraField),
+            ("abc,us", ParseReason::BadTimestamp),
+            ("-1.0,us", ParseReason::NegativeTimestamp),
+            ("1.0,us\t2", ParseReason::MixedDelimiters),
+        ];
+        for (line, expected) in cases {
+            match explain_line(line, 12) {
+                Err(SmasherError::RowError { line: 12, reason, .. }) => assert_eq!(reason, expected, "{:?}", line),
+                other => panic!("{:?}: {:?}", line, other),
+            }
+        }
+        let message = explain_line("abc,us", 12).unwrap_err().to_string();
+        assert_eq!(error_to_json("abc,us", &message), format!("{{\"input\":\"abc,us\",\"ok\":false,\"error\":{}}}", json::string(&message)));
+        assert!(message.starts_with("行動データ形式エラー: 12行目: "), "{}", message);
+    }
+}
@@ -0,0 +1,149 @@
+// follow.rs
+// 追従モジュール（--follow）
+//
+// 書き込み中のファイルや名前付きパイプのような終端しない入力を
+// tail -f のように読み続け、一定間隔で集計を再表示する
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::analyzer;
+use crate::cli::Options;
+use crate::error::{Result, SmasherError};
+use crate::model::{Action, BattleLog, PlayerInfo};
+use crate::output::{self, DisplayOptions};
+use crate::parser;
+use crate::signal;
+
+/// 新しい行が来ていないときに待つ時間
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 入力を読み続けて集計を表示する
+///
+/// # 処理の流れ
+/// 1. 1行目（プレイヤー情報）が来るまで待つ
+/// 2. 行動データを1行ずつ読み、`refresh_secs` ごとに集計を再表示する
+/// 3. 新しい行が来ない間は待機する
+/// 4. --snapshot-interval があれば、その間隔で途中の解析結果をファイルに保存する
+/// 5. Ctrl-Cで最終結果を表示して終了する
+pub fn follow(options: &Options, display: &DisplayOptions) -> Result<()> {
+    signal::install_interrupt_handler();
+
+    let file = File::open(&options.file_paths[0])?;
+    let mut reader = BufReader::new(file);
+    let refresh = Duration::from_secs_f64(options.refresh_secs);
+
+    // 改行がまだ来ていない行の断片
+    let mut pending = String::new();
+    let mut player_info: Option<PlayerInfo> = None;
+    let mut actions: Vec<Action> = Vec::new();
+    let mut line_number = 0;
+    let mut context = parser::ParseContext::new();
+    // 前回表示してから新しい行動が来たかどうか
+    let mut updated = false;
+    let mut last_display = Instant::now();
+    let mut last_snapshot = Instant::now();
+
+    while !signal::interrupted() {
+        if let Some(interval) = options.snapshot_interval {
+            if last_snapshot.elapsed() >= Duration::from_secs_f64(interval) {
+                if let Some(player_info) = &player_info {
+                    save_snapshot(player_info, &actions, options)?;
+                }
+                last_snapshot = Instant::now();
+            }
+        }
+
+        let read = reader.read_line(&mut pending)?;
+
+        // 行が最後まで書かれていない（または何も来ていない）間は待機
+        if read == 0 || !pending.ends_with('\n') {
+            if updated && last_display.elapsed() >= refresh {
+                display_progress(player_info.as_ref(), &actions, options, display);
+                updated = false;
+                last_display = Instant::now();
+            }
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        // std::mem::take: 中身を取り出して空文字列に置き換える
+        let line = std::mem::take(&mut pending);
+        line_number += 1;
+        let normalized = parser::normalize_delimiters(line.trim(), line_number);
+        // 1行目の区切り文字のエラーはヘッダのエラーとして扱う
+        let (line, _) = match player_info {
+            None => normalized.map_err(SmasherError::into_header_error)?,
+            Some(_) => normalized?,
+        };
+        let trimmed = line.as_str();
+
+        match player_info {
+            None => player_info = Some(parser::parse_header_line(trimmed)?),
+            Some(_) if trimmed.is_empty() => {}
+            // フェーズマーカーは逐次表示では使わない
+            Some(_) if parser::parse_marker_line(trimmed, line_number)?.is_some() => {}
+            Some(_) => {
+                let mut action = parser::parse_action_line(trimmed, line_number, &mut context)?;
+                action.timestamp += options.shift_secs;
+                actions.push(action);
+                updated = true;
+            }
+        }
+    }
+
+    // Ctrl-Cで抜けたら最終結果を表示
+    let player_info = player_info
+        .ok_or_else(|| SmasherError::EmptyData("ファイルが空です".to_string()))?;
+    if actions.is_empty() {
+        return Err(SmasherError::EmptyData(
+            "行動データが1つも見つかりませんでした".to_string()
+        ));
+    }
+
+    println!();
+    println!("追従を終了しました。最終結果: {} 件の行動データ", actions.len());
+    println!();
+    let battle_log = BattleLog::new(player_info, actions);
+    output::display_result(&analyzer::analyze(&battle_log, &options.analysis), display);
+
+    Ok(())
+}
+
+/// 途中の解析結果をタイムスタンプ付きのJSONファイルに保存する
+///
+/// ファイル名は snapshot-<学籍番号>-<保存時刻のUNIX時間（秒）>.json。
+/// 保存先ディレクトリがなければ作る。行動がまだなければ保存しない
+fn save_snapshot(player_info: &PlayerInfo, actions: &[Action], options: &Options) -> Result<()> {
+    if actions.is_empty() {
+        return Ok(());
+    }
+
+    let battle_log = BattleLog::new(player_info.clone(), actions.to_vec());
+    let result = analyzer::analyze(&battle_log, &options.analysis);
+
+    // 時計が1970年より前を指していることはまずないので、その場合は0秒として扱う
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    fs::create_dir_all(&options.snapshot_dir)?;
+    let path = Path::new(&options.snapshot_dir).join(format!("snapshot-{}-{}.json", player_info.student_id, now));
+    fs::write(&path, output::to_json(&result) + "\n")?;
+    Ok(())
+}
+
+/// 途中経過の集計を画面を消してから表示する
+fn display_progress(player_info: Option<&PlayerInfo>, actions: &[Action], options: &Options, display: &DisplayOptions) {
+    let Some(player_info) = player_info else {
+        return;
+    };
+
+    // 画面を消してカーソルを左上に戻す（ANSIエスケープシーケンス）
+    print!("\x1b[2J\x1b[H");
+    println!("追従中: {} ({} 件, Ctrl-Cで終了)", options.file_paths[0], actions.len());
+    println!();
+
+    let battle_log = BattleLog::new(player_info.clone(), actions.to_vec());
+    output::display_result(&analyzer::analyze(&battle_log, &options.analysis), display);
+}
@@ -0,0 +1,66 @@
+// group.rs
+// グループ（チーム）割り当てモジュール
+//
+// 学籍番号 → グループ名 の対応をファイルから読み込む
+// 対応にない学籍番号は「未分類」グループとして扱う
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Result, SmasherError};
+
+/// 対応にない学籍番号が入るグループの名前
+pub const UNGROUPED: &str = "未分類";
+
+/// 学籍番号 → グループ名 の対応
+#[derive(Debug, Clone)]
+pub struct GroupMap {
+    groups: HashMap<String, String>,
+}
+
+impl GroupMap {
+    /// グループ割り当てファイルを読み込む
+    ///
+    /// # ファイル形式
+    /// 1行に「学籍番号,グループ名」を書く。空行と # で始まる行は無視する。
+    /// ```text
+    /// # チームA
+    /// b1022024,チームA
+    /// b1022025,チームA
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let mut groups = HashMap::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split(',').map(|p| p.trim()).collect();
+            match parts[..] {
+                [student_id, group] if !student_id.is_empty() && !group.is_empty() => {
+                    groups.insert(student_id.to_string(), group.to_string());
+                }
+                _ => {
+                    return Err(SmasherError::InvalidArgument(format!(
+                        "グループファイル {} の{}行目: 「学籍番号,グループ名」の形式である必要があります。実際: {}",
+                        path.display(),
+                        index + 1,
+                        line
+                    )));
+                }
+            }
+        }
+
+        Ok(GroupMap { groups })
+    }
+
+    /// 学籍番号が属するグループ名（対応になければ「未分類」）
+    pub fn group_of(&self, student_id: &str) -> &str {
+        self.groups.get(student_id).map(|g| g.as_str()).unwrap_or(UNGROUPED)
+    }
+}
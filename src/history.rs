@@ -0,0 +1,117 @@
+// history.rs
+// 前回の解析結果のキャッシュモジュール（--compare-last）
+//
+// 入力ファイルのパスごとに、前回解析したときの行動回数を1行ずつテキストで保存しておく
+// 保存先はユーザーのキャッシュディレクトリ（$XDG_CACHE_HOME/smasher、なければ ~/.cache/smasher）
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::model::ActionCounts;
+
+/// キャッシュのファイル名
+const CACHE_FILE: &str = "last_counts.tsv";
+
+/// キャッシュのファイルのパス
+///
+/// # 戻り値
+/// キャッシュのパス。ホームディレクトリもわからないときはNone
+fn cache_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        // Windows ではホームの代わりに %LOCALAPPDATA% を使う
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))?;
+    Some(base.join("smasher").join(CACHE_FILE))
+}
+
+/// キャッシュのキーにする入力ファイルのパス
+///
+/// 別のディレクトリから実行しても同じキーになるように絶対パスにする（できなければ指定されたまま）
+fn cache_key(file_path: &str) -> String {
+    fs::canonicalize(file_path)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| file_path.to_string())
+}
+
+/// キャッシュの1行（パス\t攻撃\tシールド\t回避\t不明）を読む
+fn parse_entry(line: &str) -> Option<(String, ActionCounts)> {
+    let parts: Vec<&str> = line.split('\t').collect();
+    let [path, attack, shield, dodge, unknown] = parts[..] else {
+        return None;
+    };
+    let counts = ActionCounts {
+        attack_count: attack.parse().ok()?,
+        shield_count: shield.parse().ok()?,
+        dodge_count: dodge.parse().ok()?,
+        unknown_count: unknown.parse().ok()?,
+    };
+    Some((path.to_string(), counts))
+}
+
+/// キャッシュをすべて読む（ファイルがない、壊れた行があるときは読める分だけ）
+fn load_all(path: &Path) -> Vec<(String, ActionCounts)> {
+    fs::read_to_string(path)
+        .map(|text| text.lines().filter_map(parse_entry).collect())
+        .unwrap_or_default()
+}
+
+/// 前回解析したときの行動回数を読む
+///
+/// # 引数
+/// * `file_path` - 入力ファイルのパス
+///
+/// # 戻り値
+/// 前回の行動回数。前回の記録がなければNone
+pub fn load_last(file_path: &str) -> Option<ActionCounts> {
+    let key = cache_key(file_path);
+    load_all(&cache_path()?)
+        .into_iter()
+        .find(|(path, _)| *path == key)
+        .map(|(_, counts)| counts)
+}
+
+/// 今回の行動回数を、次回の比較用に保存する（同じパスの前回の記録は置き換える）
+///
+/// # 引数
+/// * `file_path` - 入力ファイルのパス
+/// * `counts` - 今回の行動回数
+///
+/// # 戻り値
+/// 保存できたらtrue、キャッシュの場所がわからない・パスにタブや改行が入っていて保存しなかったらfalse
+pub fn save_last(file_path: &str, counts: &ActionCounts) -> Result<bool> {
+    let key = cache_key(file_path);
+    let Some(path) = cache_path() else {
+        return Ok(false);
+    };
+    // 1行1件のタブ区切りなので、タブや改行の入ったパスは保存できない
+    if key.contains(['\t', '\n', '\r']) {
+        return Ok(false);
+    }
+
+    let mut entries = load_all(&path);
+    entries.retain(|(entry_path, _)| *entry_path != key);
+    entries.push((key, *counts));
+
+    let mut text = String::new();
+    for (entry_path, c) in &entries {
+        text.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            entry_path, c.attack_count, c.shield_count, c.dodge_count, c.unknown_count
+        ));
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // 一時ファイルに書いてから置き換える（書いている途中で止まっても、前の記録が壊れないように）
+    let temp = path.with_extension(format!("tsv.{}.tmp", std::process::id()));
+    fs::write(&temp, text)?;
+    if let Err(e) = fs::rename(&temp, &path) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(true)
+}
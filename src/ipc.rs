@@ -0,0 +1,83 @@
+// ipc.rs
+// 別プロセスへの結果送信モジュール（--ipc）
+//
+// GUIフロントエンドなどが待ち受けているUnixソケットに接続し、解析結果のJSONを1行で送る
+// フロントエンドがまだ待ち受けていないときは、待つかスキップするかを選べる
+
+use std::thread;
+use std::time::Duration;
+
+use crate::error::{Result, SmasherError};
+use crate::signal;
+
+/// 接続できなかったときに再接続を試みる間隔
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 送り先が待ち受けていないときの動作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpcMode {
+    /// 接続できるまで待つ（Ctrl-Cでやめる）
+    Wait,
+    /// 送らずに続ける
+    Skip,
+}
+
+impl IpcMode {
+    /// 名前から動作を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wait" => Some(IpcMode::Wait),
+            "skip" => Some(IpcMode::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// Unixソケットに1行のJSONを送る
+///
+/// # 引数
+/// * `path` - ソケットのパス
+/// * `json` - 送るJSON（末尾に改行を付けて送る）
+/// * `mode` - 接続できないときの動作
+///
+/// # 戻り値
+/// 送れたらtrue、Skipで送らなかったらfalse
+pub fn send(path: &str, json: &str, mode: IpcMode) -> Result<bool> {
+    imp::send(path, json, mode)
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::net::UnixStream;
+
+    pub fn send(path: &str, json: &str, mode: IpcMode) -> Result<bool> {
+        signal::install_interrupt_handler();
+        let mut stream = loop {
+            match UnixStream::connect(path) {
+                Ok(stream) => break stream,
+                Err(_) if mode == IpcMode::Skip => return Ok(false),
+                Err(_) if signal::interrupted() => {
+                    return Err(SmasherError::InvalidArgument(format!(
+                        "{} への接続を待っている間に中断されました",
+                        path
+                    )));
+                }
+                Err(_) => thread::sleep(RETRY_INTERVAL),
+            }
+        };
+        stream.write_all(json.as_bytes())?;
+        stream.write_all(b"\n")?;
+        Ok(true)
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    use super::*;
+
+    pub fn send(_path: &str, _json: &str, _mode: IpcMode) -> Result<bool> {
+        Err(SmasherError::InvalidArgument("--ipc はUnix系のOSでのみ使えます".to_string()))
+    }
+}
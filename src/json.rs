@@ -0,0 +1,386 @@
+// json.rs
+// JSON出力用の小さなヘルパー
+//
+// 標準ライブラリだけでJSONを組み立てるための、文字列のエスケープと数値の書式。
+// 以前に書き出したJSONを読み直すための、小さな読み込み（parse）もここに置く
+
+/// 文字列をJSONの文字列リテラル（ダブルクォート付き）にする
+pub fn string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // その他の制御文字は \uXXXX で書く
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            // JSONとしては書いてよいが、JavaScriptの文字列では改行扱いになる文字も \uXXXX で書く
+            '\u{2028}' | '\u{2029}' => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// 浮動小数点数をJSONの数値にする
+///
+/// JSONはNaNや無限大を表せないので、その場合はnullにする
+pub fn number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// 浮動小数点数を小数点以下の桁数を固定したJSONの数値にする
+///
+/// 0.1 + 0.2 のような計算誤差で 0.30000000000000004 と出ないように、出力を安定させたいときに使う
+///
+/// # 引数
+/// * `value` - 数値
+/// * `decimals` - 小数点以下の桁数
+///
+/// # 戻り値
+/// JSONの数値（NaNや無限大ならnull）
+pub fn fixed(value: f64, decimals: usize) -> String {
+    if value.is_finite() {
+        format!("{:.*}", decimals, value)
+    } else {
+        "null".to_string()
+    }
+}
+
+/// 読み込んだJSONの値（smasher migrate で以前の出力を読み直すときに使う）
+///
+/// 数値は書かれていた文字列のまま持ち、オブジェクトのキーは書かれていた順に持つ。
+/// 書き直したときに、変えていないところは元の文字列と同じになるようにするため
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// 数値（書かれていた文字列のまま）
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// オブジェクト（キーと値の組を書かれていた順に）
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// オブジェクトのキーの値を取得する（オブジェクトでないか、キーがなければNone）
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// オブジェクトのキーの値を、書き換えられる形で取得する
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        match self {
+            Value::Object(fields) => fields.iter_mut().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// 数値ならf64にする
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// オブジェクトのキーの値を置き換える（キーがなければ最後に足す）
+    pub fn set(&mut self, key: &str, value: Value) {
+        match self.get_mut(key) {
+            Some(old) => *old = value,
+            None => self.insert_after("", key, value),
+        }
+    }
+
+    /// オブジェクトにキーを足す（すでにあれば値を置き換える）
+    ///
+    /// 新しく足すときは `after` のキーの直後に入れる（`after` がなければ最後に入れる）。
+    /// オブジェクトでなければ何もしない
+    pub fn insert_after(&mut self, after: &str, key: &str, value: Value) {
+        let Value::Object(fields) = self else {
+            return;
+        };
+        if let Some((_, old)) = fields.iter_mut().find(|(k, _)| k == key) {
+            *old = value;
+            return;
+        }
+        let at = fields.iter().position(|(k, _)| k == after).map_or(fields.len(), |i| i + 1);
+        fields.insert(at, (key.to_string(), value));
+    }
+
+    /// 1行のJSON文字列にする（空白は入れない）
+    pub fn to_json(&self) -> String {
+        match self {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Number(text) => text.clone(),
+            Value::String(text) => string(text),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(Value::to_json).collect();
+                format!("[{}]", items.join(","))
+            }
+            Value::Object(fields) => {
+                let fields: Vec<String> = fields.iter().map(|(k, v)| format!("{}:{}", string(k), v.to_json())).collect();
+                format!("{{{}}}", fields.join(","))
+            }
+        }
+    }
+}
+
+/// JSONの文字列を読み込む
+///
+/// # 引数
+/// * `text` - JSONの文字列（前後の空白は読み飛ばす）
+///
+/// # 戻り値
+/// 読み込んだ値、または何文字目がおかしいかの説明
+pub fn parse(text: &str) -> std::result::Result<Value, String> {
+    let mut reader = Reader { chars: text.chars().collect(), at: 0 };
+    let value = reader.value()?;
+    reader.skip_whitespace();
+    if reader.at < reader.chars.len() {
+        return Err(reader.error("値のあとに余分な文字があります"));
+    }
+    Ok(value)
+}
+
+/// parse の中身（文字の並びと、いま読んでいる位置）
+struct Reader {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Reader {
+    fn error(&self, message: &str) -> String {
+        format!("{}文字目: {}", self.at + 1, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| matches!(c, ' ' | '\t' | '\n' | '\r')) {
+            self.at += 1;
+        }
+    }
+
+    /// 次の文字が `expected` なら読み進める
+    fn expect(&mut self, expected: char) -> std::result::Result<(), String> {
+        if self.peek() == Some(expected) {
+            self.at += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("「{}」がありません", expected)))
+        }
+    }
+
+    fn value(&mut self) -> std::result::Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => self.string().map(Value::String),
+            Some('-' | '0'..='9') => self.number(),
+            Some(_) => {
+                for (word, value) in [("null", Value::Null), ("true", Value::Bool(true)), ("false", Value::Bool(false))] {
+                    if self.chars[self.at..].starts_with(&word.chars().collect::<Vec<_>>()) {
+                        self.at += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("JSONの値ではありません"))
+            }
+            None => Err(self.error("値がありません")),
+        }
+    }
+
+    fn object(&mut self) -> std::result::Result<Value, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.at += 1;
+            return Ok(Value::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.at += 1,
+                Some('}') => {
+                    self.at += 1;
+                    return Ok(Value::Object(fields));
+                }
+                _ => return Err(self.error("「,」か「}」がありません")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> std::result::Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.at += 1;
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.at += 1,
+                Some(']') => {
+                    self.at += 1;
+                    return Ok(Value::Array(items));
+                }
+                _ => return Err(self.error("「,」か「]」がありません")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> std::result::Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("文字列が閉じていません"));
+            };
+            self.at += 1;
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let escaped = self.peek().ok_or_else(|| self.error("文字列が閉じていません"))?;
+                    self.at += 1;
+                    match escaped {
+                        '"' | '\\' | '/' => out.push(escaped),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'u' => out.push(self.unicode_escape()?),
+                        _ => return Err(self.error("不正なエスケープです")),
+                    }
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("文字列の中に制御文字があります")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// \uXXXX（サロゲートペアなら2つ続けて）を1文字にする。\u はすでに読んだ状態で呼ぶ
+    fn unicode_escape(&mut self) -> std::result::Result<char, String> {
+        let first = self.hex4()?;
+        if (0xD800..0xDC00).contains(&first) {
+            if self.chars.get(self.at..self.at + 2) == Some(&['\\', 'u']) {
+                self.at += 2;
+                let second = self.hex4()?;
+                if (0xDC00..0xE000).contains(&second) {
+                    let code = 0x10000 + ((first - 0xD800) << 10) + (second - 0xDC00);
+                    return char::from_u32(code).ok_or_else(|| self.error("不正な文字です"));
+                }
+            }
+            return Err(self.error("対になっていないサロゲートです"));
+        }
+        char::from_u32(first).ok_or_else(|| self.error("対になっていないサロゲートです"))
+    }
+
+    fn hex4(&mut self) -> std::result::Result<u32, String> {
+        let digits: String = self.chars.get(self.at..self.at + 4).map(|c| c.iter().collect()).unwrap_or_default();
+        let code = u32::from_str_radix(&digits, 16).ok().filter(|_| digits.len() == 4).ok_or_else(|| self.error("\\u のあとに16進数4桁が必要です"))?;
+        self.at += 4;
+        Ok(code)
+    }
+
+    fn number(&mut self) -> std::result::Result<Value, String> {
+        let start = self.at;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')) {
+            self.at += 1;
+        }
+        let text: String = self.chars[start..self.at].iter().collect();
+        // Rustの f64 の読み方はJSONより少しゆるい（"1." や "+1" も読める）ので、形も確かめる
+        let json_like = !text.starts_with('+') && !text.ends_with('.') && !text.contains(".e") && !text.contains(".E");
+        if json_like && text.parse::<f64>().is_ok_and(f64::is_finite) {
+            Ok(Value::Number(text))
+        } else {
+            self.at = start;
+            Err(self.error(&format!("数値として読めません: {}", text)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn string_escapes_quotes_and_control_characters() {
+        assert_eq!(string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(string("1\n2\r3\t4\u{1}"), r#""1\n2\r3\t4\u0001""#);
+    }
+
+    #[test]
+    fn string_escapes_javascript_line_separators() {
+        assert_eq!(string("a\u{2028}b\u{2029}c"), r#""a\u2028b\u2029c""#);
+    }
+
+    #[test]
+    fn string_keeps_other_unicode_as_is() {
+        for text in ["👨\u{200d}👩\u{200d}👧", "e\u{301}", "مرحبا", "\u{202e}abc\u{202c}", "🇯🇵"] {
+            assert_eq!(string(text), format!("\"{}\"", text));
+        }
+    }
+
+    #[test]
+    fn number_writes_null_for_non_finite_values() {
+        assert_eq!(number(f64::NAN), "null");
+        assert_eq!(number(f64::INFINITY), "null");
+    }
+
+    #[test]
+    fn parse_reads_every_kind_of_value_and_keeps_the_text() {
+        let text = r#"{"a":1.000,"b":[true,false,null],"c":{"d":"x\"é🎮"},"e":-2.5e3,"f":[]}"#;
+        let value = parse(text).unwrap();
+        assert_eq!(value.get("a"), Some(&Value::Number("1.000".to_string())));
+        assert_eq!(value.get("a").and_then(Value::as_f64), Some(1.0));
+        assert_eq!(value.get("b"), Some(&Value::Array(vec![Value::Bool(true), Value::Bool(false), Value::Null])));
+        assert_eq!(value.get("c").and_then(|c| c.get("d")), Some(&Value::String("x\"é🎮".to_string())));
+        // 数値とキーの順番はそのまま、文字列は string() と同じ書き方で書き直す
+        assert_eq!(value.to_json(), r#"{"a":1.000,"b":[true,false,null],"c":{"d":"x\"é🎮"},"e":-2.5e3,"f":[]}"#);
+        assert_eq!(parse(" \n[ 1 , {} ]\r\n").unwrap().to_json(), "[1,{}]");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_json() {
+        for text in ["", "{", r#"{"a" 1}"#, r#"{"a":1,}"#, "[1 2]", r#""abc"#, "01x", "1.", "+1", "nul", r#""\ud800""#, "{} {}", "NaN"] {
+            assert!(parse(text).is_err(), "{:?}", text);
+        }
+        assert_eq!(parse("[1, ?]").unwrap_err(), "5文字目: JSONの値ではありません");
+    }
+
+    #[test]
+    fn insert_after_keeps_the_key_order() {
+        let mut value = parse(r#"{"a":1,"c":3}"#).unwrap();
+        value.insert_after("a", "b", Value::Number("2".to_string()));
+        value.insert_after("missing", "d", Value::Null);
+        value.insert_after("a", "c", Value::Bool(true));
+        assert_eq!(value.to_json(), r#"{"a":1,"b":2,"c":true,"d":null}"#);
+    }
+}
@@ -0,0 +1,46 @@
+// lib.rs
+// ライブラリとしての入り口
+//
+// 読み込み・解析・表示の処理をほかのRustプログラムから使えるように公開する
+// smasher コマンド（main.rs）もこのライブラリを使って動いている
+//
+// 使い方（Cargo.toml の dependencies に smasher を追加して）:
+//     let result = smasher::analyze_file("b1022024_1times_251221_1439.csv")?;
+//     println!("攻撃: {} 回", result.counts.attack_count);
+
+use std::path::Path;
+
+// 解析の流れの中心になるモジュール
+pub mod model;
+pub mod error;
+pub mod parser;
+pub mod analyzer;
+pub mod output;
+
+// 上のモジュールが使っている補助のモジュール（smasher コマンドからも使う）
+pub mod datetime;
+pub mod group;
+pub mod json;
+pub mod schema;
+pub mod sniff;
+pub mod sparkline;
+pub mod style;
+
+use analyzer::AnalysisOptions;
+use error::Result;
+use model::AnalysisResult;
+
+/// CSVファイルを読み込んで、デフォルトの設定で解析する
+///
+/// parser::read_battle_log と analyzer::analyze をまとめて呼ぶだけの関数。
+/// 設定を変えたいときは、この2つを直接呼ぶ。
+///
+/// # 引数
+/// * `path` - 入力CSVファイルのパス
+///
+/// # 戻り値
+/// 解析結果。ファイルが開けない・形式が不正ならエラー
+pub fn analyze_file<P: AsRef<Path>>(path: P) -> Result<AnalysisResult> {
+    let battle_log = parser::read_battle_log(path)?;
+    Ok(analyzer::analyze(&battle_log, &AnalysisOptions::new()))
+}
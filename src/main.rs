@@ -4,15 +4,30 @@
 // main.rs
 // エントリポイント
 
-// モジュールの宣言
-mod model;
-mod error;
-mod parser;
-mod analyzer;
-mod output;
+// 解析の処理はライブラリ（lib.rs）にある
+// ここで use しておくと、コマンド用のモジュールからも crate::model のように書ける
+use smasher::{analyzer, error, group, json, model, output, parser, schema, sniff, sparkline, style};
+
+// コマンド用のモジュールの宣言
+mod cli;
+mod completion;
+mod context;
+mod convert;
+mod follow;
+mod explain;
+mod generate;
+mod history;
+mod ipc;
+mod migrate;
+mod pipeline;
+mod signal;
+mod watch;
 
 use std::env;
+use std::fs;
+use std::io;
 use error::Result;
+use output::Verbosity;
 
 /// # 処理の流れ
 /// 1. コマンドライン引数からファイルパスを取得
@@ -24,9 +39,13 @@ fn main() {
     if let Err(e) = run() {
         // エラーメッセージを赤文字で表示（ANSIエスケープシーケンス）
         eprintln!("\x1b[31mエラーが発生しました: {}\x1b[0m", e);
+        // 直し方が分かるエラーは提案も表示する
+        if let Some(suggestion) = e.suggestion() {
+            eprintln!("ヒント: {}", suggestion);
+        }
         
-        // エラー終了（終了コード1）
-        std::process::exit(1);
+        // エラー終了（Ctrl-Cで中断したときは130、それ以外は終了コード1）
+        std::process::exit(e.exit_code());
     }
 }
 
@@ -37,38 +56,750 @@ fn main() {
 fn run() -> Result<()> {
     // コマンドライン引数を取得
     // ::  Javaでいう . 
-    let args: Vec<String> = env::args().collect();
+    // skip(1): 先頭のプログラム名を除く
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match cli::parse_args(&args)? {
+        cli::Command::Analyze(options) => options,
+        cli::Command::Completions(shell) => {
+            // 補完スクリプトだけを出力して終了
+            print!("{}", completion::generate(shell));
+            return Ok(());
+        }
+        cli::Command::Explain { line, line_number, json } => return run_explain(&line, line_number, json),
+        cli::Command::Context { file_path, at, n, keep_raw, json } => {
+            return run_context(&file_path, at, n, keep_raw, json)
+        }
+        cli::Command::Migrate(migrate_options) => return migrate::migrate_dir(&migrate_options),
+        cli::Command::SelfCheck => return run_self_check(),
+        cli::Command::PrintSchema => {
+            // スキーマだけを出力して終了
+            println!("{}", schema::analysis_schema());
+            return Ok(());
+        }
+        cli::Command::ListFormats { json } => {
+            print_list("formats", &cli::formats(), json);
+            return Ok(());
+        }
+        cli::Command::ListSections { json } => {
+            print_list("sections", &schema::sections(), json);
+            return Ok(());
+        }
+        cli::Command::Generate(generate_options) => {
+            // 生成したCSVだけを出力して終了（リダイレクトしてファイルにできる）
+            print!("{}", generate::generate_csv(&generate_options));
+            return Ok(());
+        }
+    };
     
-    // 引数の数をチェック
-    if args.len() < 2 {
-        return Err(error::SmasherError::InvalidFormat(
-            "ファイルパスが指定されていません".to_string()
-        ));
+    // 表示の設定を作る（色ルールファイルがあれば読み込む）
+    let rules = match &options.color_rules {
+        Some(path) => style::ColorRules::load(path)?,
+        None => style::ColorRules::new(),
+    };
+    let mut style = style::Style::new(options.color, rules)
+        .with_width(options.width)
+        .with_lang(options.lang);
+    // -o / --outputなら、レポートだけをファイルに書く（途中経過は今まで通り端末に出す）
+    let mut temp_output = None;
+    if let Some(path) = &options.output {
+        let (sink, temp) = open_output(path, options.append)?;
+        style = style.with_sink(sink, options.color);
+        temp_output = temp;
+    }
+    let display = output::DisplayOptions {
+        verbosity: options.verbosity,
+        style,
+        ascii: sparkline::prefer_ascii(),
+        sort_categories: options.sort_categories,
+        narrate: options.narrate,
+    };
+    
+    let outcome = run_analysis(&options, &display);
+    // 解析が途中で失敗しても、それまでに書いた分はファイルに残す（エラーは解析のほうを優先して知らせる）
+    if let (Some(path), Some(sink)) = (&options.output, &display.style.sink) {
+        let finished = sink.finish().map_err(|e| output_error(path, e));
+        let finished = match &temp_output {
+            Some(temp) => finished.and_then(|()| replace_output(temp, path)),
+            None => finished,
+        };
+        outcome?;
+        return finished;
+    }
+    outcome
+}
+
+/// --output のファイルを開く
+///
+/// 上書きするときは、同じディレクトリの一時ファイルに書いて、書き終わってから replace_output で置き換える。
+/// 途中で失敗・中断しても、前の内容が書きかけのファイルで壊れないようにするため
+///
+/// # 引数
+/// * `path` - ファイルのパス
+/// * `append` - trueなら末尾に追記する（--append）、falseなら上書きする
+///
+/// # 戻り値
+/// (書き出し先, 一時ファイルのパス（直接書くときはNone）)。開けなければファイル名付きの IoError
+fn open_output(path: &str, append: bool) -> Result<(style::Sink, Option<String>)> {
+    let temp = if append { None } else { temp_output_path(path) };
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(temp.as_deref().unwrap_or(path))
+        .map_err(|e| output_error(path, e))?;
+    Ok((style::Sink::new(io::BufWriter::new(file)), temp))
+}
+
+/// 上書きする書き出し先の代わりに書く、一時ファイルのパス
+///
+/// # 戻り値
+/// 一時ファイルのパス。/dev/stdout のような普通のファイルでない書き出し先なら、直接書くのでNone
+fn temp_output_path(path: &str) -> Option<String> {
+    match fs::metadata(path) {
+        Ok(metadata) if !metadata.is_file() => None,
+        _ => Some(format!("{}.{}.tmp", path, std::process::id())),
+    }
+}
+
+/// 書き終えた一時ファイルで、書き出し先を置き換える（置き換えられなければ一時ファイルを消す）
+fn replace_output(temp: &str, path: &str) -> Result<()> {
+    fs::rename(temp, path).map_err(|e| {
+        let _ = fs::remove_file(temp);
+        output_error(path, e)
+    })
+}
+
+/// --out-csv: 解析結果を1試合1行の集計CSVにしてファイルに書く（ファイルがあれば上書きする）
+///
+/// # 引数
+/// * `path` - 書き出すファイルのパス
+/// * `results` - 解析結果（この順で1行ずつ書く）
+fn write_out_csv(path: &str, results: &[model::AnalysisResult]) -> Result<()> {
+    let temp = temp_output_path(path);
+    let file = fs::File::create(temp.as_deref().unwrap_or(path)).map_err(|e| output_error(path, e))?;
+    let written = output::write_summary_csv(results, io::BufWriter::new(file)).map_err(|e| match e {
+        error::SmasherError::IoError(e) => output_error(path, e),
+        e => e,
+    });
+    match temp {
+        Some(temp) if written.is_ok() => replace_output(&temp, path),
+        Some(temp) => {
+            let _ = fs::remove_file(temp);
+            written
+        }
+        None => written,
+    }
+}
+
+/// --output のファイルの入出力エラーに、どのファイルかを付ける
+fn output_error(path: &str, e: io::Error) -> error::SmasherError {
+    error::SmasherError::IoError(io::Error::new(e.kind(), format!("{}: {}", path, e)))
+}
+
+/// 解析のモードを選んで実行する（表示の設定を作ったあとの処理）
+fn run_analysis(options: &cli::Options, display: &output::DisplayOptions) -> Result<()> {
+    // --followなら読み続けるモードに切り替える
+    if options.follow {
+        return follow::follow(options, display);
+    }
+    
+    // --watchならファイルの更新を監視して解析し直すモードに切り替える
+    if options.watch {
+        return watch::watch(options, display);
+    }
+    
+    // --new-sinceなら以前のログとの差分だけを表示する
+    if let Some(old_path) = &options.new_since {
+        let old = read_log(options, old_path)?;
+        let new = read_log(options, &options.file_paths[0])?;
+        output::display_log_diff(&analyzer::diff_logs(&old, &new));
+        return Ok(());
+    }
+    
+    // --convertなら解析せずに形式だけ変換する
+    if let (Some(format), Some(out_dir)) = (options.convert, &options.out_dir) {
+        return convert::convert_files(&options.file_paths, format, out_dir, options.overwrite);
+    }
+    
+    // --compare-actionsなら2つのログの行動の使用頻度を比べる
+    if options.compare_actions {
+        let (path_a, path_b) = (&options.file_paths[0], &options.file_paths[1]);
+        let a = read_log(options, path_a)?;
+        let b = read_log(options, path_b)?;
+        let (categories, comparisons) = (analyzer::compare_categories(&a, &b), analyzer::compare_actions(&a, &b));
+        let rates = match options.normalize {
+            model::Normalize::Raw => None,
+            model::Normalize::PerMinute => {
+                let rates = analyzer::compare_per_minute(&a, &b);
+                if rates.is_none() {
+                    eprintln!("※ 試合時間が0秒のログ（行動が1件以下、またはすべて同じ時刻）があるので、1分あたりに直さず回数のまま比べます");
+                }
+                rates
+            }
+        };
+        if options.json {
+            println!("{}", output::action_comparison_to_json(path_a, path_b, &categories, &comparisons, rates.as_deref()));
+        } else {
+            output::display_action_comparison(path_a, path_b, &categories, &comparisons, rates.as_deref());
+        }
+        return Ok(());
+    }
+    
+    // --improvementなら複数の試合から改善レポートを作る
+    if options.improvement {
+        return run_improvement(options);
+    }
+    
+    // --groupsなら複数ファイルをグループ別に集計する
+    if let Some(groups_path) = &options.groups {
+        return run_groups(options, groups_path);
+    }
+    
+    // --similarityなら2つのログの行動IDの使い方がどれくらい似ているかを出す
+    if options.similarity {
+        let (path_a, path_b) = (&options.file_paths[0], &options.file_paths[1]);
+        let a = read_log(options, path_a)?;
+        let b = read_log(options, path_b)?;
+        output::display_similarity(path_a, path_b, analyzer::cosine_similarity(&a, &b));
+        return Ok(());
+    }
+    
+    // --clusterなら行動IDの使い方が似た試合同士に分ける
+    if let Some(k) = options.cluster {
+        return run_cluster(options, k, display);
+    }
+    
+    // --format csv / --excelなら1試合1行の統計CSVだけを出す
+    if options.csv {
+        return run_csv(options, &display.style);
+    }
+    
+    // --jobsなら複数のファイルを並列に読み込んで、合算とランキングだけを表示する
+    if let Some(jobs) = options.jobs {
+        return run_parallel(options, jobs, display);
+    }
+    
+    // 複数のファイルなら1つずつ解析し、最後に合算したまとめを表示する
+    if options.file_paths.len() > 1 {
+        return run_batch(options, display);
     }
     
     // ファイルパスを取得
-    let file_path = &args[1];
+    let file_path = &options.file_paths[0];
+    // -q ではサマリ1行だけを出すので、途中経過のメッセージも出さない
+    // --narrate でも、記号入りの途中経過は読み上げの邪魔になるので出さない
+    // --json では標準出力をJSONだけにするので出さない
+    let show_progress = options.verbosity != Verbosity::Quiet && !options.narrate && !options.json;
     
     // 処理開始メッセージ
-    println!("対戦ゲーム行動ログ解析ツール");
-    println!("ファイル: {}", file_path);
-    println!();
+    if show_progress {
+        println!("対戦ゲーム行動ログ解析ツール");
+        println!("ファイル: {}", source_label(file_path));
+        println!();
+    }
     
     // 1. CSVファイル読み込み
-    println!("CSVファイルを読み込んでいます...");
-    let battle_log = parser::read_battle_log(file_path)?;
-    println!("✓ 読み込み完了: {} 件の行動データ", battle_log.actions.len());
-    println!();
+    if show_progress {
+        println!("CSVファイルを読み込んでいます...");
+    }
+    let mut battle_log = read_log(options, file_path)?;
+    options.prepare(&mut battle_log);
+    if show_progress {
+        println!("✓ 読み込み完了: {} 件の行動データ", battle_log.actions.len());
+        output::display_warnings(&battle_log.warnings);
+        println!();
+    }
     
     // 2. データ解析
-    println!("データを解析しています...");
-    let result = analyzer::analyze(&battle_log);
-    println!("✓ 解析完了");
-    println!();
+    if show_progress {
+        println!("データを解析しています...");
+    }
+    let result = analyzer::analyze(&battle_log, &options.analysis);
+    if show_progress {
+        println!("✓ 解析完了");
+        println!();
+    }
+    
+    // 3. レポートを組み立てる（--compare-lastなら前回の結果も入れる）
+    let report = output::ReportData {
+        result: &result,
+        warnings: &battle_log.warnings,
+        previous: if options.compare_last { history::load_last(file_path) } else { None },
+    };
+    
+    // 4. 結果表示
+    if options.json {
+        output::display_report_json(&report, &display.style);
+    } else {
+        output::display_report(&report, display);
+    }
+    
+    // --out-csvなら集計CSVもファイルに書く
+    if let Some(path) = &options.out_csv {
+        write_out_csv(path, std::slice::from_ref(&result))?;
+    }
     
-    // 3. 結果表示
-    output::display_result(&result);
+    // --compare-lastなら今回の結果を次回用に保存する
+    if options.compare_last {
+        match history::save_last(file_path, &result.counts) {
+            Ok(true) => {}
+            Ok(false) => eprintln!("※ キャッシュの保存先がわからないので、今回の結果を保存しませんでした"),
+            // 保存できなくても解析結果は表示できているので、エラーにはしない
+            Err(e) => eprintln!("※ 今回の結果を保存できませんでした: {}", e),
+        }
+    }
     
+    // 5. --ipcなら結果のJSONをフロントエンドに送る
+    if let Some(path) = &options.ipc {
+        if !ipc::send(path, &output::report_to_json(&report), options.ipc_mode)? {
+            eprintln!("※ {} に接続できなかったので、結果を送りませんでした", path);
+        }
+    }
+    
+    Ok(())
+}
+
+/// 対戦ログを読み込み、--lenient で読み飛ばした行があればエラー出力に知らせる
+fn read_log(options: &cli::Options, file_path: &str) -> Result<model::BattleLog> {
+    let battle_log = options.read(file_path)?;
+    output::display_skipped_lines(source_label(file_path), &battle_log.skipped_lines);
+    Ok(battle_log)
+}
+
+/// 途中経過に出す読み込み元の名前（「-」は標準入力）
+fn source_label(file_path: &str) -> &str {
+    if file_path == parser::STDIN_PATH {
+        "（標準入力）"
+    } else {
+        file_path
+    }
+}
+
+/// 複数のCSV: ファイルごとの解析結果を表示し、最後に合算したまとめを表示する
+/// 
+/// 読み込めないファイルがあっても、どのファイルかを表示して残りのファイルを解析する。
+/// その場合は最後にエラー（終了コード1）にする。
+/// 同じ試合のログが複数あれば --dup-policy に従って合算する。
+/// --json ではファイルごとの結果は出さず、合算結果だけをJSONで出す。
+/// Ctrl-Cが押されたら、今のファイルを終えたところで止めて、そこまでの合算を出してから
+/// エラー（終了コード130）にする
+fn run_batch(options: &cli::Options, display: &output::DisplayOptions) -> Result<()> {
+    let show_progress = options.verbosity != Verbosity::Quiet && !options.narrate && !options.json;
+    let mut paths = Vec::new();
+    let mut results = Vec::new();
+    let mut failed = 0;
+    let mut processed = 0;
+
+    signal::install_interrupt_handler();
+    for file_path in &options.file_paths {
+        // Ctrl-Cが押されたら、次のファイルには進まない
+        if signal::interrupted() {
+            break;
+        }
+        processed += 1;
+        if show_progress {
+            println!("ファイル: {}", source_label(file_path));
+        }
+        let mut battle_log = match read_log(options, file_path) {
+            Ok(battle_log) => battle_log,
+            Err(e) => {
+                // 1つのファイルの失敗で全体を止めず、どのファイルかを知らせて次へ進む
+                eprintln!("\x1b[31m{} を解析できませんでした: {}\x1b[0m", file_path, e);
+                if let Some(suggestion) = e.suggestion() {
+                    eprintln!("ヒント: {}", suggestion);
+                }
+                if !options.json {
+                    println!();
+                }
+                failed += 1;
+                continue;
+            }
+        };
+        options.prepare(&mut battle_log);
+        if show_progress {
+            output::display_warnings(&battle_log.warnings);
+        }
+        let result = analyzer::analyze(&battle_log, &options.analysis);
+        if !options.json {
+            output::display_result(&result, display);
+            display.style.emit("");
+        }
+        paths.push(file_path.clone());
+        results.push(result);
+    }
+
+    // 集計CSVには、読み込めたファイルの結果を合算する前の1試合1行で書く
+    if let Some(path) = &options.out_csv {
+        write_out_csv(path, &results)?;
+    }
+
+    let interrupted = interruption(processed, options.file_paths.len());
+    if !results.is_empty() {
+        let mut aggregate = analyzer::aggregate_files(paths.into_iter().zip(results).collect(), options.dup_policy)?;
+        aggregate.interrupted = interrupted;
+        if options.json {
+            display.style.write(&format!("{}\n", output::aggregate_to_json(&aggregate)));
+        } else {
+            output::display_aggregate(&aggregate, &display.style);
+        }
+    }
+
+    // 中断したことは、読み込めなかったファイルがあることより優先して知らせる
+    if let Some(interruption) = interrupted {
+        return Err(interrupted_error(interruption));
+    }
+    if failed > 0 {
+        return Err(error::SmasherError::FilesFailed {
+            failed,
+            total: options.file_paths.len(),
+        });
+    }
     Ok(())
 }
 
+/// --jobs: 複数のファイルを並列に読み込んで合算し、合算結果と学籍番号ごとのランキングを表示する
+///
+/// ファイルごとの結果は表示しない。--json では run_batch と同じ合算結果のJSONだけを出す。
+/// 読み込めないファイルがあれば、残りを合算して表示したあとでエラー（終了コード1）にする。
+/// Ctrl-Cで中断したときは、run_batch と同じくそこまでの合算を出してから終了コード130にする
+fn run_parallel(options: &cli::Options, jobs: usize, display: &output::DisplayOptions) -> Result<()> {
+    signal::install_interrupt_handler();
+    if options.verbosity != Verbosity::Quiet && !options.narrate && !options.json {
+        println!("{} 件のファイルを {} 個のスレッドで読み込みます", options.file_paths.len(), jobs.min(options.file_paths.len()));
+        println!();
+    }
+    let (aggregate, failed) = pipeline::aggregate_parallel(options, jobs);
+    let aggregate = aggregate?;
+
+    if aggregate.files > 0 {
+        if options.json {
+            display.style.write(&format!("{}\n", output::aggregate_to_json(&aggregate)));
+        } else {
+            output::display_aggregate(&aggregate, &display.style);
+            display.style.emit("");
+            output::display_ranking(&analyzer::rank_students(&aggregate), options.top, &display.style);
+        }
+    }
+
+    if let Some(interruption) = aggregate.interrupted {
+        return Err(interrupted_error(interruption));
+    }
+    if failed > 0 {
+        return Err(error::SmasherError::FilesFailed {
+            failed,
+            total: options.file_paths.len(),
+        });
+    }
+    Ok(())
+}
+
+/// --format csv / --excel: ファイルごとの統計を1行ずつCSVで標準出力（--output ならそのファイル）に出す
+///
+/// 標準出力はCSVだけにするので、途中経過や警告は出さない。
+/// 読み込めないファイルはエラー出力に知らせて飛ばし、最後にエラー（終了コード1）にする。
+/// Ctrl-Cが押されたら、そこまでのファイルの行を出してからエラー（終了コード130）にする
+fn run_csv(options: &cli::Options, style: &style::Style) -> Result<()> {
+    let mut results = Vec::new();
+    let mut failed = 0;
+    let mut processed = 0;
+    signal::install_interrupt_handler();
+    for file_path in &options.file_paths {
+        if signal::interrupted() {
+            break;
+        }
+        processed += 1;
+        match read_log(options, file_path) {
+            Ok(mut battle_log) => {
+                options.prepare(&mut battle_log);
+                results.push(analyzer::analyze(&battle_log, &options.analysis));
+            }
+            Err(e) => {
+                eprintln!("\x1b[31m{} を解析できませんでした: {}\x1b[0m", file_path, e);
+                if let Some(suggestion) = e.suggestion() {
+                    eprintln!("ヒント: {}", suggestion);
+                }
+                failed += 1;
+            }
+        }
+    }
+
+    style.write(&output::to_csv(&results, options.excel));
+    if let Some(path) = &options.out_csv {
+        write_out_csv(path, &results)?;
+    }
+
+    if let Some(interruption) = interruption(processed, options.file_paths.len()) {
+        return Err(interrupted_error(interruption));
+    }
+    if failed > 0 {
+        return Err(error::SmasherError::FilesFailed {
+            failed,
+            total: options.file_paths.len(),
+        });
+    }
+    Ok(())
+}
+
+/// 処理したファイルの数から、途中で中断したかどうかを決める
+///
+/// # 戻り値
+/// すべてのファイルを処理する前にCtrl-Cで止めていれば、その記録。最後まで処理していればNone
+fn interruption(processed: usize, total: usize) -> Option<model::Interruption> {
+    (processed < total).then_some(model::Interruption { processed, total })
+}
+
+/// 中断の記録を、終了コード130で終わるためのエラーにする
+fn interrupted_error(interruption: model::Interruption) -> error::SmasherError {
+    error::SmasherError::Interrupted {
+        processed: interruption.processed,
+        total: interruption.total,
+    }
+}
+
+/// --groups: 複数のCSVを解析し、グループ別の平均指標を表示する
+fn run_groups(options: &cli::Options, groups_path: &str) -> Result<()> {
+    let groups = group::GroupMap::load(groups_path)?;
+
+    let mut results = Vec::new();
+    for file_path in &options.file_paths {
+        let mut battle_log = read_log(options, file_path)?;
+        options.prepare(&mut battle_log);
+        results.push(analyzer::analyze(&battle_log, &options.analysis));
+    }
+
+    output::display_group_summaries(&analyzer::summarize_groups(&results, &groups));
+    Ok(())
+}
+
+/// --cluster: 複数のCSVを解析し、行動IDの使い方が似た試合同士のクラスタに分けて表示する
+fn run_cluster(options: &cli::Options, k: usize, display: &output::DisplayOptions) -> Result<()> {
+    let mut results = Vec::new();
+    for file_path in &options.file_paths {
+        let mut battle_log = read_log(options, file_path)?;
+        options.prepare(&mut battle_log);
+        results.push(analyzer::analyze(&battle_log, &options.analysis));
+    }
+
+    output::display_clusters(&analyzer::cluster_matches(&results, k), &display.style);
+    Ok(())
+}
+
+/// 改善レポート: 同じ学生の複数の試合を回次順に並べ、指標の推移を表示する
+fn run_improvement(options: &cli::Options) -> Result<()> {
+    let mut results = Vec::new();
+    for file_path in &options.file_paths {
+        let mut battle_log = read_log(options, file_path)?;
+        options.prepare(&mut battle_log);
+        results.push(analyzer::analyze(&battle_log, &options.analysis));
+    }
+
+    let report = analyzer::improvement_report(&results, options.order)?;
+    if options.json {
+        println!("{}", output::improvement_to_json(&report));
+    } else {
+        output::display_improvement_report(&report);
+    }
+    Ok(())
+}
+
+/// explainサブコマンド: 1行だけを解析して説明を表示する
+/// 
+/// 解析エラーの場合も、JSONモードではエラー内容をJSONで出力してからエラー終了する
+fn run_explain(line: &str, line_number: usize, json: bool) -> Result<()> {
+    match explain::explain_line(line, line_number) {
+        Ok(explanation) if json => println!("{}", explain::explanation_to_json(&explanation)),
+        Ok(explanation) => explain::display_explanation(&explanation),
+        Err(e) => {
+            if json {
+                println!("{}", explain::error_to_json(line, &e.to_string()));
+            }
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// contextサブコマンド: 指定した時刻の前後n件ずつの行動を表示する
+/// 
+/// 各行動にはCSVファイルでの行番号を添える。--keep-raw なら元の行の文字列も表示する
+fn run_context(file_path: &str, at: f64, n: usize, keep_raw: bool, json: bool) -> Result<()> {
+    let read_options = parser::ReadOptions { keep_raw, ..parser::ReadOptions::default() };
+    let battle_log = parser::read_battle_log_as(file_path, &read_options)?;
+    let (before, after) = battle_log.context(at, n);
+    if json {
+        println!("{}", context::context_to_json(at, &before, &after));
+    } else {
+        context::display_context(at, &before, &after);
+    }
+    Ok(())
+}
+
+/// --list-formats / --list-sections: 名前と説明の一覧を表示する
+///
+/// # 引数
+/// * `key` - JSONで一覧を入れるキー（"formats" など）
+/// * `entries` - (名前, 説明) の一覧
+/// * `json` - JSONで出力するか
+fn print_list(key: &str, entries: &[(&str, &str)], json: bool) {
+    if json {
+        println!("{}", list_to_json(key, entries));
+    } else {
+        print!("{}", list_to_text(entries));
+    }
+}
+
+/// 一覧を「名前  説明」の行にする（名前の幅を揃える）
+fn list_to_text(entries: &[(&str, &str)]) -> String {
+    let width = entries.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    entries
+        .iter()
+        .map(|(name, description)| format!("{:<width$}  {}\n", name, description, width = width))
+        .collect()
+}
+
+/// 一覧を1行のJSONにする（例: {"formats":[{"name":"text","description":"..."}]}）
+fn list_to_json(key: &str, entries: &[(&str, &str)]) -> String {
+    let items: Vec<String> = entries
+        .iter()
+        .map(|(name, description)| format!("{{\"name\":{},\"description\":{}}}", json::string(name), json::string(description)))
+        .collect();
+    format!("{{{}:[{}]}}", json::string(key), items.join(","))
+}
+
+/// --self-check: 行動分類テーブルとfrom_action_idの整合性を診断する
+fn run_self_check() -> Result<()> {
+    println!("行動分類テーブルの自己診断");
+    let (checked, problems) = model::check_catalog();
+    
+    if problems.is_empty() {
+        println!("✓ {} 件のIDを確認しました。問題はありません", checked);
+        return Ok(());
+    }
+    
+    for problem in &problems {
+        println!("  ✗ {}", problem);
+    }
+    Err(error::SmasherError::SelfCheckFailed(problems.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 一時ディレクトリに小さなログを書き、そのパスを返す
+    fn write_logs(name: &str, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| {
+                let path = env::temp_dir().join(format!("smasher_{}_{}_{}.csv", name, std::process::id(), i));
+                fs::write(&path, format!("b{},1\n1.0,us\n2.0,s\n", i)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    fn options(args: &[String]) -> cli::Options {
+        match cli::with_env(&[], || cli::parse_args(args)) {
+            Ok(cli::Command::Analyze(options)) => *options,
+            other => panic!("解析のコマンドになっていません: {:?}", other),
+        }
+    }
+
+    fn display() -> output::DisplayOptions {
+        output::DisplayOptions {
+            verbosity: Verbosity::Quiet,
+            style: style::Style::new(style::ColorChoice::Never, style::ColorRules::new()),
+            ascii: true,
+            sort_categories: false,
+            narrate: false,
+        }
+    }
+
+    /// 最初のファイルの前で中断したエラーで、終了コードが130になることを確かめる
+    fn assert_interrupted(result: Result<()>, total: usize) {
+        match result {
+            Err(e @ error::SmasherError::Interrupted { processed: 0, total: t }) if t == total => {
+                assert_eq!(e.exit_code(), error::INTERRUPTED_EXIT_CODE);
+            }
+            other => panic!("中断のエラーになっていません: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn interruption_only_when_files_remain() {
+        assert_eq!(interruption(1, 3), Some(model::Interruption { processed: 1, total: 3 }));
+        assert_eq!(interruption(3, 3), None);
+    }
+
+    #[test]
+    fn batch_and_csv_runs_stop_when_interrupted() {
+        let _lock = signal::TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let paths = write_logs("main_interrupted", 2);
+        let batch = options(&[paths.clone(), vec!["--json".to_string()]].concat());
+        let csv = options(&[paths.clone(), vec!["--format".to_string(), "csv".to_string()]].concat());
+
+        signal::set_interrupted(true);
+        let batch_result = run_batch(&batch, &display());
+        let csv_result = run_csv(&csv, &display().style);
+        signal::set_interrupted(false);
+        paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+
+        assert_interrupted(batch_result, 2);
+        assert_interrupted(csv_result, 2);
+    }
+
+    #[test]
+    fn batch_run_succeeds_when_not_interrupted() {
+        let _lock = signal::TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let paths = write_logs("main_complete", 2);
+        let batch = options(&[paths.clone(), vec!["--json".to_string()]].concat());
+        let result = run_batch(&batch, &display());
+        paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[test]
+    fn lists_show_every_registered_item() {
+        for (key, entries) in [("formats", cli::formats()), ("sections", schema::sections())] {
+            let text = list_to_text(&entries);
+            let json = list_to_json(key, &entries);
+            assert_eq!(text.lines().count(), entries.len());
+            assert!(json.starts_with(&format!("{{\"{}\":[", key)), "{}", json);
+            for (name, description) in &entries {
+                assert!(!description.is_empty(), "{} の説明がありません", name);
+                assert!(text.lines().any(|line| line.starts_with(name) && line.ends_with(description)), "{}", text);
+                assert!(json.contains(&format!("{{\"name\":\"{}\",\"description\":{}}}", name, json::string(description))));
+            }
+        }
+    }
+
+    #[test]
+    fn batch_out_csv_leaves_out_files_that_failed() {
+        let _lock = signal::TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let paths = write_logs("main_out_csv", 2);
+        let missing = env::temp_dir().join(format!("smasher_main_out_csv_{}_missing.csv", std::process::id()));
+        let summary = env::temp_dir().join(format!("smasher_main_out_csv_{}_summary.csv", std::process::id()));
+        let args = [
+            paths.clone(),
+            vec![missing.to_string_lossy().into_owned(), "--json".to_string(), "--out-csv".to_string()],
+            vec![summary.to_string_lossy().into_owned()],
+        ]
+        .concat();
+        let result = run_batch(&options(&args), &display());
+        let written = fs::read_to_string(&summary).unwrap();
+        paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+        fs::remove_file(&summary).unwrap();
+
+        assert!(matches!(result, Err(error::SmasherError::FilesFailed { failed: 1, total: 3 })), "{:?}", result);
+        let students: Vec<&str> = written.lines().skip(1).map(|line| line.split(',').next().unwrap()).collect();
+        assert_eq!(students, vec!["b0", "b1"]);
+    }
+
+    #[test]
+    fn out_csv_write_errors_name_the_file() {
+        let path = env::temp_dir().join(format!("smasher_no_such_dir_{}", std::process::id())).join("summary.csv");
+        let path = path.to_string_lossy().into_owned();
+        let error = write_out_csv(&path, &[]).unwrap_err().to_string();
+        assert!(error.contains(&path), "{}", error);
+    }
+}
@@ -0,0 +1,365 @@
+// migrate.rs
+// JSON出力の移行モジュール（smasher migrate）
+//
+// 以前のバージョンの smasher が --json で書き出した解析結果を、新しいスキーマのバージョンの形に書き直す。
+// バージョンを1つずつ上げる関数を STEPS に並べておき、目的のバージョンまで順にあてはめる
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Result, SmasherError};
+use crate::json::{self, Value};
+use crate::model::{ActionCounts, ActionType};
+
+/// migrate の設定
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrateOptions {
+    /// 以前の出力（*.json）が入ったディレクトリ
+    pub dir: String,
+    /// 書き直した後のスキーマのバージョン（--to-version。既定は今のバージョン）
+    pub to_version: u32,
+    /// 書き出し先のディレクトリ（--output-dir）。Noneなら元のファイルを書き換える
+    pub output_dir: Option<String>,
+}
+
+/// 比率の小数点以下の桁数（output::to_json と同じ）
+const RATIO_DECIMALS: usize = 3;
+
+/// バージョンを1つ上げる書き直し
+struct Step {
+    /// 書き直す前のバージョン（from → from + 1）
+    from: u32,
+    /// 書き直し。元の出力から決められない値が必要なときはエラーの説明を返す
+    apply: fn(&mut Value) -> std::result::Result<(), String>,
+}
+
+/// バージョンごとの書き直し（from の順）
+///
+/// 7→8（shield_cover）、8→9（reliability）、9→10（bursts）は、増えた項目を元のCSVの
+/// 行動の並びから計算するので、以前の出力だけからは作れない。UNSUPPORTED に理由を書いておく
+const STEPS: &[Step] = &[
+    // v2: file_modified が増えたが、取れないときはキーがなくてよいので何も足さない
+    Step { from: 1, apply: |_| Ok(()) },
+    Step { from: 2, apply: split_unknown_counts },
+    // v4: momentum は --momentum を付けたときだけ値があり、付けていなければnull
+    Step { from: 3, apply: |value| insert_after(value, "phases", "momentum", Value::Null) },
+    // v5: debounce は --debounce を付けたときだけ値があり、付けていなければnull
+    Step { from: 4, apply: |value| insert_after(value, "momentum", "debounce", Value::Null) },
+    Step { from: 5, apply: add_longest_combo },
+    // v7: warnings と previous_counts が増えたが、どちらもないときはキーがなくてよい
+    Step { from: 6, apply: |_| Ok(()) },
+    Step { from: 10, apply: add_histogram_overflow },
+];
+
+/// 以前の出力からは書き直せないバージョン（書き直す前のバージョン, 増えた項目）
+const UNSUPPORTED: &[(u32, &str)] = &[(7, "shield_cover"), (8, "reliability"), (9, "bursts")];
+
+/// ディレクトリの中の *.json をすべて書き直す
+///
+/// 1つのファイルには、解析結果のJSONが1つ（整形されていてもよい）か、1行に1つずつ入っている。
+/// 書き直せなかったファイルがあっても残りのファイルは続け、最後にまとめてエラーにする
+///
+/// # 戻り値
+/// 成功、または書き直せなかったファイルがあったときのエラー（FilesFailed）
+pub fn migrate_dir(options: &MigrateOptions) -> Result<()> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(&options.dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json")))
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        return Err(SmasherError::EmptyData(format!("{} に .json のファイルがありません", options.dir)));
+    }
+    if let Some(output_dir) = &options.output_dir {
+        fs::create_dir_all(output_dir)?;
+    }
+
+    let (mut migrated, mut unchanged, mut failed) = (0, 0, 0);
+    for path in &paths {
+        match migrate_file(path, options) {
+            Ok(Some(from)) => {
+                println!("移行: {} v{} → v{}", path.display(), from, options.to_version);
+                migrated += 1;
+            }
+            Ok(None) => {
+                println!("そのまま: {}（すでに v{}）", path.display(), options.to_version);
+                unchanged += 1;
+            }
+            Err(reason) => {
+                eprintln!("失敗: {}: {}", path.display(), reason);
+                failed += 1;
+            }
+        }
+    }
+    println!("{} 件移行しました（そのまま {} 件、失敗 {} 件）", migrated, unchanged, failed);
+
+    if failed > 0 {
+        return Err(SmasherError::FilesFailed { failed, total: paths.len() });
+    }
+    Ok(())
+}
+
+/// 1つのファイルを書き直して書き出す
+///
+/// # 戻り値
+/// 書き直す前のいちばん古いバージョン（書き直しが要らなければNone）、または失敗の理由
+fn migrate_file(path: &Path, options: &MigrateOptions) -> std::result::Result<Option<u32>, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut values = read_values(&text)?;
+    let mut oldest: Option<u32> = None;
+    for value in &mut values {
+        let from = migrate_value(value, options.to_version)?;
+        if from < options.to_version {
+            oldest = Some(oldest.map_or(from, |oldest| oldest.min(from)));
+        }
+    }
+
+    let out_path = match &options.output_dir {
+        Some(output_dir) => Path::new(output_dir).join(path.file_name().ok_or("ファイル名を取得できません")?),
+        None if oldest.is_none() => return Ok(None),
+        None => path.to_path_buf(),
+    };
+    let lines: Vec<String> = values.iter().map(Value::to_json).collect();
+    fs::write(&out_path, lines.join("\n") + "\n").map_err(|e| e.to_string())?;
+    Ok(oldest)
+}
+
+/// ファイルの中身からJSONの値を読み込む（全体で1つ、または1行に1つ）
+fn read_values(text: &str) -> std::result::Result<Vec<Value>, String> {
+    if let Ok(value) = json::parse(text) {
+        return Ok(vec![value]);
+    }
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| json::parse(line).map_err(|e| format!("{}行目: {}", i + 1, e)))
+        .collect()
+}
+
+/// 解析結果のJSONを、`to_version` の形に書き直す
+///
+/// # 戻り値
+/// 書き直す前のバージョン、または書き直せない理由
+pub fn migrate_value(value: &mut Value, to_version: u32) -> std::result::Result<u32, String> {
+    let from = value
+        .get("schema_version")
+        .and_then(Value::as_f64)
+        .filter(|version| version.fract() == 0.0 && *version >= 1.0)
+        .ok_or("schema_version がありません（smasher の --json の出力ではないようです）")? as u32;
+    if from > to_version {
+        return Err(format!("v{} を古い v{} に戻すことはできません", from, to_version));
+    }
+
+    for version in from..to_version {
+        if let Some((_, added)) = UNSUPPORTED.iter().find(|(v, _)| *v == version) {
+            return Err(format!(
+                "v{} → v{} で増えた {} は元のCSVから計算する値なので、書き直せません。CSVを解析し直してください",
+                version,
+                version + 1,
+                added
+            ));
+        }
+        let step = STEPS
+            .iter()
+            .find(|step| step.from == version)
+            .ok_or_else(|| format!("v{} → v{} の書き直し方がありません", version, version + 1))?;
+        (step.apply)(value).map_err(|reason| format!("v{} → v{}: {}", version, version + 1, reason))?;
+        value.set("schema_version", Value::Number((version + 1).to_string()));
+    }
+    Ok(from)
+}
+
+/// オブジェクトの `after` の直後にキーを足す（`after` がなければエラー）
+fn insert_after(value: &mut Value, after: &str, key: &str, new: Value) -> std::result::Result<(), String> {
+    if value.get(after).is_none() {
+        return Err(format!("{} がありません", after));
+    }
+    value.insert_after(after, key, new);
+    Ok(())
+}
+
+/// オブジェクトの数値のキーを、0以上の整数として読む
+fn count_at(value: &Value, key: &str) -> std::result::Result<u32, String> {
+    value
+        .get(key)
+        .and_then(Value::as_f64)
+        .filter(|n| n.fract() == 0.0 && *n >= 0.0 && *n <= u32::MAX as f64)
+        .map(|n| n as u32)
+        .ok_or_else(|| format!("{} が0以上の整数ではありません", key))
+}
+
+/// v2 → v3: 未登録の行動IDを攻撃から分ける
+///
+/// v2までは未登録の行動IDを攻撃に数えていた。action_id_counts から未登録のIDを探し、
+/// その回数を攻撃から unknown に移して、比率と unknown_ids を作り直す
+fn split_unknown_counts(value: &mut Value) -> std::result::Result<(), String> {
+    let Some(Value::Array(ids)) = value.get("action_id_counts") else {
+        return Err("action_id_counts がありません".to_string());
+    };
+    let mut unknown_ids = Vec::new();
+    let mut unknown = 0u32;
+    // action_id_counts は回数の多い順なので、unknown_ids も同じ順になる
+    for entry in ids {
+        let Some(Value::String(id)) = entry.get("id") else {
+            return Err("action_id_counts の id がありません".to_string());
+        };
+        if ActionType::from_action_id(id) == ActionType::Unknown {
+            let count = count_at(entry, "count")?;
+            unknown += count;
+            unknown_ids.push(Value::Object(vec![
+                ("id".to_string(), Value::String(id.clone())),
+                ("count".to_string(), Value::Number(count.to_string())),
+            ]));
+        }
+    }
+
+    let counts = value.get_mut("counts").ok_or("counts がありません")?;
+    let attack = count_at(counts, "attack")?
+        .checked_sub(unknown)
+        .ok_or("attack が未登録の行動IDの回数より少なくなっています")?;
+    let split = ActionCounts {
+        attack_count: attack,
+        shield_count: count_at(counts, "shield")?,
+        dodge_count: count_at(counts, "dodge")?,
+        unknown_count: unknown,
+    };
+    let number = |n: u32| Value::Number(n.to_string());
+    let ratio = |r: f64| Value::Number(json::fixed(r, RATIO_DECIMALS));
+    counts.set("attack", number(split.attack_count));
+    counts.insert_after("dodge", "unknown", number(split.unknown_count));
+    counts.set("attack_ratio", ratio(split.attack_ratio()));
+    counts.insert_after("dodge_ratio", "unknown_ratio", ratio(split.unknown_ratio()));
+    value.insert_after("counts", "unknown_ids", Value::Array(unknown_ids));
+    Ok(())
+}
+
+/// v5 → v6: combos に最大コンボの並び（longest）を足す
+///
+/// コンボがなければ空の配列。コンボがあると並びは元のCSVからしか分からないので書き直せない
+fn add_longest_combo(value: &mut Value) -> std::result::Result<(), String> {
+    let combos = value.get_mut("combos").ok_or("combos がありません")?;
+    if count_at(combos, "count")? > 0 {
+        return Err("コンボがあるので、最大コンボの並び（combos.longest）は元のCSVがないと分かりません".to_string());
+    }
+    insert_after(combos, "max_length", "longest", Value::Array(Vec::new()))
+}
+
+/// v10 → v11: shield_cover に histogram_overflow を足す
+///
+/// v10まではヒストグラムの区間数に上限がなく、長い時間をまとめることはなかったのでfalse
+fn add_histogram_overflow(value: &mut Value) -> std::result::Result<(), String> {
+    match value.get_mut("shield_cover") {
+        Some(Value::Null) => Ok(()),
+        Some(cover) => insert_after(cover, "histogram", "histogram_overflow", Value::Bool(false)),
+        None => Err("shield_cover がありません".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::{self, AnalysisOptions};
+    use crate::model::{Action, BattleLog, PlayerInfo};
+    use crate::output;
+    use crate::schema::SCHEMA_VERSION;
+
+    /// (時刻, 行動ID) の並びを解析して、今のバージョンの --json の出力にする
+    fn exported(actions: &[(f64, &str)]) -> String {
+        let actions = actions.iter().map(|&(timestamp, id)| Action::new(timestamp, id.to_string())).collect();
+        let battle_log = BattleLog::new(PlayerInfo::new("b1022024".to_string(), 3), actions);
+        output::to_json(&analyzer::analyze(&battle_log, &AnalysisOptions::default()))
+    }
+
+    fn migrated(text: &str, to_version: u32) -> std::result::Result<(u32, Value), String> {
+        let mut value = json::parse(text)?;
+        let from = migrate_value(&mut value, to_version)?;
+        Ok((from, value))
+    }
+
+    #[test]
+    fn v10_export_matches_a_fresh_export_after_migrating() {
+        let fresh = exported(&[(1.0, "us"), (1.3, "s"), (4.0, "fa"), (5.0, "s"), (9.0, "j1")]);
+        assert!(fresh.contains(r#","histogram_overflow":false"#), "{}", fresh);
+        let v10 = fresh
+            .replace(r#","histogram_overflow":false"#, "")
+            .replace(&format!(r#""schema_version":{}"#, SCHEMA_VERSION), r#""schema_version":10"#);
+
+        let (from, value) = migrated(&v10, SCHEMA_VERSION).unwrap();
+        assert_eq!(from, 10);
+        assert_eq!(value.to_json(), fresh);
+    }
+
+    #[test]
+    fn v2_export_moves_unknown_ids_out_of_attack() {
+        // jump は登録されていない行動ID。v2までは攻撃に数えていた
+        let fresh = json::parse(&exported(&[(1.0, "jump"), (2.0, "us"), (3.0, "jump"), (4.0, "s")])).unwrap();
+        let v2 = concat!(
+            r#"{"schema_version":2,"#,
+            r#""counts":{"attack":3,"shield":1,"dodge":0,"total":4,"attack_ratio":75.000,"shield_ratio":25.000,"dodge_ratio":0.000},"#,
+            r#""action_id_counts":[{"id":"jump","count":2},{"id":"s","count":1},{"id":"us","count":1}],"#,
+            r#""combos":{"gap":1.0,"count":0,"max_length":0},"phases":[]}"#
+        );
+
+        let (_, value) = migrated(v2, 3).unwrap();
+        assert_eq!(value.get("schema_version").and_then(Value::as_f64), Some(3.0));
+        assert_eq!(value.get("counts").unwrap().to_json(), fresh.get("counts").unwrap().to_json());
+        assert_eq!(value.get("unknown_ids").unwrap().to_json(), fresh.get("unknown_ids").unwrap().to_json());
+    }
+
+    #[test]
+    fn longest_combo_is_empty_only_without_combos() {
+        let v5 = |count: u32| {
+            format!(
+                r#"{{"schema_version":5,"combos":{{"gap":1.0,"count":{},"max_length":{}}},"momentum":null}}"#,
+                count,
+                count.min(1) * 3
+            )
+        };
+        let (_, value) = migrated(&v5(0), 6).unwrap();
+        assert_eq!(value.get("combos").unwrap().to_json(), r#"{"gap":1.0,"count":0,"max_length":0,"longest":[]}"#);
+        assert!(migrated(&v5(2), 6).unwrap_err().contains("combos.longest"));
+    }
+
+    #[test]
+    fn values_computed_from_the_csv_are_not_invented() {
+        let error = migrated(r#"{"schema_version":7}"#, SCHEMA_VERSION).unwrap_err();
+        assert!(error.contains("shield_cover") && error.contains("CSVを解析し直してください"), "{}", error);
+        // 目的のバージョンが手前なら書き直せる
+        assert_eq!(migrated(r#"{"schema_version":6,"a":1}"#, 7).unwrap().1.to_json(), r#"{"schema_version":7,"a":1}"#);
+    }
+
+    #[test]
+    fn newer_exports_are_not_downgraded() {
+        assert!(migrated(r#"{"schema_version":11}"#, 10).unwrap_err().contains("戻すことはできません"));
+        assert!(migrated(r#"{"counts":{}}"#, 11).unwrap_err().contains("schema_version がありません"));
+    }
+
+    #[test]
+    fn migrate_dir_keeps_going_past_a_bad_file() {
+        let dir = std::env::temp_dir().join(format!("smasher_migrate_{}", std::process::id()));
+        let output_dir = dir.join("out");
+        fs::create_dir_all(&dir).unwrap();
+        let fresh = exported(&[(1.0, "us"), (1.2, "s")]);
+        let v10 = fresh
+            .replace(r#","histogram_overflow":false"#, "")
+            .replace(&format!(r#""schema_version":{}"#, SCHEMA_VERSION), r#""schema_version":10"#);
+        // 1行に1つずつ書いたファイル、壊れたファイル、JSONではないファイル
+        fs::write(dir.join("a.json"), format!("{}\n{}\n", v10, fresh)).unwrap();
+        fs::write(dir.join("b.json"), "{\"schema_version\":").unwrap();
+        fs::write(dir.join("c.txt"), "not json").unwrap();
+
+        let options = MigrateOptions {
+            dir: dir.to_string_lossy().into_owned(),
+            to_version: SCHEMA_VERSION,
+            output_dir: Some(output_dir.to_string_lossy().into_owned()),
+        };
+        let result = migrate_dir(&options);
+        let written = fs::read_to_string(output_dir.join("a.json")).unwrap();
+        let bad_written = output_dir.join("b.json").exists();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(matches!(result, Err(SmasherError::FilesFailed { failed: 1, total: 2 })), "{:?}", result);
+        assert_eq!(written, format!("{}\n{}\n", fresh, fresh));
+        assert!(!bad_written);
+    }
+}
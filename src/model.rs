@@ -2,6 +2,12 @@
 // データ構造定義モジュール
 //
 
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::{Add, AddAssign};
+use std::sync::Arc;
+use std::time::SystemTime;
+
 /// 攻撃系の行動ID一覧
 pub const ATTACK_IDS: &[(&str, &str)] = &[
     // 弱攻撃
@@ -55,15 +61,154 @@ pub const DODGE_IDS: &[(&str, &str)] = &[
     ("ad", "空中回避"),
 ];
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// 行動IDの英語名一覧（--lang en / ja+en）
+///
+/// ATTACK_IDS・SHIELD_IDS・DODGE_IDSのIDをすべて同じ順で書く（漏れは check_catalog で見つける）
+pub const ENGLISH_NAMES: &[(&str, &str)] = &[
+    ("j1", "Jab 1"),
+    ("j2", "Jab 2"),
+    ("st", "Forward Tilt"),
+    ("ut", "Up Tilt"),
+    ("dt", "Down Tilt"),
+    ("DA", "Dash Attack"),
+    ("ss", "Forward Smash"),
+    ("us", "Up Smash"),
+    ("ds", "Down Smash"),
+    ("na", "Neutral Air"),
+    ("fa", "Forward Air"),
+    ("ba", "Back Air"),
+    ("ua", "Up Air"),
+    ("da", "Down Air"),
+    ("nb_c", "Neutral Special (Charge)"),
+    ("nb_a", "Neutral Special (Attack)"),
+    ("sb", "Side Special"),
+    ("ub_g", "Up Special (Ground)"),
+    ("ub_a", "Up Special (Air)"),
+    ("db_g", "Down Special (Ground)"),
+    ("db_a", "Down Special (Air)"),
+    ("g", "Grab"),
+    ("ga", "Pummel"),
+    ("fth", "Forward Throw"),
+    ("bth", "Back Throw"),
+    ("uth", "Up Throw"),
+    ("dth", "Down Throw"),
+    ("fc", "Forward Throw (Forward Follow-up)"),
+    ("bc", "Forward Throw (Back Follow-up)"),
+    ("uc", "Forward Throw (Up Follow-up)"),
+    ("dc", "Forward Throw (Down Follow-up)"),
+    ("s", "Shield"),
+    ("nd", "Spot Dodge"),
+    ("sd", "Roll"),
+    ("ad", "Air Dodge"),
+];
+
+/// 行動名を表示する言語（--lang）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lang {
+    /// 日本語（既定）
+    #[default]
+    Ja,
+    /// 英語
+    En,
+    /// 日本語と英語を併記する（例: 横スマ / Forward Smash）
+    JaEn,
+}
+
+impl Lang {
+    /// 指定できる名前の一覧
+    pub const NAMES: &'static [&'static str] = &["ja", "en", "ja+en"];
+
+    /// 名前から言語を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ja" => Some(Lang::Ja),
+            "en" => Some(Lang::En),
+            "ja+en" => Some(Lang::JaEn),
+            _ => None,
+        }
+    }
+}
+
+/// (ID, 日本語名) の一覧テーブル
+pub type IdTable = &'static [(&'static str, &'static str)];
+
+/// 行動分類テーブルの自己診断
+/// 
+/// ATTACK_IDS・SHIELD_IDS・DODGE_IDSの全IDをfrom_action_idに通し、
+/// 所属するテーブルと分類結果が一致するかを確認する。
+/// ATTACK_IDSのIDは、攻撃の種類（AttackCategory）が「その他の攻撃」になっていないかも確認する。
+/// 同じIDが複数のテーブル（または同じテーブルに2回）登録されている場合も問題として報告する。
+/// 
+/// # 戻り値
+/// (確認したIDの数, 見つかった問題の説明のリスト)
+pub fn check_catalog() -> (usize, Vec<String>) {
+    let tables = [
+        ("ATTACK_IDS", ATTACK_IDS as IdTable, ActionType::Attack),
+        ("SHIELD_IDS", SHIELD_IDS, ActionType::Shield),
+        ("DODGE_IDS", DODGE_IDS, ActionType::Dodge),
+    ];
+
+    let mut checked = 0;
+    let mut problems = Vec::new();
+    let mut seen: Vec<(&str, &str)> = Vec::new();
+
+    for (table_name, table, expected) in &tables {
+        for (id, name) in table.iter() {
+            checked += 1;
+
+            let actual = ActionType::from_action_id(id);
+            if actual != *expected {
+                problems.push(format!(
+                    "{} ({}) は {} にあるが、from_action_id は {} に分類しています",
+                    id, name, table_name, actual.label()
+                ));
+            }
+            if *expected == ActionType::Attack && AttackCategory::from_action_id(id) == AttackCategory::OtherAttack {
+                problems.push(format!(
+                    "{} ({}) は ATTACK_IDS にあるが、AttackCategory::from_action_id で攻撃の種類が決まりません",
+                    id, name
+                ));
+            }
+
+            if let Some((_, first_table)) = seen.iter().find(|(seen_id, _)| seen_id == id) {
+                problems.push(format!(
+                    "{} ({}) が {} と {} に重複して登録されています",
+                    id, name, first_table, table_name
+                ));
+            }
+            seen.push((id, table_name));
+
+            if ActionType::get_english_name(id).is_none() {
+                problems.push(format!("{} ({}) の英語名が ENGLISH_NAMES にありません", id, name));
+            }
+        }
+    }
+
+    // 英語名だけがあるIDは、分類のテーブルへの登録漏れか打ち間違い
+    for (id, english) in ENGLISH_NAMES {
+        if !ActionType::is_known_id(id) {
+            problems.push(format!(
+                "{} ({}) は ENGLISH_NAMES にあるが、ATTACK_IDS・SHIELD_IDS・DODGE_IDSのどれにもありません",
+                id, english
+            ));
+        }
+    }
+
+    (checked, problems)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 // Debug: デバッグ出力できるようにする
 // Clone: 値渡しできるようにする
 // PartialEq: == で比較できるようにする
 // Eq: JSでいう === を可能にする
+// Hash: HashMap/HashSetのキーにできるようにする（Javaでいう hashCode）
 pub enum ActionType {
     Attack,
     Shield,
     Dodge,
+    /// どの行動ID一覧にもないID（打ち間違いなど）。攻撃・シールド・回避とは別に数える
+    Unknown,
 }
 
 // impl: 型の機能を実装する
@@ -95,8 +240,8 @@ impl ActionType {
             // つかみ・投げ
             "g" | "ga" | "fth" | "bth" | "uth" | "dth" | "fc" | "bc" | "uc" | "dc" => ActionType::Attack,
             
-            // 上記以外は攻撃として扱う（互換性のため）
-            _ => ActionType::Attack,
+            // 上記以外は未登録のIDとして別に数える（打ち間違いで攻撃の回数が増えないように）
+            _ => ActionType::Unknown,
         }
     }
     
@@ -125,9 +270,366 @@ impl ActionType {
         // 見つからない場合は元のIDを返す
         action_id.to_string()
     }
+
+    /// 行動IDの英語名を取得する
+    ///
+    /// # 戻り値
+    /// 英語名。ENGLISH_NAMES に登録されていなければNone
+    pub fn get_english_name(action_id: &str) -> Option<&'static str> {
+        ENGLISH_NAMES.iter().find(|(id, _)| *id == action_id).map(|(_, name)| *name)
+    }
+
+    /// 行動IDの名前を、指定した言語で取得する（--lang）
+    ///
+    /// # 引数
+    /// * `action_id` - 行動ID
+    /// * `lang` - 表示する言語
+    ///
+    /// # 戻り値
+    /// 行動名。英語名がなければ日本語名（未登録のIDなら元のID）にする
+    pub fn get_action_name_in(action_id: &str, lang: Lang) -> String {
+        match (lang, Self::get_english_name(action_id)) {
+            (Lang::En, Some(english)) => english.to_string(),
+            (Lang::JaEn, Some(english)) => format!("{} / {}", Self::get_action_name(action_id), english),
+            _ => Self::get_action_name(action_id),
+        }
+    }
+
+    /// 行動IDがATTACK_IDS・SHIELD_IDS・DODGE_IDSのどれかに登録されているか
+    pub fn is_known_id(action_id: &str) -> bool {
+        ATTACK_IDS
+            .iter()
+            .chain(SHIELD_IDS)
+            .chain(DODGE_IDS)
+            .any(|(id, _)| *id == action_id)
+    }
+
+    /// 表示用の日本語名
+    pub fn label(&self) -> &'static str {
+        match self {
+            ActionType::Attack => "攻撃",
+            ActionType::Shield => "シールド",
+            ActionType::Dodge => "回避",
+            ActionType::Unknown => "不明",
+        }
+    }
+
+    /// JSONなど機械向けの出力で使うキー
+    pub fn key(&self) -> &'static str {
+        match self {
+            ActionType::Attack => "attack",
+            ActionType::Shield => "shield",
+            ActionType::Dodge => "dodge",
+            ActionType::Unknown => "unknown",
+        }
+    }
+}
+
+/// 攻撃の種類（ATTACK_IDS のコメントの区分）
+///
+/// ActionType の3分類（攻撃・シールド・回避）とは別に、攻撃をさらに細かく分ける
+// PartialOrd, Ord: BTreeMap のキーにして、宣言した順（表示する順）に並べるため
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum AttackCategory {
+    /// 弱攻撃
+    Jab,
+    /// 強攻撃
+    Tilt,
+    /// ダッシュアタック
+    DashAttack,
+    /// スマッシュ攻撃
+    Smash,
+    /// 空中攻撃
+    Aerial,
+    /// 必殺技
+    Special,
+    /// つかみ・投げ
+    Grab,
+    /// どの区分にもない攻撃（独自の分類で攻撃になったIDなど）
+    OtherAttack,
+}
+
+impl AttackCategory {
+    /// 表示する順番のすべての種類
+    pub const ALL: [AttackCategory; 8] = [
+        AttackCategory::Jab,
+        AttackCategory::Tilt,
+        AttackCategory::DashAttack,
+        AttackCategory::Smash,
+        AttackCategory::Aerial,
+        AttackCategory::Special,
+        AttackCategory::Grab,
+        AttackCategory::OtherAttack,
+    ];
+
+    /// 行動IDから攻撃の種類を決める
+    ///
+    /// ATTACK_IDS にないIDは OtherAttack にする
+    pub fn from_action_id(action_id: &str) -> Self {
+        match action_id {
+            "j1" | "j2" => AttackCategory::Jab,
+            "st" | "ut" | "dt" => AttackCategory::Tilt,
+            "DA" => AttackCategory::DashAttack,
+            "ss" | "us" | "ds" => AttackCategory::Smash,
+            "na" | "fa" | "ba" | "ua" | "da" => AttackCategory::Aerial,
+            "nb_c" | "nb_a" | "sb" | "ub_g" | "ub_a" | "db_g" | "db_a" => AttackCategory::Special,
+            "g" | "ga" | "fth" | "bth" | "uth" | "dth" | "fc" | "bc" | "uc" | "dc" => AttackCategory::Grab,
+            _ => AttackCategory::OtherAttack,
+        }
+    }
+
+    /// 表示用の日本語名
+    pub fn label(&self) -> &'static str {
+        match self {
+            AttackCategory::Jab => "弱攻撃",
+            AttackCategory::Tilt => "強攻撃",
+            AttackCategory::DashAttack => "ダッシュアタック",
+            AttackCategory::Smash => "スマッシュ",
+            AttackCategory::Aerial => "空中攻撃",
+            AttackCategory::Special => "必殺技",
+            AttackCategory::Grab => "つかみ・投げ",
+            AttackCategory::OtherAttack => "その他の攻撃",
+        }
+    }
+}
+
+/// 行動IDの攻撃の種類
+///
+/// # 戻り値
+/// ATTACK_IDS にある攻撃のIDなら種類、シールド・回避・未登録のIDならNone
+pub fn attack_category(action_id: &str) -> Option<AttackCategory> {
+    if ActionType::from_action_id(action_id) == ActionType::Attack {
+        Some(AttackCategory::from_action_id(action_id))
+    } else {
+        None
+    }
+}
+
+/// 行動の向き（前後方向）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// 前方向（空前・前投げなど）
+    Forward,
+    /// 後方向（空後・後投げなど）
+    Backward,
+}
+
+impl Direction {
+    /// 行動IDから向きを推定する
+    /// 
+    /// IDの名前に前後がはっきり表れているものだけを判定し、
+    /// 上下やその場の行動、向きが決まらない行動（横強・横回避など）はNoneにする
+    pub fn from_action_id(action_id: &str) -> Option<Self> {
+        match action_id {
+            // 空前・前投げ・前投げ（前派生）
+            "fa" | "fth" | "fc" => Some(Direction::Forward),
+            // 空後・後投げ・前投げ（後派生）
+            "ba" | "bth" | "bc" => Some(Direction::Backward),
+            _ => None,
+        }
+    }
+}
+
+/// 改善レポートで試合を並べる順番（推移の横軸）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchOrder {
+    /// 対戦回次の順。傾きは1回次あたり（既定）
+    #[default]
+    Match,
+    /// ファイルの更新日時の順。傾きは1日あたり
+    Mtime,
+}
+
+impl MatchOrder {
+    /// オプションの値から並べ方を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "match" => Some(MatchOrder::Match),
+            "mtime" => Some(MatchOrder::Mtime),
+            _ => None,
+        }
+    }
+
+    /// JSON用の名前
+    pub fn key(&self) -> &'static str {
+        match self {
+            MatchOrder::Match => "match",
+            MatchOrder::Mtime => "mtime",
+        }
+    }
+
+    /// 傾きの単位（表示用。「1回次あたり」の「回次」の部分）
+    pub fn unit(&self) -> &'static str {
+        match self {
+            MatchOrder::Match => "回次",
+            MatchOrder::Mtime => "日",
+        }
+    }
+}
+
+/// --compare-actions で回数をそのまま比べるか、1分あたりに直して比べるか（--normalize）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Normalize {
+    /// 回数のまま比べる（既定）
+    #[default]
+    Raw,
+    /// 回数をそれぞれの試合時間（分）で割って比べる
+    PerMinute,
+}
+
+impl Normalize {
+    /// オプションの値から比べ方を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "raw" => Some(Normalize::Raw),
+            "per-minute" => Some(Normalize::PerMinute),
+            _ => None,
+        }
+    }
+
+    /// JSON用の名前
+    pub fn key(&self) -> &'static str {
+        match self {
+            Normalize::Raw => "raw",
+            Normalize::PerMinute => "per-minute",
+        }
+    }
+}
+
+/// 合算するとき、同じ試合（学籍番号と対戦回次が同じ）のログが複数あったときの扱い（--dup-policy）
+///
+/// ファイルのコピーや書き出し直しで同じ試合を二重に数えないようにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DupPolicy {
+    /// 行動数の多いほうを使う。同数ならパスの辞書順で先のほうを使う（既定）
+    #[default]
+    Largest,
+    /// エラーにする
+    Error,
+    /// 指定した順で最初のファイルを使う
+    First,
+    /// 指定した順で最後のファイルを使う
+    Last,
+    /// すべて合算する（警告だけ出す）
+    KeepAll,
+}
+
+impl DupPolicy {
+    /// 指定できる名前の一覧
+    pub const NAMES: &'static [&'static str] = &["largest", "error", "first", "last", "keep-all"];
+
+    /// オプションの値から扱いを取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "largest" => Some(DupPolicy::Largest),
+            "error" => Some(DupPolicy::Error),
+            "first" => Some(DupPolicy::First),
+            "last" => Some(DupPolicy::Last),
+            "keep-all" => Some(DupPolicy::KeepAll),
+            _ => None,
+        }
+    }
+
+    /// JSON用の名前
+    pub fn key(&self) -> &'static str {
+        match self {
+            DupPolicy::Largest => "largest",
+            DupPolicy::Error => "error",
+            DupPolicy::First => "first",
+            DupPolicy::Last => "last",
+            DupPolicy::KeepAll => "keep-all",
+        }
+    }
+}
+
+/// 時間重み付き比率で使う重みの形
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WeightShape {
+    /// 試合開始時1倍 → 終了時2倍まで直線的に増える（既定）
+    #[default]
+    Linear,
+    /// 試合開始時1倍 → 終了時 e^2 (約7.4) 倍まで指数的に増える
+    Exponential,
+}
+
+impl WeightShape {
+    /// オプションの値から重みの形を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "linear" => Some(WeightShape::Linear),
+            "exp" | "exponential" => Some(WeightShape::Exponential),
+            _ => None,
+        }
+    }
+
+    /// 表示用の名前
+    pub fn label(&self) -> &'static str {
+        match self {
+            WeightShape::Linear => "線形",
+            WeightShape::Exponential => "指数",
+        }
+    }
+
+    /// 試合内の相対位置 t（0.0〜1.0）に対する重み
+    pub fn weight(&self, t: f64) -> f64 {
+        match self {
+            WeightShape::Linear => 1.0 + t,
+            WeightShape::Exponential => (2.0 * t).exp(),
+        }
+    }
+}
+
+/// 攻撃継続時間を計算するときの、単発の攻撃（前後が攻撃以外）の扱い
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SingleAttackMode {
+    /// 区間長0秒として扱う（既定）
+    #[default]
+    Zero,
+    /// 次の行動までの時間を区間長とする（最後の行動なら0秒）
+    UntilNext,
+}
+
+impl SingleAttackMode {
+    /// オプションの値から取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "zero" => Some(SingleAttackMode::Zero),
+            "next" => Some(SingleAttackMode::UntilNext),
+            _ => None,
+        }
+    }
+
+    /// 表示用の説明
+    pub fn label(&self) -> &'static str {
+        match self {
+            SingleAttackMode::Zero => "0秒として扱う",
+            SingleAttackMode::UntilNext => "次の行動までの時間",
+        }
+    }
+}
+
+/// 行動IDを行動タイプに分類する方法
+/// 
+/// Javaでいう interface。分類のルールを実験的に差し替えたいときは、
+/// これを実装した型を analyzer::analyze_with に渡す
+pub trait Classifier {
+    /// 行動IDを分類する
+    fn classify(&self, action_id: &str) -> ActionType;
+}
+
+/// 標準の分類（ActionType::from_action_id と同じ）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultClassifier;
+
+impl Classifier for DefaultClassifier {
+    fn classify(&self, action_id: &str) -> ActionType {
+        ActionType::from_action_id(action_id)
+    }
 }
 
 /// 1回の行動を表す
+/// 
+/// == での比較は、timestampをビット単位で比べる（PartialEqの実装を参照）
 #[derive(Debug, Clone)]
 pub struct Action {
     /// 行動が発生した時刻（秒）
@@ -135,22 +637,55 @@ pub struct Action {
     /// 行動の種類
     pub action_type: ActionType,
     /// 元の行動ID
-    pub original_id: String,
+    /// 同じIDの行動どうしで文字列を共有できるようArc<str>で持つ
+    pub original_id: Arc<str>,
+    /// CSVファイルでの行番号（1始まり）。ファイルから読んでいない行動は0
+    /// 並べ替えや取り除きをしても、元の行を辿れるように行動と一緒に持ち歩く
+    pub line: u32,
+    /// CSVファイルに書かれていた行そのもの（改行を除く）
+    /// --keep-raw で読んだときだけSome。ふだんは文字列を確保しない
+    pub raw: Option<Box<str>>,
 }
 
 impl Action {
+    /// 時刻と行動IDから作成する。行動タイプは ActionType::from_action_id で分類する
+    ///
+    /// 行番号と元の行は持たない（テストやライブラリから行動を組み立てるとき用）
     pub fn new(timestamp: f64, action_id: String) -> Self {
         let action_type = ActionType::from_action_id(&action_id);
+        Action::with_type(timestamp, action_id.into(), action_type)
+    }
+
+    /// 分類済みの行動タイプと共有済みのIDを使って作成する（読み込み処理用）
+    pub fn with_type(timestamp: f64, action_id: Arc<str>, action_type: ActionType) -> Self {
         Action {
             timestamp,
             action_type,
             original_id: action_id,
+            line: 0,
+            raw: None,
         }
     }
 }
 
+// timestampはf64なので、deriveした == だと NaN どうしが等しくならない。
+// 読み込み→書き出しの往復で同じ値が保たれたかを確かめたいので、
+// ビット単位（to_bits）で完全に一致したときだけ等しいとみなす。
+// そのため 0.0 と -0.0 は別の値として扱われる。
+// 行番号と元の行は読み込み方で変わるので比べない
+impl PartialEq for Action {
+    fn eq(&self, other: &Self) -> bool {
+        self.timestamp.to_bits() == other.timestamp.to_bits()
+            && self.action_type == other.action_type
+            && self.original_id == other.original_id
+    }
+}
+
+// ビット単位の比較は反射律（a == a）を満たすのでEqにもできる
+impl Eq for Action {}
+
 /// プレイヤーの情報を表す構造体
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PlayerInfo {
     /// 学籍番号
     pub student_id: String,
@@ -170,11 +705,72 @@ impl PlayerInfo {
 /// 1回の対戦における全行動ログ
 /// プレイヤー情報と、その対戦中の全行動を保持
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct BattleLog {
     pub player_info: PlayerInfo,
     // Vec: 可変長配列
     pub actions: Vec<Action>,
+    /// 読み込み時の注意・警告（全角カンマの変換など）
+    pub warnings: Vec<String>,
+    /// フェーズの区切り（phase:名前 の行）。行動には含めない
+    pub markers: Vec<PhaseMarker>,
+    /// ファイルの更新日時（ログに日付がないので試合の日時の代わりに使う）。
+    /// ファイル以外から読んだとき、ファイルシステムが更新日時を持たないときはNone
+    pub modified: Option<SystemTime>,
+    /// 入力のノイズとして取り除いた行動（--debounce を指定しなければNone）
+    pub debounce: Option<DebounceReport>,
+    /// 未登録の行動IDごとの、その行動が書かれていた行番号（ファイルの順）
+    pub unknown_id_lines: IdLines,
+    /// タイムスタンプが前の行より小さかった（逆行した）行の数（--sort で並べ替えたとき）
+    pub reordered: usize,
+    /// 読み込むときに除外した行の数（途中で切れた最後の行、読み飛ばした不正な行）
+    pub dropped_rows: usize,
+    /// 解析できずに読み飛ばした行（--lenient で読んだときだけ。ファイルの順）
+    pub skipped_lines: Vec<SkippedLine>,
+}
+
+/// 解析できずに読み飛ばした行動データの行（--lenient）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedLine {
+    /// 行番号（1始まり）
+    pub line: usize,
+    /// 行の内容（前後の空白を除いたもの）
+    pub content: String,
+    /// 読み飛ばした理由
+    pub reason: String,
+}
+
+/// 行動IDごとの、その行動が書かれていた行番号（ファイルの順）
+pub type IdLines = HashMap<String, Vec<usize>>;
+
+/// 同じ行動IDが極端に短い間隔で続いた行（スイッチのチャタリングなど）を取り除いた結果（--debounce）
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DebounceReport {
+    /// 取り除く間隔の上限（ミリ秒）。これより短い間隔で続いた同じIDを取り除く
+    pub threshold_ms: f64,
+    /// 行動IDごとの取り除いた件数（件数の多い順、同数ならID順）
+    pub dropped: Vec<(String, usize)>,
+}
+
+impl DebounceReport {
+    /// 取り除いた件数の合計
+    pub fn total(&self) -> usize {
+        self.dropped.iter().map(|(_, count)| count).sum()
+    }
+}
+
+/// フェーズの区切りを表すマーカー行（例: 120.0,phase:neutral_reset）
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseMarker {
+    /// フェーズが始まる時刻（秒）
+    pub timestamp: f64,
+    /// フェーズ名
+    pub name: String,
+}
+
+impl PhaseMarker {
+    /// マーカーとして扱う行動IDの接頭辞
+    pub const PREFIX: &'static str = "phase:";
 }
 
 impl BattleLog {
@@ -182,14 +778,159 @@ impl BattleLog {
         BattleLog {
             player_info,
             actions,
+            warnings: Vec::new(),
+            markers: Vec::new(),
+            modified: None,
+            debounce: None,
+            unknown_id_lines: HashMap::new(),
+            reordered: 0,
+            dropped_rows: 0,
+            skipped_lines: Vec::new(),
+        }
+    }
+
+    /// 指定した時刻の前後n件ずつの行動を取り出す
+    /// 
+    /// 行動を時刻順に並べ、二分探索で基準時刻の位置を探す。
+    /// ちょうど基準時刻の行動は「後」に入れる。
+    /// 最初の行動より前や最後の行動より後を指定した場合は、足りない側が短くなる。
+    /// 
+    /// # 引数
+    /// * `at` - 基準の時刻（秒）
+    /// * `n` - 前後それぞれで取り出す最大件数
+    /// 
+    /// # 戻り値
+    /// (基準より前の行動, 基準以降の行動)。どちらも時刻順
+    pub fn context(&self, at: f64, n: usize) -> (Vec<&Action>, Vec<&Action>) {
+        let mut sorted: Vec<&Action> = self.actions.iter().collect();
+        sorted.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+
+        // partition_point: 条件を満たす要素が先頭に並んでいるとき、満たさない最初の位置を二分探索で返す
+        let split = sorted.partition_point(|action| action.timestamp < at);
+        let before = sorted[split.saturating_sub(n)..split].to_vec();
+        let after = sorted[split..(split + n).min(sorted.len())].to_vec();
+        (before, after)
+    }
+
+    /// すべての行動のタイムスタンプに一定の秒数を足す（負なら引く）
+    /// 
+    /// 記録開始のずれを補正して、複数のログを同じ基準に揃えるために使う。
+    /// 補正後に負のタイムスタンプができた場合は警告を追加する。
+    /// 
+    /// # 引数
+    /// * `offset` - 足す秒数
+    pub fn shift_time(&mut self, offset: f64) {
+        for action in &mut self.actions {
+            action.timestamp += offset;
+        }
+
+        let negatives = self.actions.iter().filter(|a| a.timestamp < 0.0).count();
+        if negatives > 0 {
+            self.warnings.push(format!(
+                "時刻を {:+} 秒補正した結果、{} 件の行動のタイムスタンプが負になりました",
+                offset, negatives
+            ));
+        }
+    }
+
+    /// すべてのタイムスタンプを、`grid` 秒刻みの最も近い時刻に丸める（--snap）
+    /// 
+    /// 記録のわずかなずれ（ノイズ）を吸収して、間隔の解析を安定させるために使う。
+    /// 丸めても時刻の順番は入れ替わらない。フェーズの区切りも同じように丸める。
+    /// 丸めた結果、直前の行動と同じ時刻になった行動も取り除かずに残す（行動回数は変えない）。
+    /// その場合は間隔が0秒になるので、件数と最初の行番号を警告に追加する。
+    /// 
+    /// # 引数
+    /// * `grid` - 丸める刻み（秒、正の数）
+    pub fn snap_time(&mut self, grid: f64) {
+        // 0.1 * 3 = 0.30000000000000004 のような誤差が表示に出ないように、1ナノ秒単位で整える
+        let snap = |time: f64| ((time / grid).round() * grid * 1e9).round() / 1e9;
+
+        // (同じ時刻になった件数, 最初の行番号)
+        let mut merged: (usize, u32) = (0, 0);
+        let mut previous: Option<(f64, f64)> = None;
+        for action in &mut self.actions {
+            let snapped = snap(action.timestamp);
+            if matches!(previous, Some((original, time)) if time == snapped && original != action.timestamp) {
+                if merged.0 == 0 {
+                    merged.1 = action.line;
+                }
+                merged.0 += 1;
+            }
+            previous = Some((action.timestamp, snapped));
+            action.timestamp = snapped;
+        }
+        for marker in &mut self.markers {
+            marker.timestamp = snap(marker.timestamp);
+        }
+
+        if merged.0 > 0 {
+            let first = match merged.1 {
+                0 => String::new(),
+                line => format!("（最初は{}行目）", line),
+            };
+            self.warnings.push(format!(
+                "タイムスタンプを {} 秒刻みに丸めた結果、{} 件の行動が直前の行動と同じ時刻になりました{}。取り除かずに間隔0秒として解析します",
+                grid, merged.0, first
+            ));
         }
     }
+
+    /// 直前の行と同じ行動IDで、間隔が `threshold_ms` ミリ秒より短い行を取り除く（--debounce）
+    /// 
+    /// 入力機器のチャタリングで同じ行動が二重に記録されるのを、ノイズとして除くために使う。
+    /// 比べるのは取り除いたかどうかに関係なくファイル上の直前の行なので、違うIDの行が間に入れば残す。
+    /// 間隔がちょうど `threshold_ms` の行は残す（小数の誤差で消えないように、1ナノ秒の余裕をみる）。
+    /// 取り除いた件数は debounce に記録し、取り除いた行があれば警告も追加する。
+    /// 
+    /// # 引数
+    /// * `threshold_ms` - 取り除く間隔の上限（ミリ秒）
+    pub fn debounce(&mut self, threshold_ms: f64) {
+        let threshold = threshold_ms / 1000.0 - 1e-9;
+        // 行動ID → (取り除いた件数, 最初に取り除いた行の行番号)
+        let mut dropped: HashMap<String, (usize, u32)> = HashMap::new();
+        let mut previous: Option<(f64, Arc<str>)> = None;
+
+        self.actions.retain(|action| {
+            let bounce = matches!(&previous, Some((time, id)) if *id == action.original_id && action.timestamp - time < threshold);
+            previous = Some((action.timestamp, action.original_id.clone()));
+            if bounce {
+                dropped.entry(action.original_id.to_string()).or_insert((0, action.line)).0 += 1;
+            }
+            !bounce
+        });
+
+        // 警告には、どの行が取り除かれたかを探せるように最初の行番号を添える（ファイルから読んでいなければ添えない）
+        let first_lines: HashMap<String, u32> = dropped.iter().map(|(id, &(_, line))| (id.clone(), line)).collect();
+        let mut dropped: Vec<(String, usize)> = dropped.into_iter().map(|(id, (count, _))| (id, count)).collect();
+        dropped.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let report = DebounceReport { threshold_ms, dropped };
+        if report.total() > 0 {
+            let details: Vec<String> = report
+                .dropped
+                .iter()
+                .map(|(id, count)| match first_lines[id] {
+                    0 => format!("{} {}件", id, count),
+                    line => format!("{} {}件（最初は{}行目）", id, count, line),
+                })
+                .collect();
+            self.warnings.push(format!(
+                "同じ行動IDが {} ミリ秒未満で続いた {} 行をノイズとして取り除きました（{}）",
+                threshold_ms,
+                report.total(),
+                details.join(", ")
+            ));
+        }
+        self.debounce = Some(report);
+    }
 }
 
 /// 各行動タイプの集計結果
 /// 各行動タイプの出現回数を保持
 
-#[derive(Debug, Clone)]
+// Default: すべて0の値を ActionCounts::default() で作れるようにする
+// Copy: u32だけの小さな構造体なので、cloneせずにコピーで渡せるようにする
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct ActionCounts {
     /// 攻撃の回数
     pub attack_count: u32,
@@ -197,19 +938,23 @@ pub struct ActionCounts {
     pub shield_count: u32,
     /// 回避の回数
     pub dodge_count: u32,
+    /// 未登録の行動IDの回数
+    pub unknown_count: u32,
 }
 
 impl ActionCounts {
     /// すべてのカウントを0で初期化
     pub fn new() -> Self {
-        ActionCounts {
-            attack_count: 0,
-            shield_count: 0,
-            dodge_count: 0,
-        }
+        ActionCounts::default()
     }
 
+    /// 合計の回数（未登録の行動IDも含む）
     pub fn total(&self) -> u32 {
+        self.known_total() + self.unknown_count
+    }
+
+    /// 攻撃・シールド・回避の合計の回数（未登録の行動IDを含まない）
+    pub fn known_total(&self) -> u32 {
         self.attack_count + self.shield_count + self.dodge_count
     }
 
@@ -219,6 +964,7 @@ impl ActionCounts {
             ActionType::Attack => self.attack_count += 1,
             ActionType::Shield => self.shield_count += 1,
             ActionType::Dodge => self.dodge_count += 1,
+            ActionType::Unknown => self.unknown_count += 1,
         }
     }
 
@@ -252,49 +998,1819 @@ impl ActionCounts {
         }
     }
 
-    /// 最も比率が高い行動タイプを返す
-    pub fn most_frequent_action(&self) -> ActionType {
-        let attack = self.attack_ratio();
-        let shield = self.shield_ratio();
-        let dodge = self.dodge_ratio();
-        
-        if attack >= shield && attack >= dodge {
-            ActionType::Attack
-        } else if shield >= dodge {
-            ActionType::Shield
+    /// 指定した行動タイプの回数
+    pub fn count(&self, action_type: &ActionType) -> u32 {
+        match action_type {
+            ActionType::Attack => self.attack_count,
+            ActionType::Shield => self.shield_count,
+            ActionType::Dodge => self.dodge_count,
+            ActionType::Unknown => self.unknown_count,
+        }
+    }
+
+    /// 未登録の行動IDの比率（%）を計算
+    pub fn unknown_ratio(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            0.0
         } else {
-            ActionType::Dodge
+            (self.unknown_count as f64 / total as f64) * 100.0
+        }
+    }
+
+    /// カテゴリを比率の高い順に並べる
+    /// 
+    /// 同じ比率のカテゴリは 攻撃 → シールド → 回避 の順に並べる（毎回同じ結果になる）。
+    /// 未登録の行動IDはカテゴリではないので含めない（比率は未登録も含めた合計に対する値）
+    /// 
+    /// # 戻り値
+    /// (行動タイプ, 比率%) のリスト（比率の降順）
+    pub fn ranking(&self) -> Vec<(ActionType, f64)> {
+        let mut ranking = vec![
+            (ActionType::Attack, self.attack_count),
+            (ActionType::Shield, self.shield_count),
+            (ActionType::Dodge, self.dodge_count),
+        ];
+        // 比率は回数から計算するので、回数で比べる（小数の比較より確実）
+        // sort_by_keyは安定ソートなので、同数なら上の並び順のまま
+        ranking.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+        let total = self.total();
+        ranking
+            .into_iter()
+            .map(|(action_type, count)| {
+                let ratio = if total == 0 { 0.0 } else { count as f64 / total as f64 * 100.0 };
+                (action_type, ratio)
+            })
+            .collect()
+    }
+
+    /// 最も比率が高い行動タイプを返す（同率なら 攻撃 → シールド → 回避 の順で優先）
+    /// 
+    /// 未登録の行動IDがどれだけ多くても、攻撃・シールド・回避が1回でもあればそちらを返す。
+    /// 未登録の行動IDしかないときだけ Unknown を返す
+    pub fn most_frequent_action(&self) -> ActionType {
+        if self.known_total() == 0 && self.unknown_count > 0 {
+            return ActionType::Unknown;
         }
+        // rankingは必ず3要素あるので、先頭は常に存在する
+        self.ranking().swap_remove(0).0
     }
 }
 
+// += で回数を足し合わせられるようにする（フェーズやログをまとめて集計するとき用）
+// Javaにはない演算子オーバーロード。a += b が a.add_assign(b) の呼び出しになる
+impl AddAssign for ActionCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.attack_count += other.attack_count;
+        self.shield_count += other.shield_count;
+        self.dodge_count += other.dodge_count;
+        self.unknown_count += other.unknown_count;
+    }
+}
 
-/// プレイヤー情報とカウント情報からなる解析結果
-#[derive(Debug, Clone)]
-pub struct AnalysisResult {
-    /// プレイヤー情報
-    pub player_info: PlayerInfo,
-    /// 各行動の回数
-    pub counts: ActionCounts,
-    /// 行動IDごとの回数（降順ソート済み）
-    pub action_id_counts: Vec<(String, u32)>,
+// a + b で新しい集計を作れるようにする
+impl Add for ActionCounts {
+    type Output = ActionCounts;
+
+    fn add(mut self, other: Self) -> ActionCounts {
+        self += other;
+        self
+    }
 }
 
-impl AnalysisResult {
-    /// BattleLogとActionCountsから解析結果を構築
-    /// 
-    /// # 引数
-    /// * `battle_log` - 対戦ログデータ
-    /// * `counts` - 集計済みの行動回数
-    /// * `action_id_counts` - 行動IDごとの回数
-    /// 
+/// 複数の試合を合算した行動回数
+///
+/// 1試合の ActionCounts は u32 で十分だが、学期中のクラス全員分のログを足すと
+/// u32 の上限（約43億）を超えることもありうるので、合算には u64 を使う
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct AggregateCounts {
+    /// 攻撃の回数
+    pub attack_count: u64,
+    /// シールドの回数
+    pub shield_count: u64,
+    /// 回避の回数
+    pub dodge_count: u64,
+    /// 未登録の行動IDの回数
+    pub unknown_count: u64,
+}
+
+impl AggregateCounts {
+    /// すべてのカウントを0で初期化
+    pub fn new() -> Self {
+        AggregateCounts::default()
+    }
+
+    /// 合計の回数（未登録の行動IDも含む。u64 に収まらなければ上限の値）
+    pub fn total(&self) -> u64 {
+        self.attack_count
+            .saturating_add(self.shield_count)
+            .saturating_add(self.dodge_count)
+            .saturating_add(self.unknown_count)
+    }
+
+    /// 1試合分の回数を足す
+    ///
     /// # 戻り値
-    /// 解析結果
-    pub fn new(battle_log: &BattleLog, counts: ActionCounts, action_id_counts: Vec<(String, u32)>) -> Self {
-        AnalysisResult {
-            player_info: battle_log.player_info.clone(),
-            counts,
-            action_id_counts,
-        }
+    /// 足した結果。u64 に収まらなければNone
+    pub fn checked_add(&self, other: &ActionCounts) -> Option<Self> {
+        Some(AggregateCounts {
+            attack_count: self.attack_count.checked_add(other.attack_count as u64)?,
+            shield_count: self.shield_count.checked_add(other.shield_count as u64)?,
+            dodge_count: self.dodge_count.checked_add(other.dodge_count as u64)?,
+            unknown_count: self.unknown_count.checked_add(other.unknown_count as u64)?,
+        })
+    }
+
+    /// 攻撃の割合（%）
+    pub fn attack_ratio(&self) -> f64 {
+        self.ratio(self.attack_count)
     }
-}
\ No newline at end of file
+
+    /// シールドの割合（%）
+    pub fn shield_ratio(&self) -> f64 {
+        self.ratio(self.shield_count)
+    }
+
+    /// 回避の割合（%）
+    pub fn dodge_ratio(&self) -> f64 {
+        self.ratio(self.dodge_count)
+    }
+
+    /// 未登録の行動IDの割合（%）
+    pub fn unknown_ratio(&self) -> f64 {
+        self.ratio(self.unknown_count)
+    }
+
+    /// 合計に対する `count` の割合（%）。合計が0なら0.0
+    fn ratio(&self, count: u64) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            0.0
+        } else {
+            (count as f64 / total as f64) * 100.0
+        }
+    }
+}
+
+impl From<&ActionCounts> for AggregateCounts {
+    fn from(counts: &ActionCounts) -> Self {
+        AggregateCounts::new() + counts
+    }
+}
+
+// 合算.checked_add(1試合) と同じだが、溢れたときは折り返さずに u64 の上限で止める
+// （u32 の試合を 40億件以上足さないと起きないので、現実には止まらない）
+impl AddAssign<&ActionCounts> for AggregateCounts {
+    fn add_assign(&mut self, other: &ActionCounts) {
+        *self = self.checked_add(other).unwrap_or(AggregateCounts {
+            attack_count: self.attack_count.saturating_add(other.attack_count as u64),
+            shield_count: self.shield_count.saturating_add(other.shield_count as u64),
+            dodge_count: self.dodge_count.saturating_add(other.dodge_count as u64),
+            unknown_count: self.unknown_count.saturating_add(other.unknown_count as u64),
+        });
+    }
+}
+
+impl Add<&ActionCounts> for AggregateCounts {
+    type Output = AggregateCounts;
+
+    fn add(mut self, other: &ActionCounts) -> AggregateCounts {
+        self += other;
+        self
+    }
+}
+
+
+/// 試合の前半と後半での使用率の変化の向き（--momentum）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Momentum {
+    /// 後半のほうが使用率が高い
+    Rising,
+    /// 後半のほうが使用率が低い
+    Falling,
+    /// ほとんど変わらない
+    Steady,
+}
+
+impl Momentum {
+    /// 使用率がこの割合（25%）より大きく増えたら Rising、減ったら Falling
+    pub const CHANGE: f64 = 0.25;
+
+    /// 前半と後半の回数から変化の向きを決める
+    ///
+    /// 使用率は「その半分での回数 / その半分の行動数」。前半に一度も使っていなくて後半に使っていれば Rising
+    ///
+    /// # 引数
+    /// * `first` - 前半の回数
+    /// * `first_total` - 前半の行動数
+    /// * `second` - 後半の回数
+    /// * `second_total` - 後半の行動数
+    pub fn classify(first: u32, first_total: u32, second: u32, second_total: u32) -> Self {
+        let rate = |count: u32, total: u32| if total == 0 { 0.0 } else { count as f64 / total as f64 };
+        let (before, after) = (rate(first, first_total), rate(second, second_total));
+        if after > before * (1.0 + Self::CHANGE) {
+            Momentum::Rising
+        } else if after < before * (1.0 - Self::CHANGE) {
+            Momentum::Falling
+        } else {
+            Momentum::Steady
+        }
+    }
+
+    /// JSONなど機械向けの出力で使うキー
+    pub fn key(&self) -> &'static str {
+        match self {
+            Momentum::Rising => "rising",
+            Momentum::Falling => "falling",
+            Momentum::Steady => "steady",
+        }
+    }
+}
+
+/// 試合を時間で前半と後半に分けた使用回数（--momentum）
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HalfCounts {
+    /// 前半と後半の境目の時刻（秒）。この時刻ちょうどの行動は後半に入れる
+    pub split: f64,
+    /// 前半のカテゴリごとの回数
+    pub first: ActionCounts,
+    /// 後半のカテゴリごとの回数
+    pub second: ActionCounts,
+    /// 行動IDごとの (前半, 後半) の回数
+    pub by_id: HashMap<String, (u32, u32)>,
+}
+
+impl HalfCounts {
+    /// カテゴリの使用率の変化の向き
+    pub fn category_momentum(&self, action_type: &ActionType) -> Momentum {
+        Momentum::classify(
+            self.first.count(action_type),
+            self.first.total(),
+            self.second.count(action_type),
+            self.second.total(),
+        )
+    }
+
+    /// 行動IDの使用率の変化の向き（そのIDを使っていなければNone）
+    pub fn id_momentum(&self, id: &str) -> Option<Momentum> {
+        let (first, second) = self.by_id.get(id)?;
+        Some(Momentum::classify(*first, self.first.total(), *second, self.second.total()))
+    }
+}
+
+/// 行動IDごとの使用時間帯（スパークライン）で試合時間を分割する区間数
+pub const SPARKLINE_BUCKETS: usize = 10;
+
+/// 解析結果を並べるときのキー（AnalysisResult::cmp_by、cmp_entries_by）
+///
+/// ファイル名は AnalysisResult に入っていないので、FileName で並べるときは
+/// (パス, 解析結果) の組にして cmp_entries_by で比べる
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SortKey {
+    /// 学籍番号
+    StudentId,
+    /// 対戦回次
+    MatchNumber,
+    /// 行動の合計回数（未登録のIDも含む）
+    Total,
+    /// 攻撃の比率
+    AttackRatio,
+    /// シールドの比率
+    ShieldRatio,
+    /// 回避の比率
+    DodgeRatio,
+    /// 試合時間
+    Duration,
+    /// ファイルのパス（cmp_entries_by だけで使う。cmp_by では同じとみなす）
+    FileName,
+}
+
+/// プレイヤー情報とカウント情報からなる解析結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisResult {
+    /// プレイヤー情報
+    pub player_info: PlayerInfo,
+    /// ログのファイルの更新日時（取れなければNone）
+    pub modified: Option<SystemTime>,
+    /// 入力のノイズとして取り除いた行動（--debounce を指定しなければNone）
+    pub debounce: Option<DebounceReport>,
+    /// 各行動の回数
+    pub counts: ActionCounts,
+    /// 行動IDごとの回数（降順ソート済み）
+    pub action_id_counts: Vec<(String, u32)>,
+    /// 未登録の行動IDごとの回数（action_id_counts のうち Unknown に分類されたもの、降順）
+    pub unknown_id_counts: Vec<(String, u32)>,
+    /// 未登録の行動ID（unknown_id_counts と同じ順）
+    pub unknown_ids: Vec<String>,
+    /// 未登録の行動IDごとの、その行動が書かれていた行番号（ファイルの順）
+    pub unknown_id_lines: IdLines,
+    /// 1分あたりの行動数（APM。試合時間が0秒なら0.0）
+    pub apm: f64,
+    /// 試合時間とAPM（最初・最後の行動の時刻など）
+    pub time_stats: TimeStats,
+    /// 試合時間を等分した区間（序盤・中盤・終盤など）ごとの行動回数（時刻順）
+    pub time_phases: Vec<ActionCounts>,
+    /// 行動間隔の基本統計（間隔がない場合はNone）
+    pub interval_stats: Option<IntervalStats>,
+    /// 行動間隔の分布の形状
+    pub interval_shape: IntervalShape,
+    /// 行動間隔のランダム性の目安（間隔が少なければNone）
+    pub randomness: Option<RandomnessCheck>,
+    /// 試合後半を重視した時間重み付きのカテゴリ比率
+    pub weighted_ratios: WeightedRatios,
+    /// 使った技の種類の時間推移
+    pub repertoire: RepertoireGrowth,
+    /// 攻撃を続けていた区間の集計
+    pub attack_streaks: AttackStreaks,
+    /// 攻撃と防御の切り替えの集計
+    pub flow_switches: FlowSwitches,
+    /// 攻防の切り替え回数の時間推移
+    pub tension: TensionCurve,
+    /// 直前と同じ行動を繰り返した割合
+    pub repeats: RepeatRates,
+    /// 攻め継続率・守り継続率（攻撃・防御の次も同じ側の行動だった割合）
+    pub continuity: Continuity,
+    /// 隣り合う行動どうしの遷移の集計（行動タイプの行列と、多い行動IDの組）
+    pub transitions: Transitions,
+    /// バースト（短い間隔で続いた行動）と単発の行動の集計
+    pub bursts: Bursts,
+    /// 記録の質から計算したデータ信頼度
+    pub reliability: Reliability,
+    /// 攻撃から次のシールドまでの時間の分布（攻撃の後のシールドが1つもなければNone）
+    pub shield_cover: Option<ShieldCover>,
+    /// コンボの集計
+    pub combos: ComboStats,
+    /// つかみの後の投げ・派生の集計
+    pub grab_follow_ups: GrabFollowUps,
+    /// 行動密度の山（時刻順）
+    pub density_peaks: Vec<DensityPeak>,
+    /// 異常に行動が集中した秒（スパイク）
+    pub density_spikes: DensitySpikes,
+    /// 試合全体の活動量
+    pub activity: ActivityStrip,
+    /// 前後方向の行動のバランス
+    pub direction_balance: DirectionBalance,
+    /// 攻撃の種類ごとの回数（AttackCategory::ALL の順）
+    pub attack_categories: Vec<(AttackCategory, u32)>,
+    /// カテゴリごとの行動時刻の分布（攻撃・シールド・回避の順）
+    pub time_spreads: Vec<CategoryTimeSpread>,
+    /// フェーズごとの集計（マーカーがなければ空）
+    pub phases: Vec<PhaseCounts>,
+    /// 行動IDごとの、試合時間を等分した各区間での使用回数（--sparklines指定時のみ）
+    pub id_usage_buckets: Option<HashMap<String, [u32; SPARKLINE_BUCKETS]>>,
+    /// 試合の前半・後半での使用回数（--momentum指定時のみ）
+    pub half_counts: Option<HalfCounts>,
+    /// 行動間隔のヒストグラム（--interval-bin指定時のみ。間隔がなければNone）
+    pub interval_histogram: Option<IntervalHistogram>,
+}
+
+impl AnalysisResult {
+    /// BattleLogとActionCountsから解析結果を構築
+    /// 
+    /// # 引数
+    /// * `battle_log` - 対戦ログデータ
+    /// * `counts` - 集計済みの行動回数
+    /// * `action_id_counts` - 行動IDごとの回数
+    /// 
+    /// # 戻り値
+    /// 解析結果
+    pub fn new(battle_log: &BattleLog, counts: ActionCounts, action_id_counts: Vec<(String, u32)>) -> Self {
+        AnalysisResult {
+            player_info: battle_log.player_info.clone(),
+            modified: battle_log.modified,
+            debounce: battle_log.debounce.clone(),
+            counts,
+            action_id_counts,
+            unknown_id_counts: Vec::new(),
+            unknown_ids: Vec::new(),
+            unknown_id_lines: battle_log.unknown_id_lines.clone(),
+            apm: 0.0,
+            time_stats: TimeStats::default(),
+            time_phases: Vec::new(),
+            interval_stats: None,
+            interval_shape: IntervalShape::new(),
+            randomness: None,
+            weighted_ratios: WeightedRatios::default(),
+            repertoire: RepertoireGrowth::new(),
+            attack_streaks: AttackStreaks::default(),
+            flow_switches: FlowSwitches::new(),
+            tension: TensionCurve::default(),
+            repeats: RepeatRates::default(),
+            continuity: Continuity::default(),
+            transitions: Transitions::new(0.0),
+            bursts: Bursts::new(0.0, 0),
+            reliability: Reliability::new(0, 0, 0, &ActionCounts::new()),
+            shield_cover: None,
+            combos: ComboStats::new(0.0),
+            grab_follow_ups: GrabFollowUps::default(),
+            density_peaks: Vec::new(),
+            density_spikes: DensitySpikes::default(),
+            activity: ActivityStrip::new(),
+            direction_balance: DirectionBalance::default(),
+            attack_categories: Vec::new(),
+            time_spreads: Vec::new(),
+            phases: Vec::new(),
+            id_usage_buckets: None,
+            half_counts: None,
+            interval_histogram: None,
+        }
+    }
+
+    /// 指定したキーで他の解析結果と比べる（sort_by にそのまま渡せる）
+    ///
+    /// キーが同じなら、学籍番号 → 対戦回次の順（いつも昇順）で比べるので、
+    /// 同じ試合でなければ並び順は1つに決まる。
+    /// 比率や試合時間が NaN のものは、昇順でも降順でも最後にする
+    ///
+    /// # 引数
+    /// * `other` - 比べる相手
+    /// * `key` - 並べるキー
+    /// * `descending` - キーを大きい順にするか（同じときの学籍番号・対戦回次は昇順のまま）
+    pub fn cmp_by(&self, other: &AnalysisResult, key: SortKey, descending: bool) -> Ordering {
+        let direction = |ordering: Ordering| if descending { ordering.reverse() } else { ordering };
+        let by_key = match key {
+            SortKey::StudentId => direction(self.player_info.student_id.cmp(&other.player_info.student_id)),
+            SortKey::MatchNumber => direction(self.player_info.match_number.cmp(&other.player_info.match_number)),
+            SortKey::Total => direction(self.counts.total().cmp(&other.counts.total())),
+            SortKey::AttackRatio => cmp_nan_last(self.counts.attack_ratio(), other.counts.attack_ratio(), descending),
+            SortKey::ShieldRatio => cmp_nan_last(self.counts.shield_ratio(), other.counts.shield_ratio(), descending),
+            SortKey::DodgeRatio => cmp_nan_last(self.counts.dodge_ratio(), other.counts.dodge_ratio(), descending),
+            SortKey::Duration => cmp_nan_last(self.time_stats.duration, other.time_stats.duration, descending),
+            // パスを持っていないので比べられない（学籍番号 → 対戦回次の順になる）
+            SortKey::FileName => Ordering::Equal,
+        };
+        by_key
+            .then_with(|| self.player_info.student_id.cmp(&other.player_info.student_id))
+            .then_with(|| self.player_info.match_number.cmp(&other.player_info.match_number))
+    }
+}
+
+/// (ファイルのパス, 解析結果) の組を、指定したキーで比べる（sort_by にそのまま渡せる）
+///
+/// FileName ならパスの辞書順で、それ以外のキーは AnalysisResult::cmp_by と同じに比べる。
+/// 学籍番号と対戦回次まで同じ（同じ試合のログが複数ある）ときは、最後にパスの昇順で比べるので、
+/// パスが違えば並び順は1つに決まる
+///
+/// # 引数
+/// * `a`, `b` - 比べる (パス, 解析結果) の組
+/// * `key` - 並べるキー
+/// * `descending` - キーを大きい順にするか
+pub fn cmp_entries_by(a: (&str, &AnalysisResult), b: (&str, &AnalysisResult), key: SortKey, descending: bool) -> Ordering {
+    let by_path = a.0.cmp(b.0);
+    let by_file_name = match key {
+        SortKey::FileName if descending => by_path.reverse(),
+        SortKey::FileName => by_path,
+        _ => Ordering::Equal,
+    };
+    by_file_name.then_with(|| a.1.cmp_by(b.1, key, descending)).then(by_path)
+}
+
+/// 小数を比べる。NaN は向きに関係なく最後にし、NaN どうしは同じとみなす
+fn cmp_nan_last(a: f64, b: f64, descending: bool) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        // total_cmp は -0.0 < 0.0 とするので、同じ 0 として比べられるように partial_cmp を使う
+        (false, false) if descending => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// 攻撃が連続した区間（別カテゴリの行動が入るまで）の集計
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AttackStreaks {
+    /// 単発の攻撃の扱い
+    pub single_mode: SingleAttackMode,
+    /// 攻撃区間の数
+    pub streak_count: usize,
+    /// そのうち単発の攻撃の区間の数
+    pub single_count: usize,
+    /// 攻撃区間の長さの合計（秒）
+    pub total_secs: f64,
+    /// 最も長い攻撃区間の長さ（秒）
+    pub longest_secs: f64,
+}
+
+impl AttackStreaks {
+    /// 区間がない状態で初期化
+    pub fn new(single_mode: SingleAttackMode) -> Self {
+        AttackStreaks {
+            single_mode,
+            ..AttackStreaks::default()
+        }
+    }
+}
+
+/// 前方向・後方向の行動の回数と、その対称性
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DirectionBalance {
+    /// 前方向の行動の回数
+    pub forward: u32,
+    /// 後方向の行動の回数
+    pub backward: u32,
+    /// 対称性スコア（少ない方/多い方、0〜1）。前後方向の行動がなければNone
+    pub score: Option<f64>,
+}
+
+/// 1つのカテゴリの行動時刻の分布（箱ひげ図用）
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryTimeSpread {
+    /// カテゴリ
+    pub action_type: ActionType,
+    /// そのカテゴリの行動の時刻（昇順）
+    pub timestamps: Vec<f64>,
+    /// 四分位（最小, 第1四分位, 中央値, 第3四分位, 最大）。行動が少なければNone
+    pub quartiles: Option<[f64; 5]>,
+}
+
+impl CategoryTimeSpread {
+    /// 四分位を計算するのに必要な最小の行動数（これより少ないと箱の形に意味がない）
+    pub const MIN_SAMPLES: usize = 4;
+}
+
+/// 1つのフェーズ（マーカーで区切った区間）の集計
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseCounts {
+    /// フェーズ名（最初のマーカーより前は「開始〜」）
+    pub name: String,
+    /// フェーズの開始時刻（秒）。最初のフェーズは最初の行動の時刻
+    pub start: f64,
+    /// フェーズ内の行動回数
+    pub counts: ActionCounts,
+}
+
+/// 試合全体を一定の長さの区間に分けた活動量（区間ごとの行動数）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivityStrip {
+    /// 1区間の長さ（秒）
+    pub slice_secs: f64,
+    /// 区間ごとの行動数（時刻順）
+    pub counts: Vec<u32>,
+}
+
+// Default: ライブラリとして使うときに ActivityStrip::default() でも作れるようにする（new() と同じ）
+impl Default for ActivityStrip {
+    fn default() -> Self {
+        ActivityStrip::new()
+    }
+}
+
+impl ActivityStrip {
+    /// 1行に並べる区間の最大数
+    pub const MAX_CELLS: usize = 40;
+
+    /// 区間がない状態で初期化
+    pub fn new() -> Self {
+        ActivityStrip {
+            slice_secs: 1.0,
+            counts: Vec::new(),
+        }
+    }
+}
+
+/// 行動密度（1秒あたりの行動数）の山
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityPeak {
+    /// 山の始まりの秒（この秒から1秒間の密度）
+    pub second: f64,
+    /// 同じ密度が続いた秒数（平らな山の幅）
+    pub width: usize,
+    /// 1秒あたりの行動数
+    pub density: u32,
+}
+
+/// 秒ごとの行動数（密度）が平均より極端に多い秒（スパイク）の集計
+///
+/// 入力ミスや連打バグの可能性がある瞬間を見つけるために使う
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DensitySpikes {
+    /// 閾値に使ったσの倍率
+    pub sigma: f64,
+    /// 1秒あたりの行動数の平均
+    pub mean: f64,
+    /// 1秒あたりの行動数の標準偏差
+    pub std_dev: f64,
+    /// スパイクとみなす密度の閾値（平均 + 倍率×標準偏差）。これより多い秒がスパイク
+    pub threshold: f64,
+    /// スパイクの秒（時刻順）
+    pub spikes: Vec<DensitySpike>,
+}
+
+/// 行動が異常に集中した1秒
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensitySpike {
+    /// スパイクの秒（この秒から1秒間の密度）
+    pub second: f64,
+    /// 1秒あたりの行動数
+    pub density: u32,
+}
+
+/// 2つのログの行動の差分
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LogDiff {
+    /// 新しいログにだけある行動（時刻順）
+    pub added: Vec<Action>,
+    /// 古いログにだけある行動（時刻順）
+    pub removed: Vec<Action>,
+}
+
+/// つかみ(g)の後にどの投げ・派生に行ったかの集計
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GrabFollowUps {
+    /// つかみの回数
+    pub grabs: usize,
+    /// 投げ・派生の行動IDごとの回数（回数降順、同回数ならID昇順）
+    pub throws: Vec<(String, usize)>,
+    /// 投げ・派生に行かなかった回数（別の行動が続いた、またはログが終わった）
+    pub others: usize,
+}
+
+impl GrabFollowUps {
+    /// つかみの後の行動として数える投げ・派生の行動ID
+    pub const THROW_IDS: &'static [&'static str] = &["fth", "bth", "uth", "dth", "fc", "bc", "uc", "dc"];
+
+    /// つかみの回数に対する割合（%）。つかみがなければNone
+    pub fn rate(&self, count: usize) -> Option<f64> {
+        if self.grabs == 0 {
+            None
+        } else {
+            Some(count as f64 / self.grabs as f64 * 100.0)
+        }
+    }
+}
+
+/// 短い間隔で続けた攻撃（コンボ）の集計
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComboStats {
+    /// コンボとみなす攻撃間隔の上限（秒）
+    pub gap: f64,
+    /// コンボの数（2回以上続いた攻撃のまとまり）
+    pub combo_count: usize,
+    /// 最大コンボ長（攻撃の回数）
+    pub max_length: usize,
+    /// 最大コンボの行動IDの並び（時刻順。コンボがなければ空）
+    pub longest: Vec<String>,
+    /// 平均コンボ長（コンボがなければNone）
+    pub mean_length: Option<f64>,
+}
+
+impl ComboStats {
+    /// コンボがない状態で初期化
+    pub fn new(gap: f64) -> Self {
+        ComboStats {
+            gap,
+            combo_count: 0,
+            max_length: 0,
+            longest: Vec::new(),
+            mean_length: None,
+        }
+    }
+}
+
+/// 攻防の切り替えの多さによるプレイスタイルの分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowStyle {
+    /// 攻撃と防御（シールド・回避）を頻繁に切り替える
+    SwitchHeavy,
+    /// 切り替えの多さが中くらい
+    Balanced,
+    /// 攻撃だけ・防御だけの時間がまとまって続く
+    PhaseHeavy,
+}
+
+impl FlowStyle {
+    /// 1分あたりの切り替え回数がこれ以上なら SwitchHeavy
+    pub const SWITCH_HEAVY_PER_MINUTE: f64 = 20.0;
+    /// 1分あたりの切り替え回数がこれ以下なら PhaseHeavy
+    pub const PHASE_HEAVY_PER_MINUTE: f64 = 8.0;
+
+    /// 1分あたりの切り替え回数から分類する
+    pub fn classify(per_minute: f64) -> Self {
+        if per_minute >= FlowStyle::SWITCH_HEAVY_PER_MINUTE {
+            FlowStyle::SwitchHeavy
+        } else if per_minute <= FlowStyle::PHASE_HEAVY_PER_MINUTE {
+            FlowStyle::PhaseHeavy
+        } else {
+            FlowStyle::Balanced
+        }
+    }
+
+    /// 表示用の名前
+    pub fn label(&self) -> &'static str {
+        match self {
+            FlowStyle::SwitchHeavy => "切り替え型（攻防を細かく入れ替える）",
+            FlowStyle::Balanced => "中間",
+            FlowStyle::PhaseHeavy => "局面型（攻めと守りがまとまって続く）",
+        }
+    }
+}
+
+/// 行動間隔の規則性の分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rhythm {
+    /// 間隔がそろっている（一定のリズムで行動している）
+    Regular,
+    /// 間隔が指数分布に近い（行動がほぼランダムに起きている）
+    Random,
+    /// 短い間隔と長い間隔が極端に混ざる（まとめて行動しては休む）
+    Bursty,
+}
+
+impl Rhythm {
+    /// 分散/平均² がこれ未満なら Regular
+    pub const REGULAR_BELOW: f64 = 0.5;
+    /// 分散/平均² がこれより大きければ Bursty
+    pub const BURSTY_ABOVE: f64 = 1.5;
+
+    /// 間隔の 分散/平均² から分類する
+    pub fn classify(dispersion: f64) -> Self {
+        if dispersion < Rhythm::REGULAR_BELOW {
+            Rhythm::Regular
+        } else if dispersion > Rhythm::BURSTY_ABOVE {
+            Rhythm::Bursty
+        } else {
+            Rhythm::Random
+        }
+    }
+
+    /// 表示用の所見
+    pub fn label(&self) -> &'static str {
+        match self {
+            Rhythm::Regular => "リズムがある（間隔がそろっている）",
+            Rhythm::Random => "行動はほぼランダム（ポアソン過程に近い）",
+            Rhythm::Bursty => "まとめて行動しては間が空く（集中と休みの差が大きい）",
+        }
+    }
+}
+
+/// 行動がランダムに起きているかの簡易チェック
+/// 
+/// 行動がポアソン過程に従うなら、間隔は指数分布になり「分散 = 平均²」になる。
+/// そこで 分散/平均² が1に近いかどうかで判定する（厳密な検定ではなく目安）
+#[derive(Debug, Clone, PartialEq)]
+pub struct RandomnessCheck {
+    /// 間隔の 分散/平均²（1ならランダム、小さいほど規則的、大きいほど偏りがある）
+    pub dispersion: f64,
+    /// 分類の結果
+    pub rhythm: Rhythm,
+}
+
+/// 攻撃と防御（シールド・回避）の切り替えの集計
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FlowSwitches {
+    /// 連続する2つの行動で攻撃⇔防御が入れ替わった回数
+    pub switches: usize,
+    /// 1分あたりの切り替え回数（試合時間が0秒ならNone）
+    pub per_minute: Option<f64>,
+    /// プレイスタイルの分類（per_minuteがNoneならNone）
+    pub style: Option<FlowStyle>,
+}
+
+impl FlowSwitches {
+    /// 切り替えがない状態で初期化
+    pub fn new() -> Self {
+        FlowSwitches::default()
+    }
+}
+
+/// 直前の行動と同じ行動を繰り返した割合（読まれやすさの目安）
+/// 
+/// 間隔が `gap` を超えたところで行動の流れが途切れたとみなし、途切れをまたいだ比較はしない
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RepeatRates {
+    /// 行動の流れが続いているとみなす間隔の上限（秒、コンボ判定と同じ値）
+    pub gap: f64,
+    /// 直前の行動と比べた行動の数（流れが途切れた直後の行動は数えない）
+    pub compared: usize,
+    /// 直前の行動と同じIDだった数
+    pub same_as_previous: usize,
+    /// 直前か2つ前の行動と同じIDだった数
+    pub same_as_recent: usize,
+}
+
+impl RepeatRates {
+    /// 割合がこれ以上なら「高い」とみなす
+    pub const HIGH_RATE: f64 = 0.3;
+
+    /// 直前と同じ行動の割合（比べた行動がなければNone）
+    pub fn previous_rate(&self) -> Option<f64> {
+        self.rate(self.same_as_previous)
+    }
+
+    /// 直前か2つ前と同じ行動の割合（比べた行動がなければNone）
+    pub fn recent_rate(&self) -> Option<f64> {
+        self.rate(self.same_as_recent)
+    }
+
+    fn rate(&self, count: usize) -> Option<f64> {
+        if self.compared == 0 {
+            None
+        } else {
+            Some(count as f64 / self.compared as f64)
+        }
+    }
+}
+
+/// 攻撃・防御の次の行動が同じ側だった割合（攻め継続率・守り継続率）
+/// 
+/// 時刻順に隣り合う2つの行動を、攻撃と防御（シールド・回避）の2状態の遷移として数える。
+/// 未登録の行動IDが入った組は数えない
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Continuity {
+    /// 攻撃の次に行動があった数（攻撃からの遷移の数）
+    pub from_attack: usize,
+    /// 攻撃の次も攻撃だった数
+    pub attack_to_attack: usize,
+    /// 防御の次に行動があった数（防御からの遷移の数）
+    pub from_defense: usize,
+    /// 防御の次も防御だった数
+    pub defense_to_defense: usize,
+}
+
+impl Continuity {
+    /// 攻め継続率（攻撃→攻撃の確率、0〜1）。攻撃からの遷移がなければNone
+    pub fn attack_rate(&self) -> Option<f64> {
+        if self.from_attack == 0 {
+            None
+        } else {
+            Some(self.attack_to_attack as f64 / self.from_attack as f64)
+        }
+    }
+
+    /// 守り継続率（防御→防御の確率、0〜1）。防御からの遷移がなければNone
+    pub fn defense_rate(&self) -> Option<f64> {
+        if self.from_defense == 0 {
+            None
+        } else {
+            Some(self.defense_to_defense as f64 / self.from_defense as f64)
+        }
+    }
+}
+
+/// 時刻順に隣り合う2つの行動の遷移の集計（行動遷移の行列）
+/// 
+/// 「シールドの次に何をしたか」のように、ある行動の直後の癖を見るためのもの。
+/// 間隔が `gap` 秒を超える組は別のやりとりとみなして数えない
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transitions {
+    /// 遷移とみなす行動間隔の上限（秒）
+    pub gap: f64,
+    /// counts[前][次]: 行動タイプどうしの遷移の回数（添字は Transitions::TYPES の順）
+    pub counts: [[u32; 4]; 4],
+    /// 間隔が `gap` 秒を超えていたので数えなかった組の数
+    pub split: u32,
+    /// 行動IDどうしの遷移のうち回数の多いもの（前のID, 次のID, 回数）
+    /// 回数降順、同回数ならIDの昇順で、TOP_ID_TRANSITIONS 件まで
+    pub top_ids: Vec<(String, String, u32)>,
+}
+
+impl Transitions {
+    /// 行列の行・列の順番（未登録の行動IDは最後）
+    pub const TYPES: [ActionType; 4] = [ActionType::Attack, ActionType::Shield, ActionType::Dodge, ActionType::Unknown];
+
+    /// 多い順に残す行動IDどうしの遷移の件数
+    pub const TOP_ID_TRANSITIONS: usize = 5;
+
+    /// 遷移が1つもない状態で初期化
+    pub fn new(gap: f64) -> Self {
+        Transitions {
+            gap,
+            counts: [[0; 4]; 4],
+            split: 0,
+            top_ids: Vec::new(),
+        }
+    }
+
+    /// 行動タイプの、行列での添字
+    pub fn index(action_type: &ActionType) -> usize {
+        match action_type {
+            ActionType::Attack => 0,
+            ActionType::Shield => 1,
+            ActionType::Dodge => 2,
+            ActionType::Unknown => 3,
+        }
+    }
+
+    /// 数えた遷移の合計
+    pub fn total(&self) -> u32 {
+        self.counts.iter().flatten().sum()
+    }
+
+    /// 未登録の行動IDが入った遷移があるか（なければ表示は3×3にする）
+    pub fn has_unknown(&self) -> bool {
+        let unknown = Transitions::index(&ActionType::Unknown);
+        (0..4).any(|i| self.counts[unknown][i] > 0 || self.counts[i][unknown] > 0)
+    }
+}
+
+/// 行動の並びを、短い間隔で続いた「バースト」と、間の空いた「単発」の行動に分けた集計
+/// 
+/// 確定反撃（punish）は間が空いた後の短い間隔の連続として、立ち回り（neutral）は
+/// 間の空いた単発の行動として現れやすい、という見方による大まかな推定。
+/// 時刻順に隣り合う行動の間隔が `gap` 秒未満なら同じ連続とし、
+/// `min_len` 個以上続いた連続をバースト、それ以外の行動を単発とする
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bursts {
+    /// 同じ連続とみなす行動間隔の上限（秒。ちょうどこの間隔なら区切る）
+    pub gap: f64,
+    /// バーストとみなす最小の行動数
+    pub min_len: usize,
+    /// バースト（時刻順）
+    pub bursts: Vec<Burst>,
+    /// バーストに入った行動の、行動タイプごとの回数
+    pub inside: ActionCounts,
+    /// 単発の行動の、行動タイプごとの回数
+    pub outside: ActionCounts,
+}
+
+/// 1つのバースト（短い間隔で続いた行動のまとまり）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Burst {
+    /// 最初の行動の時刻（秒）
+    pub start: f64,
+    /// 最後の行動の時刻（秒）
+    pub end: f64,
+    /// 行動の数
+    pub actions: usize,
+}
+
+impl Bursts {
+    /// バーストが1つもない状態で初期化
+    pub fn new(gap: f64, min_len: usize) -> Self {
+        Bursts {
+            gap,
+            min_len,
+            bursts: Vec::new(),
+            inside: ActionCounts::new(),
+            outside: ActionCounts::new(),
+        }
+    }
+
+    /// 全行動に占める、バーストに入った行動の割合（%）
+    /// 
+    /// # 戻り値
+    /// 割合。行動が1つもなければNone
+    pub fn share(&self) -> Option<f64> {
+        let total = self.inside.total() + self.outside.total();
+        if total == 0 {
+            None
+        } else {
+            Some(self.inside.total() as f64 / total as f64 * 100.0)
+        }
+    }
+
+    /// バーストの平均の長さ（行動数）
+    /// 
+    /// # 戻り値
+    /// 平均の行動数。バーストが1つもなければNone
+    pub fn average_len(&self) -> Option<f64> {
+        if self.bursts.is_empty() {
+            None
+        } else {
+            Some(self.inside.total() as f64 / self.bursts.len() as f64)
+        }
+    }
+}
+
+/// 記録の質（逆行・欠損・記録の途切れ・未登録の行動ID）から計算したデータ信頼度（0〜100）
+/// 
+/// 100点から、問題の種類ごとに次のとおり減点する（種類ごとに上限あり、合計が100を超えたら0点）
+/// - 逆行（タイムスタンプが前の行より小さかった行）: 1行につき5点、最大30点
+/// - 欠損（読み込むときに除外した行）: 1行につき10点、最大30点
+/// - 記録の途切れ（行動のない時間が GAP_SECS 秒を超えた区間）: 1か所につき10点、最大30点
+/// - 未登録の行動ID: 全行動に占める割合1%につき1点（端数は切り上げ）、最大40点
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reliability {
+    /// 信頼度（0〜100）
+    pub score: u32,
+    /// 逆行した行の数
+    pub reordered: usize,
+    /// 読み込むときに除外した行の数
+    pub dropped: usize,
+    /// 記録の途切れの数
+    pub gaps: usize,
+    /// 未登録の行動IDの行動の数
+    pub unknown: u32,
+    /// 未登録の行動IDの行動の割合（%）
+    pub unknown_ratio: f64,
+}
+
+impl Reliability {
+    /// 逆行1行あたりの減点と、その上限
+    pub const REORDERED_PENALTY: (u32, u32) = (5, 30);
+    /// 欠損1行あたりの減点と、その上限
+    pub const DROPPED_PENALTY: (u32, u32) = (10, 30);
+    /// 記録の途切れ1か所あたりの減点と、その上限
+    pub const GAP_PENALTY: (u32, u32) = (10, 30);
+    /// 未登録の行動IDの割合1%あたりの減点と、その上限
+    pub const UNKNOWN_PENALTY: (u32, u32) = (1, 40);
+    /// 記録の途切れとみなす、行動のない時間の長さ（秒）。これより長く空いたら記録が欠けたとみなす
+    pub const GAP_SECS: f64 = 10.0;
+
+    /// 問題の件数から信頼度を計算する
+    /// 
+    /// # 引数
+    /// * `reordered` - 逆行した行の数
+    /// * `dropped` - 読み込むときに除外した行の数
+    /// * `gaps` - 記録の途切れの数
+    /// * `counts` - 行動回数（未登録の行動IDの割合に使う）
+    pub fn new(reordered: usize, dropped: usize, gaps: usize, counts: &ActionCounts) -> Self {
+        let penalty = |count: u32, (per, max): (u32, u32)| count.saturating_mul(per).min(max);
+        let as_u32 = |count: usize| u32::try_from(count).unwrap_or(u32::MAX);
+        let unknown_ratio = counts.unknown_ratio();
+        let deducted = penalty(as_u32(reordered), Reliability::REORDERED_PENALTY)
+            + penalty(as_u32(dropped), Reliability::DROPPED_PENALTY)
+            + penalty(as_u32(gaps), Reliability::GAP_PENALTY)
+            + penalty(unknown_ratio.ceil() as u32, Reliability::UNKNOWN_PENALTY);
+        Reliability {
+            score: 100u32.saturating_sub(deducted),
+            reordered,
+            dropped,
+            gaps,
+            unknown: counts.unknown_count,
+            unknown_ratio,
+        }
+    }
+
+    /// 見つかった問題の短い説明（例: 「逆行1件」）。問題がなければ空
+    pub fn issues(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        if self.reordered > 0 {
+            issues.push(format!("逆行{}件", self.reordered));
+        }
+        if self.dropped > 0 {
+            issues.push(format!("欠損{}行", self.dropped));
+        }
+        if self.gaps > 0 {
+            issues.push(format!("記録の途切れ{}か所", self.gaps));
+        }
+        if self.unknown > 0 {
+            issues.push(format!("未登録ID {}件（{:.1}%）", self.unknown, self.unknown_ratio));
+        }
+        issues
+    }
+}
+
+/// 攻撃から次のシールドまでの時間の分布
+/// 
+/// 攻撃を空振りしたあと、どれだけ早くシールドで守れているかの目安にする
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShieldCover {
+    /// 「すぐにシールドした」とみなす時間の上限（秒）
+    pub window: f64,
+    /// ヒストグラムの1区間の幅（秒）
+    pub bin_width: f64,
+    /// 攻撃の数（後にシールドがない攻撃も含む）
+    pub attacks: usize,
+    /// `window` 秒以内にシールドが続いた攻撃の数
+    pub within: usize,
+    /// 攻撃から次のシールドまでの時間の統計（後にシールドがある攻撃だけ）
+    pub latency: IntervalStats,
+    /// 攻撃から次のシールドまでの時間のヒストグラム（i番目は i×bin_width 秒以上 (i+1)×bin_width 秒未満の数）
+    pub histogram: Vec<u32>,
+    /// ヒストグラムの最後の区間に、それより長い時間もまとめたか
+    pub overflow: bool,
+}
+
+impl ShieldCover {
+    /// ヒストグラムの区間の最大数（これを超える長い時間は最後の区間にまとめる）
+    pub const MAX_BINS: usize = 40;
+
+    /// `window` 秒以内にシールドが続いた攻撃の割合（0〜1、全攻撃に対して）
+    pub fn within_rate(&self) -> f64 {
+        self.within as f64 / self.attacks as f64
+    }
+}
+
+/// 攻防の切り替え回数の時間推移（緊張度カーブ）
+/// 
+/// 試合を一定の長さの時間窓に分け、窓ごとに攻撃⇔防御の切り替えを数える。
+/// 切り替えが多い窓ほど激しい攻防だったとみなす
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TensionCurve {
+    /// 時間窓の長さ（秒）
+    pub window_secs: f64,
+    /// 最初の窓の開始時刻（最初の行動の時刻）
+    pub start: f64,
+    /// 窓ごとの切り替え回数（時刻順）
+    pub switches: Vec<u32>,
+}
+
+impl TensionCurve {
+    /// 窓の最大数（これを超える場合は窓を広げる）
+    pub const MAX_WINDOWS: usize = 60;
+}
+
+/// 使った技の種類（ユニークな行動ID数）の時間推移
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RepertoireGrowth {
+    /// 各行動IDを初めて使った時刻と行動ID（時刻順）
+    /// i番目の要素の時刻で、累積の種類数が i+1 になる
+    pub first_uses: Vec<(f64, String)>,
+    /// 最初の行動の時刻
+    pub start: f64,
+    /// 最後の行動の時刻
+    pub end: f64,
+}
+
+impl RepertoireGrowth {
+    /// 行動がない状態で初期化
+    pub fn new() -> Self {
+        RepertoireGrowth::default()
+    }
+
+    /// 指定した時刻までに使った技の種類数
+    pub fn count_at(&self, time: f64) -> usize {
+        self.first_uses.iter().filter(|(t, _)| *t <= time).count()
+    }
+}
+
+/// 試合時間と1分あたりの行動数（APM）
+///
+/// 試合時間は最初の行動から最後の行動までの時間。
+/// 試合時間が0秒（行動が1件だけ、すべて同じ時刻）のときはAPMを計算できないのでNone
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TimeStats {
+    /// 最初の行動の時刻（秒）
+    pub first: f64,
+    /// 最後の行動の時刻（秒）
+    pub last: f64,
+    /// 試合時間（秒）
+    pub duration: f64,
+    /// 全行動のAPM
+    pub apm: Option<f64>,
+    /// 攻撃のAPM
+    pub attack_apm: Option<f64>,
+    /// シールドのAPM
+    pub shield_apm: Option<f64>,
+    /// 回避のAPM
+    pub dodge_apm: Option<f64>,
+    /// 未登録の行動IDのAPM
+    pub unknown_apm: Option<f64>,
+}
+
+/// 時間重み付きのカテゴリ比率（%）
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WeightedRatios {
+    /// 使用した重みの形
+    pub shape: WeightShape,
+    /// 攻撃の比率
+    pub attack_ratio: f64,
+    /// シールドの比率
+    pub shield_ratio: f64,
+    /// 回避の比率
+    pub dodge_ratio: f64,
+    /// 未登録の行動IDの比率
+    pub unknown_ratio: f64,
+}
+
+impl WeightedRatios {
+    /// すべての比率を0で初期化
+    pub fn new(shape: WeightShape) -> Self {
+        WeightedRatios {
+            shape,
+            ..WeightedRatios::default()
+        }
+    }
+}
+
+/// 行動間隔の基本統計（秒）
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalStats {
+    /// 平均
+    pub mean: f64,
+    /// 中央値
+    pub median: f64,
+    /// 最小値
+    pub min: f64,
+    /// 最大値
+    pub max: f64,
+    /// 標準偏差
+    pub std_dev: f64,
+}
+
+/// 行動間隔のヒストグラム（--interval-bin）
+/// 
+/// 間隔を `bin_width` 秒ごとのビンに分けて数える。ビンは最大 MAX_BINS 個で、
+/// 入りきらない長い間隔（外れ値）は最後のビンにまとめる
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntervalHistogram {
+    /// ビンの幅（秒）
+    pub bin_width: f64,
+    /// ビンごとの件数（i番目のビンは i×bin_width 秒以上 (i+1)×bin_width 秒未満）
+    pub counts: Vec<u32>,
+    /// 最後のビンに、それより長い間隔もまとめたか
+    pub overflow: bool,
+}
+
+impl IntervalHistogram {
+    /// ビンの最大数（これを超える長い間隔は最後のビンにまとめる）
+    pub const MAX_BINS: usize = 12;
+}
+
+/// 行動間隔の分布の形状（歪度・尖度）
+/// サンプルが少なく計算できない場合はNone
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IntervalShape {
+    /// 歪度（正なら右裾が長い）
+    pub skewness: Option<f64>,
+    /// 尖度（正規分布を0とした超過尖度）
+    pub kurtosis: Option<f64>,
+}
+
+impl IntervalShape {
+    /// どちらも計算できていない状態で初期化
+    pub fn new() -> Self {
+        IntervalShape::default()
+    }
+}
+/// 複数のCSVをまとめて解析したときの合算結果
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregateResult {
+    /// 合算したログ（ファイル）の数
+    pub files: usize,
+    /// 含まれていた学籍番号（初めて出てきた順、重複なし）
+    pub student_ids: Vec<String>,
+    /// 全ログの行動回数の合計
+    pub counts: AggregateCounts,
+    /// 全ログの行動IDごとの回数の合計（回数降順、同回数ならID昇順）
+    pub action_id_counts: Vec<(String, u64)>,
+    /// 試合ごとの行動回数（入力した順）
+    pub matches: Vec<MatchCounts>,
+    /// 同じ試合のログが複数あったときの扱い
+    pub dup_policy: DupPolicy,
+    /// 同じ試合のログが複数あった試合（最初に出てきた順）
+    pub duplicates: Vec<DuplicateMatch>,
+    /// Ctrl-Cで途中で中断したとき、そこまでに処理したファイルの数（中断していなければNone）
+    pub interrupted: Option<Interruption>,
+}
+
+/// 複数のファイルの処理を、Ctrl-Cで途中で中断したときの記録
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interruption {
+    /// 中断するまでに処理したファイルの数（読み込めなかったファイルも含む）
+    pub processed: usize,
+    /// 指定されたファイルの数
+    pub total: usize,
+}
+
+/// 同じ試合（学籍番号と対戦回次が同じ）のログが複数あった試合
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateMatch {
+    /// 学籍番号
+    pub student_id: String,
+    /// 対戦回次
+    pub match_number: u32,
+    /// この試合のログのファイル（指定した順）
+    pub files: Vec<String>,
+    /// 合算に使ったファイル（DupPolicy::KeepAll ならすべて）
+    pub kept: Vec<String>,
+}
+
+/// 合算結果の中の1試合分の行動回数
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchCounts {
+    /// プレイヤー情報
+    pub player_info: PlayerInfo,
+    /// ログのファイルの更新日時（取れなければNone）
+    pub modified: Option<SystemTime>,
+    /// 行動回数
+    pub counts: ActionCounts,
+}
+
+/// 学籍番号ごとの合計（--jobs のランキングの1行）
+#[derive(Debug, Clone, PartialEq)]
+pub struct StudentRank {
+    /// 学籍番号
+    pub student_id: String,
+    /// 合算した試合の数
+    pub matches: usize,
+    /// 行動回数の合計
+    pub counts: AggregateCounts,
+}
+
+/// グループ（チーム）ごとの平均指標
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupSummary {
+    /// グループ名
+    pub name: String,
+    /// 集計したログ（ファイル）の数
+    pub logs: usize,
+    /// 1ログあたりの平均行動数
+    pub mean_actions: f64,
+    /// 攻撃比率の平均（%）
+    pub attack_ratio: f64,
+    /// シールド比率の平均（%）
+    pub shield_ratio: f64,
+    /// 回避比率の平均（%）
+    pub dodge_ratio: f64,
+    /// 平均行動間隔の平均（秒）。間隔があるログがなければNone
+    pub mean_interval: Option<f64>,
+}
+
+/// 行動IDの使い方が似た試合のまとまり（--cluster）
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayStyleCluster {
+    /// このクラスタに入った試合（入力した順）
+    pub members: Vec<PlayerInfo>,
+    /// クラスタの中心（行動IDごとの使用割合の平均、0〜1）。割合の大きい順、0の行動IDは含めない
+    pub centroid: Vec<(String, f64)>,
+    /// メンバーの行動回数の合計
+    pub counts: ActionCounts,
+    /// 中心が最も近いほかのクラスタ（clusters の中の位置）と、中心どうしの距離。ほかにクラスタがなければNone
+    pub nearest: Option<(usize, f64)>,
+}
+
+/// 指標の推移の向き
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    /// 回次が進むにつれて増えている
+    Rising,
+    /// 回次が進むにつれて減っている
+    Falling,
+    /// ほとんど変わっていない
+    Flat,
+}
+
+impl Trend {
+    /// 全回次を通した変化量（傾き×回次の幅）が、平均値のこの割合未満なら Flat
+    pub const FLAT_FRACTION: f64 = 0.05;
+
+    /// 表示用の名前
+    pub fn label(&self) -> &'static str {
+        match self {
+            Trend::Rising => "上昇",
+            Trend::Falling => "低下",
+            Trend::Flat => "横ばい",
+        }
+    }
+
+    /// JSON用の名前
+    pub fn key(&self) -> &'static str {
+        match self {
+            Trend::Rising => "rising",
+            Trend::Falling => "falling",
+            Trend::Flat => "flat",
+        }
+    }
+}
+
+/// 1つの指標の、試合をまたいだ推移
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricTrend {
+    /// 表示用の指標名
+    pub label: &'static str,
+    /// JSON用の指標名
+    pub key: &'static str,
+    /// 値の単位（表示用）
+    pub unit: &'static str,
+    /// 最初の回次の値
+    pub first: f64,
+    /// 最後の回次の値
+    pub last: f64,
+    /// 横軸に対する値の傾き（1回次または1日あたりの変化量、最小二乗法）。横軸の値が1種類しかなければNone
+    pub slope: Option<f64>,
+    /// 推移の向き（slopeがNoneならNone）
+    pub trend: Option<Trend>,
+}
+
+/// 1人の学生の複数の試合をまとめた改善レポート
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImprovementReport {
+    /// 学籍番号
+    pub student_id: String,
+    /// 集計した試合の回次（並べた順。欠番があってもよい）
+    pub match_numbers: Vec<u32>,
+    /// 試合の並べ方（推移の横軸）
+    pub order: MatchOrder,
+    /// 並べた順の各試合のファイルの更新日時（取れなければNone）
+    pub modified: Vec<Option<SystemTime>>,
+    /// 指標ごとの推移（シールド比率・技の種類・APM・回避比率の順）
+    pub metrics: Vec<MetricTrend>,
+}
+
+/// 2つのログでの、1つの行動IDの使用頻度の比較（2標本の比率の差のz検定）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionComparison {
+    /// 行動ID
+    pub action_id: String,
+    /// 1つ目のログでの回数
+    pub count_a: u32,
+    /// 2つ目のログでの回数
+    pub count_b: u32,
+    /// 1つ目のログでの割合（全行動に対する）
+    pub ratio_a: f64,
+    /// 2つ目のログでの割合（全行動に対する）
+    pub ratio_b: f64,
+    /// z値（2つ目 - 1つ目の向き）。どちらのログでも同じ割合（0か100%）で計算できなければNone
+    pub z: Option<f64>,
+    /// 両側p値の目安（zがNoneならNone）
+    pub p_value: Option<f64>,
+    /// 期待度数が小さく、正規近似があてにならないか
+    pub small_sample: bool,
+}
+
+impl ActionComparison {
+    /// p値がこれ未満なら差があるとみなす
+    pub const SIGNIFICANCE: f64 = 0.05;
+    /// 期待度数（行動数×合算した割合）がこれ未満なら小さいサンプルとみなす
+    pub const MIN_EXPECTED: f64 = 5.0;
+}
+
+/// 2つのログでの、1つの行動IDの1分あたりの回数の比較（--normalize per-minute）
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateComparison {
+    /// 行動ID
+    pub action_id: String,
+    /// 1つ目のログでの回数
+    pub count_a: u32,
+    /// 2つ目のログでの回数
+    pub count_b: u32,
+    /// 1つ目のログでの1分あたりの回数
+    pub per_minute_a: f64,
+    /// 2つ目のログでの1分あたりの回数
+    pub per_minute_b: f64,
+}
+
+impl RateComparison {
+    /// 1分あたりの回数の差（2つ目 - 1つ目）
+    pub fn difference(&self) -> f64 {
+        self.per_minute_b - self.per_minute_a
+    }
+}
+
+/// 2つのログでの、1つの行動タイプ（攻撃・シールド・回避）の割合の比較（2標本の比率の差のz検定）
+#[derive(Debug, Clone, PartialEq)]
+pub struct CategoryComparison {
+    /// 行動タイプ
+    pub action_type: ActionType,
+    /// 1つ目のログでの回数
+    pub count_a: u32,
+    /// 2つ目のログでの回数
+    pub count_b: u32,
+    /// 1つ目のログでの割合（全行動に対する）
+    pub ratio_a: f64,
+    /// 2つ目のログでの割合（全行動に対する）
+    pub ratio_b: f64,
+    /// z値（2つ目 - 1つ目の向き）。計算できないときや suppressed のときはNone
+    pub z: Option<f64>,
+    /// 両側p値の目安（zがNoneならNone）
+    pub p_value: Option<f64>,
+    /// どちらかのログの行動数が MIN_ACTIONS 未満で、有意差の目安を出さないか
+    pub suppressed: bool,
+}
+
+impl CategoryComparison {
+    /// どちらかのログの行動数がこれ未満なら、有意差の目安を出さない
+    pub const MIN_ACTIONS: usize = 30;
+
+    /// 有意差ありか（p値が ActionComparison::SIGNIFICANCE 未満）。p値がなければNone
+    pub fn significant(&self) -> Option<bool> {
+        self.p_value.map(|p| p < ActionComparison::SIGNIFICANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// (時刻, 行動ID) の並びからログを作る
+    fn log(actions: &[(f64, &str)]) -> BattleLog {
+        let actions = actions.iter().map(|&(timestamp, id)| Action::new(timestamp, id.to_string())).collect();
+        BattleLog::new(PlayerInfo::new("b1022024".to_string(), 1), actions)
+    }
+
+    /// 取り出した行動の時刻だけを並べる
+    fn times(actions: &[&Action]) -> Vec<f64> {
+        actions.iter().map(|action| action.timestamp).collect()
+    }
+
+    #[test]
+    fn context_splits_around_the_timestamp() {
+        // ファイルの順は時刻順でなくてもよい
+        let battle_log = log(&[(3.0, "us"), (1.0, "us"), (2.0, "ss"), (5.0, "ds"), (4.0, "s")]);
+        let (before, after) = battle_log.context(3.5, 2);
+        assert_eq!((times(&before), times(&after)), (vec![2.0, 3.0], vec![4.0, 5.0]));
+    }
+
+    #[test]
+    fn context_puts_the_exact_time_after() {
+        let battle_log = log(&[(1.0, "us"), (2.0, "ss"), (3.0, "ds")]);
+        let (before, after) = battle_log.context(2.0, 1);
+        assert_eq!((times(&before), times(&after)), (vec![1.0], vec![2.0]));
+    }
+
+    #[test]
+    fn context_is_shorter_near_the_ends() {
+        let battle_log = log(&[(1.0, "us"), (2.0, "ss"), (3.0, "ds")]);
+        let (before, after) = battle_log.context(0.0, 2);
+        assert_eq!((times(&before), times(&after)), (vec![], vec![1.0, 2.0]));
+        let (before, after) = battle_log.context(10.0, 5);
+        assert_eq!((times(&before), times(&after)), (vec![1.0, 2.0, 3.0], vec![]));
+        let (before, after) = battle_log.context(2.5, 0);
+        assert!(before.is_empty() && after.is_empty());
+    }
+
+    #[test]
+    fn known_ids_keep_their_category() {
+        for (table, action_type) in [(ATTACK_IDS, ActionType::Attack), (SHIELD_IDS, ActionType::Shield), (DODGE_IDS, ActionType::Dodge)] {
+            for (id, _) in table {
+                assert_eq!(ActionType::from_action_id(id), action_type, "{}", id);
+                assert!(ActionType::is_known_id(id), "{}", id);
+            }
+        }
+    }
+
+    #[test]
+    fn garbage_ids_are_unknown() {
+        // 打ち間違い・大文字小文字の違い・空白付き・空文字列は攻撃として数えない
+        for id in ["ssss", "jump", "SS", "S", " s", "s ", "", "phase:", "攻撃"] {
+            assert_eq!(ActionType::from_action_id(id), ActionType::Unknown, "{:?}", id);
+            assert!(!ActionType::is_known_id(id), "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn every_attack_id_has_a_category() {
+        for (id, name) in ATTACK_IDS {
+            assert_ne!(attack_category(id), Some(AttackCategory::OtherAttack), "{} ({})", id, name);
+            assert!(attack_category(id).is_some(), "{} ({})", id, name);
+        }
+        // OtherAttack 以外のすべての種類に、少なくとも1つのIDがある
+        for category in AttackCategory::ALL.iter().filter(|&&category| category != AttackCategory::OtherAttack) {
+            assert!(ATTACK_IDS.iter().any(|(id, _)| attack_category(id) == Some(*category)), "{:?}", category);
+        }
+    }
+
+    #[test]
+    fn attack_category_samples() {
+        assert_eq!(attack_category("j1"), Some(AttackCategory::Jab));
+        assert_eq!(attack_category("dt"), Some(AttackCategory::Tilt));
+        assert_eq!(attack_category("DA"), Some(AttackCategory::DashAttack));
+        assert_eq!(attack_category("us"), Some(AttackCategory::Smash));
+        // "da"（空下）と "DA"（ダッシュアタック）は大文字小文字で区別する
+        assert_eq!(attack_category("da"), Some(AttackCategory::Aerial));
+        assert_eq!(attack_category("ub_a"), Some(AttackCategory::Special));
+        assert_eq!(attack_category("fth"), Some(AttackCategory::Grab));
+    }
+
+    #[test]
+    fn non_attack_ids_have_no_category() {
+        for id in SHIELD_IDS.iter().chain(DODGE_IDS).map(|(id, _)| *id).chain(["ssss", ""]) {
+            assert_eq!(attack_category(id), None, "{:?}", id);
+        }
+    }
+
+    #[test]
+    fn catalog_has_no_problems() {
+        let (checked, problems) = check_catalog();
+        assert_eq!(checked, ATTACK_IDS.len() + SHIELD_IDS.len() + DODGE_IDS.len());
+        assert!(problems.is_empty(), "{:?}", problems);
+    }
+
+    #[test]
+    fn unknown_ids_are_counted_separately() {
+        let mut counts = ActionCounts::new();
+        for id in ["us", "s", "sd", "ssss", "jump", "ssss"] {
+            counts.increment(&ActionType::from_action_id(id));
+        }
+        assert_eq!(counts, ActionCounts { attack_count: 1, shield_count: 1, dodge_count: 1, unknown_count: 3 });
+        assert_eq!((counts.known_total(), counts.total()), (3, 6));
+    }
+
+    #[test]
+    fn debounce_keeps_a_gap_of_exactly_the_threshold() {
+        // 0.30 - 0.25 は小数では 0.05 より少しだけ小さくなるが、ちょうど50ミリ秒として残す
+        let mut battle_log = log(&[(0.25, "us"), (0.30, "us")]);
+        battle_log.debounce(50.0);
+        assert_eq!(battle_log.actions.len(), 2);
+        assert_eq!(battle_log.debounce.as_ref().unwrap().total(), 0);
+        assert!(battle_log.warnings.is_empty());
+    }
+
+    #[test]
+    fn debounce_drops_a_gap_just_under_the_threshold() {
+        let mut battle_log = log(&[(0.25, "us"), (0.299, "us"), (1.0, "s"), (1.01, "s"), (1.02, "s")]);
+        battle_log.debounce(50.0);
+        assert_eq!(times(&battle_log.actions.iter().collect::<Vec<_>>()), vec![0.25, 1.0]);
+        let report = battle_log.debounce.unwrap();
+        assert_eq!(report.dropped, vec![("s".to_string(), 2), ("us".to_string(), 1)]);
+        assert_eq!(battle_log.warnings.len(), 1);
+    }
+
+    #[test]
+    fn debounce_keeps_interleaved_ids() {
+        let mut battle_log = log(&[(0.0, "us"), (0.01, "ss"), (0.02, "us")]);
+        battle_log.debounce(50.0);
+        assert_eq!(battle_log.actions.len(), 3);
+    }
+
+    #[test]
+    fn action_equality_compares_timestamp_bits() {
+        let action = |timestamp: f64| Action::new(timestamp, "us".to_string());
+        assert_eq!(action(f64::NAN), action(f64::NAN));
+        assert_ne!(action(0.0), action(-0.0));
+        assert_eq!(action(1.5), action(1.5));
+        assert_ne!(action(1.5), Action::new(1.5, "ss".to_string()));
+
+        // 行番号と元の行は比べない
+        let mut with_line = action(1.5);
+        with_line.line = 12;
+        with_line.raw = Some("1.5,us".into());
+        assert_eq!(with_line, action(1.5));
+    }
+
+    #[test]
+    fn action_counts_add_up() {
+        let a = counts(1, 2, 3, 4);
+        let b = counts(10, 20, 30, 40);
+        assert_eq!(a + b, counts(11, 22, 33, 44));
+        let mut sum = ActionCounts::default();
+        sum += a;
+        sum += b;
+        assert_eq!(sum, a + b);
+        assert_eq!(ActionCounts::new(), ActionCounts::default());
+    }
+
+    #[test]
+    fn aggregate_counts_widen_match_counts() {
+        let aggregate = AggregateCounts::from(&counts(u32::MAX, 1, 2, 3)) + &counts(u32::MAX, 0, 0, 0);
+        assert_eq!(aggregate.attack_count, 2 * u32::MAX as u64);
+        assert_eq!(aggregate.total(), 2 * u32::MAX as u64 + 6);
+        assert_eq!(AggregateCounts::new().attack_ratio(), 0.0);
+    }
+
+    #[test]
+    fn aggregate_counts_checked_add_detects_u64_overflow() {
+        let full = AggregateCounts { attack_count: u64::MAX, ..AggregateCounts::default() };
+        assert_eq!(full.checked_add(&counts(1, 0, 0, 0)), None);
+        assert_eq!(full.checked_add(&counts(0, 1, 0, 0)).map(|sum| sum.shield_count), Some(1));
+
+        // += は折り返さずに上限で止まる
+        let mut saturated = full;
+        saturated += &counts(5, 0, 0, 0);
+        assert_eq!(saturated.attack_count, u64::MAX);
+        saturated += &counts(0, 1, 0, 0);
+        assert_eq!(saturated.total(), u64::MAX);
+    }
+
+    fn counts(attack_count: u32, shield_count: u32, dodge_count: u32, unknown_count: u32) -> ActionCounts {
+        ActionCounts { attack_count, shield_count, dodge_count, unknown_count }
+    }
+
+    /// 順位の行動タイプだけを並べる
+    fn order(counts: &ActionCounts) -> Vec<ActionType> {
+        counts.ranking().into_iter().map(|(action_type, _)| action_type).collect()
+    }
+
+    #[test]
+    fn ranking_sorts_by_count() {
+        assert_eq!(order(&counts(1, 5, 3, 0)), vec![ActionType::Shield, ActionType::Dodge, ActionType::Attack]);
+        let ratios: Vec<f64> = counts(1, 5, 4, 0).ranking().into_iter().map(|(_, ratio)| ratio).collect();
+        assert_eq!(ratios, vec![50.0, 40.0, 10.0]);
+    }
+
+    #[test]
+    fn ranking_breaks_ties_as_attack_shield_dodge() {
+        assert_eq!(order(&counts(2, 2, 2, 0)), vec![ActionType::Attack, ActionType::Shield, ActionType::Dodge]);
+        assert_eq!(order(&counts(1, 3, 3, 0)), vec![ActionType::Shield, ActionType::Dodge, ActionType::Attack]);
+        assert_eq!(order(&counts(3, 1, 3, 0)), vec![ActionType::Attack, ActionType::Dodge, ActionType::Shield]);
+        assert_eq!(counts(0, 0, 0, 0).most_frequent_action(), ActionType::Attack);
+        assert_eq!(counts(0, 4, 4, 0).most_frequent_action(), ActionType::Shield);
+    }
+
+    #[test]
+    fn most_frequent_action_ignores_unknown_ids_when_any_known_action_exists() {
+        assert_eq!(counts(0, 0, 1, 100).most_frequent_action(), ActionType::Dodge);
+        assert_eq!(counts(0, 0, 0, 1).most_frequent_action(), ActionType::Unknown);
+    }
+    /// 学籍番号・対戦回次・行動回数・試合時間を指定した解析結果
+    fn result(student_id: &str, match_number: u32, counts: ActionCounts, duration: f64) -> AnalysisResult {
+        let battle_log = BattleLog::new(PlayerInfo::new(student_id.to_string(), match_number), Vec::new());
+        let mut result = AnalysisResult::new(&battle_log, counts, Vec::new());
+        result.time_stats.duration = duration;
+        result
+    }
+
+    /// 指定したキーで並べ、(学籍番号, 対戦回次) の並びにする
+    fn sorted(results: &[AnalysisResult], key: SortKey, descending: bool) -> Vec<(String, u32)> {
+        let mut sorted: Vec<&AnalysisResult> = results.iter().collect();
+        sorted.sort_by(|a, b| a.cmp_by(b, key, descending));
+        sorted.iter().map(|r| (r.player_info.student_id.clone(), r.player_info.match_number)).collect()
+    }
+
+    fn pairs(items: &[(&str, u32)]) -> Vec<(String, u32)> {
+        items.iter().map(|&(id, number)| (id.to_string(), number)).collect()
+    }
+
+    #[test]
+    fn cmp_by_sorts_by_each_key() {
+        let results = [
+            result("b2", 1, counts(6, 2, 2, 0), 30.0),
+            result("b1", 2, counts(1, 1, 2, 0), 60.0),
+            result("b3", 3, counts(3, 6, 0, 0), 10.0),
+        ];
+        let cases = [
+            (SortKey::StudentId, [("b1", 2), ("b2", 1), ("b3", 3)]),
+            (SortKey::MatchNumber, [("b2", 1), ("b1", 2), ("b3", 3)]),
+            (SortKey::Total, [("b1", 2), ("b3", 3), ("b2", 1)]),
+            (SortKey::AttackRatio, [("b1", 2), ("b3", 3), ("b2", 1)]),
+            (SortKey::ShieldRatio, [("b2", 1), ("b1", 2), ("b3", 3)]),
+            (SortKey::DodgeRatio, [("b3", 3), ("b2", 1), ("b1", 2)]),
+            (SortKey::Duration, [("b3", 3), ("b2", 1), ("b1", 2)]),
+        ];
+        for (key, expected) in cases {
+            assert_eq!(sorted(&results, key, false), pairs(&expected), "{:?}", key);
+            let mut reversed = pairs(&expected);
+            reversed.reverse();
+            assert_eq!(sorted(&results, key, true), reversed, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn cmp_by_breaks_ties_by_student_and_match() {
+        let results = [
+            result("b2", 2, counts(1, 0, 0, 0), 0.0),
+            result("b1", 3, counts(1, 0, 0, 0), 0.0),
+            result("b2", 1, counts(1, 0, 0, 0), 0.0),
+        ];
+        let expected = pairs(&[("b1", 3), ("b2", 1), ("b2", 2)]);
+        // キーが全部同じなら、降順でも学籍番号 → 対戦回次の昇順になる
+        for key in [SortKey::Total, SortKey::AttackRatio, SortKey::Duration] {
+            assert_eq!(sorted(&results, key, false), expected, "{:?}", key);
+            assert_eq!(sorted(&results, key, true), expected, "{:?}", key);
+        }
+        assert_eq!(sorted(&results, SortKey::StudentId, true), pairs(&[("b2", 1), ("b2", 2), ("b1", 3)]));
+    }
+
+    #[test]
+    fn cmp_by_puts_nan_last_in_both_directions() {
+        let results = [
+            result("b1", 1, counts(1, 0, 0, 0), f64::NAN),
+            result("b2", 1, counts(1, 0, 0, 0), 20.0),
+            result("b3", 1, counts(1, 0, 0, 0), 0.0),
+            result("b4", 1, counts(1, 0, 0, 0), f64::NAN),
+        ];
+        assert_eq!(sorted(&results, SortKey::Duration, false), pairs(&[("b3", 1), ("b2", 1), ("b1", 1), ("b4", 1)]));
+        assert_eq!(sorted(&results, SortKey::Duration, true), pairs(&[("b2", 1), ("b3", 1), ("b1", 1), ("b4", 1)]));
+    }
+
+    #[test]
+    fn cmp_by_treats_empty_matches_as_zero_ratio() {
+        // 行動がない試合は比率0・試合時間0として並ぶ
+        let results = [result("b1", 1, counts(0, 1, 0, 0), 5.0), result("b2", 1, ActionCounts::new(), 0.0)];
+        assert_eq!(sorted(&results, SortKey::ShieldRatio, false), pairs(&[("b2", 1), ("b1", 1)]));
+        assert_eq!(sorted(&results, SortKey::Duration, false), pairs(&[("b2", 1), ("b1", 1)]));
+        assert_eq!(cmp_nan_last(-0.0, 0.0, false), Ordering::Equal);
+    }
+
+    /// (パス, 解析結果) の組を指定したキーで並べ、パスの並びにする
+    fn sorted_paths(entries: &[(&str, AnalysisResult)], key: SortKey, descending: bool) -> Vec<String> {
+        let mut sorted: Vec<&(&str, AnalysisResult)> = entries.iter().collect();
+        sorted.sort_by(|a, b| cmp_entries_by((a.0, &a.1), (b.0, &b.1), key, descending));
+        sorted.iter().map(|(path, _)| path.to_string()).collect()
+    }
+
+    #[test]
+    fn cmp_entries_by_sorts_by_file_name() {
+        let entries = [
+            ("logs/b.csv", result("b1", 1, counts(1, 0, 0, 0), 0.0)),
+            ("logs/c.csv", result("b3", 2, counts(3, 0, 0, 0), 0.0)),
+            ("logs/a.csv", result("b2", 1, counts(2, 0, 0, 0), 0.0)),
+        ];
+        assert_eq!(sorted_paths(&entries, SortKey::FileName, false), ["logs/a.csv", "logs/b.csv", "logs/c.csv"]);
+        assert_eq!(sorted_paths(&entries, SortKey::FileName, true), ["logs/c.csv", "logs/b.csv", "logs/a.csv"]);
+        // ほかのキーは cmp_by と同じ
+        assert_eq!(sorted_paths(&entries, SortKey::Total, true), ["logs/c.csv", "logs/a.csv", "logs/b.csv"]);
+        // cmp_by だけでは FileName を比べられないので、学籍番号 → 対戦回次の順になる
+        assert_eq!(sorted(&entries.map(|(_, r)| r), SortKey::FileName, true), pairs(&[("b1", 1), ("b2", 1), ("b3", 2)]));
+    }
+
+    #[test]
+    fn cmp_entries_by_orders_logs_of_the_same_match_by_path() {
+        let entries = [
+            ("z.csv", result("b1", 1, counts(1, 0, 0, 0), f64::NAN)),
+            ("y.csv", result("b1", 1, counts(1, 0, 0, 0), f64::NAN)),
+        ];
+        for key in [SortKey::Total, SortKey::Duration, SortKey::StudentId] {
+            // 降順でも、同じ試合どうしはパスの昇順
+            assert_eq!(sorted_paths(&entries, key, true), ["y.csv", "z.csv"], "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn momentum_needs_more_than_a_quarter_change() {
+        // 前半は 4/8 = 50%。後半が 62.5%（ちょうど25%増）まではほとんど変わらないとみなす
+        assert_eq!(Momentum::classify(4, 8, 5, 8), Momentum::Steady);
+        assert_eq!(Momentum::classify(4, 8, 6, 8), Momentum::Rising);
+        assert_eq!(Momentum::classify(4, 8, 3, 8), Momentum::Steady);
+        assert_eq!(Momentum::classify(4, 8, 2, 8), Momentum::Falling);
+        // 行動数が違っても、回数ではなく使用率で比べる
+        assert_eq!(Momentum::classify(4, 8, 8, 16), Momentum::Steady);
+        // 前半に使っていなければ、後半に1回でも使うと Rising
+        assert_eq!(Momentum::classify(0, 8, 1, 8), Momentum::Rising);
+        assert_eq!(Momentum::classify(0, 0, 0, 0), Momentum::Steady);
+    }
+}
@@ -3,12 +3,118 @@
 //
 // 解析結果を見やすく表示する
 
-use crate::model::{ActionType, AnalysisResult};
+use std::io::Write;
+
+use crate::datetime;
+use crate::error::Result;
+use crate::json;
+use crate::model::{
+    ActionComparison, ActionCounts, ActionType, AttackCategory, CategoryComparison, AggregateResult, AnalysisResult, DupPolicy, GroupSummary, ImprovementReport, LogDiff, MatchOrder, Normalize, PlayStyleCluster, RateComparison, RepeatRates, SkippedLine, StudentRank, Transitions, SPARKLINE_BUCKETS,
+};
+use crate::schema;
+use crate::sparkline;
+use crate::style::{display_width, graphemes, momentum_glyph, truncate, Style};
+
+/// 1行を表示する（println! の代わり）。--width があれば Style::emit がその幅に収める
+macro_rules! say {
+    ($style:expr, $($arg:tt)*) => {
+        $style.emit(&format!($($arg)*))
+    };
+}
+
+/// 出力の詳細度
+/// 
+/// 大小比較できるように、情報量の少ない順に並べている
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// サマリ1行のみ（-q）
+    Quiet,
+    /// 通常の表示
+    Normal,
+    /// 間隔の統計を追加（-v）
+    Verbose,
+    /// 行動IDごとの詳細まで全部表示（-vv）
+    Full,
+}
+
+/// 表示の設定
+#[derive(Debug, Clone)]
+pub struct DisplayOptions {
+    /// 出力の詳細度
+    pub verbosity: Verbosity,
+    /// 色付けの設定
+    pub style: Style,
+    /// スパークラインをASCII文字だけで描くか
+    pub ascii: bool,
+    /// 行動回数のカテゴリを回数の多い順に並べるか（--sort-categories）
+    pub sort_categories: bool,
+    /// 記号や罫線を使わず文章で出力するか（--narrate）
+    pub narrate: bool,
+}
+
+/// 1試合分のレポートに必要なものをまとめたデータ
+///
+/// main.rs で1回だけ組み立て、テキスト表示（display_report）にもJSON（report_to_json）にも
+/// 同じものを渡す。どちらの出力も同じ情報から作られるので、内容が食い違わない
+#[derive(Debug, Clone)]
+pub struct ReportData<'a> {
+    /// 解析結果
+    pub result: &'a AnalysisResult,
+    /// 読み込み時の警告（テキスト表示では読み込みの途中経過として表示済み）
+    pub warnings: &'a [String],
+    /// 前回の同じファイルの行動回数（--compare-last で前回の記録があるときだけ）
+    pub previous: Option<ActionCounts>,
+}
+
+/// レポートをテキストで表示する
+///
+/// 解析結果のあとに、前回の記録があれば【前回との比較】を続ける
+///
+/// # 引数
+/// * `report` - レポートのデータ
+/// * `display` - 表示の設定
+pub fn display_report(report: &ReportData, display: &DisplayOptions) {
+    display_result(report.result, display);
+    if let Some(previous) = &report.previous {
+        say!(display.style, "");
+        display_last_comparison(previous, &report.result.counts, &display.style);
+    }
+}
+
+/// レポートを1行のJSONにする（--json / --ipc の出力）
+///
+/// to_json の内容に、警告があれば warnings、前回の記録があれば previous_counts を足す。
+/// どちらもないときはキーごと出さない
+///
+/// # 引数
+/// * `report` - レポートのデータ
+///
+/// # 戻り値
+/// JSON文字列（改行なし）
+pub fn report_to_json(report: &ReportData) -> String {
+    let mut extra = String::new();
+    if !report.warnings.is_empty() {
+        let warnings: Vec<String> = report.warnings.iter().map(|w| json::string(w)).collect();
+        extra.push_str(&format!(",\"warnings\":[{}]", warnings.join(",")));
+    }
+    if let Some(previous) = &report.previous {
+        extra.push_str(&format!(
+            ",\"previous_counts\":{{\"attack\":{},\"shield\":{},\"dodge\":{},\"unknown\":{},\"total\":{}}}",
+            previous.attack_count,
+            previous.shield_count,
+            previous.dodge_count,
+            previous.unknown_count,
+            previous.total()
+        ));
+    }
+    format!("{{{}{}}}", result_fields(report.result), extra)
+}
 
 /// 解析結果を標準出力に表示する
 /// 
 /// # 引数
 /// * `result` - 解析結果
+/// * `display` - 表示の設定
 /// 
 /// # 表示内容
 /// - 学籍番号
@@ -16,80 +122,947 @@ use crate::model::{ActionType, AnalysisResult};
 /// - 各行動タイプの回数
 /// - 各行動タイプの比率（%）
 /// - 最も多い行動タイプ
-pub fn display_result(result: &AnalysisResult) {
-    println!("========================================");
-    println!("対戦ゲーム行動ログ解析結果");
-    println!("========================================");
-    println!();
+/// 
+/// -q ではサマリ1行だけ、-v / -vv では追加のセクションを表示する
+/// --narrate では文章だけで表示する
+pub fn display_result(result: &AnalysisResult, display: &DisplayOptions) {
+    let verbosity = display.verbosity;
+    if display.narrate {
+        display_result_narrative(result, &display.style);
+        return;
+    }
+    if verbosity == Verbosity::Quiet {
+        display_result_compact(result, &display.style);
+        return;
+    }
+
+    say!(display.style, "========================================");
+    say!(display.style, "対戦ゲーム行動ログ解析結果");
+    say!(display.style, "========================================");
+    say!(display.style, "");
     
-    display_player_info(result);
-    println!();
+    display_player_info(result, display);
+    say!(display.style, "");
     
-    display_counts(result);
-    println!();
+    display_counts(result, display);
+    say!(display.style, "");
 
-    display_action_id_counts(result);
-    println!();
+    if !result.unknown_id_counts.is_empty() {
+        display_unknown_ids(result, &display.style);
+        say!(display.style, "");
+    }
 
-    display_ratios(result);
-    println!();
+    display_time_stats(result, &display.style);
+    say!(display.style, "");
+
+    display_action_id_counts(result, display);
+    say!(display.style, "");
+
+    if verbosity >= Verbosity::Full {
+        display_action_id_details(result, &display.style);
+        say!(display.style, "");
+    }
+
+    display_ratios(result, &display.style);
+    say!(display.style, "");
+
+    display_time_phases(result, &display.style);
+    say!(display.style, "");
+
+    display_attack_categories(result, &display.style);
+    say!(display.style, "");
+
+    display_direction_balance(result, &display.style);
+    say!(display.style, "");
+
+    if verbosity >= Verbosity::Verbose {
+        display_interval_stats(result, &display.style);
+        say!(display.style, "");
+    }
+
+    if result.interval_histogram.is_some() {
+        display_interval_histogram(result, &display.style);
+        say!(display.style, "");
+    }
+
+    display_interval_shape(result, &display.style);
+    say!(display.style, "");
+
+    if !result.phases.is_empty() {
+        display_phases(result, &display.style);
+        say!(display.style, "");
+    }
+
+    display_repertoire(result, &display.style);
+    say!(display.style, "");
+
+    display_time_spreads(result, &display.style);
+    say!(display.style, "");
+
+    display_attack_streaks(result, &display.style);
+    say!(display.style, "");
+
+    display_tension_curve(result, &display.style);
+    say!(display.style, "");
+
+    display_combos(result, &display.style);
+    say!(display.style, "");
+
+    display_grab_follow_ups(result, &display.style);
+    say!(display.style, "");
+
+    display_repeat_rates(result, &display.style);
+    say!(display.style, "");
+
+    display_continuity(result, &display.style);
+    say!(display.style, "");
+
+    display_transitions(result, &display.style);
+    say!(display.style, "");
+
+    display_bursts(result, &display.style);
+    say!(display.style, "");
+
+    display_shield_cover(result, &display.style);
+    say!(display.style, "");
+
+    display_density_peaks(result, &display.style);
+    say!(display.style, "");
+
+    display_density_spikes(result, &display.style);
+    say!(display.style, "");
     
-    display_most_frequent(result);
+    display_most_frequent(result, &display.style);
     
-    println!("========================================");
+    say!(display.style, "========================================");
 }
 
 /// プレイヤー情報を表示
-fn display_player_info(result: &AnalysisResult) {
-    println!("【プレイヤー情報】");
-    println!("  学籍番号: {}", result.player_info.student_id);
-    println!("  対戦回次: {}", result.player_info.match_number);
+/// 
+/// 試合全体の活動量（区間ごとの行動数）も1行のスパークラインで表示し、
+/// 最後にデータ信頼度を「データ信頼度: 85（逆行1件）」の形で添える
+fn display_player_info(result: &AnalysisResult, display: &DisplayOptions) {
+    say!(display.style, "【プレイヤー情報】");
+    say!(display.style, "  学籍番号: {}", result.player_info.student_id);
+    say!(display.style, "  対戦回次: {}", result.player_info.match_number);
+    let activity = &result.activity;
+    if !activity.counts.is_empty() {
+        say!(
+            display.style,
+            "  活動量  : {} （1文字={}秒）",
+            sparkline::render(&activity.counts, display.ascii),
+            activity.slice_secs
+        );
+    }
+    let reliability = &result.reliability;
+    let issues = reliability.issues();
+    let detail = if issues.is_empty() { "問題なし".to_string() } else { issues.join("、") };
+    say!(display.style, "  データ信頼度: {}（{}）", reliability.score, detail);
 }
 
 /// 行動回数を表示
-fn display_counts(result: &AnalysisResult) {
-    println!("【行動回数】");
-    println!("  攻撃   (Attack): {} 回", result.counts.attack_count);
-    println!("  シールド(Shield): {} 回", result.counts.shield_count);
-    println!("  回避   (Dodge) : {} 回", result.counts.dodge_count);
-    println!("  合計           : {} 回", result.counts.total());
+/// 
+/// `display.sort_categories` がtrueなら回数の多い順に並べる（同数なら攻撃→シールド→回避の順のまま）。
+/// --momentum 指定時は、前半→後半の使用率の変化を矢印で行末に付ける
+fn display_counts(result: &AnalysisResult, display: &DisplayOptions) {
+    let style = &display.style;
+    say!(style, "【行動回数】");
+    let mut rows = [
+        ("攻撃   (Attack)", ActionType::Attack, result.counts.attack_count),
+        ("シールド(Shield)", ActionType::Shield, result.counts.shield_count),
+        ("回避   (Dodge) ", ActionType::Dodge, result.counts.dodge_count),
+    ];
+    if display.sort_categories {
+        // sort_by_keyは安定ソートなので、同数のカテゴリは元の順番を保つ
+        rows.sort_by_key(|row| std::cmp::Reverse(row.2));
+    }
+    let arrow = |action_type: &ActionType| match &result.half_counts {
+        Some(halves) => format!(" {}", momentum_glyph(halves.category_momentum(action_type), display.ascii)),
+        None => String::new(),
+    };
+    for (label, action_type, count) in &rows {
+        say!(style, "  {}: {} 回{}", label, count, arrow(action_type));
+    }
+    // 未登録の行動IDはあるときだけ表示する（並べ替えの対象にもしない）
+    if result.counts.unknown_count > 0 {
+        say!(style, "  不明  (Unknown): {} 回{}", result.counts.unknown_count, arrow(&ActionType::Unknown));
+    }
+    say!(style, "  合計           : {} 回", result.counts.total());
 }
 
-/// 行動比率を表示
-fn display_ratios(result: &AnalysisResult) {
-    println!("【行動比率】");
-    
+/// 未登録の行動IDと、それぞれの回数・書かれていた行番号を表示（打ち間違いを見つけるため）
+/// 
+/// 行番号は読み込み時の警告と同じく最初の MAX_WARNING_LINES 個だけを表示する
+fn display_unknown_ids(result: &AnalysisResult, style: &Style) {
+    say!(style, "【未知の行動ID】");
+    say!(style, "  攻撃・シールド・回避のどれにも数えていません。打ち間違いがないか確認してください");
+    for (id, count) in &result.unknown_id_counts {
+        let lines = result.unknown_id_lines.get(id).map(Vec::as_slice).unwrap_or_default();
+        if lines.is_empty() {
+            say!(style, "  {}: {} 回", pad_right(id, 12), count);
+            continue;
+        }
+        let shown: Vec<String> = lines.iter().take(MAX_WARNING_LINES).map(|l| l.to_string()).collect();
+        let rest = lines.len().saturating_sub(MAX_WARNING_LINES);
+        let more = if rest > 0 { format!(" …他 {} 行", rest) } else { String::new() };
+        say!(style, "  {}: {} 回（{}行目{}）", pad_right(id, 12), count, shown.join(", "), more);
+    }
+}
+
+/// 行動比率を表示（通常の比率と時間重み付き比率を並べる）
+fn display_ratios(result: &AnalysisResult, style: &Style) {
+    let weighted = &result.weighted_ratios;
+    say!(style, "【行動比率】");
+    let mut rows = vec![
+        ("攻撃   (Attack)", result.counts.attack_ratio(), weighted.attack_ratio),
+        ("シールド(Shield)", result.counts.shield_ratio(), weighted.shield_ratio),
+        ("回避   (Dodge) ", result.counts.dodge_ratio(), weighted.dodge_ratio),
+    ];
+    if result.counts.unknown_count > 0 {
+        rows.push(("不明  (Unknown)", result.counts.unknown_ratio(), weighted.unknown_ratio));
+    }
     // 小数点以下1桁で表示
-    println!("  攻撃   (Attack): {:.1}%", result.counts.attack_ratio());
-    println!("  シールド(Shield): {:.1}%", result.counts.shield_ratio());
-    println!("  回避   (Dodge) : {:.1}%", result.counts.dodge_ratio());
+    if style.stacked() {
+        // 幅が狭いときは2列の表をやめて、通常と時間重み付きを縦に並べる
+        say!(style, "  時間重み付き: {}", weighted.shape.label());
+        for (label, plain, weighted) in rows {
+            say!(style, "  {}", label.trim_end());
+            say!(style, "    通常    : {:>5.1}%", plain);
+            say!(style, "    重み付き: {:>5.1}%", weighted);
+        }
+    } else {
+        say!(style, "                     通常   時間重み付き({})", weighted.shape.label());
+        for (label, plain, weighted) in rows {
+            say!(style, "  {}: {:>5.1}%   {:>5.1}%", label, plain, weighted);
+        }
+    }
+
+    // 比率の順位を「攻撃 > 回避 = シールド」のように1行で表示する
+    let ranking = result.counts.ranking();
+    let mut line = String::new();
+    for (i, (action_type, ratio)) in ranking.iter().enumerate() {
+        if i > 0 {
+            line.push_str(if *ratio == ranking[i - 1].1 { " = " } else { " > " });
+        }
+        line.push_str(action_type.label());
+    }
+    say!(style, "  順位            : {}", line);
+}
+
+/// 試合時間と1分あたりの行動数（APM）を表示
+///
+/// 試合時間は mm:ss で表示する。試合時間が0秒でAPMを計算できないときは「—」
+fn display_time_stats(result: &AnalysisResult, style: &Style) {
+    let stats = &result.time_stats;
+    let apm = |apm: Option<f64>| apm.map(|apm| format!("{:.1} 回/分", apm)).unwrap_or_else(|| "—".to_string());
+    say!(style, "【時間統計】");
+    say!(style, "  最初の行動: {:.2}秒", stats.first);
+    say!(style, "  最後の行動: {:.2}秒", stats.last);
+    say!(style, "  試合時間  : {}", minutes_seconds(stats.duration));
+    say!(style, "  APM       : {}", apm(stats.apm));
+    say!(style, "    攻撃   (Attack): {}", apm(stats.attack_apm));
+    say!(style, "    シールド(Shield): {}", apm(stats.shield_apm));
+    say!(style, "    回避   (Dodge) : {}", apm(stats.dodge_apm));
+    if result.counts.unknown_count > 0 {
+        say!(style, "    不明  (Unknown): {}", apm(stats.unknown_apm));
+    }
+    if stats.apm.is_none() {
+        say!(style, "  （試合時間が0秒のため、APMは計算できません）");
+    }
+}
+
+/// 秒数を mm:ss の形にする（1秒未満は切り捨て。60分以上は分が3桁以上になる）
+fn minutes_seconds(secs: f64) -> String {
+    let total = secs.max(0.0) as u64;
+    format!("{:02}:{:02}", total / 60, total % 60)
+}
+
+/// 行動間隔の基本統計を表示
+fn display_interval_stats(result: &AnalysisResult, style: &Style) {
+    say!(style, "【行動間隔の統計】");
+    match &result.interval_stats {
+        Some(stats) => {
+            say!(style, "  平均    : {:.2}秒", stats.mean);
+            say!(style, "  中央値  : {:.2}秒", stats.median);
+            say!(style, "  最小    : {:.2}秒", stats.min);
+            say!(style, "  最大    : {:.2}秒", stats.max);
+            say!(style, "  標準偏差: {:.2}秒", stats.std_dev);
+        }
+        None => say!(style, "  データがありません（行動が1件のみ）"),
+    }
+}
+
+/// 行動間隔のヒストグラムを、ビンごとの横棒のテキストグラフで表示する（--interval-bin）
+///
+/// 長い外れ値をまとめた最後のビンは「N秒以上」と表示する
+fn display_interval_histogram(result: &AnalysisResult, style: &Style) {
+    let Some(histogram) = &result.interval_histogram else {
+        return;
+    };
+    say!(style, "【行動間隔の分布（{}秒刻み）】", histogram.bin_width);
+
+    let last = histogram.counts.len() - 1;
+    let labels: Vec<String> = (0..histogram.counts.len())
+        .map(|i| {
+            let start = i as f64 * histogram.bin_width;
+            if i == last && histogram.overflow {
+                format!("{:>6.2}秒以上", start)
+            } else {
+                format!("{:>6.2}〜{:>6.2}秒", start, start + histogram.bin_width)
+            }
+        })
+        .collect();
+    let label_width = labels.iter().map(|label| display_width(label)).max().unwrap_or(0);
+
+    // 「  ラベル |」と件数の分を空ける
+    let bar_width = style.budget(30, label_width + 10);
+    let max = histogram.counts.iter().copied().max().unwrap_or(0);
+    for (label, &count) in labels.iter().zip(&histogram.counts) {
+        say!(
+            style,
+            "  {} |{} {:>4}",
+            pad_right(label, label_width),
+            pad_right(&bar(count, max, bar_width), bar_width),
+            count
+        );
+    }
+}
+
+/// 前後方向の行動の回数と対称性スコアを表示
+fn display_direction_balance(result: &AnalysisResult, style: &Style) {
+    say!(style, "【前後のバランス】");
+    let balance = &result.direction_balance;
+    say!(style, "  前方向: {}回 / 後方向: {}回（向きが決まらない行動は除外）", balance.forward, balance.backward);
+    match balance.score {
+        Some(score) => {
+            let note = if score >= 0.8 {
+                "前後に偏りなく使えている"
+            } else if balance.forward > balance.backward {
+                "前方向に偏っている"
+            } else {
+                "後方向に偏っている"
+            };
+            say!(style, "  対称性スコア: {:.2} → {}", score, note);
+        }
+        None => say!(style, "  対称性スコア: N/A（前後方向の行動なし）"),
+    }
+}
+
+/// 攻撃の種類ごとの回数と、攻撃全体に対する比率を「スマッシュ: 12回 (18.5%)」の形で表示
+///
+/// 「その他の攻撃」は1回以上あるときだけ表示する
+fn display_attack_categories(result: &AnalysisResult, style: &Style) {
+    say!(style, "【攻撃内訳】");
+    let total: u32 = result.attack_categories.iter().map(|(_, count)| count).sum();
+    if total == 0 {
+        say!(style, "  攻撃がありません");
+        return;
+    }
+    let width = result
+        .attack_categories
+        .iter()
+        .map(|(category, _)| display_width(category.label()))
+        .max()
+        .unwrap_or(0);
+    for (category, count) in &result.attack_categories {
+        if *category == AttackCategory::OtherAttack && *count == 0 {
+            continue;
+        }
+        let ratio = *count as f64 / total as f64 * 100.0;
+        say!(style, "  {}: {:>3}回 ({:.1}%)", pad_right(category.label(), width), count, ratio);
+    }
+}
+
+/// 行動間隔の分布の形状（歪度・尖度・ランダム性）と所見を表示
+fn display_interval_shape(result: &AnalysisResult, style: &Style) {
+    say!(style, "【間隔分布の形状】");
+    let shape = &result.interval_shape;
+
+    match shape.skewness {
+        Some(skew) => {
+            let note = if skew > 0.5 {
+                "右裾が長い（ときどき長い間が空く）"
+            } else if skew < -0.5 {
+                "左裾が長い（短い間隔側にばらつく）"
+            } else {
+                "ほぼ左右対称"
+            };
+            say!(style, "  歪度: {:.2} → {}", skew, note);
+        }
+        None => say!(style, "  歪度: N/A（サンプル不足）"),
+    }
+
+    match shape.kurtosis {
+        Some(kurt) => {
+            let note = if kurt > 1.0 {
+                "尖っている（一定の間隔に集中し、外れ値がある）"
+            } else if kurt < -1.0 {
+                "平ら（間隔が幅広くばらつく）"
+            } else {
+                "正規分布に近い"
+            };
+            say!(style, "  尖度: {:.2} → {}", kurt, note);
+        }
+        None => say!(style, "  尖度: N/A（サンプル不足）"),
+    }
+
+    match &result.randomness {
+        Some(check) => say!(
+            style,
+            "  ランダム性: 分散/平均² = {:.2} → {}",
+            check.dispersion,
+            check.rhythm.label()
+        ),
+        None => say!(style, "  ランダム性: N/A（サンプル不足）"),
+    }
+}
+
+/// 使った技の種類の推移を折れ線のテキストグラフで表示し、初めて使った時刻を併記する
+fn display_repertoire(result: &AnalysisResult, style: &Style) {
+    say!(style, "【技の種類の推移】");
+    let growth = &result.repertoire;
+    let total = growth.first_uses.len();
+    if total == 0 {
+        say!(style, "  データがありません");
+        return;
+    }
+
+    // 縦軸の目盛りと、横軸の右端の時刻がはみ出す分を空ける
+    let graph_width = style.budget(40, 9);
+    const MAX_HEIGHT: usize = 10;
+    let height = total.min(MAX_HEIGHT);
+    let duration = growth.end - growth.start;
+
+    // 各列（時間区間の終わり）までの累積種類数を、グラフの高さ（1〜height）に換算する
+    let levels: Vec<usize> = (0..graph_width)
+        .map(|col| {
+            let time = growth.start + duration * (col + 1) as f64 / graph_width as f64;
+            let count = growth.count_at(time);
+            ((count * height) as f64 / total as f64).round() as usize
+        })
+        .collect();
+
+    for row in (1..=height).rev() {
+        // 縦軸の目盛りはその行に相当する種類数
+        let label = (row * total).div_ceil(height);
+        let line: String = levels.iter().map(|&level| if level == row { '*' } else { ' ' }).collect();
+        say!(style, "  {:>3} |{}", label, line.trim_end());
+    }
+    say!(style, "      +{}", "-".repeat(graph_width));
+    say!(style, "       {:<width$}{:.1}秒", format!("{:.1}秒", growth.start), growth.end, width = graph_width.saturating_sub(6));
+
+    say!(style, "  初めて使った時刻:");
+    for (i, (time, id)) in growth.first_uses.iter().enumerate() {
+        let name = style.action_name(id);
+        let label = style.paint_id(id, &format!("{} ({})", name, id));
+        say!(style, "    {:>7.2}秒  {} [{}種類目]", time, label, i + 1);
+    }
+}
+
+/// カテゴリごとの行動時刻の分布を、同じ時間軸の箱ひげ図で縦に並べて表示
+/// 
+/// `|---[==|==]---|` の形で、両端の | が最小・最大、[ ] が四分位、中の | が中央値。
+/// 行動が少なく四分位を出せないカテゴリは、行動した位置に o を打つ。
+fn display_time_spreads(result: &AnalysisResult, style: &Style) {
+    say!(style, "【カテゴリ別の時間帯】");
+    let (start, end) = (result.repertoire.start, result.repertoire.end);
+    let duration = end - start;
+    if duration <= 0.0 {
+        say!(style, "  データがありません");
+        return;
+    }
+
+    // カテゴリ名・枠と、右側の中央値の注記の分を空ける
+    let axis_width = style.budget(40, 12 + 16);
+    // 時刻を軸の列の位置に換算する
+    let column = |time: f64| (((time - start) / duration) * (axis_width - 1) as f64).round() as usize;
+
+    for spread in &result.time_spreads {
+        let mut cells = vec![' '; axis_width];
+        let note = match spread.quartiles {
+            Some([min, q1, median, q3, max]) => {
+                for cell in &mut cells[column(min)..=column(max)] {
+                    *cell = '-';
+                }
+                for cell in &mut cells[column(q1)..=column(q3)] {
+                    *cell = '=';
+                }
+                cells[column(min)] = '|';
+                cells[column(max)] = '|';
+                cells[column(q1)] = '[';
+                cells[column(q3)] = ']';
+                cells[column(median)] = '|';
+                format!("中央値 {:.1}秒", median)
+            }
+            None if spread.timestamps.is_empty() => "行動なし".to_string(),
+            None => {
+                for &time in &spread.timestamps {
+                    cells[column(time)] = 'o';
+                }
+                format!("{}件のみ（位置だけ表示）", spread.timestamps.len())
+            }
+        };
+        let line: String = cells.into_iter().collect();
+        say!(style, "  {} |{}| {}", pad_right(spread.action_type.label(), 8), line, note);
+    }
+    // 軸の左端に開始時刻、右端に終了時刻を合わせる
+    let start_label = format!("{:.1}秒", start);
+    let end_label = format!("{:.1}秒", end);
+    say!(
+        style,
+        "  {}  {}{}",
+        pad_right("", 8),
+        pad_right(&start_label, axis_width.saturating_sub(display_width(&end_label))),
+        end_label
+    );
+}
+
+/// 攻撃を続けていた時間の合計を表示
+fn display_attack_streaks(result: &AnalysisResult, style: &Style) {
+    say!(style, "【攻撃の継続】");
+    let streaks = &result.attack_streaks;
+    if streaks.streak_count == 0 {
+        say!(style, "  攻撃がありません");
+        return;
+    }
+
+    say!(style, "  攻撃継続合計時間: {:.1}秒", streaks.total_secs);
+    say!(style, "  攻撃区間の数    : {} 区間（うち単発 {}）", streaks.streak_count, streaks.single_count);
+    say!(style, "  最長の区間      : {:.1}秒", streaks.longest_secs);
+    say!(style, "  単発の攻撃の扱い: {}", streaks.single_mode.label());
+
+    let flow = &result.flow_switches;
+    match (flow.per_minute, flow.style) {
+        (Some(per_minute), Some(flow_style)) => say!(
+            style,
+            "  攻防の切り替え  : {} 回（{:.1}回/分）→ {}",
+            flow.switches,
+            per_minute,
+            flow_style.label()
+        ),
+        _ => say!(style, "  攻防の切り替え  : {} 回", flow.switches),
+    }
+}
+
+/// 直前と同じ行動を繰り返した割合を、高いかどうかの所見付きで表示
+fn display_repeat_rates(result: &AnalysisResult, style: &Style) {
+    let repeats = &result.repeats;
+    say!(style, "【同じ行動の繰り返し（{:.2}秒以内の連続）】", repeats.gap);
+    let (Some(previous), Some(recent)) = (repeats.previous_rate(), repeats.recent_rate()) else {
+        say!(style, "  続けて行った行動がありません");
+        return;
+    };
+
+    let note = |rate: f64| if rate >= RepeatRates::HIGH_RATE { "高い（読まれやすい）" } else { "低い" };
+    say!(
+        style,
+        "  直前と同じ行動の割合       : {:.0}% → {}",
+        previous * 100.0,
+        note(previous)
+    );
+    say!(
+        style,
+        "  直前か2つ前と同じ行動の割合: {:.0}% → {}",
+        recent * 100.0,
+        note(recent)
+    );
+    say!(style, "  （比べた行動: {} 件）", repeats.compared);
+}
+
+/// 攻め継続率・守り継続率を表示（遷移がなく計算できなければN/A）
+fn display_continuity(result: &AnalysisResult, style: &Style) {
+    let continuity = &result.continuity;
+    let rate = |rate: Option<f64>, hits: usize, total: usize| match rate {
+        Some(rate) => format!("{:.1}%（{} / {} 回）", rate * 100.0, hits, total),
+        None => "N/A".to_string(),
+    };
+    say!(style, "【行動の連続性】");
+    say!(
+        style,
+        "  攻め継続率（攻撃→攻撃）: {}",
+        rate(continuity.attack_rate(), continuity.attack_to_attack, continuity.from_attack)
+    );
+    say!(
+        style,
+        "  守り継続率（防御→防御）: {}",
+        rate(continuity.defense_rate(), continuity.defense_to_defense, continuity.from_defense)
+    );
+}
+
+/// 行動タイプどうしの遷移を行列の表で表示し、多い行動IDの遷移を「s → g: 14回」の形で続ける
+///
+/// 行が前の行動、列が次の行動。未登録の行動IDの遷移がなければ3×3にする
+fn display_transitions(result: &AnalysisResult, style: &Style) {
+    let transitions = &result.transitions;
+    say!(style, "【行動遷移】");
+    if transitions.total() == 0 {
+        say!(style, "  遷移はありません（{}秒以内に続いた行動がありません）", transitions.gap);
+        return;
+    }
+
+    let shown = if transitions.has_unknown() { 4 } else { 3 };
+    let types = &Transitions::TYPES[..shown];
+    let corner = "前＼次";
+    let row_width = types.iter().map(|t| display_width(t.label())).chain([display_width(corner)]).max().unwrap_or(0);
+    // 各列の幅は、見出しと数値のうち広いほう
+    let column_width = |to: usize| {
+        let widest = (0..shown).map(|from| transitions.counts[from][to].to_string().len()).max().unwrap_or(0);
+        widest.max(display_width(types[to].label()))
+    };
+    let widths: Vec<usize> = (0..shown).map(column_width).collect();
+
+    let header: Vec<String> = types.iter().zip(&widths).map(|(t, &width)| pad_left(t.label(), width)).collect();
+    say!(style, "  {}  {}", pad_right(corner, row_width), header.join("  "));
+    for (from, from_type) in types.iter().enumerate() {
+        let cells: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(to, &width)| pad_left(&transitions.counts[from][to].to_string(), width))
+            .collect();
+        say!(style, "  {}  {}", pad_right(from_type.label(), row_width), cells.join("  "));
+    }
+
+    say!(style, "  よく出る遷移:");
+    for (from, to, count) in &transitions.top_ids {
+        say!(
+            style,
+            "    {} → {}: {}回（{} → {}）",
+            from,
+            to,
+            count,
+            style.action_name(from),
+            style.action_name(to)
+        );
+    }
+    say!(
+        style,
+        "  （数えた組: {} 件、{}秒より間が空いて数えなかった組: {} 件）",
+        transitions.total(),
+        transitions.gap,
+        transitions.split
+    );
+}
+
+/// バースト（短い間隔の連続）に入った行動の割合と、バースト内・単発の行動のカテゴリの内訳を表示する
+///
+/// バーストは確定反撃、単発は立ち回りの目安として並べて比べられるようにする
+fn display_bursts(result: &AnalysisResult, style: &Style) {
+    let bursts = &result.bursts;
+    say!(style, "【連続行動（バースト）】");
+    let Some(share) = bursts.share() else {
+        say!(style, "  行動がありません");
+        return;
+    };
+    let (inside, outside) = (&bursts.inside, &bursts.outside);
+    say!(
+        style,
+        "  バーストに入った行動: {} / {} 件（{:.1}%）",
+        inside.total(),
+        inside.total() + outside.total(),
+        share
+    );
+    match bursts.average_len() {
+        Some(average) => say!(style, "  バースト: {} 回（平均 {:.1} 行動）", bursts.bursts.len(), average),
+        None => say!(style, "  バースト: 0 回"),
+    }
+
+    // 行動が1つもない側は比率を出せないので N/A にする
+    let ratio = |counts: &ActionCounts, value: f64| {
+        if counts.total() == 0 {
+            "  N/A".to_string()
+        } else {
+            format!("{:>5.1}%", value)
+        }
+    };
+    let mut rows = vec![
+        (ActionType::Attack, inside.attack_ratio(), outside.attack_ratio()),
+        (ActionType::Shield, inside.shield_ratio(), outside.shield_ratio()),
+        (ActionType::Dodge, inside.dodge_ratio(), outside.dodge_ratio()),
+    ];
+    if inside.unknown_count + outside.unknown_count > 0 {
+        rows.push((ActionType::Unknown, inside.unknown_ratio(), outside.unknown_ratio()));
+    }
+    let width = rows.iter().map(|(action_type, _, _)| display_width(action_type.label())).max().unwrap_or(0);
+    say!(style, "  カテゴリの内訳（バースト内 / 単発）:");
+    for (action_type, in_burst, isolated) in rows {
+        say!(
+            style,
+            "    {}: {} / {}",
+            pad_right(action_type.label(), width),
+            ratio(inside, in_burst),
+            ratio(outside, isolated)
+        );
+    }
+    say!(
+        style,
+        "  （{}秒未満の間隔で{}個以上続いた行動をバーストとしています。バーストは確定反撃、単発は立ち回りの目安です）",
+        bursts.gap,
+        bursts.min_len
+    );
+}
+
+/// 攻撃から次のシールドまでの時間を2行で表示（ヒストグラムはJSONの shield_cover に出す）
+fn display_shield_cover(result: &AnalysisResult, style: &Style) {
+    say!(style, "【攻撃後のシールド】");
+    let Some(cover) = &result.shield_cover else {
+        say!(style, "  N/A（攻撃の後のシールドがありません）");
+        return;
+    };
+    say!(
+        style,
+        "  次のシールドまで: 平均 {:.2}秒 / 中央値 {:.2}秒",
+        cover.latency.mean,
+        cover.latency.median
+    );
+    say!(
+        style,
+        "  {:.2}秒以内にシールド: {:.1}%（{} / {} 回の攻撃）",
+        cover.window,
+        cover.within_rate() * 100.0,
+        cover.within,
+        cover.attacks
+    );
+}
+
+/// 緊張度カーブ（時間窓ごとの攻防の切り替え回数）を横棒のテキストグラフで表示
+/// 
+/// 切り替えが最も多かった窓には印を付ける
+fn display_tension_curve(result: &AnalysisResult, style: &Style) {
+    let tension = &result.tension;
+    say!(style, "【緊張度カーブ（{:.1}秒ごとの攻防の切り替え）】", tension.window_secs);
+    if tension.switches.is_empty() {
+        say!(style, "  行動がありません");
+        return;
+    }
+
+    // 「  ####.#秒 |」と回数の分を空ける
+    let bar_width = style.budget(30, 18);
+    let max = tension.switches.iter().copied().max().unwrap_or(0);
+    for (i, &count) in tension.switches.iter().enumerate() {
+        let start = tension.start + i as f64 * tension.window_secs;
+        let mark = if count > 0 && count == max { " ← 最も激しい" } else { "" };
+        say!(
+            style,
+            "  {:>8.1}秒 |{} {:>3}{}",
+            start,
+            pad_right(&bar(count, max, bar_width), bar_width),
+            count,
+            mark
+        );
+    }
+}
+
+/// フェーズごとの行動回数と比率を表にして表示
+fn display_phases(result: &AnalysisResult, style: &Style) {
+    say!(style, "【フェーズ別の行動】");
+    let headers = ["開始", "攻撃", "シールド", "回避", "合計", "攻撃%"];
+    if style.stacked() {
+        // 幅が狭いときは表をやめて、フェーズごとに「項目: 値」を縦に並べる
+        for phase in &result.phases {
+            let counts = &phase.counts;
+            let values = [
+                format!("{:.2}秒", phase.start),
+                counts.attack_count.to_string(),
+                counts.shield_count.to_string(),
+                counts.dodge_count.to_string(),
+                counts.total().to_string(),
+                format!("{:.1}", counts.attack_ratio()),
+            ];
+            say!(style, "  {}", phase.name);
+            for (header, value) in headers.iter().zip(values.iter()) {
+                say!(style, "    {}: {}", pad_right(header, 8), value);
+            }
+        }
+        return;
+    }
+    let header: Vec<String> = headers.iter().map(|h| pad_left(h, 9)).collect();
+    say!(style, "  {}{}", pad_right("フェーズ", 20), header.join(""));
+    for phase in &result.phases {
+        let counts = &phase.counts;
+        let columns = [
+            format!("{:.2}秒", phase.start),
+            counts.attack_count.to_string(),
+            counts.shield_count.to_string(),
+            counts.dodge_count.to_string(),
+            counts.total().to_string(),
+            format!("{:.1}", counts.attack_ratio()),
+        ];
+        let columns: Vec<String> = columns.iter().map(|c| pad_left(c, 9)).collect();
+        say!(style, "  {}{}", pad_right(&phase.name, 20), columns.join(""));
+    }
+}
+
+/// 試合時間を等分した区間ごとの行動比率を表示（試合の流れで戦い方がどう変わったか）
+///
+/// 区間が3つなら「序盤・中盤・終盤」、それ以外は「1/4」のように番号で呼ぶ
+fn display_time_phases(result: &AnalysisResult, style: &Style) {
+    let phases = &result.time_phases;
+    say!(style, "【時間帯別の行動比率】");
+    if phases.is_empty() {
+        say!(style, "  データがありません");
+        return;
+    }
+
+    let stats = &result.time_stats;
+    let width = stats.duration / phases.len() as f64;
+    let name = |i: usize| match (phases.len(), i) {
+        (3, 0) => "序盤".to_string(),
+        (3, 1) => "中盤".to_string(),
+        (3, _) => "終盤".to_string(),
+        (n, i) => format!("{}/{}", i + 1, n),
+    };
+    let headers = ["区間", "攻撃%", "シールド%", "回避%", "行動数"];
+    if !style.stacked() {
+        let header: Vec<String> = headers[1..].iter().map(|h| pad_left(h, 10)).collect();
+        say!(style, "  {}{}{}", pad_right("", 6), pad_right(headers[0], 18), header.join(""));
+    }
+    for (i, counts) in phases.iter().enumerate() {
+        let start = stats.first + width * i as f64;
+        let range = format!("{:.1}〜{:.1}秒", start, start + width);
+        let columns = [
+            format!("{:.1}", counts.attack_ratio()),
+            format!("{:.1}", counts.shield_ratio()),
+            format!("{:.1}", counts.dodge_ratio()),
+            counts.total().to_string(),
+        ];
+        if style.stacked() {
+            // 幅が狭いときは表をやめて、区間ごとに「項目: 値」を縦に並べる
+            say!(style, "  {}", name(i));
+            for (header, value) in headers.iter().zip([&range].into_iter().chain(columns.iter())) {
+                say!(style, "    {}: {}", pad_right(header, 10), value);
+            }
+        } else {
+            let columns: Vec<String> = columns.iter().map(|c| pad_left(c, 10)).collect();
+            say!(style, "  {}{}{}", pad_right(&name(i), 6), pad_right(&range, 18), columns.join(""));
+        }
+    }
+}
+
+/// 短い間隔で続けた攻撃（コンボ）の集計を表示
+fn display_combos(result: &AnalysisResult, style: &Style) {
+    let combos = &result.combos;
+    say!(style, "【コンボ（{:.2}秒以内の連続攻撃）】", combos.gap);
+    match combos.mean_length {
+        Some(mean) => {
+            say!(style, "  コンボ数      : {} 回", combos.combo_count);
+            say!(style, "  最大コンボ長  : {} 連続", combos.max_length);
+            say!(style, "  平均コンボ長  : {:.1} 連続", mean);
+            say!(style, "  最大コンボ    :");
+            for (i, id) in combos.longest.iter().enumerate() {
+                let name = style.action_name(id);
+                let label = style.paint_id(id, &format!("{} ({})", name, id));
+                say!(style, "    {:>2}. {}", i + 1, label);
+            }
+        }
+        None => say!(style, "  コンボはありません"),
+    }
+}
+
+/// つかみの後にどの投げ・派生に行ったかを「つかみ後: 上投げ 40%, 下投げ 30%」の形で表示
+fn display_grab_follow_ups(result: &AnalysisResult, style: &Style) {
+    let follow_ups = &result.grab_follow_ups;
+    say!(style, "【つかみ後の投げ・派生】");
+    if follow_ups.grabs == 0 {
+        say!(style, "  つかみ後: N/A（つかみがありません）");
+        return;
+    }
+
+    let mut parts: Vec<String> = follow_ups
+        .throws
+        .iter()
+        .map(|(id, count)| {
+            let rate = follow_ups.rate(*count).unwrap_or(0.0);
+            format!("{} {:.0}%", style.action_name(id), rate)
+        })
+        .collect();
+    if follow_ups.others > 0 {
+        let rate = follow_ups.rate(follow_ups.others).unwrap_or(0.0);
+        parts.push(format!("その他 {:.0}%", rate));
+    }
+    say!(style, "  つかみ後: {}", parts.join(", "));
+    say!(style, "  （つかみ: {} 回）", follow_ups.grabs);
+}
+
+/// 行動密度の山（試合の山場）を時刻順に表示
+fn display_density_peaks(result: &AnalysisResult, style: &Style) {
+    say!(style, "【行動密度のピーク】");
+    if result.density_peaks.is_empty() {
+        say!(style, "  ピークはありません");
+        return;
+    }
+    for peak in &result.density_peaks {
+        let end = peak.second + peak.width as f64;
+        say!(style, "  {:>6.0}秒〜{:>6.0}秒: {} 回/秒", peak.second, end, peak.density);
+    }
+}
+
+/// 行動が異常に集中した秒（スパイク）を時刻順に表示（入力ミスや連打バグの確認用）
+fn display_density_spikes(result: &AnalysisResult, style: &Style) {
+    let spikes = &result.density_spikes;
+    say!(style, "【行動の集中（スパイク）】");
+    say!(
+        style,
+        "  閾値: {:.2} 回/秒（平均 {:.2} + {}σ, σ = {:.2}）",
+        spikes.threshold,
+        spikes.mean,
+        spikes.sigma,
+        spikes.std_dev
+    );
+    if spikes.spikes.is_empty() {
+        say!(style, "  スパイクはありません");
+        return;
+    }
+    for spike in &spikes.spikes {
+        say!(style, "  {:>6.0}秒: {} 回/秒", spike.second, spike.density);
+    }
+    say!(style, "  入力ミスや連打がないか、この時刻のログを確認してください");
+}
+
+/// 同じファイルを前回解析したときからの行動比率の差を表示（--compare-last）
+///
+/// # 引数
+/// * `previous` - 前回の行動回数
+/// * `current` - 今回の行動回数
+/// * `style` - 表示の設定
+pub fn display_last_comparison(previous: &ActionCounts, current: &ActionCounts, style: &Style) {
+    say!(style, "【前回との比較】");
+    let mut rows = vec![
+        ("攻撃   (Attack)", previous.attack_ratio(), current.attack_ratio()),
+        ("シールド(Shield)", previous.shield_ratio(), current.shield_ratio()),
+        ("回避   (Dodge) ", previous.dodge_ratio(), current.dodge_ratio()),
+    ];
+    if previous.unknown_count > 0 || current.unknown_count > 0 {
+        rows.push(("不明  (Unknown)", previous.unknown_ratio(), current.unknown_ratio()));
+    }
+    for (label, before, after) in rows {
+        say!(style, "  {}: {:>5.1}% → {:>5.1}% ({:+.1}pt)", label, before, after, after - before);
+    }
+    say!(style, "  行動数         : {} → {} 回", previous.total(), current.total());
 }
 
 /// 最も多い行動IDを表示（日本語名付き）
-fn display_most_frequent(result: &AnalysisResult) {
-    println!("【最も多い行動】");
+fn display_most_frequent(result: &AnalysisResult, style: &Style) {
+    say!(style, "【最も多い行動】");
     if let Some((id, count)) = result.action_id_counts.first() {
-        let name = ActionType::get_action_name(id);
-        println!("  {} ({}) - {}回", name, id, count);
+        let name = style.action_name(id);
+        say!(style, "  {} ({}) - {}回", name, id, count);
     } else {
-        println!("  データがありません");
+        say!(style, "  データがありません");
     }
 }
 
 /// 簡易版の結果表示（1行で出力）
-
-// 使われていなくても警告を出さないようにする
-#[allow(dead_code)]
-pub fn display_result_compact(result: &AnalysisResult) {
+pub fn display_result_compact(result: &AnalysisResult, style: &Style) {
     let most = result.action_id_counts.first();
     let summary = if let Some((id, count)) = most {
-        let name = ActionType::get_action_name(id);
+        let name = style.action_name(id);
         format!("{} ({}) - {}回", name, id, count)
     } else {
         "データなし".to_string()
     };
 
-    println!(
+    say!(
+        style,
         "{} (対戦{}) - Attack:{:.1}%, Shield:{:.1}%, Dodge:{:.1}% → 最多: {}",
         result.player_info.student_id,
         result.player_info.match_number,
@@ -100,30 +1073,1774 @@ pub fn display_result_compact(result: &AnalysisResult) {
     );
 }
 
+/// 読み上げ向けに、記号や罫線を使わず文章で結果を表示する
+/// 
+/// スクリーンリーダーで聞き取りやすいように、比率は整数の%、秒は小数第1位に丸める
+pub fn display_result_narrative(result: &AnalysisResult, style: &Style) {
+    let counts = &result.counts;
+    say!(
+        style,
+        "学籍番号 {} の対戦{}回目の結果です。",
+        result.player_info.student_id, result.player_info.match_number
+    );
+    say!(style, "行動は全部で{}回でした。", counts.total());
+
+    // 「攻撃が60%で最も多く、次に回避が30%、シールドが10%でした。」
+    // 同率1位は「攻撃とシールドがどちらも40%で最も多く」とまとめる
+    let ranking = counts.ranking();
+    let top_ratio = ranking[0].1;
+    let (top, rest): (Vec<_>, Vec<_>) = ranking.iter().partition(|(_, ratio)| *ratio == top_ratio);
+    let top_names: Vec<&str> = top.iter().map(|(action_type, _)| action_type.label()).collect();
+    let sentence = if rest.is_empty() {
+        format!("このプレイヤーは{}が同じ割合でした。", top_names.join("と"))
+    } else {
+        let lead = if top.len() == 1 {
+            format!("{}が{}%で最も多く", top_names[0], top_ratio.round())
+        } else {
+            format!("{}がどちらも{}%で最も多く", top_names.join("と"), top_ratio.round())
+        };
+        let rest: Vec<String> = rest
+            .iter()
+            .map(|(action_type, ratio)| format!("{}が{}%", action_type.label(), ratio.round()))
+            .collect();
+        format!("このプレイヤーは{}、次に{}でした。", lead, rest.join("、"))
+    };
+    say!(style, "{}", sentence);
+
+    if let Some((id, count)) = result.action_id_counts.first() {
+        say!(style, "最も多かった行動は{}で、{}回でした。", style.action_name(id), count);
+    }
+    if let Some(stats) = &result.interval_stats {
+        say!(style, "行動と行動の間隔は、平均でおよそ{:.1}秒でした。", stats.mean);
+    }
+    let streaks = &result.attack_streaks;
+    if streaks.streak_count > 0 {
+        say!(
+            style,
+            "攻撃を続けていた時間は合計でおよそ{:.1}秒で、いちばん長く続いたのはおよそ{:.1}秒でした。",
+            streaks.total_secs, streaks.longest_secs
+        );
+    }
+}
+
 /// 行動IDごとの回数を表示（降順）
-fn display_action_id_counts(result: &AnalysisResult) {
-    println!("【行動IDごとの回数（降順）】");
+/// --sparklines指定時は、各行の右に試合のどの時間帯で使ったかのスパークラインを付ける
+fn display_action_id_counts(result: &AnalysisResult, display: &DisplayOptions) {
+    let style = &display.style;
+    say!(style, "【行動IDごとの回数（降順）】");
     let data = &result.action_id_counts;
     if data.is_empty() {
-        println!("  データがありません");
+        say!(style, "  データがありません");
         return;
     }
 
     let max = data.iter().map(|(_, c)| *c).max().unwrap_or(0);
     if max == 0 {
-        println!("  データがありません");
+        say!(style, "  データがありません");
         return;
     }
 
-    const MAX_WIDTH: u32 = 30;
-    let to_bar = |count: u32| -> String {
-        let width = ((count as f64 / max as f64) * MAX_WIDTH as f64).round() as u32;
-        let width = width.max(1);
-        "#".repeat(width as usize)
-    };
+    // 「  ID          : 」の16文字と、スパークライン表示時の回数・スパークライン、矢印の分を空ける
+    let mut reserved = if result.id_usage_buckets.is_some() { 16 + 6 + SPARKLINE_BUCKETS } else { 16 };
+    if result.half_counts.is_some() {
+        reserved += 2;
+    }
+    let bar_width = style.budget(30, reserved);
 
     for (id, count) in data {
-        let bar = to_bar(*count);
-        println!("  {:<12}: {}", id, bar);
+        let bar = bar(*count, max, bar_width);
+        // 桁揃えしてから色を付ける（エスケープシーケンスが幅に数えられないように）
+        // 長いIDは棒の位置がずれないように切り詰める
+        let label = style.paint_id(id, &pad_right(&truncate(id, 12), 12));
+        // 矢印はバーの長さがそろっていないと縦に並ばないので、付けるときはバーを桁揃えする
+        let arrow = result
+            .half_counts
+            .as_ref()
+            .and_then(|halves| halves.id_momentum(id))
+            .map(|momentum| format!(" {}", momentum_glyph(momentum, display.ascii)));
+        match result.id_usage_buckets.as_ref().and_then(|buckets| buckets.get(id)) {
+            Some(buckets) => {
+                let bar = format!("{:<width$}", bar, width = bar_width);
+                let spark = sparkline::render(buckets, display.ascii);
+                let arrow = arrow.unwrap_or_default();
+                say!(style, "  {}: {} {:>4} {}{}", label, style.paint_id(id, &bar), count, spark, arrow);
+            }
+            None => match arrow {
+                Some(arrow) => {
+                    let bar = format!("{:<width$}", bar, width = bar_width);
+                    say!(style, "  {}: {}{}", label, style.paint_id(id, &bar), arrow);
+                }
+                None => say!(style, "  {}: {}", label, style.paint_id(id, &bar)),
+            },
+        }
+    }
+}
+
+/// 行動IDごとの回数と全体に対する割合を、日本語名付きで表示
+fn display_action_id_details(result: &AnalysisResult, style: &Style) {
+    say!(style, "【行動IDごとの詳細】");
+    let total = result.counts.total();
+    if total == 0 {
+        say!(style, "  データがありません");
+        return;
+    }
+
+    // 英語名を併記すると日本語名だけより長くなるので、名前の列の幅は一番長い名前に合わせる
+    let names: Vec<String> = result.action_id_counts.iter().map(|(id, _)| style.action_name(id)).collect();
+    let name_width = names.iter().map(|name| display_width(name)).max().unwrap_or(0).max(16);
+    for ((id, count), name) in result.action_id_counts.iter().zip(&names) {
+        let ratio = *count as f64 / total as f64 * 100.0;
+        let label = style.paint_id(id, &format!("{} {}", pad_right(id, 6), pad_right(name, name_width)));
+        say!(style, "  {} {:>4}回 ({:>5.1}%)", label, count, ratio);
+    }
+}
+
+/// JSONに書く小数の桁数（秒は1ミリ秒、比率は0.001%まで）
+const JSON_DECIMALS: usize = 3;
+
+/// レポートをJSONで表示する（--format json / --json）
+///
+/// # 引数
+/// * `report` - レポートのデータ
+/// * `style` - 出力先（--output）を持つ表示のスタイル。JSONは折り返さない
+pub fn display_report_json(report: &ReportData, style: &Style) {
+    style.write(&format!("{}\n", report_to_json(report)));
+}
+
+/// 解析結果を1行のJSONにする（--follow のスナップショットで使う。--json / --ipc は report_to_json）
+/// 
+/// 比率は整形済みの文字列ではなく数値（%）で出す。小数は JSON_DECIMALS 桁に固定する。
+/// 行動IDがなくても action_id_counts は空の配列にする。
+/// 出力を変えたら schema.rs のスキーマも直して SCHEMA_VERSION を上げること
+/// 
+/// # 引数
+/// * `result` - 解析結果
+/// 
+/// # 戻り値
+/// JSON文字列（改行なし）
+pub fn to_json(result: &AnalysisResult) -> String {
+    format!("{{{}}}", result_fields(result))
+}
+
+/// 解析結果のJSONの中身（外側の {} を除いたキーの並び）
+fn result_fields(result: &AnalysisResult) -> String {
+    let counts = &result.counts;
+    let action_ids: Vec<String> = result
+        .action_id_counts
+        .iter()
+        .map(|(id, count)| {
+            format!(
+                "{{\"id\":{},\"name\":{},\"count\":{}}}",
+                json::string(id),
+                json::string(&ActionType::get_action_name(id)),
+                count
+            )
+        })
+        .collect();
+    let unknown_ids: Vec<String> = result
+        .unknown_id_counts
+        .iter()
+        .map(|(id, count)| format!("{{\"id\":{},\"count\":{}}}", json::string(id), count))
+        .collect();
+    let intervals = match &result.interval_stats {
+        Some(stats) => format!(
+            "{{\"mean\":{},\"median\":{},\"min\":{},\"max\":{},\"std_dev\":{}}}",
+            json::fixed(stats.mean, JSON_DECIMALS),
+            json::fixed(stats.median, JSON_DECIMALS),
+            json::fixed(stats.min, JSON_DECIMALS),
+            json::fixed(stats.max, JSON_DECIMALS),
+            json::fixed(stats.std_dev, JSON_DECIMALS)
+        ),
+        None => "null".to_string(),
+    };
+    let streaks = &result.attack_streaks;
+    let combos = &result.combos;
+    let repeats = &result.repeats;
+    let optional = |rate: Option<f64>| {
+        rate.map(|rate| json::fixed(rate, JSON_DECIMALS))
+            .unwrap_or_else(|| "null".to_string())
+    };
+    let phases: Vec<String> = result
+        .phases
+        .iter()
+        .map(|phase| {
+            format!(
+                "{{\"name\":{},\"start\":{},\"attack\":{},\"shield\":{},\"dodge\":{}}}",
+                json::string(&phase.name),
+                json::fixed(phase.start, JSON_DECIMALS),
+                phase.counts.attack_count,
+                phase.counts.shield_count,
+                phase.counts.dodge_count
+            )
+        })
+        .collect();
+    // 前半・後半の回数（--momentum 指定時のみ。ないときはnull）
+    let momentum = match &result.half_counts {
+        Some(halves) => {
+            let categories: Vec<String> = [ActionType::Attack, ActionType::Shield, ActionType::Dodge, ActionType::Unknown]
+                .iter()
+                .map(|action_type| {
+                    format!(
+                        "{{\"category\":{},\"first\":{},\"second\":{},\"momentum\":{}}}",
+                        json::string(action_type.key()),
+                        halves.first.count(action_type),
+                        halves.second.count(action_type),
+                        json::string(halves.category_momentum(action_type).key())
+                    )
+                })
+                .collect();
+            // 行動IDは action_id_counts と同じ順に並べる
+            let ids: Vec<String> = result
+                .action_id_counts
+                .iter()
+                .filter_map(|(id, _)| {
+                    let (first, second) = halves.by_id.get(id)?;
+                    let momentum = halves.id_momentum(id)?;
+                    Some(format!(
+                        "{{\"id\":{},\"first\":{},\"second\":{},\"momentum\":{}}}",
+                        json::string(id),
+                        first,
+                        second,
+                        json::string(momentum.key())
+                    ))
+                })
+                .collect();
+            format!(
+                "{{\"split\":{},\"first_total\":{},\"second_total\":{},\"categories\":[{}],\"ids\":[{}]}}",
+                json::fixed(halves.split, JSON_DECIMALS),
+                halves.first.total(),
+                halves.second.total(),
+                categories.join(","),
+                ids.join(",")
+            )
+        }
+        None => "null".to_string(),
+    };
+    // --debounce で取り除いた行（指定しなかったときはnull）
+    let debounce = match &result.debounce {
+        Some(report) => {
+            let dropped: Vec<String> = report
+                .dropped
+                .iter()
+                .map(|(id, count)| format!("{{\"id\":{},\"count\":{}}}", json::string(id), count))
+                .collect();
+            format!(
+                "{{\"threshold_ms\":{},\"total\":{},\"dropped\":[{}]}}",
+                json::fixed(report.threshold_ms, JSON_DECIMALS),
+                report.total(),
+                dropped.join(",")
+            )
+        }
+        None => "null".to_string(),
+    };
+    let shield_cover = match &result.shield_cover {
+        Some(cover) => format!(
+            concat!(
+                "{{\"window\":{},\"bin_width\":{},\"attacks\":{},\"within\":{},\"within_rate\":{},",
+                "\"mean\":{},\"median\":{},\"histogram\":[{}],\"histogram_overflow\":{}}}"
+            ),
+            json::fixed(cover.window, JSON_DECIMALS),
+            json::fixed(cover.bin_width, JSON_DECIMALS),
+            cover.attacks,
+            cover.within,
+            json::fixed(cover.within_rate(), JSON_DECIMALS),
+            json::fixed(cover.latency.mean, JSON_DECIMALS),
+            json::fixed(cover.latency.median, JSON_DECIMALS),
+            cover.histogram.iter().map(|count| count.to_string()).collect::<Vec<_>>().join(","),
+            cover.overflow
+        ),
+        None => "null".to_string(),
+    };
+    let bursts = &result.bursts;
+    let burst_counts = |counts: &ActionCounts| {
+        format!(
+            "{{\"attack\":{},\"shield\":{},\"dodge\":{},\"unknown\":{}}}",
+            counts.attack_count, counts.shield_count, counts.dodge_count, counts.unknown_count
+        )
+    };
+    let segments: Vec<String> = bursts
+        .bursts
+        .iter()
+        .map(|burst| {
+            format!(
+                "{{\"start\":{},\"end\":{},\"actions\":{}}}",
+                json::fixed(burst.start, JSON_DECIMALS),
+                json::fixed(burst.end, JSON_DECIMALS),
+                burst.actions
+            )
+        })
+        .collect();
+    let bursts = format!(
+        concat!(
+            "{{\"gap\":{},\"min_len\":{},\"count\":{},\"actions\":{},\"share\":{},\"average_length\":{},",
+            "\"inside\":{},\"outside\":{},\"segments\":[{}]}}"
+        ),
+        json::fixed(bursts.gap, JSON_DECIMALS),
+        bursts.min_len,
+        bursts.bursts.len(),
+        bursts.inside.total(),
+        optional(bursts.share()),
+        optional(bursts.average_len()),
+        burst_counts(&bursts.inside),
+        burst_counts(&bursts.outside),
+        segments.join(",")
+    );
+    let reliability = &result.reliability;
+    let reliability = format!(
+        "{{\"score\":{},\"reordered\":{},\"dropped\":{},\"gaps\":{},\"unknown\":{}}}",
+        reliability.score, reliability.reordered, reliability.dropped, reliability.gaps, reliability.unknown
+    );
+    // ファイルの更新日時（RFC 3339）。取れなかったときはキーごと出さない
+    let modified = result
+        .modified
+        .and_then(datetime::rfc3339)
+        .map(|time| format!("\"file_modified\":{},", json::string(&time)))
+        .unwrap_or_default();
+
+    format!(
+        concat!(
+            "\"schema_version\":{},\"player_info\":{{\"student_id\":{},\"match_number\":{}}},{}",
+            "\"counts\":{{\"attack\":{},\"shield\":{},\"dodge\":{},\"unknown\":{},\"total\":{},",
+            "\"attack_ratio\":{},\"shield_ratio\":{},\"dodge_ratio\":{},\"unknown_ratio\":{}}},",
+            "\"unknown_ids\":[{}],",
+            "\"action_id_counts\":[{}],\"intervals\":{},",
+            "\"attack_streaks\":{{\"count\":{},\"total_secs\":{},\"longest_secs\":{}}},",
+            "\"combos\":{{\"gap\":{},\"count\":{},\"max_length\":{},\"longest\":[{}]}},",
+            "\"repeats\":{{\"gap\":{},\"compared\":{},\"previous_rate\":{},\"recent_rate\":{}}},",
+            "\"flow_switches\":{},\"phases\":[{}],\"momentum\":{},\"debounce\":{},\"shield_cover\":{},",
+            "\"bursts\":{},\"reliability\":{}"
+        ),
+        schema::SCHEMA_VERSION,
+        json::string(&result.player_info.student_id),
+        result.player_info.match_number,
+        modified,
+        counts.attack_count,
+        counts.shield_count,
+        counts.dodge_count,
+        counts.unknown_count,
+        counts.total(),
+        json::fixed(counts.attack_ratio(), JSON_DECIMALS),
+        json::fixed(counts.shield_ratio(), JSON_DECIMALS),
+        json::fixed(counts.dodge_ratio(), JSON_DECIMALS),
+        json::fixed(counts.unknown_ratio(), JSON_DECIMALS),
+        unknown_ids.join(","),
+        action_ids.join(","),
+        intervals,
+        streaks.streak_count,
+        json::fixed(streaks.total_secs, JSON_DECIMALS),
+        json::fixed(streaks.longest_secs, JSON_DECIMALS),
+        json::fixed(combos.gap, JSON_DECIMALS),
+        combos.combo_count,
+        combos.max_length,
+        combos.longest.iter().map(|id| json::string(id)).collect::<Vec<_>>().join(","),
+        json::fixed(repeats.gap, JSON_DECIMALS),
+        repeats.compared,
+        optional(repeats.previous_rate()),
+        optional(repeats.recent_rate()),
+        result.flow_switches.switches,
+        phases.join(","),
+        momentum,
+        debounce,
+        shield_cover,
+        bursts,
+        reliability
+    )
+}
+
+/// 以前のログから追記された行動を表示する
+pub fn display_log_diff(diff: &LogDiff) {
+    println!("【追記された行動】 {} 件", diff.added.len());
+    for action in &diff.added {
+        let name = ActionType::get_action_name(&action.original_id);
+        println!("  {:>8.2}秒  {} ({})", action.timestamp, name, action.original_id);
+    }
+    // 追記ではなく書き換えられている可能性があるので知らせる
+    if !diff.removed.is_empty() {
+        println!();
+        println!("  ※ 以前のログにだけある行動が {} 件あります（追記以外の変更があるかもしれません）", diff.removed.len());
+    }
+}
+
+/// グループ別の平均指標を並べて表示する
+pub fn display_group_summaries(summaries: &[GroupSummary]) {
+    println!("========================================");
+    println!("グループ別比較");
+    println!("========================================");
+    let headers = ["ログ数", "平均行動数", "攻撃%", "シールド%", "回避%", "平均間隔"];
+    let header: Vec<String> = headers.iter().map(|h| pad_left(h, 12)).collect();
+    println!("  {}{}", pad_right("グループ", 14), header.join(""));
+    for summary in summaries {
+        let interval = match summary.mean_interval {
+            Some(mean) => format!("{:.2}秒", mean),
+            None => "N/A".to_string(),
+        };
+        let columns = [
+            summary.logs.to_string(),
+            format!("{:.1}", summary.mean_actions),
+            format!("{:.1}", summary.attack_ratio),
+            format!("{:.1}", summary.shield_ratio),
+            format!("{:.1}", summary.dodge_ratio),
+            interval,
+        ];
+        let columns: Vec<String> = columns.iter().map(|c| pad_left(c, 12)).collect();
+        println!("  {}{}", pad_right(&summary.name, 14), columns.join(""));
+    }
+    println!("========================================");
+}
+
+/// 2つのログの行動IDの使い方の類似度（--similarity）を、目安の言葉を添えて表示する
+/// 
+/// # 引数
+/// * `path_a` - 1つ目のログのパス
+/// * `path_b` - 2つ目のログのパス
+/// * `similarity` - コサイン類似度（0〜1。どちらかに行動がなければNone）
+pub fn display_similarity(path_a: &str, path_b: &str, similarity: Option<f64>) {
+    println!("========================================");
+    println!("行動の同期率（行動IDの使い方の類似度）");
+    println!("========================================");
+    println!("  A: {}", path_a);
+    println!("  B: {}", path_b);
+    println!();
+
+    match similarity {
+        Some(similarity) => {
+            let note = if similarity >= 0.9 {
+                "ほぼ同じ使い方"
+            } else if similarity >= 0.7 {
+                "よく似ている"
+            } else if similarity >= 0.4 {
+                "ある程度似ている"
+            } else {
+                "あまり似ていない"
+            };
+            println!("  類似度: {:.3} → {}", similarity, note);
+        }
+        None => println!("  類似度: N/A（行動が1つもないログがあります）"),
+    }
+    println!("  （行動IDごとの回数のコサイン類似度。1に近いほど、同じ行動IDを同じ割合で使っています）");
+    println!("========================================");
+}
+
+/// 行動IDの使い方が似た試合のクラスタ（--cluster）を表示する
+/// 
+/// クラスタごとにメンバーの試合と、代表的な傾向（カテゴリ比率とよく使う行動）、
+/// 中心が最も近いほかのクラスタを出す
+/// 
+/// # 引数
+/// * `clusters` - クラスタのリスト
+/// * `style` - 表示の設定
+pub fn display_clusters(clusters: &[PlayStyleCluster], style: &Style) {
+    say!(style, "========================================");
+    say!(style, "プレイスタイルのクラスタ（{} 個）", clusters.len());
+    say!(style, "========================================");
+    for (i, cluster) in clusters.iter().enumerate() {
+        say!(style, "");
+        say!(style, "【クラスタ {}】 {} 試合", i + 1, cluster.members.len());
+        let members: Vec<String> = cluster
+            .members
+            .iter()
+            .map(|info| format!("{} #{}", info.student_id, info.match_number))
+            .collect();
+        say!(style, "  試合      : {}", members.join(", "));
+        let counts = &cluster.counts;
+        say!(
+            style,
+            "  比率      : 攻撃 {:.1}% / シールド {:.1}% / 回避 {:.1}%",
+            counts.attack_ratio(),
+            counts.shield_ratio(),
+            counts.dodge_ratio()
+        );
+        // 中心で割合の大きい行動が、このクラスタの代表的な行動
+        let top: Vec<String> = cluster
+            .centroid
+            .iter()
+            .take(CLUSTER_TOP_ACTIONS)
+            .map(|(id, share)| format!("{}（{}）{:.0}%", id, style.action_name(id), share * 100.0))
+            .collect();
+        say!(style, "  よく使う  : {}", top.join(", "));
+        if let Some((nearest, distance)) = cluster.nearest {
+            say!(style, "  近いクラスタ: クラスタ {}（距離 {:.3}）", nearest + 1, distance);
+        }
+    }
+    say!(style, "========================================");
+}
+
+/// クラスタの代表的な行動として表示する行動IDの数
+const CLUSTER_TOP_ACTIONS: usize = 3;
+
+/// 複数のCSVをまとめて解析したときの合算結果を表示する
+/// 
+/// 合計の行動回数と、試合ごとの比率を並べた表を出す。学籍番号が混ざっていれば警告する
+/// 
+/// # 引数
+/// * `aggregate` - 合算結果
+/// * `style` - 表示の設定
+pub fn display_aggregate(aggregate: &AggregateResult, style: &Style) {
+    say!(style, "========================================");
+    say!(style, "【全試合集計】（{} ファイル）", aggregate.files);
+    say!(style, "========================================");
+    if let Some(interruption) = aggregate.interrupted {
+        say!(
+            style,
+            "※ 中断: {} 件中 {} 件のファイルを処理したところで中断しました（ここまでの合算です）",
+            interruption.total,
+            interruption.processed
+        );
+    }
+    say!(style, "");
+
+    say!(style, "【対象】");
+    say!(style, "  ファイル数: {}", aggregate.files);
+    say!(style, "  学籍番号  : {}", aggregate.student_ids.join(", "));
+    if aggregate.student_ids.len() > 1 {
+        say!(style, "  ※ 学籍番号の違うファイルが混ざっています（別の学生の試合を合算しています）");
+    }
+    // 同じ試合のログが複数あれば、どのファイルを使ったかを知らせる
+    for duplicate in &aggregate.duplicates {
+        say!(
+            style,
+            "  ※ 同じ試合（{} #{}）のログが複数あります: {}",
+            duplicate.student_id,
+            duplicate.match_number,
+            duplicate.files.join(", ")
+        );
+        if aggregate.dup_policy == DupPolicy::KeepAll {
+            say!(style, "     すべて合算しています（--dup-policy keep-all）");
+        } else {
+            say!(style, "     {} を使いました（--dup-policy {}）", duplicate.kept.join(", "), aggregate.dup_policy.key());
+        }
+    }
+    say!(style, "");
+
+    let counts = &aggregate.counts;
+    say!(style, "【行動回数（合計）】");
+    say!(style, "  攻撃   (Attack): {} 回（{:.1}%）", counts.attack_count, counts.attack_ratio());
+    say!(style, "  シールド(Shield): {} 回（{:.1}%）", counts.shield_count, counts.shield_ratio());
+    say!(style, "  回避   (Dodge) : {} 回（{:.1}%）", counts.dodge_count, counts.dodge_ratio());
+    if counts.unknown_count > 0 {
+        say!(style, "  不明  (Unknown): {} 回（{:.1}%）", counts.unknown_count, counts.unknown_ratio());
+    }
+    say!(style, "  合計           : {} 回", counts.total());
+    say!(style, "");
+
+    say!(style, "【試合ごとの比率】");
+    // 更新日時が1つも取れなかった（ファイルシステムが持っていない）ときは日時の列を出さない
+    let show_modified = aggregate.matches.iter().any(|m| m.modified.is_some());
+    let mut headers = vec!["攻撃%", "シールド%", "回避%", "行動数"];
+    if show_modified {
+        headers.push("日時（更新）");
+    }
+    if !style.stacked() {
+        // 日時は長さがそろっているので、数値の列だけ右揃えにして最後に左揃えで付ける
+        let header: Vec<String> = headers.iter().map(|h| pad_left(h, 10)).collect();
+        let header = if show_modified {
+            format!("{}  {}", header[..4].join(""), headers[4])
+        } else {
+            header.join("")
+        };
+        say!(style, "  {}{}", pad_right("試合", 16), header);
+    }
+    for m in &aggregate.matches {
+        let name = format!("{} #{}", m.player_info.student_id, m.player_info.match_number);
+        let modified = m.modified.map(datetime::display).unwrap_or_else(|| "不明".to_string());
+        let columns = [
+            format!("{:.1}", m.counts.attack_ratio()),
+            format!("{:.1}", m.counts.shield_ratio()),
+            format!("{:.1}", m.counts.dodge_ratio()),
+            m.counts.total().to_string(),
+        ];
+        if style.stacked() {
+            // 幅が狭いときは表をやめて、試合ごとに「項目: 値」を縦に並べる
+            say!(style, "  {}", name);
+            for (header, value) in headers.iter().zip(columns.iter().chain([&modified])) {
+                say!(style, "    {}: {}", pad_right(header, 12), value);
+            }
+        } else {
+            let columns: Vec<String> = columns.iter().map(|c| pad_left(c, 10)).collect();
+            let modified = if show_modified { format!("  {}", modified) } else { String::new() };
+            say!(style, "  {}{}{}", pad_right(&name, 16), columns.join(""), modified);
+        }
+    }
+    say!(style, "");
+
+    say!(style, "【行動IDごとの回数（合計）】");
+    if aggregate.action_id_counts.is_empty() {
+        say!(style, "  データがありません");
+    }
+    for (id, count) in &aggregate.action_id_counts {
+        let label = style.paint_id(id, &pad_right(id, 12));
+        say!(style, "  {}: {} 回", label, count);
+    }
+    say!(style, "========================================");
+}
+
+/// 学籍番号ごとの行動数のランキング（--jobs）を表示する
+///
+/// 何千人分あっても上位 `top` 人だけにし、残りは人数だけを出す
+///
+/// # 引数
+/// * `ranks` - 学籍番号ごとの合計（analyzer::rank_students の結果）
+/// * `top` - 表示する学籍番号の数（--top）
+/// * `style` - 表示のスタイル
+pub fn display_ranking(ranks: &[StudentRank], top: usize, style: &Style) {
+    say!(style, "【ランキング（行動数の多い順）】");
+    if ranks.is_empty() {
+        say!(style, "  データがありません");
+    }
+    for (i, rank) in ranks.iter().take(top).enumerate() {
+        say!(
+            style,
+            "  {:>3}位 {}  {} 試合  {} 回（攻撃 {:.1}%）",
+            i + 1,
+            pad_right(&rank.student_id, 12),
+            rank.matches,
+            rank.counts.total(),
+            rank.counts.attack_ratio()
+        );
+    }
+    if ranks.len() > top {
+        say!(style, "  …ほか {} 人", ranks.len() - top);
+    }
+    say!(style, "========================================");
+}
+
+/// 2つのログの行動IDごとの使用頻度の比較（--compare-actions）を表示する
+/// 
+/// 先に攻撃・シールド・回避の割合の差と有意差の目安を出し、そのあとに行動IDごとの表を出す。
+/// 期待度数が小さい行は「※」を付け、結果があてにならないことを最後に警告する
+/// 
+/// # 引数
+/// * `path_a` - 1つ目のログのパス
+/// * `path_b` - 2つ目のログのパス
+/// * `categories` - 行動タイプごとの比較
+/// * `comparisons` - 行動IDごとの比較
+/// * `rates` - 行動IDごとの1分あたりの回数の比較（--normalize per-minute のときだけ）
+pub fn display_action_comparison(
+    path_a: &str,
+    path_b: &str,
+    categories: &[CategoryComparison],
+    comparisons: &[ActionComparison],
+    rates: Option<&[RateComparison]>,
+) {
+    println!("========================================");
+    println!("行動の使用頻度の比較");
+    println!("========================================");
+    println!("  A: {}", path_a);
+    println!("  B: {}", path_b);
+    println!();
+
+    for line in category_comparison_lines(categories) {
+        println!("{}", line);
+    }
+    println!();
+
+    let headers = ["A回数(%)", "B回数(%)", "差(pt)", "z", "p", "判定"];
+    let header: Vec<String> = headers.iter().map(|h| pad_left(h, 12)).collect();
+    println!("  {}{}", pad_right("行動ID", 12), header.join(""));
+    for row in comparisons {
+        let (z, p, verdict) = match (row.z, row.p_value) {
+            (Some(z), Some(p)) => {
+                let verdict = if p < ActionComparison::SIGNIFICANCE { "差あり" } else { "差なし" };
+                (format!("{:+.2}", z), format!("{:.3}", p), verdict)
+            }
+            _ => ("N/A".to_string(), "N/A".to_string(), "-"),
+        };
+        let mark = if row.small_sample { "※" } else { "" };
+        let columns = [
+            format!("{}({:.1})", row.count_a, row.ratio_a * 100.0),
+            format!("{}({:.1})", row.count_b, row.ratio_b * 100.0),
+            format!("{:+.1}", (row.ratio_b - row.ratio_a) * 100.0),
+            z,
+            p,
+            format!("{}{}", mark, verdict),
+        ];
+        let columns: Vec<String> = columns.iter().map(|c| pad_left(c, 12)).collect();
+        println!("  {}{}", pad_right(&row.action_id, 12), columns.join(""));
+    }
+
+    if comparisons.iter().any(|row| row.small_sample) {
+        println!();
+        println!(
+            "  ※ 回数が少なく（期待度数{}未満）、正規近似によるp値はあてになりません",
+            ActionComparison::MIN_EXPECTED
+        );
+    }
+    println!("  （p < {} を「差あり」としています。簡易的なz検定による目安です）", ActionComparison::SIGNIFICANCE);
+    if let Some(rates) = rates {
+        println!();
+        for line in rate_comparison_lines(rates) {
+            println!("{}", line);
+        }
+    }
+    println!("========================================");
+}
+
+/// 行動IDごとの1分あたりの回数の比較を、表示する行にする（--normalize per-minute）
+///
+/// 1分あたりの回数のあとのカッコ内に、もとの回数を付ける（例: 「5.0(10)」）
+fn rate_comparison_lines(rates: &[RateComparison]) -> Vec<String> {
+    let mut lines = vec!["  1分あたりの回数（カッコ内は回数）".to_string()];
+    let headers = ["A/分(回数)", "B/分(回数)", "差/分", "多いのは"];
+    let header: Vec<String> = headers.iter().map(|h| pad_left(h, 12)).collect();
+    lines.push(format!("  {}{}", pad_right("行動ID", 12), header.join("")));
+    for rate in rates {
+        let more = match rate.per_minute_a.total_cmp(&rate.per_minute_b) {
+            std::cmp::Ordering::Greater => "A",
+            std::cmp::Ordering::Less => "B",
+            std::cmp::Ordering::Equal => "同じ",
+        };
+        let columns = [
+            format!("{:.1}({})", rate.per_minute_a, rate.count_a),
+            format!("{:.1}({})", rate.per_minute_b, rate.count_b),
+            format!("{:+.1}", rate.difference()),
+            more.to_string(),
+        ];
+        let columns: Vec<String> = columns.iter().map(|c| pad_left(c, 12)).collect();
+        lines.push(format!("  {}{}", pad_right(&rate.action_id, 12), columns.join("")));
+    }
+    lines
+}
+
+/// 行動タイプごとの割合の差と有意差の目安を、表示する行にする
+///
+/// 例: 「シールド   8.0% →  11.0%  (+3.0pt)  有意差なし (p≈0.47)」。
+/// どちらかの試合の行動数が少ないときは目安を出さず、その旨の注記を付ける
+fn category_comparison_lines(categories: &[CategoryComparison]) -> Vec<String> {
+    let mut lines = vec!["  行動タイプ別の割合（A → B）".to_string()];
+    for category in categories {
+        let hint = match (category.significant(), category.p_value) {
+            (Some(true), Some(p)) => format!("  有意差あり ({})", approx_p(p)),
+            (Some(false), Some(p)) => format!("  有意差なし ({})", approx_p(p)),
+            _ if category.suppressed => String::new(),
+            _ => "  判定できません".to_string(),
+        };
+        lines.push(format!(
+            "  {}{} → {}  ({:+.1}pt){}",
+            pad_right(category.action_type.label(), 10),
+            pad_left(&format!("{:.1}%", category.ratio_a * 100.0), 7),
+            pad_left(&format!("{:.1}%", category.ratio_b * 100.0), 7),
+            (category.ratio_b - category.ratio_a) * 100.0,
+            hint
+        ));
+    }
+    if categories.iter().any(|category| category.suppressed) {
+        lines.push(format!(
+            "  ※ どちらかの試合の行動数が{}未満のため、有意差の目安は出していません",
+            CategoryComparison::MIN_ACTIONS
+        ));
+    } else {
+        lines.push(format!(
+            "  （有意差は2標本の比率の差のz検定（p < {}）による近似的な目安です）",
+            ActionComparison::SIGNIFICANCE
+        ));
+    }
+    lines
+}
+
+/// p値を「p≈0.03」の形にする。小さすぎて0.00になるときは「p<0.01」にする
+fn approx_p(p: f64) -> String {
+    if p < 0.005 {
+        "p<0.01".to_string()
+    } else {
+        format!("p≈{:.2}", p)
+    }
+}
+
+/// 2つのログの使用頻度の比較（--compare-actions --json）を1行のJSONにする
+///
+/// z値・p値は近似なので approximate を true にする。計算できないときや、
+/// 行動数が少なくて目安を出さないとき（suppressed）はnull
+///
+/// # 引数
+/// * `path_a` - 1つ目のログのパス
+/// * `path_b` - 2つ目のログのパス
+/// * `categories` - 行動タイプごとの比較
+/// * `comparisons` - 行動IDごとの比較
+/// * `rates` - 行動IDごとの1分あたりの回数の比較。あれば normalize を "per-minute" にして per_minute に出す
+pub fn action_comparison_to_json(
+    path_a: &str,
+    path_b: &str,
+    categories: &[CategoryComparison],
+    comparisons: &[ActionComparison],
+    rates: Option<&[RateComparison]>,
+) -> String {
+    let optional = |value: Option<f64>| value.map(json::number).unwrap_or_else(|| "null".to_string());
+    let categories: Vec<String> = categories
+        .iter()
+        .map(|category| {
+            format!(
+                concat!(
+                    "{{\"category\":{},\"count_a\":{},\"count_b\":{},\"ratio_a\":{},\"ratio_b\":{},",
+                    "\"z\":{},\"p_value\":{},\"significant\":{},\"suppressed\":{}}}"
+                ),
+                json::string(category.action_type.key()),
+                category.count_a,
+                category.count_b,
+                json::number(category.ratio_a),
+                json::number(category.ratio_b),
+                optional(category.z),
+                optional(category.p_value),
+                category.significant().map(|s| s.to_string()).unwrap_or_else(|| "null".to_string()),
+                category.suppressed
+            )
+        })
+        .collect();
+    let actions: Vec<String> = comparisons
+        .iter()
+        .map(|row| {
+            format!(
+                concat!(
+                    "{{\"id\":{},\"count_a\":{},\"count_b\":{},\"ratio_a\":{},\"ratio_b\":{},",
+                    "\"z\":{},\"p_value\":{},\"small_sample\":{}}}"
+                ),
+                json::string(&row.action_id),
+                row.count_a,
+                row.count_b,
+                json::number(row.ratio_a),
+                json::number(row.ratio_b),
+                optional(row.z),
+                optional(row.p_value),
+                row.small_sample
+            )
+        })
+        .collect();
+    let (normalize, per_minute) = match rates {
+        Some(rates) => {
+            let rows: Vec<String> = rates
+                .iter()
+                .map(|rate| {
+                    format!(
+                        "{{\"id\":{},\"count_a\":{},\"count_b\":{},\"per_minute_a\":{},\"per_minute_b\":{},\"difference\":{}}}",
+                        json::string(&rate.action_id),
+                        rate.count_a,
+                        rate.count_b,
+                        json::number(rate.per_minute_a),
+                        json::number(rate.per_minute_b),
+                        json::number(rate.difference())
+                    )
+                })
+                .collect();
+            (Normalize::PerMinute, format!(",\"per_minute\":[{}]", rows.join(",")))
+        }
+        None => (Normalize::Raw, String::new()),
+    };
+    format!(
+        concat!(
+            "{{\"compare\":{{\"a\":{},\"b\":{},\"approximate\":true,\"significance\":{},",
+            "\"min_actions\":{},\"normalize\":{},\"categories\":[{}],\"actions\":[{}]{}}}}}"
+        ),
+        json::string(path_a),
+        json::string(path_b),
+        json::number(ActionComparison::SIGNIFICANCE),
+        CategoryComparison::MIN_ACTIONS,
+        json::string(normalize.key()),
+        categories.join(","),
+        actions.join(","),
+        per_minute
+    )
+}
+
+/// 改善レポート（--improvement）を表示する
+/// 
+/// 指標ごとの最初・最後の値と1回次あたりの傾きを表にし、そのあとに指標ごとの所見を1行ずつ出す
+/// 
+/// # 引数
+/// * `report` - 改善レポート
+pub fn display_improvement_report(report: &ImprovementReport) {
+    let matches: Vec<String> = report.match_numbers.iter().map(|n| n.to_string()).collect();
+    println!("========================================");
+    println!("改善レポート");
+    println!("========================================");
+    println!("  学籍番号: {}", report.student_id);
+    println!("  対戦回次: {} （{}試合）", matches.join(", "), report.match_numbers.len());
+    if report.order == MatchOrder::Mtime {
+        println!("  並べ方  : ファイルの更新日時の順（傾きは1日あたり）");
+        for (number, modified) in report.match_numbers.iter().zip(&report.modified) {
+            let modified = modified.map(datetime::display).unwrap_or_else(|| "不明".to_string());
+            println!("    回次 {:>3}: {}", number, modified);
+        }
+    }
+    println!();
+
+    let slope_header = match report.order {
+        MatchOrder::Match => "傾き/回",
+        MatchOrder::Mtime => "傾き/日",
+    };
+    let headers = ["最初", "最後", slope_header, "傾向"];
+    let header: Vec<String> = headers.iter().map(|h| pad_left(h, 12)).collect();
+    println!("  {}{}", pad_right("指標", 16), header.join(""));
+    for metric in &report.metrics {
+        let slope = match metric.slope {
+            Some(slope) => format!("{:+.2}", slope),
+            None => "N/A".to_string(),
+        };
+        let trend = metric.trend.map(|t| t.label()).unwrap_or("N/A");
+        let columns = [format!("{:.1}", metric.first), format!("{:.1}", metric.last), slope, trend.to_string()];
+        let columns: Vec<String> = columns.iter().map(|c| pad_left(c, 12)).collect();
+        println!("  {}{}", pad_right(&format!("{}({})", metric.label, metric.unit), 16), columns.join(""));
+    }
+    println!();
+
+    println!("【所見】");
+    for metric in &report.metrics {
+        match (metric.slope, metric.trend) {
+            (Some(slope), Some(trend)) => println!(
+                "  {}は {:.1}{} → {:.1}{}（1{}あたり {:+.2}）: {}傾向",
+                metric.label,
+                metric.first,
+                metric.unit,
+                metric.last,
+                metric.unit,
+                report.order.unit(),
+                slope,
+                trend.label()
+            ),
+            _ => println!("  {}は 試合が1回次分しかないため推移を判定できません", metric.label),
+        }
+    }
+    println!("========================================");
+}
+
+/// 改善レポートをJSON文字列にする
+pub fn improvement_to_json(report: &ImprovementReport) -> String {
+    let matches: Vec<String> = report.match_numbers.iter().map(|n| n.to_string()).collect();
+    let metrics: Vec<String> = report
+        .metrics
+        .iter()
+        .map(|metric| {
+            format!(
+                "{{\"metric\":{},\"first\":{},\"last\":{},\"slope\":{},\"trend\":{}}}",
+                json::string(metric.key),
+                json::number(metric.first),
+                json::number(metric.last),
+                metric.slope.map(json::number).unwrap_or_else(|| "null".to_string()),
+                metric.trend.map(|t| json::string(t.key())).unwrap_or_else(|| "null".to_string())
+            )
+        })
+        .collect();
+    // 更新日時はRFC 3339形式。取れなかった試合はnull
+    let modified: Vec<String> = report
+        .modified
+        .iter()
+        .map(|modified| modified.and_then(datetime::rfc3339).map(|t| json::string(&t)).unwrap_or_else(|| "null".to_string()))
+        .collect();
+    format!(
+        concat!(
+            "{{\"improvement\":{{\"student_id\":{},\"order\":{},\"match_numbers\":[{}],",
+            "\"file_modified\":[{}],\"metrics\":[{}]}}}}"
+        ),
+        json::string(&report.student_id),
+        json::string(report.order.key()),
+        matches.join(","),
+        modified.join(","),
+        metrics.join(",")
+    )
+}
+
+/// 合算結果を1行のJSONにする（複数のファイルを --json で解析したときの出力）
+///
+/// dedup には重複を見分けるキー（学籍番号と対戦回次）と --dup-policy の扱い、重複した試合を入れる
+///
+/// # 引数
+/// * `aggregate` - 合算結果
+pub fn aggregate_to_json(aggregate: &AggregateResult) -> String {
+    let student_ids: Vec<String> = aggregate.student_ids.iter().map(|id| json::string(id)).collect();
+    let strings = |items: &[String]| items.iter().map(|item| json::string(item)).collect::<Vec<_>>().join(",");
+    let duplicates: Vec<String> = aggregate
+        .duplicates
+        .iter()
+        .map(|duplicate| {
+            format!(
+                "{{\"student_id\":{},\"match_number\":{},\"files\":[{}],\"kept\":[{}]}}",
+                json::string(&duplicate.student_id),
+                duplicate.match_number,
+                strings(&duplicate.files),
+                strings(&duplicate.kept)
+            )
+        })
+        .collect();
+    let action_ids: Vec<String> = aggregate
+        .action_id_counts
+        .iter()
+        .map(|(id, count)| {
+            format!(
+                "{{\"id\":{},\"name\":{},\"count\":{}}}",
+                json::string(id),
+                json::string(&ActionType::get_action_name(id)),
+                count
+            )
+        })
+        .collect();
+    // 更新日時はRFC 3339形式。取れなかった試合はnull
+    let matches: Vec<String> = aggregate
+        .matches
+        .iter()
+        .map(|m| {
+            format!(
+                "{{\"student_id\":{},\"match_number\":{},\"file_modified\":{},\"total\":{}}}",
+                json::string(&m.player_info.student_id),
+                m.player_info.match_number,
+                m.modified.and_then(datetime::rfc3339).map(|t| json::string(&t)).unwrap_or_else(|| "null".to_string()),
+                m.counts.total()
+            )
+        })
+        .collect();
+    // 中断していなければnull
+    let interrupted = match aggregate.interrupted {
+        Some(interruption) => format!("{{\"processed\":{},\"total\":{}}}", interruption.processed, interruption.total),
+        None => "null".to_string(),
+    };
+    let counts = &aggregate.counts;
+    format!(
+        concat!(
+            "{{\"aggregate\":{{\"files\":{},\"interrupted\":{},\"student_ids\":[{}],",
+            "\"dedup\":{{\"key\":[\"student_id\",\"match_number\"],\"policy\":{},\"duplicates\":[{}]}},",
+            "\"counts\":{{\"attack\":{},\"shield\":{},\"dodge\":{},\"unknown\":{},\"total\":{}}},",
+            "\"action_id_counts\":[{}],\"matches\":[{}]}}}}"
+        ),
+        aggregate.files,
+        interrupted,
+        student_ids.join(","),
+        json::string(aggregate.dup_policy.key()),
+        duplicates.join(","),
+        counts.attack_count,
+        counts.shield_count,
+        counts.dodge_count,
+        counts.unknown_count,
+        counts.total(),
+        action_ids.join(","),
+        matches.join(",")
+    )
+}
+
+/// 統計CSV（--format csv / --excel）の列。この順で固定する
+///
+/// 先生が毎回同じExcelのテンプレートに貼り付けられるように、列を足すときは末尾に足す
+pub const CSV_COLUMNS: &[&str] = &[
+    "学籍番号",
+    "対戦回次",
+    "攻撃",
+    "シールド",
+    "回避",
+    "不明",
+    "合計",
+    "攻撃比率(%)",
+    "シールド比率(%)",
+    "回避比率(%)",
+    "不明比率(%)",
+    "試合時間(秒)",
+    "APM",
+    "コンボ数",
+    "最大コンボ長",
+    "最も多い行動ID",
+    "最も多い行動",
+    "最も多い行動の回数",
+];
+
+/// 統計CSVの小数点以下の桁数
+const CSV_DECIMALS: usize = 1;
+
+/// Excelが文字コードをUTF-8だと判断するための印（BOM）
+const UTF8_BOM: &str = "\u{FEFF}";
+
+/// 解析結果を統計CSVにする（1試合1行、見出し付き）
+///
+/// 数値はクォートせずにそのまま出す（Excelで数値として計算できるように）。
+/// 文字列は、カンマ・ダブルクォート・改行を含むときだけダブルクォートで囲む（--excel ならすべて囲む）。
+/// 値がないところ（試合時間が0秒のAPMなど）は空欄にする
+///
+/// # 引数
+/// * `results` - 解析結果（この順で1行ずつ出す）
+/// * `excel` - trueなら先頭にBOMを付け、改行をCRLFにし、見出しも含めて文字列をすべてダブルクォートで囲む
+///   （--excel。Excelで文字化けしたり、学籍番号が数値に変換されたりしないように）
+///
+/// # 戻り値
+/// CSVの文字列（最後の行も改行で終わる）
+pub fn to_csv(results: &[AnalysisResult], excel: bool) -> String {
+    let newline = if excel { "\r\n" } else { "\n" };
+    // 文字列の値の書き方（関数ポインタなので、行ごとに分岐しなくてよい）
+    let text: fn(&str) -> String = if excel { quoted_csv_field } else { csv_field };
+    let mut csv = String::new();
+    if excel {
+        csv.push_str(UTF8_BOM);
+    }
+    let header: Vec<String> = CSV_COLUMNS.iter().map(|column| text(column)).collect();
+    csv.push_str(&header.join(","));
+    csv.push_str(newline);
+    for result in results {
+        csv.push_str(&csv_row(result, text).join(","));
+        csv.push_str(newline);
+    }
+    csv
+}
+
+/// 解析結果1件を CSV_COLUMNS の順の値にする
+///
+/// # 引数
+/// * `result` - 解析結果
+/// * `text` - 文字列の値の書き方（csv_field か quoted_csv_field）
+fn csv_row(result: &AnalysisResult, text: fn(&str) -> String) -> Vec<String> {
+    let counts = &result.counts;
+    let decimal = |value: f64| format!("{:.*}", CSV_DECIMALS, value);
+    let (most_id, most_name, most_count) = match result.action_id_counts.first() {
+        Some((id, count)) => (text(id), text(&ActionType::get_action_name(id)), count.to_string()),
+        None => (String::new(), String::new(), String::new()),
+    };
+    let row = vec![
+        text(&result.player_info.student_id),
+        result.player_info.match_number.to_string(),
+        counts.attack_count.to_string(),
+        counts.shield_count.to_string(),
+        counts.dodge_count.to_string(),
+        counts.unknown_count.to_string(),
+        counts.total().to_string(),
+        decimal(counts.attack_ratio()),
+        decimal(counts.shield_ratio()),
+        decimal(counts.dodge_ratio()),
+        decimal(counts.unknown_ratio()),
+        decimal(result.time_stats.duration),
+        result.time_stats.apm.map(decimal).unwrap_or_default(),
+        result.combos.combo_count.to_string(),
+        result.combos.max_length.to_string(),
+        most_id,
+        most_name,
+        most_count,
+    ];
+    debug_assert_eq!(row.len(), CSV_COLUMNS.len());
+    row
+}
+
+/// 集計CSV（--out-csv）の列。この順で固定する
+///
+/// 表計算ソフトや他のツールで列を名前で参照しやすいように、列名は英語にする
+pub const SUMMARY_CSV_COLUMNS: &[&str] = &[
+    "student_id",
+    "match_number",
+    "attack_count",
+    "shield_count",
+    "dodge_count",
+    "attack_ratio",
+    "shield_ratio",
+    "dodge_ratio",
+    "top_action_id",
+    "top_action_count",
+    "burst_count",
+    "burst_share",
+    "burst_avg_length",
+];
+
+/// 解析結果を集計CSV（1試合1行、見出し付き）にして書き出す（--out-csv）
+///
+/// 比率は「63.2」のように%を付けない数値で書く（Excelが数値として読めるように）。
+/// 行動がなく最も多い行動IDがないときは、その2列を空欄にする。
+/// バーストの割合・平均の長さも、計算できない（行動・バーストがない）ときは空欄にする
+///
+/// # 引数
+/// * `results` - 解析結果（この順で1行ずつ書く）
+/// * `writer` - 書き出し先（ファイルなど）。最後にflushする
+///
+/// # 戻り値
+/// 書き出せたらOk、書き出し先のエラーはIoError
+pub fn write_summary_csv<W: Write>(results: &[AnalysisResult], mut writer: W) -> Result<()> {
+    writeln!(writer, "{}", SUMMARY_CSV_COLUMNS.join(","))?;
+    for result in results {
+        let counts = &result.counts;
+        let decimal = |value: f64| format!("{:.*}", CSV_DECIMALS, value);
+        let (top_id, top_count) = match result.action_id_counts.first() {
+            Some((id, count)) => (csv_field(id), count.to_string()),
+            None => (String::new(), String::new()),
+        };
+        let row = [
+            csv_field(&result.player_info.student_id),
+            result.player_info.match_number.to_string(),
+            counts.attack_count.to_string(),
+            counts.shield_count.to_string(),
+            counts.dodge_count.to_string(),
+            decimal(counts.attack_ratio()),
+            decimal(counts.shield_ratio()),
+            decimal(counts.dodge_ratio()),
+            top_id,
+            top_count,
+            result.bursts.bursts.len().to_string(),
+            result.bursts.share().map(decimal).unwrap_or_default(),
+            result.bursts.average_len().map(decimal).unwrap_or_default(),
+        ];
+        writeln!(writer, "{}", row.join(","))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// 文字列をCSVの1つの値にする（必要なときだけダブルクォートで囲む）
+///
+/// 読み込む側で前後の空白（全角空白なども含む）が取り除かれたり、先頭のBOMの文字が
+/// ファイルのBOMと間違えられたりしないように、そういう値も囲む
+fn csv_field(text: &str) -> String {
+    let padded = text.starts_with(char::is_whitespace) || text.ends_with(char::is_whitespace);
+    if padded || text.starts_with('\u{FEFF}') || text.contains([',', '"', '\r', '\n']) {
+        quoted_csv_field(text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// 文字列を、中身にかかわらずダブルクォートで囲んだCSVの1つの値にする（--excel）
+fn quoted_csv_field(text: &str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// 回数を最大値に対する割合で「#」の棒にする
+/// 
+/// どんな値でもパニックしないように範囲を抑える:
+/// - 回数が0、最大値が0、幅が0のときは空文字列
+/// - 0でない回数は最低1文字（小さすぎて見えなくならないように）
+/// - 回数が最大値を超えていても幅を超えない
+/// 
+/// # 引数
+/// * `count` - 回数
+/// * `max` - 棒の長さが `width` になる回数
+/// * `width` - 棒の最大の長さ（文字数）
+fn bar(count: u32, max: u32, width: usize) -> String {
+    if count == 0 || max == 0 || width == 0 {
+        return String::new();
+    }
+    let ratio = (count as f64 / max as f64).min(1.0);
+    let length = ((ratio * width as f64).round() as usize).clamp(1, width);
+    "#".repeat(length)
+}
+
+/// 同じ内容の警告で、行番号を並べる最大数（残りは件数だけ表示する）
+const MAX_WARNING_LINES: usize = 5;
+
+/// 警告を折り返す表示幅
+const WARNING_WRAP_WIDTH: usize = 76;
+
+/// --lenient で読み飛ばした行のうち、1行ずつ内容を表示する最大の行数
+const MAX_SKIPPED_DETAILS: usize = 10;
+
+/// 読み飛ばした行の内容を表示するときの最大の表示幅
+const SKIPPED_CONTENT_WIDTH: usize = 40;
+
+/// --lenient で読み飛ばした行を、件数と1行ずつの内容・理由でエラー出力に表示する
+/// 
+/// 解析結果より前に、-q や --json のときも表示する（標準出力は汚さない）。
+/// 読み飛ばした行がなければ何も表示しない
+/// 
+/// # 引数
+/// * `file_path` - 読み込んだファイルのパス
+/// * `skipped` - 読み飛ばした行（BattleLog::skipped_lines）
+pub fn display_skipped_lines(file_path: &str, skipped: &[SkippedLine]) {
+    if skipped.is_empty() {
+        return;
+    }
+    eprintln!("※ {}: {}行をスキップしました（--lenient）", file_path, skipped.len());
+    for line in skipped.iter().take(MAX_SKIPPED_DETAILS) {
+        eprintln!("  {}行目: {}  ← {}", line.line, truncate(&line.content, SKIPPED_CONTENT_WIDTH), line.reason);
+    }
+    if skipped.len() > MAX_SKIPPED_DETAILS {
+        eprintln!("  …他 {} 行", skipped.len() - MAX_SKIPPED_DETAILS);
+    }
+}
+
+/// 読み込み時の警告を、同じ内容ごとにまとめて表示する
+/// 
+/// 「12行目: 〜」のような行番号を除いた内容が同じ警告は1行にまとめ、件数と
+/// 最初の MAX_WARNING_LINES 個の行番号だけを表示する（例: ×214（3, 4, 5, 6, 7行目 …他 209 行））。
+/// 長い警告は WARNING_WRAP_WIDTH で折り返し、警告が2件以上あれば最後に合計を表示する。
+/// 
+/// # 引数
+/// * `warnings` - 警告の一覧（BattleLog::warnings）
+pub fn display_warnings(warnings: &[String]) {
+    for line in warning_lines(warnings) {
+        println!("{}", line);
+    }
+}
+
+/// 読み込み時の警告を、同じ内容ごとにまとめて表示する行にする（まとめ方は display_warnings を参照）
+fn warning_lines(warnings: &[String]) -> Vec<String> {
+    // 行番号を除いた内容が同じ警告のまとまり（最初に現れた順）
+    struct WarningGroup<'a> {
+        key: String,
+        first: &'a str,
+        lines: Vec<usize>,
+        count: usize,
+    }
+
+    let mut groups: Vec<WarningGroup> = Vec::new();
+    for warning in warnings {
+        let (key, line) = split_line_number(warning);
+        match groups.iter_mut().find(|group| group.key == key) {
+            Some(group) => {
+                group.count += 1;
+                group.lines.extend(line);
+            }
+            None => groups.push(WarningGroup {
+                key,
+                first: warning,
+                lines: line.into_iter().collect(),
+                count: 1,
+            }),
+        }
+    }
+
+    let mut lines = Vec::new();
+    for group in &groups {
+        let text = if group.count == 1 {
+            group.first.to_string()
+        } else if group.lines.is_empty() {
+            format!("{} ×{}", group.key, group.count)
+        } else {
+            let shown: Vec<String> = group.lines.iter().take(MAX_WARNING_LINES).map(|l| l.to_string()).collect();
+            let rest = group.lines.len().saturating_sub(MAX_WARNING_LINES);
+            let more = if rest > 0 { format!(" …他 {} 行", rest) } else { String::new() };
+            format!("{} ×{}（{}行目{}）", group.key, group.count, shown.join(", "), more)
+        };
+        for (i, line) in wrap_text(&text, WARNING_WRAP_WIDTH).iter().enumerate() {
+            let mark = if i == 0 { "※" } else { "  " };
+            lines.push(format!("  {} {}", mark, line));
+        }
+    }
+
+    if warnings.len() > 1 {
+        lines.push(format!("  警告 {} 種類, 合計 {} 件", groups.len(), warnings.len()));
+    }
+    lines
+}
+
+/// 警告から「N行目: 」の部分を取り除き、(残りの内容, 行番号) に分ける
+/// 
+/// 行番号がない警告はそのままの内容とNoneを返す
+fn split_line_number(warning: &str) -> (String, Option<usize>) {
+    const SUFFIX: &str = "行目: ";
+    if let Some(pos) = warning.find(SUFFIX) {
+        let before = &warning[..pos];
+        // 「行目」の直前に続く数字を行番号として取り出す
+        let digits_start = before.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        if let Ok(line) = before[digits_start..].parse::<usize>() {
+            let key = format!("{}{}", &before[..digits_start], &warning[pos + SUFFIX.len()..]);
+            return (key, Some(line));
+        }
+    }
+    (warning.to_string(), None)
+}
+
+/// 表示幅がwidthを超えないように文字列を折り返す（単語の区切りは考えない。絵文字や結合文字の途中では折り返さない）
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for grapheme in graphemes(text) {
+        if display_width(&current) + display_width(grapheme) > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        current.push_str(grapheme);
+    }
+    lines.push(current);
+    lines
+}
+
+/// 表示幅がwidthになるように右側を空白で埋める
+/// （format!の {:<N} は文字数で数えるので、日本語が入るとずれる）
+fn pad_right(text: &str, width: usize) -> String {
+    format!("{}{}", text, " ".repeat(width.saturating_sub(display_width(text))))
+}
+
+/// 表示幅がwidthになるように左側を空白で埋める
+fn pad_left(text: &str, width: usize) -> String {
+    format!("{}{}", " ".repeat(width.saturating_sub(display_width(text))), text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_field_quotes_values_a_reader_would_change() {
+        assert_eq!(csv_field("us"), "us");
+        assert_eq!(csv_field("👨\u{200d}👩\u{200d}👧"), "👨\u{200d}👩\u{200d}👧");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field(" us"), "\" us\"");
+        assert_eq!(csv_field("us\u{3000}"), "\"us\u{3000}\"");
+        assert_eq!(csv_field("\u{feff}us"), "\"\u{feff}us\"");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn unicode_fixture_csv_reads_back_with_the_same_columns() {
+        let battle_log = crate::parser::read_battle_log_from(
+            include_str!("../fixtures/unicode_stress.csv").as_bytes(),
+            &crate::parser::ParseLimits::unlimited(),
+        )
+        .unwrap();
+        let result = crate::analyzer::analyze(&battle_log, &crate::analyzer::AnalysisOptions::default());
+        let csv = to_csv(&[result], false);
+        let rows: Vec<Vec<String>> = csv.lines().map(crate::parser::split_csv_fields).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].len(), rows[1].len());
+        assert_eq!(rows[1][0], "🎮 שחקן\u{301}");
+    }
+
+    #[test]
+    fn bar_edge_cases() {
+        assert_eq!(bar(0, 10, 30), "");
+        assert_eq!(bar(5, 0, 30), "");
+        assert_eq!(bar(5, 10, 0), "");
+        assert_eq!(bar(10, 10, 30), "#".repeat(30));
+        assert_eq!(bar(5, 10, 30), "#".repeat(15));
+        assert_eq!(bar(1, u32::MAX, 30), "#");
+        assert_eq!(bar(u32::MAX, 1, 30), "#".repeat(30));
+        assert_eq!(bar(u32::MAX, u32::MAX, 200), "#".repeat(200));
+    }
+
+    #[test]
+    fn bar_properties_hold_for_many_inputs() {
+        // 外部クレートを使わないので、境目の値と小さな値の組み合わせを総当たりで確かめる
+        let values = [0, 1, 2, 3, 7, 10, 29, 30, 31, 100, 1_000_000, u32::MAX - 1, u32::MAX];
+        for &count in &values {
+            for &max in &values {
+                for width in [0, 1, 2, 5, 30, 80] {
+                    let drawn = bar(count, max, width);
+                    assert!(drawn.chars().all(|c| c == '#'));
+                    assert!(drawn.len() <= width, "bar({}, {}, {}) が幅を超えています", count, max, width);
+                    let visible = count > 0 && max > 0 && width > 0;
+                    assert_eq!(!drawn.is_empty(), visible, "bar({}, {}, {})", count, max, width);
+                    if count >= max && visible {
+                        assert_eq!(drawn.len(), width);
+                    }
+                    // 回数が増えても棒は短くならない
+                    if count < u32::MAX {
+                        assert!(bar(count + 1, max, width).len() >= drawn.len());
+                    }
+                }
+            }
+        }
+    }
+    /// 行動タイプの比較（suppressed でなければ与えたp値を使う）
+    fn category(action_type: ActionType, ratio_a: f64, ratio_b: f64, p_value: Option<f64>, suppressed: bool) -> CategoryComparison {
+        CategoryComparison {
+            action_type,
+            count_a: (ratio_a * 100.0) as u32,
+            count_b: (ratio_b * 100.0) as u32,
+            ratio_a,
+            ratio_b,
+            z: p_value.map(|_| 1.0),
+            p_value,
+            suppressed,
+        }
+    }
+
+    #[test]
+    fn category_lines_label_the_hint_as_approximate() {
+        let lines = category_comparison_lines(&[
+            category(ActionType::Shield, 0.08, 0.11, Some(0.4694), false),
+            category(ActionType::Attack, 0.50, 0.65, Some(0.0319), false),
+            category(ActionType::Dodge, 0.0, 0.0, None, false),
+        ]);
+        assert_eq!(lines[1], "  シールド     8.0% →   11.0%  (+3.0pt)  有意差なし (p≈0.47)");
+        assert_eq!(lines[2], "  攻撃        50.0% →   65.0%  (+15.0pt)  有意差あり (p≈0.03)");
+        assert!(lines[3].ends_with("判定できません"));
+        assert!(lines[4].contains("近似"));
+    }
+
+    #[test]
+    fn category_lines_suppress_the_hint_with_a_note() {
+        let lines = category_comparison_lines(&[category(ActionType::Shield, 0.08, 0.11, None, true)]);
+        assert!(lines[1].ends_with("(+3.0pt)"));
+        assert!(lines[2].contains(&format!("{}未満", CategoryComparison::MIN_ACTIONS)));
+    }
+
+    #[test]
+    fn approx_p_rounds_to_two_decimals() {
+        assert_eq!(approx_p(0.0319), "p≈0.03");
+        assert_eq!(approx_p(0.05), "p≈0.05");
+        assert_eq!(approx_p(0.0009), "p<0.01");
+    }
+
+    #[test]
+    fn action_comparison_json_includes_p_values() {
+        let comparison = ActionComparison {
+            action_id: "s".to_string(),
+            count_a: 8,
+            count_b: 11,
+            ratio_a: 0.08,
+            ratio_b: 0.11,
+            z: None,
+            p_value: None,
+            small_sample: true,
+        };
+        let text = action_comparison_to_json(
+            "a.csv",
+            "b.csv",
+            &[category(ActionType::Shield, 0.5, 0.25, Some(0.5), false), category(ActionType::Dodge, 0.0, 0.0, None, true)],
+            &[comparison],
+            None,
+        );
+        assert!(text.starts_with(r#"{"compare":{"a":"a.csv","b":"b.csv","approximate":true,"significance":0.05,"min_actions":30,"normalize":"raw","#));
+        assert!(!text.contains("per_minute"));
+        assert!(text.contains(r#""category":"shield","count_a":50,"count_b":25,"ratio_a":0.5,"ratio_b":0.25,"z":1,"p_value":0.5,"significant":false,"suppressed":false"#));
+        assert!(text.contains(r#""category":"dodge","count_a":0,"count_b":0,"ratio_a":0,"ratio_b":0,"z":null,"p_value":null,"significant":null,"suppressed":true"#));
+        assert!(text.contains(r#""id":"s","count_a":8,"count_b":11,"ratio_a":0.08,"ratio_b":0.11,"z":null,"p_value":null,"small_sample":true"#));
+    }
+    /// (時刻, 行動ID) の並びを解析する
+    fn analyzed(actions: &[(f64, &str)]) -> AnalysisResult {
+        let actions = actions.iter().map(|&(timestamp, id)| crate::model::Action::new(timestamp, id.to_string())).collect();
+        let battle_log = crate::model::BattleLog::new(crate::model::PlayerInfo::new("b1022024".to_string(), 3), actions);
+        crate::analyzer::analyze(&battle_log, &crate::analyzer::AnalysisOptions::default())
+    }
+
+    #[test]
+    fn to_json_keeps_an_empty_action_id_array() {
+        let json = to_json(&analyzed(&[]));
+        assert!(json.starts_with('{') && json.ends_with('}'), "{}", json);
+        assert!(json.contains(r#""player_info":{"student_id":"b1022024","match_number":3}"#), "{}", json);
+        assert!(json.contains(r#""action_id_counts":[],"#), "{}", json);
+        assert!(json.contains(r#""total":0,"#), "{}", json);
+        assert!(!json.contains('\n'));
+    }
+
+    #[test]
+    fn to_json_emits_ratios_as_numbers() {
+        let json = to_json(&analyzed(&[(1.0, "us"), (2.0, "s"), (3.0, "us"), (4.0, "nd")]));
+        let decimals = "0".repeat(JSON_DECIMALS);
+        for (key, value) in [("attack_ratio", "50"), ("shield_ratio", "25"), ("dodge_ratio", "25"), ("unknown_ratio", "0")] {
+            let field = format!("\"{}\":{}.{}", key, value, decimals);
+            assert!(json.contains(&field), "{} がありません: {}", field, json);
+        }
+        assert!(!json.contains(r#"_ratio":""#), "{}", json);
+        assert!(json.contains(r#""action_id_counts":[{"id":"us","name":"上スマ","count":2},"#), "{}", json);
+    }
+
+    #[test]
+    fn identical_warnings_collapse_to_one_counted_line() {
+        let warnings: Vec<String> = (2..1002).map(|line| format!("{}行目: 行動IDの前後に空白があります", line)).collect();
+        let lines = warning_lines(&warnings);
+        assert_eq!(
+            lines,
+            vec![
+                "  ※ 行動IDの前後に空白があります ×1000（2, 3, 4, 5, 6行目 …他 995 行）".to_string(),
+                "  警告 1 種類, 合計 1000 件".to_string(),
+            ]
+        );
+        // 行番号のない警告も件数だけでまとめる。1件だけならそのまま
+        let plain = vec!["時刻が逆行しています".to_string(); 3];
+        assert_eq!(warning_lines(&plain)[0], "  ※ 時刻が逆行しています ×3");
+        assert_eq!(warning_lines(&warnings[..1]), vec!["  ※ 2行目: 行動IDの前後に空白があります".to_string()]);
+    }
+
+    #[test]
+    fn report_json_keeps_every_warning() {
+        let warnings: Vec<String> = (2..1002).map(|line| format!("{}行目: 行動IDの前後に空白があります", line)).collect();
+        let result = analyzed(&[(1.0, "us")]);
+        let json = report_to_json(&ReportData { result: &result, warnings: &warnings, previous: None });
+        let list = json.split_once(",\"warnings\":[").unwrap().1;
+        assert_eq!(list.matches("行動IDの前後に空白があります").count(), 1000);
+        assert!(list.starts_with("\"2行目: 行動IDの前後に空白があります\","), "{}", list);
+        assert!(list.ends_with("\"1001行目: 行動IDの前後に空白があります\"]}"), "{}", list);
+    }
+
+    #[test]
+    fn excel_csv_starts_with_a_bom_and_ends_lines_with_crlf() {
+        let results = [analyzed(&[(1.0, "us"), (2.0, "s")]), analyzed(&[])];
+        let bytes = to_csv(&results, true).into_bytes();
+        assert_eq!(&bytes[..3], [0xEF, 0xBB, 0xBF]);
+        // 3行（見出しと2試合）がすべて CRLF で終わり、CRを伴わないLFはない
+        assert_eq!(bytes.windows(2).filter(|pair| pair == b"\r\n").count(), 3);
+        assert_eq!(bytes.iter().filter(|&&b| b == b'\n').count(), 3);
+        assert!(bytes.ends_with(b"\r\n"));
+
+        let plain = to_csv(&results, false).into_bytes();
+        assert!(plain.starts_with("学籍番号,".as_bytes()));
+        assert!(!plain.contains(&b'\r'));
+        assert_eq!(plain.iter().filter(|&&b| b == b'\n').count(), 3);
+    }
+
+    #[test]
+    fn excel_csv_quotes_every_text_field() {
+        let result = analyzed(&[(1.0, "us"), (2.0, "us"), (3.0, "s")]);
+        let csv = to_csv(std::slice::from_ref(&result), true);
+        let lines: Vec<&str> = csv.trim_start_matches(UTF8_BOM).split("\r\n").collect();
+        let header: Vec<String> = CSV_COLUMNS.iter().map(|column| format!("\"{}\"", column)).collect();
+        assert_eq!(lines[0], header.join(","));
+        let row: Vec<&str> = lines[1].split(',').collect();
+        assert_eq!(row[0], "\"b1022024\"");
+        assert_eq!(row[1], "3");
+        assert_eq!(row[2], "2");
+        assert_eq!(&row[15..], ["\"us\"", "\"上スマ\"", "2"]);
+        // 既定ではクォートが必要な値だけ囲む
+        assert!(to_csv(&[result], false).contains("\nb1022024,3,2,"));
+        // 値がない列は Excel でも空欄のまま
+        assert!(to_csv(&[analyzed(&[])], true).ends_with(",0,0,,,\r\n"));
+    }
+
+    fn rate(action_id: &str, count_a: u32, count_b: u32, per_minute_a: f64, per_minute_b: f64) -> RateComparison {
+        RateComparison { action_id: action_id.to_string(), count_a, count_b, per_minute_a, per_minute_b }
+    }
+
+    #[test]
+    fn rate_lines_show_per_minute_values_with_raw_counts() {
+        let lines = rate_comparison_lines(&[rate("us", 10, 20, 5.0, 20.0 / 6.0), rate("s", 1, 1, 0.5, 0.5)]);
+        assert_eq!(lines[0], "  1分あたりの回数（カッコ内は回数）");
+        assert_eq!(lines[2], format!("  {}{}{}{}{}", pad_right("us", 12), pad_left("5.0(10)", 12), pad_left("3.3(20)", 12), pad_left("-1.7", 12), pad_left("A", 12)));
+        assert!(lines[3].ends_with(&pad_left("同じ", 12)), "{}", lines[3]);
+    }
+
+    #[test]
+    fn action_comparison_json_marks_per_minute_rates() {
+        let text = action_comparison_to_json("a.csv", "b.csv", &[], &[], Some(&[rate("us", 10, 20, 5.0, 2.5)]));
+        assert!(text.contains(r#""normalize":"per-minute","categories":[],"actions":[],"per_minute":[{"id":"us","count_a":10,"count_b":20,"per_minute_a":5,"per_minute_b":2.5,"difference":-2.5}]}}"#), "{}", text);
+    }
+
+    #[test]
+    fn every_listed_section_appears_in_the_report_json() {
+        let mut result = analyzed(&[(1.0, "us"), (2.0, "s")]);
+        result.modified = Some(std::time::SystemTime::UNIX_EPOCH);
+        let warnings = vec!["2行目: 警告".to_string()];
+        let previous = Some(result.counts);
+        let json = report_to_json(&ReportData { result: &result, warnings: &warnings, previous });
+        for (key, _) in schema::sections() {
+            assert!(json.contains(&format!("\"{}\":", key)), "{} がありません: {}", key, json);
+        }
+    }
+
+    /// テスト用の書き出し先。Sink に渡した後も中身を読めるように Vec<u8> を共有する
+    #[derive(Clone, Default)]
+    struct SharedBuf(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 幅を指定して、-vv の表示を文字列にする
+    fn render(width: Option<usize>, draw: impl FnOnce(&DisplayOptions)) -> String {
+        let buf = SharedBuf::default();
+        let style = Style::new(crate::style::ColorChoice::Never, crate::style::ColorRules::default())
+            .with_width(width)
+            .with_sink(crate::style::Sink::new(buf.clone()), crate::style::ColorChoice::Never);
+        let display = DisplayOptions { verbosity: Verbosity::Full, style, ascii: true, sort_categories: false, narrate: false };
+        draw(&display);
+        display.style.sink.as_ref().unwrap().finish().unwrap();
+        let bytes = buf.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    /// 幅を指定して、-vv のレポートを文字列にする
+    fn rendered(result: &AnalysisResult, width: Option<usize>) -> String {
+        render(width, |display| display_result(result, display))
+    }
+
+    #[test]
+    fn narrow_width_keeps_every_report_line_inside_it() {
+        let actions: Vec<(f64, &str)> = (0..40)
+            .map(|i| (i as f64 * 1.5, ["us", "s", "fa", "nd", "j1", "ub_a", "s", "dth"][i % 8]))
+            .collect();
+        let result = analyzed(&actions);
+        let text = rendered(&result, Some(30));
+        for line in text.lines() {
+            assert!(display_width(line) <= 30, "30桁を超えています: {:?}", line);
+        }
+        // 幅を指定しなければ、同じ内容でもっと長い行がある
+        assert!(rendered(&result, None).lines().any(|line| display_width(line) > 30));
+    }
+
+    #[test]
+    fn to_json_writes_file_modified_only_when_known() {
+        let mut result = analyzed(&[(1.0, "us")]);
+        assert!(!to_json(&result).contains("file_modified"));
+        result.modified = Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_766_327_945));
+        assert!(to_json(&result).contains(r#""match_number":3},"file_modified":"2025-12-21T14:39:05Z","counts""#));
+    }
+
+    #[test]
+    fn to_json_writes_momentum_only_with_half_counts() {
+        let actions = [(0.0, "us"), (1.0, "us"), (3.9, "s"), (4.0, "s"), (8.0, "s")];
+        let mut result = analyzed(&actions);
+        assert!(to_json(&result).contains(r#""momentum":null,"#));
+
+        let options = crate::analyzer::AnalysisOptions { momentum: true, ..Default::default() };
+        let battle_log = crate::model::BattleLog::new(
+            crate::model::PlayerInfo::new("b1022024".to_string(), 3),
+            actions.iter().map(|&(t, id)| crate::model::Action::new(t, id.to_string())).collect(),
+        );
+        result = crate::analyzer::analyze(&battle_log, &options);
+        let json = to_json(&result);
+        assert!(json.contains(r#""momentum":{"split":4.000,"first_total":3,"second_total":2,"categories":[{"category":"attack","first":2,"second":0,"momentum":"falling"},"#), "{}", json);
+        // 行動IDは action_id_counts と同じ（回数の多い）順
+        assert!(json.contains(r#""ids":[{"id":"s","first":1,"second":2,"momentum":"rising"},{"id":"us","first":2,"second":0,"momentum":"falling"}]}"#), "{}", json);
+    }
+
+    #[test]
+    fn unknown_ids_list_their_first_lines() {
+        let mut result = analyzed(&[(1.0, "us"), (2.0, "jump"), (3.0, "ssss")]);
+        result.unknown_id_lines.insert("jump".to_string(), vec![3]);
+        result.unknown_id_lines.insert("ssss".to_string(), (4..=10).collect());
+        let text = rendered(&result, None);
+        assert!(text.contains("【未知の行動ID】"), "{}", text);
+        assert!(text.contains("  jump        : 1 回（3行目）\n"), "{}", text);
+        // 最初の MAX_WARNING_LINES 個だけを表示し、残りは数だけ知らせる
+        assert!(text.contains("  ssss        : 1 回（4, 5, 6, 7, 8行目 …他 2 行）\n"), "{}", text);
+    }
+
+    #[test]
+    fn report_json_adds_optional_keys_only_when_present() {
+        let result = analyzed(&[(1.0, "us"), (2.0, "s")]);
+        let bare = ReportData { result: &result, warnings: &[], previous: None };
+        assert_eq!(report_to_json(&bare), to_json(&result));
+
+        let previous = ActionCounts { attack_count: 3, shield_count: 1, dodge_count: 0, unknown_count: 2 };
+        let warnings = ["2行目: 行動IDの前後に空白があります".to_string()];
+        let full = report_to_json(&ReportData { result: &result, warnings: &warnings, previous: Some(previous) });
+        let fields = to_json(&result);
+        assert_eq!(
+            full,
+            format!(
+                r#"{},"warnings":["2行目: 行動IDの前後に空白があります"],"previous_counts":{{"attack":3,"shield":1,"dodge":0,"unknown":2,"total":6}}}}"#,
+                &fields[..fields.len() - 1]
+            )
+        );
+    }
+
+    #[test]
+    fn text_report_compares_with_the_previous_run_only_when_known() {
+        let result = analyzed(&[(1.0, "us"), (2.0, "s")]);
+        let text = |previous| {
+            let report = ReportData { result: &result, warnings: &[], previous };
+            render(None, |display| display_report(&report, display))
+        };
+        let without = text(None);
+        assert_eq!(without, rendered(&result, None));
+        let with = text(Some(ActionCounts { attack_count: 3, shield_count: 1, dodge_count: 0, unknown_count: 0 }));
+        assert!(with.starts_with(&without));
+        assert!(with[without.len()..].contains("【前回との比較】"), "{}", with);
+    }
+
+    #[test]
+    fn shield_cover_section_says_na_without_a_shield() {
+        let text = rendered(&analyzed(&[(0.0, "s"), (1.0, "us")]), None);
+        assert!(text.contains("【攻撃後のシールド】\n  N/A（攻撃の後のシールドがありません）\n"), "{}", text);
+        let text = rendered(&analyzed(&[(0.0, "us"), (0.5, "s"), (1.0, "us")]), None);
+        assert!(text.contains("  次のシールドまで: 平均 0.50秒 / 中央値 0.50秒\n"), "{}", text);
+        assert!(text.contains("秒以内にシールド: 50.0%（1 / 2 回の攻撃）\n"), "{}", text);
+    }
+
+    #[test]
+    fn summary_csv_writes_one_row_per_match() {
+        let mut quoted = analyzed(&[(1.0, "us"), (2.0, "us"), (3.0, "s")]);
+        quoted.player_info.student_id = "b1,\"x\"".to_string();
+        let results = [quoted, analyzed(&[])];
+        let mut bytes = Vec::new();
+        write_summary_csv(&results, &mut bytes).unwrap();
+        let text = String::from_utf8(bytes).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], SUMMARY_CSV_COLUMNS.join(","));
+        // 比率は%を付けない数値。学籍番号は統計CSVと同じ規則で囲む
+        assert_eq!(lines[1], "\"b1,\"\"x\"\"\",3,2,1,0,66.7,33.3,0.0,us,2,0,0.0,");
+        // 行動のない試合は、最も多い行動IDとバーストの割合・平均を空欄にする
+        assert_eq!(lines[2], "b1022024,3,0,0,0,0.0,0.0,0.0,,,0,,");
+        assert_eq!(lines.len(), 3);
+        assert!(text.ends_with('\n'));
+    }
+}
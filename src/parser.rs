@@ -3,17 +3,94 @@
 //
 // CSVファイルをBattleLog構造体に変換する
 
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
+use std::sync::Arc;
 
-use crate::error::{Result, SmasherError};
-use crate::model::{Action, BattleLog, PlayerInfo};
+use crate::error::{FileKind, InputLimit, ParseReason, Result, SmasherError};
+use crate::model::{Action, ActionType, BattleLog, Classifier, DefaultClassifier, IdLines, PhaseMarker, PlayerInfo, SkippedLine};
+use crate::sniff::{self, Encoding, Sniffed, SNIFF_LEN};
+
+/// 標準入力から読むときに指定するファイルパス（`my_logger | smasher -`）
+pub const STDIN_PATH: &str = "-";
+
+/// タイムスタンプが前の行より小さい行（順番が入れ替わった行）の扱い
+///
+/// 同じタイムスタンプが続くのは問題ない（同じフレームに2つの行動をすることはある）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RowOrder {
+    /// エラーにする（既定）
+    #[default]
+    Strict,
+    /// 時刻順に並べ替え、並べ替えた行数を警告する（--sort）
+    Sort,
+}
+
+/// 対戦ログの読み方の設定
+///
+/// Default は通常の読み方（順番の入れ替わりと不正な行はエラー、文字コードは自動判定）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReadOptions {
+    /// タイムスタンプが前の行より小さい行の扱い（--sort）
+    pub order: RowOrder,
+    /// 文字コードの指定（--encoding）
+    pub encoding: Encoding,
+    /// trueなら各行動に元の行の文字列（Action::raw）も持たせる（--keep-raw）
+    pub keep_raw: bool,
+    /// trueなら解析できない行動データの行をエラーにせず読み飛ばし、BattleLog::skipped_lines に記録する（--lenient）
+    pub lenient: bool,
+}
+
+/// 1回の読み込みの間だけ使う解析コンテキスト
+/// 
+/// 同じ数十種類の行動IDが何十万回も出てくるので、
+/// IDごとの分類結果と共有用の文字列をメモして、分類と文字列の確保はIDの種類ごとに1回だけ行う
+pub struct ParseContext {
+    /// 行動ID → (分類結果, 共有するID文字列)
+    memo: HashMap<Box<str>, (ActionType, Arc<str>)>,
+    /// 行動IDの分類方法
+    classifier: Box<dyn Classifier>,
+}
+
+// Default: ライブラリとして使うときに ParseContext::default() でも作れるようにする（new() と同じ）
+impl Default for ParseContext {
+    fn default() -> Self {
+        ParseContext::new()
+    }
+}
+
+impl ParseContext {
+    /// 空のメモと標準の分類で初期化
+    pub fn new() -> Self {
+        ParseContext::with_classifier(Box::new(DefaultClassifier))
+    }
+    
+    /// 分類の方法を指定して初期化
+    pub fn with_classifier(classifier: Box<dyn Classifier>) -> Self {
+        ParseContext {
+            memo: HashMap::new(),
+            classifier,
+        }
+    }
+    
+    /// 行動IDの分類と共有用の文字列を返す（初めてのIDなら分類してメモする）
+    pub fn resolve(&mut self, action_id: &str) -> (ActionType, Arc<str>) {
+        if let Some((action_type, shared_id)) = self.memo.get(action_id) {
+            // Arc::cloneは参照カウントを増やすだけで文字列はコピーしない
+            return (action_type.clone(), Arc::clone(shared_id));
+        }
+        let resolved = (self.classifier.classify(action_id), Arc::from(action_id));
+        self.memo.insert(action_id.into(), resolved.clone());
+        resolved
+    }
+}
 
 /// CSVファイルから対戦ログを読み込む
 /// 
 /// # ファイル形式
-/// ```
+/// ```text
 /// b1022024,1
 /// 1.04,us
 /// 1.64,ss
@@ -31,62 +108,330 @@ use crate::model::{Action, BattleLog, PlayerInfo};
 /// - 形式が不正
 /// - データの解析に失敗
 pub fn read_battle_log<P: AsRef<Path>>(file_path: P) -> Result<BattleLog> {
+    read_battle_log_with(file_path, RowOrder::Strict)
+}
+
+/// CSVファイルから対戦ログを読み込む（順番が入れ替わった行の扱いを指定する）
+/// 
+/// # 引数
+/// * `file_path` - CSVファイルのパス
+/// * `order` - タイムスタンプが前の行より小さい行の扱い
+/// 
+/// # 戻り値
+/// 読み込んだBattleLog、またはエラー
+pub fn read_battle_log_with<P: AsRef<Path>>(file_path: P, order: RowOrder) -> Result<BattleLog> {
+    read_battle_log_as(file_path, &ReadOptions { order, ..ReadOptions::default() })
+}
+
+/// CSVファイルから対戦ログを読み込む（読み方を指定する）
+/// 
+/// 読み込む前にファイルの先頭を見て、Excelのブック・ZIP・PDFならその種類のエラーにする。
+/// UTF-16のテキストは、BOMがあるか `options.encoding` が Utf16 ならUTF-8に変換して読み込み、警告を追加する
+/// 
+/// # 引数
+/// * `file_path` - CSVファイルのパス
+/// * `options` - 読み方の設定（順番が入れ替わった行の扱い、文字コードなど）
+/// 
+/// # 戻り値
+/// 読み込んだBattleLog、またはエラー
+pub fn read_battle_log_as<P: AsRef<Path>>(file_path: P, options: &ReadOptions) -> Result<BattleLog> {
     // ファイルを開く
     let file = File::open(file_path)?;
-    let reader = BufReader::new(file);
+    // 更新日時を持たないファイルシステムもあるので、取れなくてもエラーにしない
+    let modified = file.metadata().and_then(|metadata| metadata.modified()).ok();
+    
+    let mut battle_log = read_battle_log_from_reader(BufReader::new(file), options)?;
+    battle_log.modified = modified;
+    Ok(battle_log)
+}
+
+/// 読み込み元（標準入力など、ファイル以外も可）から対戦ログを読み込む
+/// 
+/// ファイルから読むときもこの関数を通るので、エラー（空のファイル、1行目の形式、
+/// 行番号付きの行動データのエラー）やファイルの種類の判定は、どの読み込み元でも同じになる。
+/// 読み込み元は先頭から1回だけ読む（読み直さない）ので、パイプからでも読める
+/// 
+/// # 引数
+/// * `reader` - 読み込み元（BufReader<File>、標準入力のロックなど）
+/// * `options` - 読み方の設定（順番が入れ替わった行の扱い、文字コードなど）
+/// 
+/// # 戻り値
+/// 読み込んだBattleLog（更新日時はNone）、またはエラー
+pub fn read_battle_log_from_reader<R: BufRead>(mut reader: R, options: &ReadOptions) -> Result<BattleLog> {
+    // fill_buf: 読み進めずに先頭のバイトを見る
+    let head = reader.fill_buf()?;
+    let head = &head[..head.len().min(SNIFF_LEN)];
+    match sniff::sniff(head, options.encoding) {
+        Ok(Sniffed::Utf8 { bom_len }) => {
+            reader.consume(bom_len);
+            read_with_limits(reader, &ParseLimits::unlimited(), options)
+        }
+        Ok(Sniffed::Utf16 { big_endian, bom_len }) => {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let text = sniff::decode_utf16(&bytes[bom_len..], big_endian)?;
+            let mut battle_log = read_with_limits(text.as_bytes(), &ParseLimits::unlimited(), options)?;
+            battle_log.warnings.push("UTF-16のファイルをUTF-8に変換して読み込みました".to_string());
+            Ok(battle_log)
+        }
+        // ZIPなら中身の一覧を読んで、Excelのブックか、中にどのCSVがあるかを知らせる
+        Err(SmasherError::WrongFileType { kind: FileKind::Zip { .. } }) => {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let kind = sniff::classify_zip(&sniff::zip_member_names(&bytes));
+            Err(SmasherError::WrongFileType { kind })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// 読み込み元（ファイル以外も可）から、大きさの上限を守って対戦ログを読み込む
+/// 
+/// # 引数
+/// * `reader` - 読み込み元（BufReader<File>、標準入力など）
+/// * `limits` - 読み込むデータの大きさの上限
+/// 
+/// # 戻り値
+/// 読み込んだBattleLog、またはエラー（上限を超えたらTooLarge）
+pub fn read_battle_log_from<R: BufRead>(reader: R, limits: &ParseLimits) -> Result<BattleLog> {
+    read_with_limits(reader, limits, &ReadOptions::default())
+}
+
+/// 信頼できない入力（アップロードされたファイルなど）から対戦ログを読み込む
+/// 
+/// 大きさの上限を必ず守ったうえで、不正な行動データの行はエラーにせず読み飛ばす。
+/// 読み飛ばした行の警告は MAX_UNTRUSTED_WARNINGS 件までにし、残りは件数だけを報告する。
+/// 1行目（学籍番号,対戦回次）が不正な場合と上限を超えた場合はエラーにする。
+/// 
+/// # 引数
+/// * `reader` - 読み込み元
+/// * `limits` - 読み込むデータの大きさの上限
+/// 
+/// # 戻り値
+/// 読み込んだBattleLog、またはエラー
+pub fn parse_untrusted<R: BufRead>(reader: R, limits: &ParseLimits) -> Result<BattleLog> {
+    let mut battle_log = read_with_limits(reader, limits, &ReadOptions { lenient: true, ..ReadOptions::default() })?;
+    
+    let extra = battle_log.skipped_lines.len().saturating_sub(MAX_UNTRUSTED_WARNINGS);
+    let skipped: Vec<String> = battle_log
+        .skipped_lines
+        .iter()
+        .take(MAX_UNTRUSTED_WARNINGS)
+        .map(|skipped| format!("読み飛ばしました: 行動データ形式エラー: {}行目: {}", skipped.line, skipped.reason))
+        .collect();
+    battle_log.warnings.extend(skipped);
+    if extra > 0 {
+        battle_log.warnings.push(format!("ほかに {} 行の不正な行を読み飛ばしました", extra));
+    }
+    Ok(battle_log)
+}
+
+/// parse_untrustedで個別に報告する警告の最大件数
+const MAX_UNTRUSTED_WARNINGS: usize = 20;
+
+/// 読み込むデータの大きさの上限
+#[derive(Debug, Clone, Copy)]
+pub struct ParseLimits {
+    /// 入力全体の最大バイト数
+    pub max_bytes: usize,
+    /// 行動データ（2行目以降の空でない行）の最大行数
+    pub max_rows: usize,
+    /// 1行の最大バイト数（改行文字を除く）
+    pub max_line_len: usize,
+}
+
+impl ParseLimits {
+    /// 上限なし（ローカルのファイルを読むとき用）
+    pub fn unlimited() -> Self {
+        ParseLimits {
+            max_bytes: usize::MAX,
+            max_rows: usize::MAX,
+            max_line_len: usize::MAX,
+        }
+    }
+}
+
+/// 上限を守りながら1行ずつ読むイテレータ
+/// 
+/// BufRead::lines() は1行全体をメモリに読み込んでしまうので、
+/// 巨大な1行が来ても上限+数バイトまでしか読まないように自前で読む
+struct LimitedLines<R> {
+    reader: R,
+    limits: ParseLimits,
+    /// ここまでに読んだバイト数
+    bytes_read: usize,
+    /// ここまでに読んだ行数
+    line_number: usize,
+    /// 最後に読んだ行が改行で終わっていなかったか（ファイルの最後の行で改行がない場合）
+    unterminated: bool,
+}
+
+impl<R: BufRead> Iterator for LimitedLines<R> {
+    type Item = Result<String>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        let too_large = |limit, max, line| Some(Err(SmasherError::TooLarge { limit, max, line }));
+        
+        // 1行の上限 + 改行（\r\n）+ 1バイトまで読めば、上限を超えたかどうかが分かる
+        // 全体の上限も、残り + 1バイトまで読めば超えたかどうかが分かる
+        let line_cap = self.limits.max_line_len.saturating_add(3);
+        let bytes_cap = (self.limits.max_bytes - self.bytes_read).saturating_add(1);
+        let cap = line_cap.min(bytes_cap) as u64;
+        
+        let mut buf = Vec::new();
+        // take: 指定したバイト数までしか読まないReaderにする
+        let read = match self.reader.by_ref().take(cap).read_until(b'\n', &mut buf) {
+            Ok(0) => return None,
+            Ok(read) => read,
+            Err(e) => return Some(Err(e.into())),
+        };
+        self.bytes_read += read;
+        self.line_number += 1;
+        
+        if self.bytes_read > self.limits.max_bytes {
+            return too_large(InputLimit::Bytes, self.limits.max_bytes, self.line_number);
+        }
+        
+        // 行末の改行を取り除く
+        self.unterminated = buf.last() != Some(&b'\n');
+        if buf.last() == Some(&b'\n') {
+            buf.pop();
+            if buf.last() == Some(&b'\r') {
+                buf.pop();
+            }
+        }
+        if buf.len() > self.limits.max_line_len {
+            return too_large(InputLimit::LineLength, self.limits.max_line_len, self.line_number);
+        }
+        
+        // lines() と同じく、UTF-8として読めない行はInvalidDataのI/Oエラーにする
+        Some(String::from_utf8(buf).map_err(|e| {
+            SmasherError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+        }))
+    }
+}
+
+/// parse_actions の結果
+struct ParsedRows {
+    /// 解析された行動（時刻順）
+    actions: Vec<Action>,
+    /// 未登録の行動IDごとの行番号
+    unknown_lines: IdLines,
+    /// タイムスタンプが前の行より小さかった行の数
+    reordered: usize,
+    /// 除外した行の数（途中で切れた最後の行、読み飛ばした不正な行）
+    dropped: usize,
+    /// 読み飛ばした不正な行（lenient のとき、ファイルの順）
+    skipped: Vec<SkippedLine>,
+}
+
+/// 上限付きで対戦ログを読み込む共通処理
+/// 
+/// # 引数
+/// * `options` - 読み方の設定（lenient なら不正な行動データの行をエラーにせず読み飛ばし、記録する）
+fn read_with_limits<R: BufRead>(reader: R, limits: &ParseLimits, options: &ReadOptions) -> Result<BattleLog> {
+    let mut lines = LimitedLines {
+        reader,
+        limits: *limits,
+        bytes_read: 0,
+        line_number: 0,
+        unterminated: false,
+    };
     
-    let mut lines = reader.lines();
+    // 全角カンマを半角カンマとして扱った回数
+    let mut fullwidth_commas = 0;
     
     // 1行目: プレイヤー情報を読み込む
-    let player_info = parse_player_info(&mut lines)?;
+    let player_info = parse_player_info(&mut lines, &mut fullwidth_commas)?;
     
     // 2行目以降: 行動データを読み込む
-    let actions = parse_actions(&mut lines)?;
+    let mut warnings = Vec::new();
+    let mut markers = Vec::new();
+    let rows = parse_actions(&mut lines, &mut fullwidth_commas, &mut warnings, &mut markers, limits.max_rows, options)?;
     
-    // 行動データが空でないか確認
-    if actions.is_empty() {
+    // 行動データが空でないか確認（lenient で全部の行を読み飛ばした場合もここでエラーになる）
+    if rows.actions.is_empty() {
         return Err(SmasherError::EmptyData(
             "行動データが1つも見つかりませんでした".to_string()
         ));
     }
     
     // BattleLogを作成して返す
-    Ok(BattleLog::new(player_info, actions))
+    let mut battle_log = BattleLog::new(player_info, rows.actions);
+    battle_log.warnings = warnings;
+    battle_log.markers = markers;
+    battle_log.unknown_id_lines = rows.unknown_lines;
+    battle_log.reordered = rows.reordered;
+    battle_log.dropped_rows = rows.dropped;
+    battle_log.skipped_lines = rows.skipped;
+    if fullwidth_commas > 0 {
+        battle_log.warnings.push(format!(
+            "全角カンマ（，）を {} 箇所 半角カンマとして扱いました",
+            fullwidth_commas
+        ));
+    }
+    Ok(battle_log)
 }
 
 /// プレイヤー情報を解析する（1行目）
 /// 
 /// # 引数
-/// * `lines` - ファイルの行イテレータ
+/// * `lines` - 行のイテレータ
+/// * `fullwidth_commas` - 全角カンマを変換した回数（加算される）
 /// 
 /// # 戻り値
 /// 解析されたPlayerInfo、またはエラー
-fn parse_player_info<B: BufRead>(lines: &mut std::io::Lines<B>) -> Result<PlayerInfo> {
+fn parse_player_info<I: Iterator<Item = Result<String>>>(lines: &mut I, fullwidth_commas: &mut usize) -> Result<PlayerInfo> {
     // 1行目を読み込む
     let first_line = lines
     // 読む行を1つ進める
         .next()
         .ok_or_else(|| SmasherError::EmptyData("ファイルが空です".to_string()))??;
     
-    // カンマで分割
-    let parts: Vec<&str> = first_line.split(',').collect();
+    // 区切り文字のエラーは行動データ用なので、1行目のエラーに付け替える
+    let (first_line, converted) = normalize_delimiters(&first_line, 1).map_err(SmasherError::into_header_error)?;
+    *fullwidth_commas += converted;
+    
+    parse_header_line(&first_line)
+}
+
+/// 1行目（学籍番号,対戦回次）の文字列を解析する
+/// 
+/// # 引数
+/// * `first_line` - 1行目の文字列（例: "b1022024,1"）
+/// 
+/// # 戻り値
+/// 解析されたPlayerInfo、またはエラー
+pub fn parse_header_line(first_line: &str) -> Result<PlayerInfo> {
+    let header_error = |reason, detail: String| SmasherError::HeaderError { line: 1, reason, detail };
+    
+    // カンマで分割（ダブルクォートで囲んだ学籍番号の中のカンマでは区切らない）
+    let parts = split_record(first_line);
     
     // 2つの要素（学籍番号、対戦回次）があるか確認
     if parts.len() != 2 {
-        return Err(SmasherError::InvalidFormat(
-            format!("1行目は「学籍番号,対戦回次」の形式である必要があります。実際: {}", first_line)
+        let reason = if parts.len() < 2 { ParseReason::MissingField } else { ParseReason::ExtraField };
+        return Err(header_error(
+            reason,
+            format!("「学籍番号,対戦回次」の形式である必要があります。実際: {}", first_line),
         ));
     }
     
     // 学籍番号を取得（文字列のまま）
-    let student_id = parts[0].trim().to_string();
+    let student_id = parts[0].clone();
+    if student_id.is_empty() || parts[1].is_empty() {
+        return Err(header_error(
+            ParseReason::EmptyField,
+            format!("学籍番号と対戦回次の両方が必要です。実際: {}", first_line),
+        ));
+    }
     
     // 対戦回次を数値に変換
     let match_number = parts[1]
-        .trim()
         .parse::<u32>()
-        .map_err(|_| SmasherError::ParseError(
-            format!("対戦回次を数値に変換できません: {}", parts[1])
+        .map_err(|_| header_error(
+            ParseReason::BadNumber,
+            format!("対戦回次を数値に変換できません: {}", parts[1]),
         ))?;
     
     // Javaでいう return。Error時はErr()を使う
@@ -95,19 +440,51 @@ fn parse_player_info<B: BufRead>(lines: &mut std::io::Lines<B>) -> Result<Player
 
 /// 行動データを解析する（2行目以降）
 /// 
+/// 記録中にログ出力が止まったファイルは、最後の行が途中で切れていることがある。
+/// 改行で終わっていない最後の行は、解析できなければ警告を出して除外し、
+/// 解析できても（他のIDはすべて登録済みなのに）未登録のIDなら切れている可能性を警告する。
+/// 
+/// タイムスタンプがそれまでの行より小さい行は、`order` が RowOrder::Strict ならエラー
+/// （`skipped` がSomeなら読み飛ばす）、RowOrder::Sort なら最後に時刻順に並べ替えて警告する。
+/// 
 /// # 引数
-/// * `lines` - ファイルの行イテレータ
+/// * `lines` - 行のイテレータ
+/// * `fullwidth_commas` - 全角カンマを変換した回数（加算される）
+/// * `warnings` - 読み込み時の警告（追加される）
+/// * `markers` - フェーズマーカー（見つかったものが追加される）
+/// * `max_rows` - 行動データの最大行数
+/// * `options` - 読み方の設定（順番が入れ替わった行の扱い、元の行の文字列を持たせるか、不正な行を読み飛ばすか）
 /// 
 /// # 戻り値
-/// 解析されたActionのベクタ、またはエラー
-fn parse_actions<B: BufRead>(lines: &mut std::io::Lines<B>) -> Result<Vec<Action>> {
+/// 解析されたActionのベクタ、未登録の行動IDごとの行番号、逆行・除外した行の数、読み飛ばした行、またはエラー
+fn parse_actions<R: BufRead>(
+    lines: &mut LimitedLines<R>,
+    fullwidth_commas: &mut usize,
+    warnings: &mut Vec<String>,
+    markers: &mut Vec<PhaseMarker>,
+    max_rows: usize,
+    options: &ReadOptions,
+) -> Result<ParsedRows> {
+    let ReadOptions { order, keep_raw, lenient, .. } = *options;
     let mut actions = Vec::new();
+    let mut unknown_lines = IdLines::new();
+    let mut context = ParseContext::new();
+    let mut rows = 0;
+    // それまでで最も大きいタイムスタンプと、その行番号
+    let mut latest: Option<(f64, usize)> = None;
+    // タイムスタンプがそれまでより小さかった行の数（RowOrder::Sort のとき）
+    let mut reordered = 0;
+    // 除外した行の数
+    let mut dropped = 0;
+    // 読み飛ばした不正な行（lenient のとき）
+    let mut skipped = Vec::new();
     
     // 残りの行を1行ずつ処理
     // parse_player_infoで1行目を読んでいるので、ここでは2行目以降を読む
-    for (line_number, line_result) in lines.enumerate() {
+    while let Some(line_result) = lines.next() {
         // 行を読み込む（エラーがあれば?で返す）
         let line = line_result?;
+        let line_number = lines.line_number;
         
         // 空行はスキップ
         let trimmed = line.trim();
@@ -115,45 +492,633 @@ fn parse_actions<B: BufRead>(lines: &mut std::io::Lines<B>) -> Result<Vec<Action
             continue;
         }
         
-        // 1つの行動を解析
-        let action = parse_action_line(trimmed, line_number + 2)?;
-        actions.push(action);
+        // 読み飛ばす不正な行も行数に数える（上限は読む量を抑えるためのものなので）
+        rows += 1;
+        if rows > max_rows {
+            return Err(SmasherError::TooLarge { limit: InputLimit::Rows, max: max_rows, line: line_number });
+        }
+        
+        // 区切り文字を揃えてから1つの行動を解析
+        let normalized = normalize_delimiters(trimmed, line_number).map(|(normalized, converted)| {
+            *fullwidth_commas += converted;
+            normalized
+        });
+        // フェーズマーカーの行は行動ではなくマーカーとして取っておく
+        let marker = normalized.as_ref().map_or(Ok(None), |line| parse_marker_line(line, line_number));
+        let parsed = match (normalized, marker) {
+            (_, Ok(Some(marker))) => {
+                markers.push(marker);
+                continue;
+            }
+            (Ok(normalized), Ok(None)) => parse_action_line(&normalized, line_number, &mut context),
+            (Err(e), _) | (_, Err(e)) => Err(e),
+        };
+        // 順番が入れ替わった行は、Strict ならほかの不正な行と同じ扱いにする
+        let parsed = match (parsed, latest) {
+            (Ok(action), Some((previous, previous_line))) if action.timestamp < previous => match order {
+                RowOrder::Strict => Err(SmasherError::RowError {
+                    line: line_number,
+                    reason: ParseReason::OutOfOrder,
+                    detail: format!(
+                        "タイムスタンプ {} が前の行（{}行目）の {} より小さくなっています",
+                        action.timestamp, previous_line, previous
+                    ),
+                }),
+                RowOrder::Sort => {
+                    reordered += 1;
+                    Ok(action)
+                }
+            },
+            (parsed, _) => parsed,
+        };
+        
+        // 改行のない最後の行は、途中で切れている可能性を考える
+        if lines.unterminated {
+            match &parsed {
+                Err(SmasherError::RowError { .. }) => {
+                    warnings.push(format!(
+                        "{}行目: 最後の行が途中で切れているようなので除外しました: {}",
+                        line_number, trimmed
+                    ));
+                    dropped += 1;
+                    continue;
+                }
+                Ok(action)
+                    if !ActionType::is_known_id(&action.original_id)
+                        && !actions.is_empty()
+                        && actions.iter().all(|a: &Action| ActionType::is_known_id(&a.original_id)) =>
+                {
+                    warnings.push(format!(
+                        "{}行目: 最後の行の行動ID「{}」は未登録です。途中で切れている可能性があります",
+                        line_number, action.original_id
+                    ));
+                }
+                _ => {}
+            }
+        }
+        
+        match parsed {
+            Ok(mut action) => {
+                if keep_raw {
+                    // 全角カンマなどを直す前の、ファイルに書かれたままの行を持たせる
+                    action.raw = Some(line.as_str().into());
+                }
+                // 未登録のIDは、打ち間違いを探せるように行番号を覚えておく
+                if action.action_type == ActionType::Unknown {
+                    unknown_lines.entry(action.original_id.to_string()).or_default().push(line_number);
+                }
+                if latest.is_none_or(|(previous, _)| action.timestamp >= previous) {
+                    latest = Some((action.timestamp, line_number));
+                }
+                actions.push(action);
+            }
+            Err(SmasherError::RowError { line, detail, .. }) if lenient => {
+                skipped.push(SkippedLine { line, content: trimmed.to_string(), reason: detail });
+                dropped += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    
+    if reordered > 0 {
+        // sort_byは安定ソートなので、同じタイムスタンプの行動はファイルの順番のまま
+        actions.sort_by(|a, b| a.timestamp.total_cmp(&b.timestamp));
+        warnings.push(format!(
+            "{} 行のタイムスタンプが前の行より小さかったので、時刻順に並べ替えました",
+            reordered
+        ));
+    }
+    
+    Ok(ParsedRows { actions, unknown_lines, reordered, dropped, skipped })
+}
+
+/// CSVの1行を項目に分ける
+/// 
+/// - ダブルクォートで囲んだ項目の中のカンマでは区切らない（例: "b102,2024",1）
+/// - 囲んだ項目の中の "" は " 1文字として扱う
+/// - 各項目の前後の空白は取り除く（ダブルクォートの内側の空白は残す）
+/// - 閉じていないダブルクォートは、行末までを1つの項目とする
+/// 
+/// # 引数
+/// * `line` - 行の文字列（区切り文字は半角カンマ）
+/// 
+/// # 戻り値
+/// 項目のリスト（空の行なら空の項目1つ）
+pub fn split_csv_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        // 項目の前の空白を読み飛ばす
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        
+        if chars.next_if_eq(&'"').is_some() {
+            // ダブルクォートで囲んだ項目: 閉じるダブルクォートまでをそのまま読む
+            while let Some(c) = chars.next() {
+                if c != '"' {
+                    field.push(c);
+                } else if chars.next_if_eq(&'"').is_some() {
+                    field.push('"');
+                } else {
+                    break;
+                }
+            }
+            // 閉じるダブルクォートの後ろに文字があれば、寛容にそのまま続ける（後ろの空白は除く）
+            let mut rest = String::new();
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                rest.push(c);
+            }
+            field.push_str(rest.trim_end());
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                field.push(c);
+            }
+            field = field.trim().to_string();
+        }
+        fields.push(field);
+        
+        // 次がカンマなら次の項目へ、行末なら終わり
+        if chars.next().is_none() {
+            return fields;
+        }
+    }
+}
+
+/// 1行を項目に分け、行末の余分な空の項目（「1.04,us,」の最後のカンマなど）を取り除く
+/// 
+/// 空の項目を取り除くのは必要な2項目より後ろだけ。足りない項目はそのまま残し、呼び出し側でエラーにする
+fn split_record(line: &str) -> Vec<String> {
+    let mut fields = split_csv_fields(line);
+    while fields.len() > 2 && fields.last().is_some_and(|field| field.is_empty()) {
+        fields.pop();
+    }
+    fields
+}
+
+/// 区切り文字を半角カンマに揃える
+/// 
+/// Excelからコピーしたデータによくある全角カンマ（，）は半角カンマとして扱う。
+/// カンマとタブが同じ行に混ざっている場合はどちらで区切るべきか判断できないのでエラーにする。
+/// 
+/// # 引数
+/// * `line` - 行の文字列
+/// * `line_number` - 行番号（エラーメッセージ用）
+/// 
+/// # 戻り値
+/// 半角カンマに揃えた行と、変換した全角カンマの数、またはエラー
+pub fn normalize_delimiters(line: &str, line_number: usize) -> Result<(String, usize)> {
+    let half = line.contains(',');
+    let full = line.contains('，');
+    let tab = line.contains('\t');
+    
+    if tab && (half || full) {
+        // 見つかった区切り文字をすべて挙げる
+        let mut seen = Vec::new();
+        if half {
+            seen.push("半角カンマ「,」");
+        }
+        if full {
+            seen.push("全角カンマ「，」");
+        }
+        seen.push("タブ");
+        return Err(SmasherError::RowError {
+            line: line_number,
+            reason: ParseReason::MixedDelimiters,
+            detail: format!("区切り文字が混在しています（{}）。どちらか一方に揃えてください: {}", seen.join("と"), line),
+        });
     }
     
-    Ok(actions)
+    let converted = line.matches('，').count();
+    Ok((line.replace('，', ","), converted))
+}
+
+/// フェーズマーカーの行（例: "120.0,phase:neutral_reset"）を解析する
+/// 
+/// # 引数
+/// * `line` - 区切り文字を揃えた行の文字列
+/// * `line_number` - 行番号（エラーメッセージ用）
+/// 
+/// # 戻り値
+/// マーカーの行ならSome(マーカー)、普通の行動の行ならNone、マーカーが不正ならエラー
+pub fn parse_marker_line(line: &str, line_number: usize) -> Result<Option<PhaseMarker>> {
+    let fields = split_record(line);
+    let [timestamp, id, ..] = fields.as_slice() else {
+        return Ok(None);
+    };
+    let Some(name) = id.strip_prefix(PhaseMarker::PREFIX) else {
+        return Ok(None);
+    };
+    
+    let marker_error = |reason, detail: String| SmasherError::RowError { line: line_number, reason, detail };
+    // 名前は表やJSONにそのまま出すので、記号や空白を含まないものに限る
+    let valid = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(marker_error(
+            ParseReason::BadMarker,
+            format!("フェーズ名は英数字と _ - で1文字以上にしてください: {}", id),
+        ));
+    }
+    let timestamp = timestamp
+        .parse::<f64>()
+        .ok()
+        .filter(|t| t.is_finite())
+        .ok_or_else(|| marker_error(
+            ParseReason::BadTimestamp,
+            format!("タイムスタンプを数値に変換できません: {}", timestamp),
+        ))?;
+    
+    Ok(Some(PhaseMarker {
+        timestamp,
+        name: name.to_string(),
+    }))
 }
 
 /// 1行の行動データを解析する
 /// 
 /// # 引数
 /// * `line` - 行の文字列（例: "1.04,us"）
-/// * `line_number` - 行番号（エラーメッセージ用）
+/// * `line_number` - 行番号（エラーメッセージと Action::line 用）
+/// * `context` - 行動IDの分類をメモする解析コンテキスト
 /// 
 /// # 戻り値
-/// 解析されたAction、またはエラー
-fn parse_action_line(line: &str, line_number: usize) -> Result<Action> {
-    let parts: Vec<&str> = line.split(',').collect();
+/// 解析されたAction（raw はNone）、またはエラー
+pub fn parse_action_line(line: &str, line_number: usize, context: &mut ParseContext) -> Result<Action> {
+    let row_error = |reason, detail: String| SmasherError::RowError { line: line_number, reason, detail };
+    
+    let parts = split_record(line);
     
     // 2つの要素（タイムスタンプ、行動ID）があるか確認
     if parts.len() != 2 {
-        return Err(SmasherError::InvalidFormat(
-            format!("{}行目: 「タイムスタンプ,行動ID」の形式である必要があります。実際: {}", line_number, line)
+        let reason = if parts.len() < 2 { ParseReason::MissingField } else { ParseReason::ExtraField };
+        return Err(row_error(
+            reason,
+            format!("「タイムスタンプ,行動ID」の形式である必要があります。実際: {}", line),
+        ));
+    }
+    if parts[0].is_empty() || parts[1].is_empty() {
+        return Err(row_error(
+            ParseReason::EmptyField,
+            format!("タイムスタンプと行動IDの両方が必要です。実際: {}", line),
         ));
     }
     
     // タイムスタンプを浮動小数点数に変換
+    // "NaN" や "inf" も f64 としては読めてしまうので、有限の値だけを受け付ける
     let timestamp = parts[0]
-        .trim()
         .parse::<f64>()
-        .map_err(|_| SmasherError::ParseError(
-            format!("{}行目: タイムスタンプを数値に変換できません: {}", line_number, parts[0])
+        .ok()
+        .filter(|t| t.is_finite())
+        .ok_or_else(|| row_error(
+            ParseReason::BadTimestamp,
+            format!("タイムスタンプを数値に変換できません: {}", parts[0]),
         ))?;
+    // タイムスタンプは試合開始からの経過秒数なので、負の値は記録の誤り
+    if timestamp < 0.0 {
+        return Err(row_error(
+            ParseReason::NegativeTimestamp,
+            format!("タイムスタンプが負になっています: {}", parts[0]),
+        ));
+    }
     
-    // 行動IDを取得
-    let action_id = parts[1].trim().to_string();
+    // 行動IDを取得し、分類はコンテキストのメモを使う
+    let (action_type, shared_id) = context.resolve(&parts[1]);
     
     // Actionを作成
-    Ok(Action::new(timestamp, action_id))
+    let mut action = Action::with_type(timestamp, shared_id, action_type);
+    // 40億行を超えるファイルは現実的でないが、念のため収まらない行番号は上限の値にする
+    action.line = u32::try_from(line_number).unwrap_or(u32::MAX);
+    Ok(action)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3つの上限から ParseLimits を作る
+    fn limits(max_bytes: usize, max_rows: usize, max_line_len: usize) -> ParseLimits {
+        ParseLimits { max_bytes, max_rows, max_line_len }
+    }
+
+    /// 上限を超えたエラーの種類と行番号を取り出す
+    fn too_large(result: Result<BattleLog>) -> (InputLimit, usize, usize) {
+        match result {
+            Err(SmasherError::TooLarge { limit, max, line }) => (limit, max, line),
+            other => panic!("上限を超えたエラーになっていません: {:?}", other),
+        }
+    }
+
+    // 19バイト、行動データ2行、いちばん長い行は6バイト（改行を除く）
+    const SMALL: &str = "b1,1\n1.0,us\n2.0,ss\n";
+
+    #[test]
+    fn untrusted_max_bytes_boundary() {
+        let len = SMALL.len();
+        assert_eq!(parse_untrusted(SMALL.as_bytes(), &limits(len, usize::MAX, usize::MAX)).unwrap().actions.len(), 2);
+        assert_eq!(
+            too_large(parse_untrusted(SMALL.as_bytes(), &limits(len - 1, usize::MAX, usize::MAX))),
+            (InputLimit::Bytes, len - 1, 3)
+        );
+    }
+
+    #[test]
+    fn untrusted_max_rows_boundary() {
+        assert_eq!(parse_untrusted(SMALL.as_bytes(), &limits(usize::MAX, 2, usize::MAX)).unwrap().actions.len(), 2);
+        let (limit, max, _) = too_large(parse_untrusted(SMALL.as_bytes(), &limits(usize::MAX, 1, usize::MAX)));
+        assert_eq!((limit, max), (InputLimit::Rows, 1));
+    }
+
+    #[test]
+    fn untrusted_max_line_len_boundary() {
+        assert_eq!(parse_untrusted(SMALL.as_bytes(), &limits(usize::MAX, usize::MAX, 6)).unwrap().actions.len(), 2);
+        assert_eq!(
+            too_large(parse_untrusted(SMALL.as_bytes(), &limits(usize::MAX, usize::MAX, 5))),
+            (InputLimit::LineLength, 5, 2)
+        );
+        // 行末の \r\n は1行の長さに数えない
+        let crlf = SMALL.replace('\n', "\r\n");
+        assert_eq!(parse_untrusted(crlf.as_bytes(), &limits(usize::MAX, usize::MAX, 6)).unwrap().actions.len(), 2);
+    }
+
+    #[test]
+    fn limited_lines_stops_reading_a_huge_line_early() {
+        // 1行の上限 + 3バイトまでしか読まないので、残りのバイトは読まれずに残る
+        let line = "x".repeat(1000);
+        let mut lines = LimitedLines {
+            reader: line.as_bytes(),
+            limits: limits(usize::MAX, usize::MAX, 10),
+            bytes_read: 0,
+            line_number: 0,
+            unterminated: false,
+        };
+        assert!(matches!(lines.next(), Some(Err(SmasherError::TooLarge { limit: InputLimit::LineLength, .. }))));
+        assert_eq!(lines.bytes_read, 13);
+    }
+
+    #[test]
+    fn untrusted_skips_bad_rows_and_caps_the_warnings() {
+        let mut input = String::from("b1,1\n1.0,us\n");
+        for _ in 0..MAX_UNTRUSTED_WARNINGS + 5 {
+            input.push_str("abc,us\n");
+        }
+        let battle_log = parse_untrusted(input.as_bytes(), &ParseLimits::unlimited()).unwrap();
+        assert_eq!(battle_log.actions.len(), 1);
+        assert_eq!(battle_log.skipped_lines.len(), MAX_UNTRUSTED_WARNINGS + 5);
+        assert_eq!(battle_log.warnings.len(), MAX_UNTRUSTED_WARNINGS + 1);
+        assert!(battle_log.warnings.last().unwrap().contains("ほかに 5 行"));
+    }
+
+    /// 上限なしで文字列から読む
+    fn read(input: &str) -> Result<BattleLog> {
+        read_battle_log_from(input.as_bytes(), &ParseLimits::unlimited())
+    }
+
+    #[test]
+    fn truncated_last_line_is_dropped_with_a_warning() {
+        let battle_log = read("b1,1\n1.0,us\n2.0,ss\n2.5").unwrap();
+        assert_eq!(battle_log.actions.len(), 2);
+        assert_eq!(battle_log.dropped_rows, 1);
+        assert!(battle_log.warnings.iter().any(|warning| warning.starts_with("4行目: 最後の行が途中で切れている")));
+    }
+
+    #[test]
+    fn truncated_unknown_id_on_the_last_line_is_kept_with_a_warning() {
+        let battle_log = read("b1,1\n1.0,us\n2.0,u").unwrap();
+        assert_eq!(battle_log.actions.len(), 2);
+        assert_eq!(battle_log.dropped_rows, 0);
+        assert!(battle_log.warnings.iter().any(|warning| warning.contains("「u」は未登録です")));
+    }
+
+    #[test]
+    fn complete_last_line_without_newline_has_no_warning() {
+        let battle_log = read("b1,1\n1.0,us\n2.0,ss").unwrap();
+        assert_eq!(battle_log.actions.len(), 2);
+        assert!(battle_log.warnings.is_empty(), "{:?}", battle_log.warnings);
+    }
+
+    #[test]
+    fn bad_line_with_a_newline_is_still_an_error() {
+        // 改行で終わっている行は途中で切れたのではないので、ふつうの形式エラー
+        assert!(matches!(read("b1,1\n1.0,us\n2.5\n"), Err(SmasherError::RowError { line: 3, .. })));
+    }
+
+    /// 読み方を指定して、メモリ上のバイト列（BufRead）から読む
+    fn read_as(input: &str, options: ReadOptions) -> Result<BattleLog> {
+        read_battle_log_from_reader(input.as_bytes(), &options)
+    }
+
+    const OUT_OF_ORDER: &str = "b1,1\n1.0,us\n3.0,ss\n2.0,ds\n3.0,s\n";
+
+    #[test]
+    fn out_of_order_rows_are_an_error_by_default() {
+        match read_as(OUT_OF_ORDER, ReadOptions::default()) {
+            Err(SmasherError::RowError { line: 4, reason: ParseReason::OutOfOrder, detail }) => {
+                assert!(detail.contains("3行目"), "{}", detail);
+            }
+            other => panic!("順番の入れ替わりのエラーになっていません: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn out_of_order_rows_are_sorted_with_sort() {
+        let battle_log = read_as(OUT_OF_ORDER, ReadOptions { order: RowOrder::Sort, ..ReadOptions::default() }).unwrap();
+        let timestamps: Vec<f64> = battle_log.actions.iter().map(|action| action.timestamp).collect();
+        assert_eq!(timestamps, vec![1.0, 2.0, 3.0, 3.0]);
+        // 同じ時刻の行動はファイルの順のまま
+        assert_eq!((&*battle_log.actions[2].original_id, &*battle_log.actions[3].original_id), ("ss", "s"));
+        assert_eq!(battle_log.reordered, 1);
+        assert!(battle_log.warnings.iter().any(|warning| warning.starts_with("1 行のタイムスタンプ")));
+    }
+
+    #[test]
+    fn equal_timestamps_are_in_order() {
+        let battle_log = read_as("b1,1\n1.0,us\n1.0,ss\n", ReadOptions::default()).unwrap();
+        assert_eq!((battle_log.actions.len(), battle_log.reordered), (2, 0));
+    }
+
+    #[test]
+    fn negative_timestamps_are_rejected_even_with_sort() {
+        for order in [RowOrder::Strict, RowOrder::Sort] {
+            let result = read_as("b1,1\n1.0,us\n-0.5,ss\n", ReadOptions { order, ..ReadOptions::default() });
+            assert!(
+                matches!(result, Err(SmasherError::RowError { line: 3, reason: ParseReason::NegativeTimestamp, .. })),
+                "{:?}: {:?}",
+                order,
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn untrusted_skips_out_of_order_rows() {
+        let battle_log = parse_untrusted(OUT_OF_ORDER.as_bytes(), &ParseLimits::unlimited()).unwrap();
+        assert_eq!(battle_log.actions.len(), 3);
+        assert_eq!(battle_log.skipped_lines.iter().map(|skipped| skipped.line).collect::<Vec<_>>(), vec![4]);
+    }
+
+    /// 壊れた行が3つ（4〜6行目）混ざったログ
+    const MALFORMED_ROWS: &str = include_str!("../fixtures/malformed_rows.csv");
+
+    #[test]
+    fn malformed_fixture_fails_on_the_first_bad_row_by_default() {
+        match read_as(MALFORMED_ROWS, ReadOptions::default()) {
+            Err(SmasherError::RowError { line: 4, reason: ParseReason::EmptyField, .. }) => {}
+            other => panic!("4行目のエラーになっていません: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_fixture_skips_bad_rows_with_lenient() {
+        let battle_log = read_as(MALFORMED_ROWS, ReadOptions { lenient: true, ..ReadOptions::default() }).unwrap();
+        let skipped: Vec<(usize, &str)> =
+            battle_log.skipped_lines.iter().map(|skipped| (skipped.line, skipped.content.as_str())).collect();
+        assert_eq!(skipped, vec![(4, "1.2,"), (5, "abc,us"), (6, "2.0,s,extra")]);
+        assert_eq!(battle_log.actions.len(), 5);
+        assert_eq!(battle_log.dropped_rows, 3);
+        // 改行のない最後の行の未登録のID（u）は残し、途中で切れている可能性を警告する
+        assert!(battle_log.warnings.iter().any(|warning| warning.contains("「u」は未登録です")));
+    }
+
+    #[test]
+    fn lenient_still_fails_when_every_row_is_bad() {
+        let result = read_as("b1,1\nabc,us\n1.0\n", ReadOptions { lenient: true, ..ReadOptions::default() });
+        assert!(matches!(result, Err(SmasherError::EmptyData(_))), "{:?}", result);
+    }
 
+    #[test]
+    fn written_log_reads_back_equal() {
+        // f64 の {} 表示は元の値に戻る最短の桁数なので、書いて読み直すとビット単位で同じになる
+        let original = BattleLog::new(
+            PlayerInfo::new("b1022024".to_string(), 3),
+            [(0.1 + 0.2, "us"), (1.0 / 3.0, "s"), (2.5, "sd"), (7.0, "custom_id")]
+                .iter()
+                .map(|&(timestamp, id)| Action::new(timestamp, id.to_string()))
+                .collect(),
+        );
+        let mut csv = format!("{},{}\n", original.player_info.student_id, original.player_info.match_number);
+        for action in &original.actions {
+            csv.push_str(&format!("{},{}\n", action.timestamp, action.original_id));
+        }
+
+        let mut read_back = read(&csv).unwrap();
+        // 未登録のIDの行番号は読み込んだときだけ分かるので、比べる前に取り除く
+        assert_eq!(read_back.unknown_id_lines.remove("custom_id"), Some(vec![5]));
+        assert_eq!(read_back, original);
+    }
+
+    #[test]
+    fn split_csv_fields_handles_quotes_and_whitespace() {
+        assert_eq!(split_csv_fields("1.04,\"us\""), vec!["1.04", "us"]);
+        assert_eq!(split_csv_fields(" 1.04 , us "), vec!["1.04", "us"]);
+        assert_eq!(split_csv_fields("\"b102,2024\",1"), vec!["b102,2024", "1"]);
+        assert_eq!(split_csv_fields("\"a \"\"q\"\" \",1"), vec!["a \"q\" ", "1"]);
+        assert_eq!(split_csv_fields("\"open,1"), vec!["open,1"]);
+        assert_eq!(split_csv_fields("\"us\"x ,1"), vec!["usx", "1"]);
+        assert_eq!(split_csv_fields(""), vec![""]);
+        assert_eq!(split_csv_fields("1.04,us,"), vec!["1.04", "us", ""]);
+    }
+
+    #[test]
+    fn quoted_action_id_parses_like_a_plain_one() {
+        let action = parse_action_line("1.04,\"us\"", 2, &mut ParseContext::new()).unwrap();
+        assert_eq!(action, Action::new(1.04, "us".to_string()));
+        assert_eq!(action.line, 2);
+        // 行末の余分なカンマは項目に数えない
+        assert!(parse_action_line("1.04,us,", 2, &mut ParseContext::new()).is_ok());
+    }
+
+    #[test]
+    fn normalize_delimiters_converts_fullwidth_commas() {
+        let (line, converted) = normalize_delimiters("1.04，us", 2).unwrap();
+        assert_eq!((line.as_str(), converted), ("1.04,us", 1));
+
+        // 半角カンマと全角カンマが混ざっていても、どちらもカンマなのでそろえるだけ
+        let (line, converted) = normalize_delimiters("1.04,us，extra", 2).unwrap();
+        assert_eq!((line.as_str(), converted), ("1.04,us,extra", 1));
+    }
+
+    #[test]
+    fn normalize_delimiters_leaves_plain_lines_alone() {
+        assert_eq!(normalize_delimiters("1.04,us", 2).unwrap(), ("1.04,us".to_string(), 0));
+        assert_eq!(normalize_delimiters("1.04\tus", 2).unwrap(), ("1.04\tus".to_string(), 0));
+    }
+
+    #[test]
+    fn normalize_delimiters_rejects_tab_mixed_with_commas() {
+        for line in ["1.04\tus,ss", "1.04\tus，ss"] {
+            match normalize_delimiters(line, 7) {
+                Err(SmasherError::RowError { line: 7, reason: ParseReason::MixedDelimiters, .. }) => {}
+                other => panic!("{:?}: 区切り文字の混在のエラーになっていません: {:?}", line, other),
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_delimiters_on_the_first_line_are_a_header_error() {
+        let result = read_battle_log_from("b1022024\t1,x\n1.04,us\n".as_bytes(), &ParseLimits::unlimited());
+        match result {
+            Err(SmasherError::HeaderError { line: 1, reason: ParseReason::MixedDelimiters, .. }) => {}
+            other => panic!("1行目のエラーに付け替えられていません: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn fullwidth_commas_are_counted_across_header_and_rows() {
+        let battle_log = read_battle_log_from("b1022024，1\n1.04，us\n1.64,ss\n".as_bytes(), &ParseLimits::unlimited()).unwrap();
+        assert_eq!(battle_log.player_info.student_id, "b1022024");
+        assert_eq!(battle_log.actions.len(), 2);
+        assert!(battle_log.warnings.iter().any(|warning| warning.contains("2 箇所")), "{:?}", battle_log.warnings);
+    }
+
+    #[test]
+    fn unknown_ids_keep_the_line_they_were_written_on() {
+        let csv = "b1,1\n1.0,us\n2.0,jump\n3.0,ssss\n2.5,jump\n4.0,s\n";
+        let battle_log = read_as(csv, ReadOptions { order: RowOrder::Sort, ..ReadOptions::default() }).unwrap();
+        // 並べ替えた後も、ファイルでの行番号のまま
+        assert_eq!(battle_log.unknown_id_lines["jump"], vec![3, 5]);
+        assert_eq!(battle_log.unknown_id_lines["ssss"], vec![4]);
+        // 登録されたIDは記録しない
+        assert_eq!(battle_log.unknown_id_lines.len(), 2);
+    }
+
+    #[test]
+    fn stdin_and_files_read_the_same_way() {
+        let inputs = ["b1,1\n1.0,us\n2.0,s\n", "", "b1\n1.0,us\n", "b1,1\n1.0,us\nabc,s\n", "PK\x03\x04"];
+        for (i, input) in inputs.iter().enumerate() {
+            let path = std::env::temp_dir().join(format!("smasher_stdin_{}_{}.csv", std::process::id(), i));
+            std::fs::write(&path, input).unwrap();
+            let from_file = read_battle_log_as(&path, &ReadOptions::default());
+            std::fs::remove_file(&path).unwrap();
+            // 標準入力のロックの代わりに、先頭から1回だけ読めるバイト列を渡す
+            let from_reader = read_battle_log_from_reader(input.as_bytes(), &ReadOptions::default());
+            match (from_file, from_reader) {
+                (Ok(mut file), Ok(reader)) => {
+                    assert!(file.modified.is_some() && reader.modified.is_none());
+                    file.modified = None;
+                    assert_eq!(file, reader, "{:?}", input);
+                }
+                (Err(file), Err(reader)) => assert_eq!(file.to_string(), reader.to_string(), "{:?}", input),
+                other => panic!("{:?}: ファイルと標準入力で結果が違います: {:?}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn actions_remember_their_line_and_keep_raw_only_on_request() {
+        let csv = "b1,1\n2.0, us\n1.0,\"s\"\n3.0,us\n3.001,us\n";
+        let plain = read_as(csv, ReadOptions { order: RowOrder::Sort, ..ReadOptions::default() }).unwrap();
+        // 並べ替えても行番号はファイルでの位置のまま（1行目は学籍番号の行）
+        let lines: Vec<u32> = plain.actions.iter().map(|action| action.line).collect();
+        assert_eq!(lines, vec![3, 2, 4, 5]);
+        assert!(plain.actions.iter().all(|action| action.raw.is_none()));
+
+        let mut kept = read_as(csv, ReadOptions { order: RowOrder::Sort, keep_raw: true, ..ReadOptions::default() }).unwrap();
+        let raws: Vec<&str> = kept.actions.iter().map(|action| action.raw.as_deref().unwrap()).collect();
+        assert_eq!(raws, vec!["1.0,\"s\"", "2.0, us", "3.0,us", "3.001,us"]);
+        // 行番号と元の行は比べないので、読み方が違っても同じ行動
+        assert_eq!(kept.actions, plain.actions);
+
+        // 取り除いた行の警告には、最初に取り除いた行の番号を添える
+        kept.debounce(50.0);
+        assert!(kept.warnings.iter().any(|warning| warning.ends_with("（us 1件（最初は5行目））")), "{:?}", kept.warnings);
+    }
+}
@@ -0,0 +1,234 @@
+// pipeline.rs
+// 並列集計モジュール（--jobs）
+//
+// 何千ものログを合算するとき、読み込みと解析を複数のスレッドで並列に行う。
+// 読み込みスレッドは解析結果をチャネルで集約スレッドに送り、
+// 集約スレッドは届いた順に analyzer::Aggregator に加えて、解析結果そのものはすぐに捨てる。
+// そのため、すべてのファイルの解析結果を同時にメモリに持つことはない。
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::analyzer::{self, Aggregator};
+use crate::cli::Options;
+use crate::error::Result;
+use crate::model::{AggregateResult, AnalysisResult, Interruption};
+use crate::output;
+use crate::signal;
+
+/// 1つの読み込みスレッドあたり、集約スレッドに送って待たせておける解析結果の数
+///
+/// 集約が追いつかないときは読み込みスレッドを待たせ、溜まる解析結果の数を抑える
+const QUEUE_PER_JOB: usize = 2;
+
+/// 読み込みスレッドから集約スレッドに送るもの（指定した順でのインデックス, 解析結果）
+type Message = (usize, Result<AnalysisResult>);
+
+/// 複数のファイルを並列に読み込んで解析し、合算する
+///
+/// # 処理の流れ
+/// 1. `jobs` 個の読み込みスレッドが、まだ読んでいないファイルを1つずつ取って読み込み・解析する
+/// 2. 解析結果を、容量に上限のあるチャネルで集約スレッドに送る
+/// 3. 集約スレッドは届いたものから合算し、読み込めなかったファイルはエラー出力に知らせる
+///
+/// Ctrl-Cが押されたら、読み込みスレッドは読んでいる途中のファイルを終えてから止まる。
+/// そのときは、それまでに届いた分の合算結果に AggregateResult.interrupted を付けて返す
+///
+/// # 引数
+/// * `options` - 設定（file_paths を読む。ファイル数より多いスレッドは作らない）
+/// * `jobs` - 読み込みスレッドの数
+///
+/// # 戻り値
+/// (合算結果, 読み込めなかったファイルの数)。
+/// 合算結果は1つずつ読んだとき（analyzer::aggregate_files）と同じ。--dup-policy error で重複があればエラー
+pub fn aggregate_parallel(options: &Options, jobs: usize) -> (Result<AggregateResult>, usize) {
+    let paths = &options.file_paths;
+    let jobs = jobs.min(paths.len()).max(1);
+    // 次に読むファイルのインデックス（スレッド間で共有して、同じファイルを2回読まないようにする）
+    let next = AtomicUsize::new(0);
+    let (sender, receiver) = mpsc::sync_channel::<Message>(jobs * QUEUE_PER_JOB);
+
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                // 次のファイルを取る前に確認する（読んでいる途中のファイルは最後まで読む）
+                if signal::interrupted() {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = paths.get(index) else {
+                    break;
+                };
+                let result = options.read(path).map(|mut battle_log| {
+                    output::display_skipped_lines(path, &battle_log.skipped_lines);
+                    options.prepare(&mut battle_log);
+                    analyzer::analyze(&battle_log, &options.analysis)
+                });
+                // 集約スレッドが先に終わっていたら（起こらないはずだが）送るのをやめる
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        // 読み込みスレッドの分だけが残るようにする（全員が終わるとチャネルが閉じ、集約が終わる）
+        drop(sender);
+
+        let collector = scope.spawn(move || {
+            let mut aggregator = Aggregator::new(options.dup_policy);
+            let mut failed = 0;
+            let mut processed = 0;
+            for (index, result) in receiver {
+                processed += 1;
+                let path = &paths[index];
+                match result {
+                    Ok(result) => aggregator.add(index, path.clone(), &result),
+                    Err(e) => {
+                        // 1つのファイルの失敗で全体を止めず、どのファイルかを知らせて次へ進む
+                        eprintln!("\x1b[31m{} を解析できませんでした: {}\x1b[0m", path, e);
+                        if let Some(suggestion) = e.suggestion() {
+                            eprintln!("ヒント: {}", suggestion);
+                        }
+                        failed += 1;
+                    }
+                }
+            }
+            let aggregate = aggregator.finish().map(|mut aggregate| {
+                if processed < paths.len() && signal::interrupted() {
+                    aggregate.interrupted = Some(Interruption { processed, total: paths.len() });
+                }
+                aggregate
+            });
+            (aggregate, failed)
+        });
+        collector.join().expect("集約スレッドが異常終了しました")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{self, Command};
+    use crate::error::SmasherError;
+
+    /// 一時ディレクトリに学籍番号の違う小さなログを書き、そのパスを返す
+    fn write_logs(name: &str, count: usize) -> Vec<String> {
+        (0..count)
+            .map(|i| {
+                let path = std::env::temp_dir().join(format!("smasher_{}_{}_{}.csv", name, std::process::id(), i));
+                std::fs::write(&path, format!("b{},1\n1.0,us\n2.0,s\n", i)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    fn options(paths: &[String]) -> Options {
+        match cli::with_env(&[], || cli::parse_args(paths)) {
+            Ok(Command::Analyze(options)) => *options,
+            other => panic!("解析のコマンドになっていません: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn interrupted_run_returns_the_partial_aggregate() {
+        let _lock = signal::TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let paths = write_logs("pipeline_interrupted", 3);
+        // 読み込みスレッドが最初のファイルを取る前に Ctrl-C が押された状態にする
+        signal::set_interrupted(true);
+        let (aggregate, failed) = aggregate_parallel(&options(&paths), 2);
+        signal::set_interrupted(false);
+        paths.iter().for_each(|path| std::fs::remove_file(path).unwrap());
+
+        let aggregate = aggregate.unwrap();
+        assert_eq!((aggregate.files, failed), (0, 0));
+        assert_eq!(aggregate.interrupted, Some(Interruption { processed: 0, total: 3 }));
+    }
+
+    #[test]
+    fn uninterrupted_run_aggregates_every_file() {
+        let _lock = signal::TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let paths = write_logs("pipeline_complete", 3);
+        let (aggregate, failed) = aggregate_parallel(&options(&paths), 2);
+        paths.iter().for_each(|path| std::fs::remove_file(path).unwrap());
+
+        let aggregate = aggregate.unwrap();
+        assert_eq!((aggregate.files, failed), (3, 0));
+        assert_eq!(aggregate.interrupted, None);
+    }
+    /// 同じ試合（b1, 1回目）のログを2つ書く。パスは `names` の順で、中身は行動数だけ違う
+    fn write_duplicates(name: &str, names: [&str; 2], actions: [usize; 2]) -> Vec<String> {
+        names
+            .iter()
+            .zip(actions)
+            .map(|(file, count)| {
+                let path = std::env::temp_dir().join(format!("smasher_{}_{}_{}.csv", name, std::process::id(), file));
+                let rows: String = (0..count).map(|i| format!("{}.0,us\n", i + 1)).collect();
+                std::fs::write(&path, format!("b1,1\n{}", rows)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    /// --dup-policy を指定して合算し、一時ファイルを消す
+    fn aggregate_duplicates(paths: &[String], policy: &str) -> Result<AggregateResult> {
+        let _lock = signal::TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let args = [paths.to_vec(), vec!["--dup-policy".to_string(), policy.to_string()]].concat();
+        let (aggregate, failed) = aggregate_parallel(&options(&args), 1);
+        paths.iter().for_each(|path| std::fs::remove_file(path).unwrap());
+        assert_eq!(failed, 0);
+        aggregate
+    }
+
+    #[test]
+    fn dup_policy_largest_keeps_the_longer_log() {
+        let paths = write_duplicates("dup_largest", ["a", "b"], [2, 3]);
+        let aggregate = aggregate_duplicates(&paths, "largest").unwrap();
+        assert_eq!((aggregate.files, aggregate.counts.total()), (1, 3));
+        assert_eq!(aggregate.duplicates[0].files, paths);
+        assert_eq!(aggregate.duplicates[0].kept, vec![paths[1].clone()]);
+    }
+
+    #[test]
+    fn dup_policy_largest_breaks_an_exact_tie_by_path() {
+        // 指定した順は z → a だが、行動数が同じならパスの辞書順で先の a を使う
+        let paths = write_duplicates("dup_tie", ["z", "a"], [2, 2]);
+        let aggregate = aggregate_duplicates(&paths, "largest").unwrap();
+        assert_eq!(aggregate.duplicates[0].kept, vec![paths[1].clone()]);
+        assert_eq!(aggregate.counts.total(), 2);
+    }
+
+    #[test]
+    fn dup_policy_error_names_both_files() {
+        let paths = write_duplicates("dup_error", ["a", "b"], [2, 3]);
+        match aggregate_duplicates(&paths, "error") {
+            Err(SmasherError::DuplicateLogs { student_id, match_number, files }) => {
+                assert_eq!((student_id.as_str(), match_number, files), ("b1", 1, paths));
+            }
+            other => panic!("重複のエラーになっていません: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn dup_policy_first_and_last_follow_the_argument_order() {
+        for (policy, kept) in [("first", 0), ("last", 1)] {
+            let paths = write_duplicates(&format!("dup_{}", policy), ["a", "b"], [3, 2]);
+            let aggregate = aggregate_duplicates(&paths, policy).unwrap();
+            assert_eq!(aggregate.duplicates[0].kept, vec![paths[kept].clone()], "{}", policy);
+            assert_eq!(aggregate.counts.total(), [3, 2][kept], "{}", policy);
+        }
+    }
+
+    #[test]
+    fn dup_policy_keep_all_counts_both_and_reports_the_key_in_json() {
+        let paths = write_duplicates("dup_keep_all", ["a", "b"], [2, 3]);
+        let aggregate = aggregate_duplicates(&paths, "keep-all").unwrap();
+        assert_eq!((aggregate.files, aggregate.counts.total()), (2, 5));
+        assert_eq!(aggregate.duplicates[0].kept, paths);
+
+        let json = output::aggregate_to_json(&aggregate);
+        assert!(json.contains(r#""dedup":{"key":["student_id","match_number"],"policy":"keep-all""#), "{}", json);
+        assert!(json.contains(r#""student_id":"b1","match_number":1,"files":["#), "{}", json);
+    }
+}
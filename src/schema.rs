@@ -0,0 +1,379 @@
+// schema.rs
+// JSON出力のスキーマ定義モジュール
+//
+// output::to_json が出すJSON（--json / --format json / --ipc）の構造をJSON Schemaで表す
+// 他のツールはこのスキーマを見てパースを組み立てられる
+//
+// 運用: to_json の出力を変えたら（キーの追加・削除・型の変更）、このファイルのスキーマも
+// 同じコミットで直し、SCHEMA_VERSION を1つ上げる
+
+/// スキーマのバージョン（to_json の出力にも schema_version として入る）
+pub const SCHEMA_VERSION: u32 = 11;
+
+/// スキーマ本体。@VERSION@ は SCHEMA_VERSION に置き換える
+const SCHEMA_TEMPLATE: &str = r#"{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "smasher analysis result",
+  "description": "smasher の解析結果（--json / --format json / --ipc の出力）",
+  "version": @VERSION@,
+  "type": "object",
+  "required": ["schema_version", "player_info", "counts", "unknown_ids", "action_id_counts", "intervals",
+               "attack_streaks", "combos", "repeats", "flow_switches", "phases", "momentum", "debounce",
+               "shield_cover", "bursts", "reliability"],
+  "properties": {
+    "schema_version": {
+      "description": "この出力が従うスキーマのバージョン",
+      "const": @VERSION@
+    },
+    "player_info": {
+      "description": "ファイル名から読み取ったプレイヤー情報",
+      "type": "object",
+      "required": ["student_id", "match_number"],
+      "properties": {
+        "student_id": { "type": "string", "description": "学籍番号" },
+        "match_number": { "type": "integer", "minimum": 0, "description": "対戦回次" }
+      }
+    },
+    "file_modified": {
+      "description": "ログのファイルの更新日時（UTC、RFC 3339）。試合の日時の代わり。取れなければキーがない",
+      "type": "string",
+      "format": "date-time"
+    },
+    "counts": {
+      "description": "行動タイプごとの回数と比率",
+      "type": "object",
+      "required": ["attack", "shield", "dodge", "unknown", "total",
+                   "attack_ratio", "shield_ratio", "dodge_ratio", "unknown_ratio"],
+      "properties": {
+        "attack": { "type": "integer", "minimum": 0, "description": "攻撃の回数" },
+        "shield": { "type": "integer", "minimum": 0, "description": "シールドの回数" },
+        "dodge": { "type": "integer", "minimum": 0, "description": "回避の回数" },
+        "unknown": { "type": "integer", "minimum": 0, "description": "未登録の行動IDの回数" },
+        "total": { "type": "integer", "minimum": 0, "description": "合計の回数（未登録の行動IDも含む）" },
+        "attack_ratio": { "type": "number", "description": "攻撃の比率（%、小数点以下3桁）" },
+        "shield_ratio": { "type": "number", "description": "シールドの比率（%、小数点以下3桁）" },
+        "dodge_ratio": { "type": "number", "description": "回避の比率（%、小数点以下3桁）" },
+        "unknown_ratio": { "type": "number", "description": "未登録の行動IDの比率（%、小数点以下3桁）" }
+      }
+    },
+    "unknown_ids": {
+      "description": "未登録の行動IDごとの回数（回数の多い順）。なければ空の配列",
+      "type": "array",
+      "items": {
+        "type": "object",
+        "required": ["id", "count"],
+        "properties": {
+          "id": { "type": "string", "description": "CSVに書かれた行動ID" },
+          "count": { "type": "integer", "minimum": 0, "description": "回数" }
+        }
+      }
+    },
+    "action_id_counts": {
+      "description": "行動IDごとの回数（回数の多い順）。行動がなければ空の配列",
+      "type": "array",
+      "items": {
+        "type": "object",
+        "required": ["id", "name", "count"],
+        "properties": {
+          "id": { "type": "string", "description": "CSVに書かれた行動ID" },
+          "name": { "type": "string", "description": "行動の日本語名" },
+          "count": { "type": "integer", "minimum": 0, "description": "回数" }
+        }
+      }
+    },
+    "intervals": {
+      "description": "行動間隔の統計（秒）。行動が1件以下ならnull",
+      "type": ["object", "null"],
+      "required": ["mean", "median", "min", "max", "std_dev"],
+      "properties": {
+        "mean": { "type": "number", "description": "平均" },
+        "median": { "type": "number", "description": "中央値" },
+        "min": { "type": "number", "description": "最小" },
+        "max": { "type": "number", "description": "最大" },
+        "std_dev": { "type": "number", "description": "標準偏差" }
+      }
+    },
+    "attack_streaks": {
+      "description": "攻撃を続けた区間の集計",
+      "type": "object",
+      "required": ["count", "total_secs", "longest_secs"],
+      "properties": {
+        "count": { "type": "integer", "minimum": 0, "description": "攻撃区間の数" },
+        "total_secs": { "type": "number", "description": "攻撃継続の合計時間（秒）" },
+        "longest_secs": { "type": "number", "description": "最長の区間の長さ（秒）" }
+      }
+    },
+    "combos": {
+      "description": "短い間隔で続けた攻撃（コンボ）の集計",
+      "type": "object",
+      "required": ["gap", "count", "max_length", "longest"],
+      "properties": {
+        "gap": { "type": "number", "description": "コンボとみなす間隔の上限（秒）" },
+        "count": { "type": "integer", "minimum": 0, "description": "コンボの数" },
+        "max_length": { "type": "integer", "minimum": 0, "description": "最大コンボ長" },
+        "longest": {
+          "description": "最大コンボの行動IDの並び（時刻順。同じ長さなら先に出たほう）。コンボがなければ空の配列",
+          "type": "array",
+          "items": { "type": "string" }
+        }
+      }
+    },
+    "repeats": {
+      "description": "直前と同じ行動を繰り返した割合",
+      "type": "object",
+      "required": ["gap", "compared", "previous_rate", "recent_rate"],
+      "properties": {
+        "gap": { "type": "number", "description": "比べる行動の間隔の上限（秒）" },
+        "compared": { "type": "integer", "minimum": 0, "description": "比べた行動の件数" },
+        "previous_rate": {
+          "type": ["number", "null"],
+          "description": "直前と同じ行動の割合（0〜1）。比べた行動がなければnull"
+        },
+        "recent_rate": {
+          "type": ["number", "null"],
+          "description": "直前か2つ前と同じ行動の割合（0〜1）。比べた行動がなければnull"
+        }
+      }
+    },
+    "flow_switches": {
+      "description": "攻撃と防御（シールド・回避）が切り替わった回数",
+      "type": "integer",
+      "minimum": 0
+    },
+    "phases": {
+      "description": "試合を区切ったフェーズごとの行動回数",
+      "type": "array",
+      "items": {
+        "type": "object",
+        "required": ["name", "start", "attack", "shield", "dodge"],
+        "properties": {
+          "name": { "type": "string", "description": "フェーズ名" },
+          "start": { "type": "number", "description": "フェーズの開始時刻（秒）" },
+          "attack": { "type": "integer", "minimum": 0, "description": "攻撃の回数" },
+          "shield": { "type": "integer", "minimum": 0, "description": "シールドの回数" },
+          "dodge": { "type": "integer", "minimum": 0, "description": "回避の回数" }
+        }
+      }
+    },
+    "momentum": {
+      "description": "試合を時間で前半・後半に分けた使用回数と、使用率の変化の向き（--momentum 指定時のみ。なければnull）。後半の使用率が前半より25%より大きく増えたら rising、減ったら falling、それ以外は steady",
+      "type": ["object", "null"],
+      "required": ["split", "first_total", "second_total", "categories", "ids"],
+      "properties": {
+        "split": { "type": "number", "description": "前半と後半の境目の時刻（秒）。この時刻ちょうどの行動は後半" },
+        "first_total": { "type": "integer", "minimum": 0, "description": "前半の行動数" },
+        "second_total": { "type": "integer", "minimum": 0, "description": "後半の行動数" },
+        "categories": {
+          "description": "行動タイプごとの前半・後半の回数（attack, shield, dodge, unknown の順）",
+          "type": "array",
+          "items": {
+            "type": "object",
+            "required": ["category", "first", "second", "momentum"],
+            "properties": {
+              "category": { "enum": ["attack", "shield", "dodge", "unknown"], "description": "行動タイプ" },
+              "first": { "type": "integer", "minimum": 0, "description": "前半の回数" },
+              "second": { "type": "integer", "minimum": 0, "description": "後半の回数" },
+              "momentum": { "enum": ["rising", "falling", "steady"], "description": "使用率の変化の向き" }
+            }
+          }
+        },
+        "ids": {
+          "description": "行動IDごとの前半・後半の回数（action_id_counts と同じ順）",
+          "type": "array",
+          "items": {
+            "type": "object",
+            "required": ["id", "first", "second", "momentum"],
+            "properties": {
+              "id": { "type": "string", "description": "CSVに書かれた行動ID" },
+              "first": { "type": "integer", "minimum": 0, "description": "前半の回数" },
+              "second": { "type": "integer", "minimum": 0, "description": "後半の回数" },
+              "momentum": { "enum": ["rising", "falling", "steady"], "description": "使用率の変化の向き" }
+            }
+          }
+        }
+      }
+    },
+    "debounce": {
+      "description": "同じ行動IDが短い間隔で続いたためノイズとして取り除いた行（--debounce 指定時のみ。なければnull）。counts などはすべて取り除いた後の値",
+      "type": ["object", "null"],
+      "required": ["threshold_ms", "total", "dropped"],
+      "properties": {
+        "threshold_ms": { "type": "number", "description": "取り除く間隔の上限（ミリ秒）" },
+        "total": { "type": "integer", "minimum": 0, "description": "取り除いた行の合計" },
+        "dropped": {
+          "description": "行動IDごとの取り除いた件数（件数の多い順）",
+          "type": "array",
+          "items": {
+            "type": "object",
+            "required": ["id", "count"],
+            "properties": {
+              "id": { "type": "string", "description": "CSVに書かれた行動ID" },
+              "count": { "type": "integer", "minimum": 1, "description": "取り除いた件数" }
+            }
+          }
+        }
+      }
+    },
+    "shield_cover": {
+      "description": "攻撃から次のシールドまでの時間の分布。攻撃の後のシールドが1つもなければnull",
+      "type": ["object", "null"],
+      "required": ["window", "bin_width", "attacks", "within", "within_rate", "mean", "median", "histogram", "histogram_overflow"],
+      "properties": {
+        "window": { "type": "number", "description": "「すぐにシールドした」とみなす時間の上限（秒、--cover-window）" },
+        "bin_width": { "type": "number", "description": "histogram の1区間の幅（秒）" },
+        "attacks": { "type": "integer", "minimum": 0, "description": "攻撃の数（後にシールドがない攻撃も含む）" },
+        "within": { "type": "integer", "minimum": 0, "description": "window 秒以内にシールドが続いた攻撃の数" },
+        "within_rate": { "type": "number", "description": "within / attacks（0〜1）" },
+        "mean": { "type": "number", "description": "次のシールドまでの時間の平均（秒、後にシールドがある攻撃だけ）" },
+        "median": { "type": "number", "description": "次のシールドまでの時間の中央値（秒）" },
+        "histogram": {
+          "description": "次のシールドまでの時間のヒストグラム。i番目は i×bin_width 秒以上 (i+1)×bin_width 秒未満の攻撃の数（最大40区間）",
+          "type": "array",
+          "items": { "type": "integer", "minimum": 0 }
+        },
+        "histogram_overflow": { "type": "boolean", "description": "histogram の最後の区間に、それより長い時間もまとめたか" }
+      }
+    },
+    "bursts": {
+      "description": "行動をバースト（時刻順に隣り合う行動の間隔が gap 秒未満で、min_len 個以上続いたもの）と単発の行動に分けた集計。バーストは確定反撃、単発は立ち回りの目安",
+      "type": "object",
+      "required": ["gap", "min_len", "count", "actions", "share", "average_length", "inside", "outside", "segments"],
+      "properties": {
+        "gap": { "type": "number", "exclusiveMinimum": 0, "description": "同じ連続とみなす行動間隔の上限（秒、--burst-gap。ちょうどこの間隔なら区切る）" },
+        "min_len": { "type": "integer", "minimum": 2, "description": "バーストとみなす最小の行動数（--burst-min）" },
+        "count": { "type": "integer", "minimum": 0, "description": "バーストの数" },
+        "actions": { "type": "integer", "minimum": 0, "description": "バーストに入った行動の数" },
+        "share": {
+          "type": ["number", "null"],
+          "minimum": 0,
+          "maximum": 100,
+          "description": "全行動に占める、バーストに入った行動の割合（%）。行動がなければnull"
+        },
+        "average_length": {
+          "type": ["number", "null"],
+          "minimum": 0,
+          "description": "バーストの平均の行動数。バーストがなければnull"
+        },
+        "inside": {
+          "description": "バーストに入った行動の、行動タイプごとの回数",
+          "type": "object",
+          "required": ["attack", "shield", "dodge", "unknown"],
+          "properties": {
+            "attack": { "type": "integer", "minimum": 0 },
+            "shield": { "type": "integer", "minimum": 0 },
+            "dodge": { "type": "integer", "minimum": 0 },
+            "unknown": { "type": "integer", "minimum": 0 }
+          }
+        },
+        "outside": {
+          "description": "単発の行動の、行動タイプごとの回数",
+          "type": "object",
+          "required": ["attack", "shield", "dodge", "unknown"],
+          "properties": {
+            "attack": { "type": "integer", "minimum": 0 },
+            "shield": { "type": "integer", "minimum": 0 },
+            "dodge": { "type": "integer", "minimum": 0 },
+            "unknown": { "type": "integer", "minimum": 0 }
+          }
+        },
+        "segments": {
+          "description": "バースト（時刻順）",
+          "type": "array",
+          "items": {
+            "type": "object",
+            "required": ["start", "end", "actions"],
+            "properties": {
+              "start": { "type": "number", "description": "最初の行動の時刻（秒）" },
+              "end": { "type": "number", "description": "最後の行動の時刻（秒）" },
+              "actions": { "type": "integer", "minimum": 2, "description": "行動の数" }
+            }
+          }
+        }
+      }
+    },
+    "reliability": {
+      "description": "記録の質から計算したデータ信頼度。100点から、逆行1行につき5点（最大30点）、欠損1行につき10点（最大30点）、記録の途切れ1か所につき10点（最大30点）、未登録の行動IDの割合1%につき1点（切り上げ、最大40点）を引く",
+      "type": "object",
+      "required": ["score", "reordered", "dropped", "gaps", "unknown"],
+      "properties": {
+        "score": { "type": "integer", "minimum": 0, "maximum": 100, "description": "信頼度（0〜100）" },
+        "reordered": { "type": "integer", "minimum": 0, "description": "タイムスタンプが前の行より小さかった（逆行した）行の数" },
+        "dropped": { "type": "integer", "minimum": 0, "description": "読み込むときに除外した行の数（欠損）" },
+        "gaps": { "type": "integer", "minimum": 0, "description": "行動のない時間が10秒を超えた区間（記録の途切れ）の数" },
+        "unknown": { "type": "integer", "minimum": 0, "description": "未登録の行動IDの行動の数" }
+      }
+    },
+    "warnings": {
+      "description": "読み込み時の警告（1件1文字列）。警告がなければキーがない",
+      "type": "array",
+      "items": { "type": "string" }
+    },
+    "previous_counts": {
+      "description": "同じファイルを前回解析したときの行動回数（--compare-last で前回の記録があるときだけ。なければキーがない）",
+      "type": "object",
+      "required": ["attack", "shield", "dodge", "unknown", "total"],
+      "properties": {
+        "attack": { "type": "integer", "minimum": 0, "description": "攻撃の回数" },
+        "shield": { "type": "integer", "minimum": 0, "description": "シールドの回数" },
+        "dodge": { "type": "integer", "minimum": 0, "description": "回避の回数" },
+        "unknown": { "type": "integer", "minimum": 0, "description": "未登録の行動IDの回数" },
+        "total": { "type": "integer", "minimum": 0, "description": "合計の回数" }
+      }
+    }
+  }
+}"#;
+
+/// 解析結果のJSON Schemaを返す（--print-schema）
+///
+/// # 戻り値
+/// 整形済みのJSON Schema文字列
+pub fn analysis_schema() -> String {
+    SCHEMA_TEMPLATE.replace("@VERSION@", &SCHEMA_VERSION.to_string())
+}
+
+/// 解析結果のJSONの一番上のキー（セクション）と、その説明の一覧（--list-sections）
+///
+/// スキーマの properties の直下にある項目を、書かれた順に取り出す。JSONとしては解析せず、
+/// このファイルの書き方（4文字下げたキーの行のすぐ次の行に description）に合わせて読む
+///
+/// # 戻り値
+/// (キー, 説明) の一覧。説明が見つからなければ空文字列
+pub fn sections() -> Vec<(&'static str, &'static str)> {
+    let mut sections = Vec::new();
+    let mut lines = SCHEMA_TEMPLATE.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some(key) = line.strip_prefix("    \"").and_then(|rest| rest.strip_suffix("\": {")) else {
+            continue;
+        };
+        let description = lines
+            .peek()
+            .and_then(|next| next.trim().strip_prefix("\"description\": \""))
+            .and_then(|rest| rest.strip_suffix("\","))
+            .unwrap_or("");
+        sections.push((key, description));
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_top_level_key_is_a_described_section() {
+        let sections = sections();
+        let keys: Vec<&str> = sections.iter().map(|&(key, _)| key).collect();
+        // required のキーはすべてセクションにある
+        let required = SCHEMA_TEMPLATE.split_once("\"required\": [").unwrap().1.split_once(']').unwrap().0;
+        for key in required.split(',').map(|key| key.trim().trim_matches('"')) {
+            assert!(keys.contains(&key), "{} がセクションにありません", key);
+        }
+        assert_eq!(keys.first(), Some(&"schema_version"));
+        assert!(keys.contains(&"warnings") && keys.contains(&"previous_counts"));
+        // 下の階層のキーは含めない
+        assert!(!keys.contains(&"student_id") && !keys.contains(&"attack_ratio"));
+        for (key, description) in &sections {
+            assert!(!description.is_empty() && !description.contains('"'), "{}: {:?}", key, description);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// signal.rs
+// 割り込み（Ctrl-C）検出モジュール
+//
+// SIGINTを受け取ったらフラグを立てるだけのハンドラを登録する
+// 長く動き続ける処理は、ループの中でinterrupted()を確認して自分で終了する
+
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(test)]
+use std::sync::Mutex;
+
+/// SIGINTを受け取ったかどうか
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Ctrl-Cで即終了せず、フラグを立てるようにする
+///
+/// Unix以外では何もしない（Ctrl-Cでそのまま終了する）
+pub fn install_interrupt_handler() {
+    imp::install();
+}
+
+/// Ctrl-Cが押されたかどうか
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// フラグを使うテストどうしが同時に動かないようにするためのロック
+///
+/// フラグはプロセスに1つしかないので、中断を再現するテストはこれをロックしている間に set_interrupted を使う
+#[cfg(test)]
+pub static TEST_LOCK: Mutex<()> = Mutex::new(());
+
+/// Ctrl-Cが押された状態をテストで再現する（falseで元に戻す）
+#[cfg(test)]
+pub fn set_interrupted(value: bool) {
+    INTERRUPTED.store(value, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+mod imp {
+    use super::INTERRUPTED;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: i32 = 2;
+
+    // C標準ライブラリのsignal関数を直接呼ぶ
+    extern "C" {
+        fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    }
+
+    // シグナルハンドラ内ではフラグを立てる以外のことはしない
+    extern "C" fn on_sigint(_signum: i32) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        // unsafe: 外部(C)の関数呼び出しはRustが安全性を保証できないため
+        unsafe {
+            signal(SIGINT, on_sigint);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod imp {
+    pub fn install() {}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    extern "C" {
+        fn raise(signum: i32) -> i32;
+    }
+
+    #[test]
+    fn sigint_sets_the_flag_instead_of_exiting() {
+        let _lock = TEST_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        set_interrupted(false);
+        install_interrupt_handler();
+        // 本物のSIGINTを自分に送る。ハンドラがなければここでテストのプロセスごと終了する
+        assert_eq!(unsafe { raise(2) }, 0);
+        assert!(interrupted());
+        set_interrupted(false);
+    }
+}
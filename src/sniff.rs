@@ -0,0 +1,312 @@
+// sniff.rs
+// 入力ファイルの種類の判定モジュール
+//
+// CSVとして読む前に、ファイルの先頭のバイト（マジックナンバー）を見て、
+// Excel（.xlsx）・ZIP・PDF・UTF-16のテキストなど、そのままでは読めないファイルを見分ける
+// 間違ったファイルを指定したときに「1行目の形式が不正」ではなく、直し方がわかるエラーにするため
+
+use crate::error::{FileKind, Result, SmasherError};
+
+/// 判定に使う先頭のバイト数（UTF-16かどうかを見分けるのに十分な長さ）
+pub const SNIFF_LEN: usize = 512;
+
+/// 入力ファイルの文字コードの指定（--encoding）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// 先頭のBOMから判定する（既定）。BOMのないUTF-16はエラーにして --encoding utf16 を提案する
+    #[default]
+    Auto,
+    /// UTF-8として読む
+    Utf8,
+    /// UTF-16として読む（BOMがなければ、0のバイトの位置から向きを推定する）
+    Utf16,
+}
+
+impl Encoding {
+    /// 指定できる名前の一覧
+    pub const NAMES: &'static [&'static str] = &["auto", "utf8", "utf16"];
+
+    /// 名前から文字コードの指定を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Encoding::Auto),
+            "utf8" => Some(Encoding::Utf8),
+            "utf16" => Some(Encoding::Utf16),
+            _ => None,
+        }
+    }
+}
+
+/// 先頭のバイトから判定した、ファイルの読み方
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sniffed {
+    /// UTF-8のテキストとして読む。先頭の `bom_len` バイト（UTF-8のBOM）は読み飛ばす
+    Utf8 { bom_len: usize },
+    /// UTF-16のテキストとして読む。先頭の `bom_len` バイト（UTF-16のBOM）は読み飛ばす
+    Utf16 { big_endian: bool, bom_len: usize },
+}
+
+/// ファイルの先頭のバイトから、読み方を決める
+///
+/// # 引数
+/// * `head` - ファイルの先頭のバイト（SNIFF_LEN バイト程度。短いファイルならその全体）
+/// * `encoding` - 文字コードの指定（--encoding）
+///
+/// # 戻り値
+/// 読み方、またはCSVとして読めないファイルのエラー（WrongFileType）。
+/// ZIPのときは中身の一覧がないので、呼び出し側で zip_member_names を使って詳しくする
+pub fn sniff(head: &[u8], encoding: Encoding) -> Result<Sniffed> {
+    let wrong = |kind| Err(SmasherError::WrongFileType { kind });
+
+    // ZIP（.xlsx も中身はZIP）とPDFは、文字コードの指定に関係なく読めない
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        return wrong(FileKind::Zip { csv_members: Vec::new() });
+    }
+    if head.starts_with(b"%PDF") {
+        return wrong(FileKind::Pdf);
+    }
+
+    if head.starts_with(b"\xEF\xBB\xBF") {
+        return Ok(Sniffed::Utf8 { bom_len: 3 });
+    }
+    // BOMがあればUTF-16だと確信できるので、--encoding の指定がなくても変換して読む
+    if encoding != Encoding::Utf8 {
+        if head.starts_with(b"\xFF\xFE") {
+            return Ok(Sniffed::Utf16 { big_endian: false, bom_len: 2 });
+        }
+        if head.starts_with(b"\xFE\xFF") {
+            return Ok(Sniffed::Utf16 { big_endian: true, bom_len: 2 });
+        }
+    }
+
+    match encoding {
+        Encoding::Utf16 => Ok(Sniffed::Utf16 { big_endian: guess_big_endian(head), bom_len: 0 }),
+        Encoding::Utf8 => Ok(Sniffed::Utf8 { bom_len: 0 }),
+        // BOMのないUTF-16は、ASCIIの文字の半分が0のバイトになる。推測で変換はせず、指定を促す
+        Encoding::Auto if head.contains(&0) => wrong(FileKind::Utf16),
+        Encoding::Auto => Ok(Sniffed::Utf8 { bom_len: 0 }),
+    }
+}
+
+/// BOMのないUTF-16の向きを推定する
+///
+/// ASCIIの文字は、ビッグエンディアンなら偶数番目、リトルエンディアンなら奇数番目のバイトが0になる
+fn guess_big_endian(head: &[u8]) -> bool {
+    let even_zeros = head.iter().step_by(2).filter(|&&b| b == 0).count();
+    let odd_zeros = head.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+    even_zeros > odd_zeros
+}
+
+/// UTF-16のバイト列をUTF-8の文字列にする
+///
+/// # 引数
+/// * `bytes` - BOMを除いたUTF-16のバイト列
+/// * `big_endian` - ビッグエンディアンか
+///
+/// # 戻り値
+/// 変換した文字列。バイト数が奇数、または対になっていないサロゲートがあればエラー
+pub fn decode_utf16(bytes: &[u8], big_endian: bool) -> Result<String> {
+    let invalid = |detail: &str| {
+        SmasherError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidData, detail.to_string()))
+    };
+    if !bytes.len().is_multiple_of(2) {
+        return Err(invalid("UTF-16として読めません（バイト数が奇数です）"));
+    }
+    let units = bytes.chunks_exact(2).map(|pair| {
+        if big_endian {
+            u16::from_be_bytes([pair[0], pair[1]])
+        } else {
+            u16::from_le_bytes([pair[0], pair[1]])
+        }
+    });
+    char::decode_utf16(units)
+        .collect::<std::result::Result<String, _>>()
+        .map_err(|_| invalid("UTF-16として読めません（不正な文字があります）"))
+}
+
+/// ZIPファイルの中身のファイル名の一覧を取得する
+///
+/// ファイルの末尾にある中央ディレクトリ（中身の一覧）を読む。
+/// 展開はしない（圧縮されたデータは読まない）
+///
+/// # 引数
+/// * `bytes` - ZIPファイル全体のバイト列
+///
+/// # 戻り値
+/// 中身のファイル名（ZIPに書かれた順）。壊れていて読めないところから先は含まない
+pub fn zip_member_names(bytes: &[u8]) -> Vec<String> {
+    let u16_at = |at: usize| bytes.get(at..at + 2).map(|b| u16::from_le_bytes([b[0], b[1]]) as usize);
+    let u32_at = |at: usize| bytes.get(at..at + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+
+    // 中央ディレクトリの終わりの記録（PK\x05\x06）を末尾から探す（後ろにコメントが付いていることがある）
+    let Some(end) = (0..bytes.len().saturating_sub(21)).rev().find(|&at| bytes[at..].starts_with(b"PK\x05\x06")) else {
+        return Vec::new();
+    };
+    let (Some(count), Some(mut at)) = (u16_at(end + 10), u32_at(end + 16)) else {
+        return Vec::new();
+    };
+
+    let mut names = Vec::new();
+    for _ in 0..count {
+        if !bytes.get(at..).is_some_and(|rest| rest.starts_with(b"PK\x01\x02")) {
+            break;
+        }
+        let (Some(name_len), Some(extra_len), Some(comment_len)) = (u16_at(at + 28), u16_at(at + 30), u16_at(at + 32)) else {
+            break;
+        };
+        let Some(name) = bytes.get(at + 46..at + 46 + name_len) else {
+            break;
+        };
+        names.push(String::from_utf8_lossy(name).into_owned());
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    names
+}
+
+/// ZIPの中身から、ファイルの種類（Excelのブックか、ただのZIPか）を決める
+///
+/// # 引数
+/// * `members` - ZIPの中身のファイル名
+///
+/// # 戻り値
+/// Excelのブック（xl/ フォルダがある）ならXlsx、それ以外は中のCSVの名前を持つZip
+pub fn classify_zip(members: &[String]) -> FileKind {
+    if members.iter().any(|name| name.starts_with("xl/")) {
+        return FileKind::Xlsx;
+    }
+    let csv_members = members
+        .iter()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".csv"))
+        .cloned()
+        .collect();
+    FileKind::Zip { csv_members }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// sniff の結果から、WrongFileType のファイルの種類だけを取り出す
+    fn wrong_kind(head: &[u8], encoding: Encoding) -> Option<FileKind> {
+        match sniff(head, encoding) {
+            Err(SmasherError::WrongFileType { kind }) => Some(kind),
+            _ => None,
+        }
+    }
+
+    /// 中央ディレクトリに `names` を並べた最小のZIP（圧縮データは空）を作る
+    fn zip_with(names: &[&str]) -> Vec<u8> {
+        let mut bytes = b"PK\x03\x04".to_vec();
+        bytes.extend([0; 26]);
+        let directory = bytes.len() as u32;
+        for name in names {
+            bytes.extend(b"PK\x01\x02");
+            bytes.extend([0; 24]);
+            bytes.extend((name.len() as u16).to_le_bytes());
+            bytes.extend([0; 16]);
+            bytes.extend(name.as_bytes());
+        }
+        bytes.extend(b"PK\x05\x06");
+        bytes.extend([0; 4]);
+        bytes.extend((names.len() as u16).to_le_bytes());
+        bytes.extend((names.len() as u16).to_le_bytes());
+        bytes.extend((bytes.len() as u32 - directory - 12).to_le_bytes());
+        bytes.extend(directory.to_le_bytes());
+        bytes.extend([0; 2]);
+        bytes
+    }
+
+    #[test]
+    fn zip_signatures_are_rejected_for_every_encoding() {
+        for encoding in [Encoding::Auto, Encoding::Utf8, Encoding::Utf16] {
+            for head in [&b"PK\x03\x04rest"[..], b"PK\x05\x06"] {
+                assert_eq!(wrong_kind(head, encoding), Some(FileKind::Zip { csv_members: Vec::new() }));
+            }
+        }
+        // PK だけではZIPとみなさない
+        assert_eq!(sniff(b"PK,1\n", Encoding::Auto).unwrap(), Sniffed::Utf8 { bom_len: 0 });
+    }
+
+    #[test]
+    fn pdf_signature_is_rejected() {
+        for encoding in [Encoding::Auto, Encoding::Utf8, Encoding::Utf16] {
+            assert_eq!(wrong_kind(b"%PDF-1.7\n", encoding), Some(FileKind::Pdf));
+        }
+    }
+
+    #[test]
+    fn utf16_le_bom_is_read_as_utf16() {
+        let head = b"\xFF\xFEb\x001\x00";
+        for encoding in [Encoding::Auto, Encoding::Utf16] {
+            assert_eq!(sniff(head, encoding).unwrap(), Sniffed::Utf16 { big_endian: false, bom_len: 2 });
+        }
+        assert_eq!(decode_utf16(&head[2..], false).unwrap(), "b1");
+        // --encoding utf8 ならBOMを見ずにUTF-8として読む
+        assert_eq!(sniff(head, Encoding::Utf8).unwrap(), Sniffed::Utf8 { bom_len: 0 });
+    }
+
+    #[test]
+    fn utf16_be_bom_is_read_as_utf16() {
+        let head = b"\xFE\xFF\x00b\x001";
+        for encoding in [Encoding::Auto, Encoding::Utf16] {
+            assert_eq!(sniff(head, encoding).unwrap(), Sniffed::Utf16 { big_endian: true, bom_len: 2 });
+        }
+        assert_eq!(decode_utf16(&head[2..], true).unwrap(), "b1");
+    }
+
+    #[test]
+    fn utf16_without_bom_needs_the_encoding_flag() {
+        let little = b"b\x001\x00,\x001\x00";
+        let big = b"\x00b\x001\x00,\x001";
+        for head in [&little[..], &big[..]] {
+            assert_eq!(wrong_kind(head, Encoding::Auto), Some(FileKind::Utf16));
+        }
+        assert_eq!(sniff(little, Encoding::Utf16).unwrap(), Sniffed::Utf16 { big_endian: false, bom_len: 0 });
+        assert_eq!(sniff(big, Encoding::Utf16).unwrap(), Sniffed::Utf16 { big_endian: true, bom_len: 0 });
+    }
+
+    #[test]
+    fn utf8_bom_and_plain_text_are_read_as_utf8() {
+        assert_eq!(sniff(b"\xEF\xBB\xBFb1,1\n", Encoding::Auto).unwrap(), Sniffed::Utf8 { bom_len: 3 });
+        assert_eq!(sniff(b"b1,1\n", Encoding::Auto).unwrap(), Sniffed::Utf8 { bom_len: 0 });
+        assert_eq!(sniff(b"", Encoding::Auto).unwrap(), Sniffed::Utf8 { bom_len: 0 });
+    }
+
+    #[test]
+    fn zip_member_names_lists_the_central_directory() {
+        let bytes = zip_with(&["logs/b1.csv", "readme.txt"]);
+        assert_eq!(zip_member_names(&bytes), vec!["logs/b1.csv", "readme.txt"]);
+        assert_eq!(classify_zip(&zip_member_names(&bytes)), FileKind::Zip { csv_members: vec!["logs/b1.csv".to_string()] });
+        let workbook = zip_with(&["[Content_Types].xml", "xl/workbook.xml"]);
+        assert_eq!(classify_zip(&zip_member_names(&workbook)), FileKind::Xlsx);
+    }
+
+    #[test]
+    fn truncated_zip_lists_what_it_can() {
+        let bytes = zip_with(&["a.csv", "b.csv"]);
+        // 末尾の記録が欠けていれば一覧は読めない（途中のどこで切れても panic しない）
+        for len in 0..bytes.len() {
+            assert!(zip_member_names(&bytes[..len]).len() <= 2);
+        }
+        assert!(zip_member_names(&bytes[..bytes.len() - 1]).is_empty());
+        assert!(zip_member_names(b"PK\x03\x04").is_empty());
+
+        // 件数が実際より多いと書かれていても、読めたところまでを返す
+        let mut overstated = bytes.clone();
+        let count_at = overstated.len() - 12;
+        overstated[count_at..count_at + 2].copy_from_slice(&5u16.to_le_bytes());
+        assert_eq!(zip_member_names(&overstated), vec!["a.csv", "b.csv"]);
+
+        // 名前の長さがファイルの外まで続いていれば、その項目から先は含めない
+        let mut overlong = bytes.clone();
+        let second = overlong.windows(4).rposition(|w| w == b"PK\x01\x02").unwrap();
+        overlong[second + 28..second + 30].copy_from_slice(&u16::MAX.to_le_bytes());
+        assert_eq!(zip_member_names(&overlong), vec!["a.csv"]);
+
+        // 中央ディレクトリの位置がファイルの外を指していれば空
+        let mut misplaced = bytes;
+        let offset_at = misplaced.len() - 6;
+        misplaced[offset_at..offset_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(zip_member_names(&misplaced).is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+// sparkline.rs
+// スパークライン（1行の小さな棒グラフ）を描くヘルパー
+//
+// 数値の列を、高さの違う文字を並べた1行の文字列に変換する
+// UTF-8を表示できない端末向けにASCII文字だけで描くこともできる
+
+/// 高さ順に並べたブロック文字（8段階）
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// ASCIIで代用するときの文字（8段階）
+const ASCII_BLOCKS: [char; 8] = ['_', '.', '-', ':', '=', '+', '*', '#'];
+
+/// 数値の列をスパークラインにする
+///
+/// 最大値を一番高い文字、0を一番低い文字に割り当てる
+///
+/// # 引数
+/// * `values` - 描く数値の列
+/// * `ascii` - trueならASCII文字だけで描く
+///
+/// # 戻り値
+/// values と同じ文字数の文字列
+pub fn render(values: &[u32], ascii: bool) -> String {
+    let blocks = if ascii { &ASCII_BLOCKS } else { &BLOCKS };
+    let max = values.iter().copied().max().unwrap_or(0);
+    let top = blocks.len() - 1;
+
+    values
+        .iter()
+        .map(|&value| {
+            if max == 0 {
+                return blocks[0];
+            }
+            let level = (value as f64 / max as f64 * top as f64).round() as usize;
+            blocks[level.min(top)]
+        })
+        .collect()
+}
+
+/// 端末がUTF-8を表示できなさそうならtrueを返す
+///
+/// ロケールの環境変数（LC_ALL → LC_CTYPE → LANG の順に最初に設定されているもの）に
+/// UTF-8 が含まれていなければASCIIで描く
+pub fn prefer_ascii() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+
+    match locale {
+        Some(value) => {
+            let value = value.to_ascii_lowercase();
+            !(value.contains("utf-8") || value.contains("utf8"))
+        }
+        // Windowsなどロケールの環境変数がない環境ではUnicodeのまま描く
+        None => false,
+    }
+}
@@ -0,0 +1,812 @@
+// style.rs
+// 表示スタイル（色）モジュール
+//
+// 行動IDやカテゴリに付ける色を決め、ANSIエスケープシーケンスで色を付ける
+// 色ルールファイルでIDごとの色を指定でき、未指定のIDはカテゴリの色になる
+// --width が指定されたときの、出力の幅の割り振り（切り詰め・折り返し）もここで行う
+// 結果の出力先（標準出力か、--output のファイルか）もここで切り替える
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Result, SmasherError};
+use crate::model::{ActionType, Lang, Momentum};
+
+/// 端末の文字色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// 基本16色（ANSIの前景色コード 30〜37, 90〜97）
+    Ansi(u8),
+    /// 24bitカラー（#RRGGBB）
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// 色の名前または #RRGGBB から色を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        let code = match name {
+            "black" => 30,
+            "red" => 31,
+            "green" => 32,
+            "yellow" => 33,
+            "blue" => 34,
+            "magenta" => 35,
+            "cyan" => 36,
+            "white" => 37,
+            "gray" | "grey" => 90,
+            "bright_red" => 91,
+            "bright_green" => 92,
+            "bright_yellow" => 93,
+            "bright_blue" => 94,
+            "bright_magenta" => 95,
+            "bright_cyan" => 96,
+            "bright_white" => 97,
+            _ => return Color::from_hex(name),
+        };
+        Some(Color::Ansi(code))
+    }
+
+    /// #RRGGBB 形式の色を解析する
+    fn from_hex(text: &str) -> Option<Self> {
+        let hex = text.strip_prefix('#')?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    /// 色を付け始めるエスケープシーケンス
+    fn escape(&self) -> String {
+        match self {
+            Color::Ansi(code) => format!("\x1b[{}m", code),
+            Color::Rgb(r, g, b) => format!("\x1b[38;2;{};{};{}m", r, g, b),
+        }
+    }
+}
+
+/// 行動ID → 色 の対応（色ルールファイルの内容）
+#[derive(Debug, Clone)]
+pub struct ColorRules {
+    rules: HashMap<String, Color>,
+}
+
+// Default: ライブラリとして使うときに ColorRules::default() でも作れるようにする（new() と同じ）
+impl Default for ColorRules {
+    fn default() -> Self {
+        ColorRules::new()
+    }
+}
+
+impl ColorRules {
+    /// ルールなし
+    pub fn new() -> Self {
+        ColorRules {
+            rules: HashMap::new(),
+        }
+    }
+
+    /// 色ルールファイルを読み込む
+    ///
+    /// # ファイル形式
+    /// 1行に「行動ID,色」を書く。色は red などの名前か #RRGGBB。
+    /// 空行と # で始まる行は無視する。
+    /// ```text
+    /// # 注目する技
+    /// ss,bright_red
+    /// ub_a,#ff8800
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let mut rules = HashMap::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split(',').map(|p| p.trim()).collect();
+            let [id, color_name] = parts[..] else {
+                return Err(SmasherError::InvalidArgument(format!(
+                    "色ルールファイル {} の{}行目: 「行動ID,色」の形式である必要があります。実際: {}",
+                    path.display(),
+                    index + 1,
+                    line
+                )));
+            };
+            let color = Color::from_name(color_name).ok_or_else(|| {
+                SmasherError::InvalidArgument(format!(
+                    "色ルールファイル {} の{}行目: 未知の色です: {}",
+                    path.display(),
+                    index + 1,
+                    color_name
+                ))
+            })?;
+            rules.insert(id.to_string(), color);
+        }
+
+        Ok(ColorRules { rules })
+    }
+}
+
+/// 色を付けるかどうかの指定（--color）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// 標準出力が端末なら色を付ける（デフォルト）
+    Auto,
+    /// パイプやファイルへの出力でも必ず色を付ける（less -R で見るときなど）
+    Always,
+    /// 色を付けない（--no-color と同じ）
+    Never,
+}
+
+impl ColorChoice {
+    /// 名前から指定を取得する
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+}
+
+/// 表示幅がwidthを超える文字列を、末尾を「…」にして切り詰める
+///
+/// 書記素（graphemes）の単位で切るので、絵文字や結合文字の途中では切れない
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    const ELLIPSIS: &str = "…";
+    let budget = width.saturating_sub(display_width(ELLIPSIS));
+    let mut out = String::new();
+    let mut used = 0;
+    for grapheme in graphemes(text) {
+        let grapheme_width = display_width(grapheme);
+        if used + grapheme_width > budget {
+            break;
+        }
+        used += grapheme_width;
+        out.push_str(grapheme);
+    }
+    out.push_str(ELLIPSIS);
+    // 色の途中で切れたときのために色を戻す
+    if text.contains('\x1b') {
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// 表示幅がwidthを超えないように、できるだけ空白の位置で折り返す
+///
+/// 続きの行は元の行の字下げ＋2文字下げる。空白がなければ文字の途中で折り返す
+fn wrap_words(line: &str, width: usize) -> Vec<String> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let continuation = " ".repeat((indent + 2).min(width / 2));
+
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    // 折り返しに使える空白の位置（currentの中のバイト位置）
+    let mut last_space: Option<usize> = None;
+    for grapheme in graphemes(line) {
+        let prefix = if lines.is_empty() { indent } else { continuation.len() };
+        let grapheme_width = display_width(grapheme);
+        if display_width(&current) + grapheme_width > width && display_width(&current) > prefix {
+            let rest = match last_space {
+                Some(space) => {
+                    let rest = current[space + 1..].to_string();
+                    current.truncate(current[..space].trim_end().len());
+                    rest
+                }
+                None => String::new(),
+            };
+            lines.push(std::mem::take(&mut current));
+            current = format!("{}{}", continuation, rest);
+            last_space = None;
+        }
+        if grapheme == " " && current.len() > prefix {
+            last_space = Some(current.len());
+        }
+        current.push_str(grapheme);
+    }
+    lines.push(current);
+    lines
+}
+
+/// 記号の表（前半→後半の変化の向き, UTF-8の記号, ASCIIでの代用）
+///
+/// UTF-8を表示できない端末では sparkline::prefer_ascii() に合わせてASCIIで描く
+const MOMENTUM_GLYPHS: [(Momentum, &str, &str); 3] = [
+    (Momentum::Rising, "↑", "+"),
+    (Momentum::Falling, "↓", "-"),
+    (Momentum::Steady, "→", "="),
+];
+
+/// 前半→後半の変化の向きを表す記号
+///
+/// # 引数
+/// * `momentum` - 変化の向き
+/// * `ascii` - trueならASCII文字（+ - =）で描く
+pub fn momentum_glyph(momentum: Momentum, ascii: bool) -> &'static str {
+    for (kind, utf8, fallback) in MOMENTUM_GLYPHS {
+        if kind == momentum {
+            return if ascii { fallback } else { utf8 };
+        }
+    }
+    // 表にすべての向きがあるのでここには来ない
+    if ascii {
+        "="
+    } else {
+        "→"
+    }
+}
+
+/// 実際に色を付けるかどうかを決める
+///
+/// # 優先順位
+/// 1. `--color always` / `--color never`（`--no-color` と環境変数 SMASHER_NO_COLOR は never として渡される）
+/// 2. 環境変数 NO_COLOR（空でない値が設定されていれば色を付けない。https://no-color.org/ の慣習）
+/// 3. 標準出力が端末かどうか
+///
+/// # 引数
+/// * `choice` - コマンドラインでの指定
+/// * `no_color_env` - 環境変数 NO_COLOR が設定されているか
+/// * `is_terminal` - 標準出力が端末か
+pub fn resolve_color(choice: ColorChoice, no_color_env: bool, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !no_color_env && is_terminal,
+    }
+}
+
+/// 表を縦並び（項目: 値）に切り替える幅。--width がこれ未満なら表を使わない
+pub const STACKED_BELOW: usize = 60;
+
+/// ゼロ幅接合子（絵文字同士をつないで1つの絵文字にする）
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// 異体字セレクタ16（直前の記号を絵文字として全角で表示させる）
+const EMOJI_PRESENTATION: char = '\u{FE0F}';
+
+/// 表示幅を持たない文字か
+///
+/// 結合文字（アクセント記号・濁点など）、異体字セレクタ、ゼロ幅の文字、
+/// 双方向テキスト（アラビア文字・ヘブライ文字など右から左に書く文字）の制御文字
+fn is_zero_width(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0300..=0x036F
+            | 0x0483..=0x0489
+            | 0x0591..=0x05BD
+            | 0x05BF
+            | 0x05C1..=0x05C2
+            | 0x05C4..=0x05C5
+            | 0x05C7
+            | 0x0610..=0x061A
+            | 0x061C
+            | 0x064B..=0x065F
+            | 0x0670
+            | 0x06D6..=0x06DC
+            | 0x06DF..=0x06E4
+            | 0x06E7..=0x06E8
+            | 0x06EA..=0x06ED
+            | 0x1AB0..=0x1AFF
+            | 0x1DC0..=0x1DFF
+            | 0x200B..=0x200F
+            | 0x202A..=0x202E
+            | 0x2060..=0x2064
+            | 0x2066..=0x2069
+            | 0x20D0..=0x20FF
+            | 0x3099..=0x309A
+            | 0xFE00..=0xFE0F
+            | 0xFE20..=0xFE2F
+            | 0xFEFF
+            | 0xE0000..=0xE007F
+            | 0xE0100..=0xE01EF
+    )
+}
+
+/// 国旗の絵文字を作る地域指示記号か（2つ並んで1つの国旗になる）
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1F1E6..=0x1F1FF)
+}
+
+/// 直前の文字とつながって、1つの文字（書記素）として表示される文字か
+fn extends_previous(c: char) -> bool {
+    // 肌の色を変える修飾子は、直前の絵文字と合わせて1つの絵文字になる
+    is_zero_width(c) || matches!(c as u32, 0x1F3FB..=0x1F3FF)
+}
+
+/// 1文字の表示幅
+///
+/// 日本語の端末に合わせて、幅のあいまいな記号（…、→、■ など）は2として数える。
+/// ラテン文字・キリル文字・ヘブライ文字・アラビア文字や半角カナなど、
+/// 必ず半角で表示される文字は1、結合文字などの幅のない文字は0
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x20..=0x7E => 1,
+        // 制御文字は表示されない
+        0x00..=0x1F | 0x7F..=0x9F => 0,
+        _ if is_zero_width(c) => 0,
+        // ハングルの字母は全角
+        0x1100..=0x115F => 2,
+        0xA0..=0x1FFF | 0xFB00..=0xFDFF | 0xFE70..=0xFEFE | 0xFF61..=0xFFDC | 0xFFE8..=0xFFEE => 1,
+        _ => 2,
+    }
+}
+
+/// 文字列を、画面上の1文字（書記素）ごとに分ける
+///
+/// 結合文字や異体字セレクタは直前の文字に、ゼロ幅接合子でつないだ絵文字（👨‍👩‍👧 など）は1つに、
+/// 地域指示記号は2つずつ（国旗）まとめる。色のエスケープシーケンスも1つにまとめる。
+/// Unicodeの書記素クラスタの規則をすべて実装したものではなく、表示幅を数えるための簡易版
+///
+/// # 戻り値
+/// 元の文字列の部分文字列の並び（つなげると元の文字列になる）
+pub fn graphemes(text: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        if c == '\x1b' {
+            // 「ESC [ 31 m」のような色の指定は m までを1つにする
+            for (i, next) in chars.by_ref() {
+                end = i + next.len_utf8();
+                if next == 'm' {
+                    break;
+                }
+            }
+        } else {
+            let mut previous = c;
+            let mut regional = usize::from(is_regional_indicator(c));
+            while let Some(&(i, next)) = chars.peek() {
+                let pairs_flag = regional == 1 && is_regional_indicator(next);
+                let joins = next != '\x1b' && (previous == ZERO_WIDTH_JOINER || extends_previous(next) || pairs_flag);
+                if !joins {
+                    break;
+                }
+                if pairs_flag {
+                    regional += 1;
+                }
+                chars.next();
+                end = i + next.len_utf8();
+                previous = next;
+            }
+        }
+        out.push(&text[start..end]);
+    }
+    out
+}
+
+/// 1つの書記素の表示幅（最初の文字の幅。絵文字として表示させる指定があれば2）
+fn grapheme_width(grapheme: &str) -> usize {
+    let Some(first) = grapheme.chars().next() else {
+        return 0;
+    };
+    if first == '\x1b' {
+        return 0;
+    }
+    if grapheme.contains(EMOJI_PRESENTATION) {
+        return 2;
+    }
+    char_width(first)
+}
+
+/// 文字列の表示幅（色のエスケープシーケンスは数えない）
+///
+/// 書記素ごとに char_width で数える。絵文字や結合文字が入っていても、端末上の桁数とずれないようにする
+pub fn display_width(text: &str) -> usize {
+    graphemes(text).into_iter().map(grapheme_width).sum()
+}
+
+/// 環境変数 NO_COLOR が空でない値で設定されているか
+fn no_color_env() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+/// 結果の書き出し先（--output のファイルなど）
+///
+/// Style をcloneしても同じ書き出し先を使うように、中身は Arc で共有する。
+/// 書き出しに失敗してもその場では止めず、最初のエラーを覚えておいて finish で返す
+#[derive(Clone)]
+pub struct Sink {
+    state: Arc<Mutex<SinkState>>,
+}
+
+/// 書き出し先の中身（書き出し先, 最初に起きたエラー）
+struct SinkState {
+    writer: Box<dyn Write + Send>,
+    error: Option<io::Error>,
+}
+
+impl Sink {
+    /// 書き出し先を作る
+    ///
+    /// # 引数
+    /// * `writer` - 書き出し先（ファイル、テスト用の Vec<u8> など）
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Sink {
+            state: Arc::new(Mutex::new(SinkState {
+                writer: Box::new(writer),
+                error: None,
+            })),
+        }
+    }
+
+    /// 文字列をそのまま書く（前に失敗していれば何もしない）
+    fn write(&self, text: &str) {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if state.error.is_none() {
+            if let Err(e) = state.writer.write_all(text.as_bytes()) {
+                state.error = Some(e);
+            }
+        }
+    }
+
+    /// 書き出しを終える
+    ///
+    /// # 戻り値
+    /// 途中の書き出しか最後のフラッシュに失敗していれば、そのエラー
+    pub fn finish(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(e) = state.error.take() {
+            return Err(e);
+        }
+        state.writer.flush()
+    }
+}
+
+impl fmt::Debug for Sink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Sink")
+    }
+}
+
+/// 出力の色付けの設定
+#[derive(Debug, Clone)]
+pub struct Style {
+    /// 色を付けるかどうか
+    pub color_enabled: bool,
+    /// 行動IDごとの色
+    pub rules: ColorRules,
+    /// 出力の最大幅（--width）。Noneなら制限しない
+    pub width: Option<usize>,
+    /// 結果の書き出し先（--output）。Noneなら標準出力
+    pub sink: Option<Sink>,
+    /// 行動名を表示する言語（--lang）
+    pub lang: Lang,
+}
+
+impl Style {
+    /// 色付けの設定を作る
+    ///
+    /// 環境変数 NO_COLOR と標準出力が端末かどうかを調べ、resolve_color の優先順位で色を付けるか決める
+    pub fn new(choice: ColorChoice, rules: ColorRules) -> Self {
+        Style {
+            color_enabled: resolve_color(choice, no_color_env(), std::io::stdout().is_terminal()),
+            rules,
+            width: None,
+            sink: None,
+            lang: Lang::default(),
+        }
+    }
+
+    /// 出力の最大幅を設定する（--width）
+    pub fn with_width(mut self, width: Option<usize>) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// 行動名を表示する言語を設定する（--lang）
+    pub fn with_lang(mut self, lang: Lang) -> Self {
+        self.lang = lang;
+        self
+    }
+
+    /// 行動IDの名前を、--lang の言語で取得する
+    pub fn action_name(&self, action_id: &str) -> String {
+        ActionType::get_action_name_in(action_id, self.lang)
+    }
+
+    /// 結果を標準出力ではなく `sink` に書く（--output）
+    ///
+    /// ファイルは端末ではないので、色は --color always のときだけ付ける
+    pub fn with_sink(mut self, sink: Sink, choice: ColorChoice) -> Self {
+        self.color_enabled = resolve_color(choice, no_color_env(), false);
+        self.sink = Some(sink);
+        self
+    }
+
+    /// 文字列を出力先にそのまま書く（改行は足さず、幅で折り返さない。JSONやCSV用）
+    pub fn write(&self, text: &str) {
+        match &self.sink {
+            Some(sink) => sink.write(text),
+            None => print!("{}", text),
+        }
+    }
+
+    /// 1行を表示する（println! の代わり）
+    ///
+    /// 最大幅があれば、見出し（【〜】）と区切り線（===）は末尾を「…」にして切り詰め、
+    /// それ以外の行は単語の区切り（空白）で折り返す
+    pub fn emit(&self, line: &str) {
+        let Some(width) = self.width else {
+            self.write(&format!("{}\n", line));
+            return;
+        };
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('【') || trimmed.starts_with('=') {
+            self.write(&format!("{}\n", truncate(line, width)));
+        } else {
+            for part in wrap_words(line, width) {
+                self.write(&format!("{}\n", part));
+            }
+        }
+    }
+
+    /// グラフや棒に使える幅
+    ///
+    /// # 引数
+    /// * `preferred` - 幅の制限がないときの幅
+    /// * `reserved` - 同じ行のラベルなどが使う幅
+    ///
+    /// # 戻り値
+    /// `preferred` 以下で、最大幅から `reserved` を引いた幅（最低1）
+    pub fn budget(&self, preferred: usize, reserved: usize) -> usize {
+        match self.width {
+            Some(width) => width.saturating_sub(reserved).clamp(1, preferred.max(1)),
+            None => preferred,
+        }
+    }
+
+    /// 表を縦並び（項目: 値）で表示すべきか
+    pub fn stacked(&self) -> bool {
+        self.width.is_some_and(|width| width < STACKED_BELOW)
+    }
+
+    /// カテゴリの色
+    pub fn category_color(action_type: &ActionType) -> Color {
+        match action_type {
+            ActionType::Attack => Color::Ansi(31),
+            ActionType::Shield => Color::Ansi(34),
+            ActionType::Dodge => Color::Ansi(32),
+            // 未登録のIDは目立たせすぎないように灰色
+            ActionType::Unknown => Color::Ansi(90),
+        }
+    }
+
+    /// 行動IDの色（ルールになければカテゴリの色）
+    pub fn id_color(&self, action_id: &str) -> Color {
+        match self.rules.rules.get(action_id) {
+            Some(color) => *color,
+            None => Style::category_color(&ActionType::from_action_id(action_id)),
+        }
+    }
+
+    /// 行動IDの色で文字列に色を付ける（色が無効ならそのまま返す）
+    pub fn paint_id(&self, action_id: &str, text: &str) -> String {
+        self.paint(self.id_color(action_id), text)
+    }
+
+    /// 文字列に色を付ける（色が無効ならそのまま返す）
+    pub fn paint(&self, color: Color, text: &str) -> String {
+        if self.color_enabled {
+            format!("{}{}\x1b[0m", color.escape(), text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用の書き出し先。Sink に渡した後も中身を読めるように Vec<u8> を共有する
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedBuf {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 何回目かの書き出しから失敗する書き出し先
+    struct FailAfter(usize);
+
+    impl Write for FailAfter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.0 == 0 {
+                return Err(io::Error::other("disk full"));
+            }
+            self.0 -= 1;
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn sink_style(buf: &SharedBuf) -> Style {
+        Style::new(ColorChoice::Never, ColorRules::default()).with_sink(Sink::new(buf.clone()), ColorChoice::Auto)
+    }
+
+    #[test]
+    fn sink_receives_emitted_lines_and_raw_text() {
+        let buf = SharedBuf::default();
+        let style = sink_style(&buf);
+        style.emit("【結果】");
+        style.write("{\"a\":1}");
+        // clone した Style も同じ書き出し先に書く
+        style.clone().emit("");
+        style.sink.as_ref().unwrap().finish().unwrap();
+        assert_eq!(buf.text(), "【結果】\n{\"a\":1}\n");
+    }
+
+    #[test]
+    fn sink_output_has_no_color_with_auto() {
+        let buf = SharedBuf::default();
+        let style = sink_style(&buf);
+        assert!(!style.color_enabled);
+        style.emit(&style.paint_id("us", "us"));
+        assert_eq!(buf.text(), "us\n");
+    }
+
+    #[test]
+    fn sink_output_is_wrapped_to_the_width() {
+        let buf = SharedBuf::default();
+        let style = sink_style(&buf).with_width(Some(10));
+        style.emit("aaaa bbbb cccc");
+        style.emit("【とても長い見出しです】");
+        // 折り返した行は字下げして続ける
+        assert_eq!(buf.text(), "aaaa bbbb\n  cccc\n【とても…\n");
+    }
+
+    /// 絵文字・結合文字・右から左の文字などを集めたログ
+    const UNICODE_STRESS: &str = include_str!("../fixtures/unicode_stress.csv");
+
+    fn unicode_stress_ids() -> Vec<String> {
+        let battle_log =
+            crate::parser::read_battle_log_from(UNICODE_STRESS.as_bytes(), &crate::parser::ParseLimits::unlimited()).unwrap();
+        battle_log.actions.iter().map(|action| action.original_id.to_string()).collect()
+    }
+
+    #[test]
+    fn unicode_fixture_display_widths() {
+        let widths: Vec<(String, usize)> = unicode_stress_ids().iter().map(|id| (id.clone(), display_width(id))).collect();
+        let expected = [
+            ("us", 2),
+            ("👨\u{200d}👩\u{200d}👧", 2),
+            ("\u{e9}", 1),
+            ("e\u{301}", 1),
+            ("مرحبا", 5),
+            ("🇯🇵", 2),
+            ("❤\u{fe0f}", 2),
+            ("カ\u{3099}", 2),
+            ("a,b", 3),
+            ("ss", 2),
+            ("👾👾👾👾👾👾👾👾", 16),
+            ("\u{202e}abc\u{202c}", 3),
+            ("👍🏽", 2),
+            ("ds", 2),
+            ("👨\u{200d}👩\u{200d}👧", 2),
+        ];
+        let expected: Vec<(String, usize)> = expected.iter().map(|&(id, width)| (id.to_string(), width)).collect();
+        assert_eq!(widths, expected);
+        assert_eq!(display_width("🎮 שחקן\u{301}"), 7);
+    }
+
+    #[test]
+    fn unicode_fixture_graphemes_are_never_split() {
+        for id in unicode_stress_ids() {
+            let parts = graphemes(&id);
+            assert_eq!(parts.concat(), id);
+            // 書記素の区切りで切った先頭部分（空文字列から全体まで）
+            let prefixes: Vec<String> = (0..=parts.len()).map(|n| parts[..n].concat()).collect();
+            // 「…」が幅2なので、それが入る2桁以上で確かめる
+            for width in 2..=20 {
+                let cut = truncate(&id, width);
+                // 切り詰めても幅を超えず、書記素の途中で切らない
+                assert!(display_width(&cut) <= width, "{:?} を {} 桁に: {:?}", id, width, cut);
+                let kept = cut.strip_suffix('…').unwrap_or(&cut);
+                assert!(prefixes.iter().any(|prefix| prefix == kept), "{:?} を {} 桁に: {:?}", id, width, cut);
+            }
+        }
+    }
+
+    #[test]
+    fn sink_keeps_the_first_error_for_finish() {
+        let sink = Sink::new(FailAfter(1));
+        sink.write("ok");
+        sink.write("fails");
+        sink.write("skipped");
+        assert_eq!(sink.finish().unwrap_err().to_string(), "disk full");
+        // エラーは一度返したら消える
+        assert!(sink.finish().is_ok());
+    }
+    #[test]
+    fn resolve_color_covers_every_flag_no_color_and_tty_combination() {
+        // (指定, NO_COLOR, 端末, 色を付けるか)
+        let cases = [
+            (ColorChoice::Always, false, false, true),
+            (ColorChoice::Always, false, true, true),
+            (ColorChoice::Always, true, false, true),
+            (ColorChoice::Always, true, true, true),
+            (ColorChoice::Never, false, false, false),
+            (ColorChoice::Never, false, true, false),
+            (ColorChoice::Never, true, false, false),
+            (ColorChoice::Never, true, true, false),
+            (ColorChoice::Auto, false, false, false),
+            (ColorChoice::Auto, false, true, true),
+            (ColorChoice::Auto, true, false, false),
+            (ColorChoice::Auto, true, true, false),
+        ];
+        for (choice, no_color, is_terminal, expected) in cases {
+            assert_eq!(resolve_color(choice, no_color, is_terminal), expected, "{:?} NO_COLOR={} 端末={}", choice, no_color, is_terminal);
+        }
+    }
+
+    #[test]
+    fn lines_are_left_alone_without_a_width() {
+        let buf = SharedBuf::default();
+        let style = sink_style(&buf);
+        let long = format!("【{}】 {}", "見出し".repeat(20), "a ".repeat(60));
+        style.emit(&long);
+        assert_eq!(buf.text(), format!("{}\n", long));
+        assert_eq!(style.budget(50, 10), 50);
+        assert!(!style.stacked());
+    }
+
+    #[test]
+    fn rules_are_cut_and_long_words_are_split() {
+        let buf = SharedBuf::default();
+        let style = sink_style(&buf).with_width(Some(8));
+        style.emit(&"=".repeat(20));
+        // 「…」は2桁とみなす。空白のない長い語は、文字の途中で折り返す
+        style.emit("abcdefghijkl");
+        assert_eq!(buf.text(), "======…\nabcdefgh\n  ijkl\n");
+    }
+
+    #[test]
+    fn budget_shrinks_graphs_to_the_space_left() {
+        let style = Style::new(ColorChoice::Never, ColorRules::default()).with_width(Some(30));
+        assert_eq!(style.budget(50, 10), 20);
+        // 幅に余裕があれば、もとの幅より広げない
+        assert_eq!(style.budget(15, 10), 15);
+        // ラベルだけで幅を使い切っても、最低1は残す
+        assert_eq!(style.budget(50, 40), 1);
+    }
+
+    #[test]
+    fn tables_are_stacked_below_sixty_columns() {
+        let stacked = |width| Style::new(ColorChoice::Never, ColorRules::default()).with_width(Some(width)).stacked();
+        assert!(stacked(STACKED_BELOW - 1));
+        assert!(!stacked(STACKED_BELOW));
+    }
+
+    #[test]
+    fn momentum_glyphs_have_an_ascii_fallback() {
+        let glyphs = |ascii| [Momentum::Rising, Momentum::Falling, Momentum::Steady].map(|m| momentum_glyph(m, ascii));
+        assert_eq!(glyphs(false), ["↑", "↓", "→"]);
+        assert_eq!(glyphs(true), ["+", "-", "="]);
+    }
+}
@@ -0,0 +1,86 @@
+// watch.rs
+// 監視モジュール（--watch）
+//
+// 入力ファイルの更新日時とサイズを一定間隔で確認し、
+// 変わっていたらファイル全体を読み直して解析結果を表示し直す。
+// notifyクレートのようなOSの通知は使わず、ポーリングで実装している
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::analyzer;
+use crate::cli::Options;
+use crate::error::Result;
+use crate::output::{self, DisplayOptions};
+use crate::signal;
+
+/// ファイルの変更を確認する間隔
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// ファイルの変更を検出するための情報（更新日時, サイズ）
+type Stamp = (Option<SystemTime>, u64);
+
+/// 入力ファイルを監視し、更新されるたびに解析し直して表示する
+///
+/// # 処理の流れ
+/// 1. 最初に1回解析して表示する
+/// 2. POLL_INTERVAL ごとに更新日時とサイズを確認する
+/// 3. 変わっていたら画面を消して解析結果を表示し直す
+/// 4. Ctrl-Cで終了する
+///
+/// 書き込み途中などで読み込みや解析に失敗したときは、エラーを表示して監視を続ける
+pub fn watch(options: &Options, display: &DisplayOptions) -> Result<()> {
+    signal::install_interrupt_handler();
+
+    let file_path = &options.file_paths[0];
+    // 最初に開けないファイルは監視しても意味がないのでエラーにする
+    let mut last_stamp = stamp(file_path)?;
+    refresh(options, display);
+
+    while !signal::interrupted() {
+        thread::sleep(POLL_INTERVAL);
+
+        // 保存のしかたによっては一瞬ファイルが消えるので、確認できない間は待つ
+        let Ok(current) = stamp(file_path) else {
+            continue;
+        };
+        if current != last_stamp {
+            last_stamp = current;
+            refresh(options, display);
+        }
+    }
+
+    println!();
+    println!("監視を終了しました");
+    Ok(())
+}
+
+/// ファイルの更新日時とサイズを取得する
+///
+/// 更新日時が取得できないファイルシステムではサイズだけで判定する
+fn stamp(file_path: &str) -> Result<Stamp> {
+    let metadata = fs::metadata(file_path)?;
+    Ok((metadata.modified().ok(), metadata.len()))
+}
+
+/// 画面を消してから、ファイルを読み直した解析結果を表示する
+fn refresh(options: &Options, display: &DisplayOptions) {
+    let file_path = &options.file_paths[0];
+
+    // 画面を消してカーソルを左上に戻す（ANSIエスケープシーケンス）
+    print!("\x1b[2J\x1b[H");
+    println!("監視中: {} (Ctrl-Cで終了)", file_path);
+    println!();
+
+    match options.read(file_path) {
+        Ok(mut battle_log) => {
+            output::display_skipped_lines(file_path, &battle_log.skipped_lines);
+            options.prepare(&mut battle_log);
+            output::display_warnings(&battle_log.warnings);
+            output::display_result(&analyzer::analyze(&battle_log, &options.analysis), display);
+        }
+        // 読み込めなくても終了せず、次の更新を待つ
+        Err(e) => println!("読み込みに失敗しました（更新を待っています）: {}", e),
+    }
+}